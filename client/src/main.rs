@@ -4,8 +4,12 @@ use youniverse_engine::prelude::*;
 
 fn main() {
     tracing_subscriber::fmt().init();
-    let Ok(win) = Window::new(String::from("Tempest Engine: Test"), 1920, 1080) else {
-        panic!("Could not create window client")
+    let win = match Window::new(String::from("Tempest Engine: Test"), 1920, 1080) {
+        Ok(win) => win,
+        Err(e) => {
+            tracing::error!("Could not create window client: {:#}", e);
+            std::process::exit(1);
+        }
     };
     win.run();
 }
@@ -0,0 +1,751 @@
+//! A CPU decoder for the LDR profile of ASTC.
+
+use std::sync::OnceLock;
+
+/// The magenta ASTC decoders return for blocks that are malformed or need the HDR profile.
+const ERROR_COLOR: [u8; 4] = [0xff, 0, 0xff, 0xff];
+
+#[derive(Clone, Copy)]
+pub(crate) enum Kind {
+    Bits,
+    Trits,
+    Quints,
+}
+
+/// A range of integer sequence encoded values, a trit or quint above `bits` plain bits.
+#[derive(Clone, Copy)]
+pub(crate) struct Range {
+    pub(crate) kind: Kind,
+    pub(crate) bits: u32,
+}
+
+impl Range {
+    pub(crate) const fn new(kind: Kind, bits: u32) -> Self {
+        Self { kind, bits }
+    }
+
+    /// The encoded size of `count` values.
+    fn bit_count(self, count: u32) -> u32 {
+        let bits = count * self.bits;
+        match self.kind {
+            Kind::Bits => bits,
+            Kind::Trits => bits + (8 * count).div_ceil(5),
+            Kind::Quints => bits + (7 * count).div_ceil(3),
+        }
+    }
+}
+
+/// The weight ranges by the high precision bit and the range bits 2 to 7 of the block mode.
+const WEIGHT_RANGES: [[Range; 6]; 2] = [
+    [
+        Range::new(Kind::Bits, 1),
+        Range::new(Kind::Trits, 0),
+        Range::new(Kind::Bits, 2),
+        Range::new(Kind::Quints, 0),
+        Range::new(Kind::Trits, 1),
+        Range::new(Kind::Bits, 3),
+    ],
+    [
+        Range::new(Kind::Quints, 1),
+        Range::new(Kind::Trits, 2),
+        Range::new(Kind::Bits, 4),
+        Range::new(Kind::Quints, 2),
+        Range::new(Kind::Trits, 3),
+        Range::new(Kind::Bits, 5),
+    ],
+];
+
+/// The ranges of color endpoint values from 6 to 256 levels, blocks use the largest that fits.
+const COLOR_RANGES: [Range; 17] = [
+    Range::new(Kind::Trits, 1),
+    Range::new(Kind::Bits, 3),
+    Range::new(Kind::Quints, 1),
+    Range::new(Kind::Trits, 2),
+    Range::new(Kind::Bits, 4),
+    Range::new(Kind::Quints, 2),
+    Range::new(Kind::Trits, 3),
+    Range::new(Kind::Bits, 5),
+    Range::new(Kind::Quints, 3),
+    Range::new(Kind::Trits, 4),
+    Range::new(Kind::Bits, 6),
+    Range::new(Kind::Quints, 4),
+    Range::new(Kind::Trits, 5),
+    Range::new(Kind::Bits, 7),
+    Range::new(Kind::Quints, 5),
+    Range::new(Kind::Trits, 6),
+    Range::new(Kind::Bits, 8),
+];
+
+/// `count` bits of `bits` from `start` up, 0 past the end of the block.
+fn field(bits: u128, start: u32, count: u32) -> u32 {
+    if start >= 128 {
+        return 0;
+    }
+
+    (bits >> start) as u32 & ((1u64 << count) - 1) as u32
+}
+
+/// Decodes a 16 byte block with a `width` by `height` footprint into its texels in row major
+/// order. With `srgb` the texels are the sRGB encoded values of the `_SRGB` formats.
+///
+/// Blocks that are malformed or use HDR endpoints decode to the magenta error color.
+pub(crate) fn decode_block(block: &[u8], width: usize, height: usize, srgb: bool) -> Vec<[u8; 4]> {
+    let bits = u128::from_le_bytes(block[..16].try_into().unwrap());
+
+    decode(bits, width, height, srgb).unwrap_or_else(|| vec![ERROR_COLOR; width * height])
+}
+
+fn decode(bits: u128, width: usize, height: usize, srgb: bool) -> Option<Vec<[u8; 4]>> {
+    let to_u8 = |value: u32| {
+        if srgb {
+            (value >> 8) as u8
+        } else {
+            ((value * 255 + 32767) / 65535) as u8
+        }
+    };
+
+    if field(bits, 0, 9) == 0x1fc {
+        // a void extent block is one color, HDR void extents need the HDR profile
+        if field(bits, 9, 1) == 1 {
+            return None;
+        }
+        let color = [0, 1, 2, 3].map(|c| to_u8(field(bits, 64 + c * 16, 16)));
+        return Some(vec![color; width * height]);
+    }
+
+    let mode = BlockMode::new(field(bits, 0, 11))?;
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = mode.width * mode.height * planes;
+    if mode.width > width || mode.height > height || weight_count > 64 {
+        return None;
+    }
+    let weight_bits = mode.weights.bit_count(weight_count as u32);
+    if !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    let partitions = field(bits, 11, 2) + 1;
+    if partitions == 4 && mode.dual_plane {
+        return None;
+    }
+
+    // config that does not fit the first 29 bits is stored below the weights
+    let mut below_weights = 128 - weight_bits;
+    let (modes, color_start) = if partitions == 1 {
+        (vec![field(bits, 13, 4)], 17)
+    } else {
+        let selector = field(bits, 23, 2);
+        if selector == 0 {
+            (vec![field(bits, 25, 4); partitions as usize], 29)
+        } else {
+            let extra_bits = 3 * partitions - 4;
+            below_weights -= extra_bits;
+            let encoded = field(bits, 25, 4) | (field(bits, below_weights, extra_bits) << 4);
+            let modes = (0..partitions)
+                .map(|i| {
+                    let class = selector - 1 + ((encoded >> i) & 1);
+                    (class << 2) | ((encoded >> (partitions + i * 2)) & 3)
+                })
+                .collect();
+            (modes, 29)
+        }
+    };
+    let color_end = below_weights - if mode.dual_plane { 2 } else { 0 };
+    let plane2_channel = mode.dual_plane.then(|| field(bits, color_end, 2) as usize);
+
+    // the HDR endpoint modes
+    if modes
+        .iter()
+        .any(|mode| matches!(mode, 2 | 3 | 7 | 11 | 14 | 15))
+    {
+        return None;
+    }
+    let value_count: u32 = modes.iter().map(|mode| (mode / 4 + 1) * 2).sum();
+    if value_count > 18 {
+        return None;
+    }
+    let color_bits = color_end.checked_sub(color_start)?;
+    let range = *COLOR_RANGES
+        .iter()
+        .rev()
+        .find(|range| range.bit_count(value_count) <= color_bits)?;
+    let values: Vec<_> = decode_ise(bits, color_start, color_end, value_count, range)
+        .into_iter()
+        .map(|value| unquantize_color(value, range) as i32)
+        .collect();
+    let mut remaining = &values[..];
+    let endpoints: Vec<_> = modes
+        .iter()
+        .map(|&mode| {
+            let (values, rest) = remaining.split_at((mode as usize / 4 + 1) * 2);
+            remaining = rest;
+            decode_endpoints(mode, values)
+        })
+        .collect();
+
+    let weights: Vec<_> = decode_ise(
+        bits.reverse_bits(),
+        0,
+        weight_bits,
+        weight_count as u32,
+        mode.weights,
+    )
+    .into_iter()
+    .map(|value| unquantize_weight(value, mode.weights))
+    .collect();
+    let infill = |plane: usize| infill(&weights, planes, plane, &mode, width, height);
+    let plane_weights = [infill(0), if mode.dual_plane { infill(1) } else { vec![] }];
+
+    let seed = field(bits, 13, 10);
+    let small_block = width * height < 31;
+    let expand = |value: i32| {
+        let value = value as u32;
+        if srgb {
+            (value << 8) | 0x80
+        } else {
+            (value << 8) | value
+        }
+    };
+
+    Some(
+        (0..width * height)
+            .map(|i| {
+                let partition = if partitions == 1 {
+                    0
+                } else {
+                    select_partition(
+                        seed,
+                        (i % width) as u32,
+                        (i / width) as u32,
+                        partitions,
+                        small_block,
+                    )
+                };
+                let [e0, e1] = endpoints[partition];
+
+                std::array::from_fn(|c| {
+                    let plane = (plane2_channel == Some(c)) as usize;
+                    let weight = plane_weights[plane][i];
+                    to_u8((expand(e0[c]) * (64 - weight) + expand(e1[c]) * weight + 32) >> 6)
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The weight grid and weight range of a block.
+struct BlockMode {
+    width: usize,
+    height: usize,
+    dual_plane: bool,
+    weights: Range,
+}
+
+impl BlockMode {
+    /// Decodes the 11 bit block mode, `None` for the reserved ones.
+    fn new(mode: u32) -> Option<Self> {
+        let bit = |i: u32| (mode >> i) & 1;
+        let bits = |start: u32, count: u32| (mode >> start) & ((1 << count) - 1);
+        let (a, b) = (bits(5, 2), bits(7, 2));
+
+        let (width, height, range, high, dual_plane) = if bits(0, 2) != 0 {
+            let (width, height) = match bits(2, 2) {
+                0 => (b + 4, a + 2),
+                1 => (b + 8, a + 2),
+                2 => (a + 2, b + 8),
+                _ if bit(8) == 0 => (a + 2, bit(7) + 6),
+                _ => (bit(7) + 2, a + 2),
+            };
+            (width, height, bit(4) | (bits(0, 2) << 1), bit(9), bit(10))
+        } else {
+            if bits(0, 4) == 0 {
+                return None;
+            }
+            let range = bit(4) | (bits(2, 2) << 1);
+            match b {
+                0 => (12, a + 2, range, bit(9), bit(10)),
+                1 => (a + 2, 12, range, bit(9), bit(10)),
+                2 => (a + 6, bits(9, 2) + 6, range, 0, 0),
+                _ => match a {
+                    0 => (6, 10, range, bit(9), bit(10)),
+                    1 => (10, 6, range, bit(9), bit(10)),
+                    _ => return None,
+                },
+            }
+        };
+        if range < 2 {
+            return None;
+        }
+
+        Some(Self {
+            width: width as usize,
+            height: height as usize,
+            dual_plane: dual_plane == 1,
+            weights: WEIGHT_RANGES[high as usize][range as usize - 2],
+        })
+    }
+}
+
+/// Reads `count` integer sequence encoded values of `range` from the bits between `start` and
+/// `end`, the bits of a last partial trit or quint group past `end` are 0.
+fn decode_ise(bits: u128, start: u32, end: u32, count: u32, range: Range) -> Vec<u32> {
+    let bits = if end < 128 {
+        bits & ((1 << end) - 1)
+    } else {
+        bits
+    };
+    let mut position = start;
+    let mut read = |count: u32| {
+        let value = field(bits, position, count);
+        position += count;
+        value
+    };
+    let mut values = Vec::with_capacity(count as usize + 4);
+
+    while values.len() < count as usize {
+        let b = range.bits;
+        match range.kind {
+            Kind::Bits => values.push(read(b)),
+            Kind::Trits => {
+                let mut m = [0; 5];
+                let mut t = 0;
+                for (i, (shift, t_bits)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)]
+                    .into_iter()
+                    .enumerate()
+                {
+                    m[i] = read(b);
+                    t |= read(t_bits) << shift;
+                }
+                values.extend(trits(t).into_iter().zip(m).map(|(t, m)| (t << b) | m));
+            }
+            Kind::Quints => {
+                let mut m = [0; 3];
+                let mut q = 0;
+                for (i, (shift, q_bits)) in [(0, 3), (3, 2), (5, 2)].into_iter().enumerate() {
+                    m[i] = read(b);
+                    q |= read(q_bits) << shift;
+                }
+                values.extend(quints(q).into_iter().zip(m).map(|(q, m)| (q << b) | m));
+            }
+        }
+    }
+
+    values.truncate(count as usize);
+    values
+}
+
+/// Unpacks the 5 trits of an 8 bit trit block.
+fn trits(t: u32) -> [u32; 5] {
+    let bit = |i: u32| (t >> i) & 1;
+
+    let (c, t4, t3) = if (t >> 2) & 7 == 7 {
+        (((t >> 5) & 7) << 2 | (t & 3), 2, 2)
+    } else if (t >> 5) & 3 == 3 {
+        (t & 0x1f, 2, bit(7))
+    } else {
+        (t & 0x1f, bit(7), (t >> 5) & 3)
+    };
+    let c_bit = |i: u32| (c >> i) & 1;
+    let (t2, t1, t0) = if c & 3 == 3 {
+        (2, c_bit(4), (c_bit(3) << 1) | (c_bit(2) & !c_bit(3) & 1))
+    } else if (c >> 2) & 3 == 3 {
+        (2, 2, c & 3)
+    } else {
+        (
+            c_bit(4),
+            (c >> 2) & 3,
+            (c_bit(1) << 1) | (c_bit(0) & !c_bit(1) & 1),
+        )
+    };
+
+    [t0, t1, t2, t3, t4]
+}
+
+/// Unpacks the 3 quints of a 7 bit quint block.
+fn quints(q: u32) -> [u32; 3] {
+    let bit = |i: u32| (q >> i) & 1;
+
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let q2 = (bit(0) << 2) | ((bit(4) & !bit(0) & 1) << 1) | (bit(3) & !bit(0) & 1);
+        return [4, 4, q2];
+    }
+    let (q2, c) = if (q >> 1) & 3 == 3 {
+        (4, (((q >> 3) & 3) << 3) | (((!q >> 5) & 3) << 1) | bit(0))
+    } else {
+        ((q >> 5) & 3, q & 0x1f)
+    };
+    let (q1, q0) = if c & 7 == 5 {
+        (4, (c >> 3) & 3)
+    } else {
+        ((c >> 3) & 3, c & 7)
+    };
+
+    [q0, q1, q2]
+}
+
+/// Writes `values` of `range` as an integer sequence into `bits` from `start` up, the inverse of
+/// [`decode_ise`].
+pub(crate) fn encode_ise(bits: &mut u128, start: u32, values: &[u32], range: Range) {
+    let mut sequence = 0u128;
+    let mut position = 0;
+    let mut write = |value: u32, count: u32| {
+        if position < 128 {
+            sequence |= ((value & ((1 << count) - 1)) as u128) << position;
+        }
+        position += count;
+    };
+    let b = range.bits;
+    let low_bits = |group: &[u32], i: usize| group.get(i).map_or(0, |value| value & ((1 << b) - 1));
+
+    match range.kind {
+        Kind::Bits => values.iter().for_each(|&value| write(value, b)),
+        Kind::Trits => {
+            for group in values.chunks(5) {
+                let t = trit_block(std::array::from_fn(|i| group.get(i).map_or(0, |v| v >> b)));
+                for (i, (shift, t_bits)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)]
+                    .into_iter()
+                    .enumerate()
+                {
+                    write(low_bits(group, i), b);
+                    write(t >> shift, t_bits);
+                }
+            }
+        }
+        Kind::Quints => {
+            for group in values.chunks(3) {
+                let q = quint_block(std::array::from_fn(|i| group.get(i).map_or(0, |v| v >> b)));
+                for (i, (shift, q_bits)) in [(0, 3), (3, 2), (5, 2)].into_iter().enumerate() {
+                    write(low_bits(group, i), b);
+                    write(q >> shift, q_bits);
+                }
+            }
+        }
+    }
+
+    // the bits of a last partial group past the sequence are 0
+    let length = range.bit_count(values.len() as u32);
+    if length < 128 {
+        sequence &= (1 << length) - 1;
+    }
+    *bits |= sequence << start;
+}
+
+/// Packs 5 trits into the smallest trit block holding them, the inverse of [`trits`].
+///
+/// The bits of trailing 0 trits are 0 in the smallest block, so a partial group can be cut short.
+fn trit_block(trits: [u32; 5]) -> u32 {
+    static BLOCKS: OnceLock<[u8; 243]> = OnceLock::new();
+    let blocks = BLOCKS.get_or_init(|| {
+        let mut blocks = [0; 243];
+        for t in (0..256).rev() {
+            blocks[digits_index(&self::trits(t), 3)] = t as u8;
+        }
+        blocks
+    });
+
+    blocks[digits_index(&trits, 3)] as u32
+}
+
+/// Packs 3 quints into the smallest quint block holding them, the inverse of [`quints`].
+fn quint_block(quints: [u32; 3]) -> u32 {
+    static BLOCKS: OnceLock<[u8; 125]> = OnceLock::new();
+    let blocks = BLOCKS.get_or_init(|| {
+        let mut blocks = [0; 125];
+        for q in (0..128).rev() {
+            blocks[digits_index(&self::quints(q), 5)] = q as u8;
+        }
+        blocks
+    });
+
+    blocks[digits_index(&quints, 5)] as u32
+}
+
+/// The number with `digits` in `base`, the first digit being the lowest.
+fn digits_index(digits: &[u32], base: u32) -> usize {
+    digits
+        .iter()
+        .rev()
+        .fold(0, |index, &digit| index * base + digit) as usize
+}
+
+/// Widens a `bits` wide value to `to` bits by repeating it.
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    let mut result = 0;
+    let mut shift = to as i32 - bits as i32;
+    while shift > -(bits as i32) {
+        result |= if shift >= 0 {
+            value << shift
+        } else {
+            value >> -shift
+        };
+        shift -= bits as i32;
+    }
+
+    result & ((1 << to) - 1)
+}
+
+/// Splits a trit or quint encoded value into its low bit mask, the `B` bits scattered by the
+/// specification's unquantization tables and the trit or quint itself.
+fn unquantize_parts(value: u32, range: Range, mask: u32) -> (u32, u32, u32) {
+    let m = value & ((1 << range.bits) - 1);
+    let a = if m & 1 == 1 { mask } else { 0 };
+
+    (a, m, value >> range.bits)
+}
+
+/// Scales a color endpoint value of `range` to 0..=255.
+fn unquantize_color(value: u32, range: Range) -> u32 {
+    if let Kind::Bits = range.kind {
+        return replicate(value, range.bits, 8);
+    }
+
+    let (a, m, d) = unquantize_parts(value, range, 0x1ff);
+    let bit = |i: u32| (m >> i) & 1;
+    let (b, c) = match (range.kind, range.bits) {
+        (Kind::Trits, 1) => (0, 204),
+        (Kind::Quints, 1) => (0, 113),
+        // b000b0bb0
+        (Kind::Trits, 2) => (
+            (bit(1) << 8) | (bit(1) << 4) | (bit(1) << 2) | (bit(1) << 1),
+            93,
+        ),
+        // b0000bb00
+        (Kind::Quints, 2) => ((bit(1) << 8) | (bit(1) << 3) | (bit(1) << 2), 54),
+        // cb000cbcb
+        (Kind::Trits, 3) => (
+            (bit(2) << 8) | (bit(1) << 7) | (bit(2) << 3) | (bit(1) << 2) | (bit(2) << 1) | bit(1),
+            44,
+        ),
+        // cb0000cbc
+        (Kind::Quints, 3) => (
+            (bit(2) << 8) | (bit(1) << 7) | (bit(2) << 2) | (bit(1) << 1) | bit(2),
+            26,
+        ),
+        // dcb000dcb
+        (Kind::Trits, 4) => ((m >> 1) << 6 | (m >> 1), 22),
+        // dcb0000dc
+        (Kind::Quints, 4) => ((m >> 1) << 6 | (m >> 2), 13),
+        // edcb000ed
+        (Kind::Trits, 5) => ((m >> 1) << 5 | (m >> 3), 11),
+        // edcb0000e
+        (Kind::Quints, 5) => ((m >> 1) << 5 | (m >> 4), 6),
+        // fedcb000f
+        _ => ((m >> 1) << 4 | (m >> 5), 5),
+    };
+
+    let t = (d * c + b) ^ a;
+    (a & 0x80) | (t >> 2)
+}
+
+/// Scales a weight of `range` to 0..=64.
+fn unquantize_weight(value: u32, range: Range) -> u32 {
+    let weight = match (range.kind, range.bits) {
+        (Kind::Bits, bits) => replicate(value, bits, 6),
+        (Kind::Trits, 0) => return [0, 32, 64][value as usize],
+        (Kind::Quints, 0) => return [0, 16, 32, 48, 64][value as usize],
+        (kind, bits) => {
+            let (a, m, d) = unquantize_parts(value, range, 0x7f);
+            let bit = |i: u32| (m >> i) & 1;
+            let (b, c) = match (kind, bits) {
+                (Kind::Trits, 1) => (0, 50),
+                (Kind::Quints, 1) => (0, 28),
+                // b000b0b
+                (Kind::Trits, 2) => ((bit(1) << 6) | (bit(1) << 2) | bit(1), 23),
+                // b0000b0
+                (Kind::Quints, 2) => ((bit(1) << 6) | (bit(1) << 1), 13),
+                // cb000cb
+                _ => ((bit(2) << 6) | (bit(1) << 5) | (bit(2) << 1) | bit(1), 11),
+            };
+
+            let t = (d * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        }
+    };
+
+    if weight > 32 {
+        weight + 1
+    } else {
+        weight
+    }
+}
+
+/// Moves a bit of `a` into `b` and leaves `a` a signed 6 bit offset.
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3f;
+    let a = if a & 0x20 != 0 { a - 0x40 } else { a };
+
+    (a, b)
+}
+
+fn blue_contract(r: i32, g: i32, b: i32, a: i32) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// Decodes the two RGBA endpoints of an LDR endpoint `mode` from its unquantized values.
+fn decode_endpoints(mode: u32, v: &[i32]) -> [[i32; 4]; 2] {
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (v1, v0) = bit_transfer_signed(v[1], v[0]);
+            let (v3, v2) = bit_transfer_signed(v[3], v[2]);
+            let l1 = v0 + v1;
+            [[v0, v0, v0, v2], [l1, l1, l1, v2 + v3]]
+        }
+        6 | 10 => {
+            let alpha = if mode == 10 { [v[4], v[5]] } else { [255, 255] };
+            [
+                [
+                    (v[0] * v[3]) >> 8,
+                    (v[1] * v[3]) >> 8,
+                    (v[2] * v[3]) >> 8,
+                    alpha[0],
+                ],
+                [v[0], v[1], v[2], alpha[1]],
+            ]
+        }
+        8 | 12 => {
+            let alpha = if mode == 12 { [v[6], v[7]] } else { [255, 255] };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], alpha[0]], [v[1], v[3], v[5], alpha[1]]]
+            } else {
+                [
+                    blue_contract(v[1], v[3], v[5], alpha[1]),
+                    blue_contract(v[0], v[2], v[4], alpha[0]),
+                ]
+            }
+        }
+        // 9 and 13
+        _ => {
+            let (v1, v0) = bit_transfer_signed(v[1], v[0]);
+            let (v3, v2) = bit_transfer_signed(v[3], v[2]);
+            let (v5, v4) = bit_transfer_signed(v[5], v[4]);
+            let (v7, v6) = if mode == 13 {
+                bit_transfer_signed(v[7], v[6])
+            } else {
+                (0, 255)
+            };
+            if v1 + v3 + v5 >= 0 {
+                [[v0, v2, v4, v6], [v0 + v1, v2 + v3, v4 + v5, v6 + v7]]
+            } else {
+                [
+                    blue_contract(v0 + v1, v2 + v3, v4 + v5, v6 + v7),
+                    blue_contract(v0, v2, v4, v6),
+                ]
+            }
+        }
+    };
+
+    endpoints.map(|endpoint| endpoint.map(|c| c.clamp(0, 255)))
+}
+
+/// Upsamples the weights of `plane` from the weight grid to each texel of the block.
+fn infill(
+    weights: &[u32],
+    planes: usize,
+    plane: usize,
+    mode: &BlockMode,
+    width: usize,
+    height: usize,
+) -> Vec<u32> {
+    let ds = (1024 + width / 2) / (width - 1);
+    let dt = (1024 + height / 2) / (height - 1);
+    let weight = |i: usize| weights.get(i * planes + plane).copied().unwrap_or(0);
+
+    (0..width * height)
+        .map(|i| {
+            let (s, t) = (i % width, i / width);
+            let gs = (ds * s * (mode.width - 1) + 32) >> 6;
+            let gt = (dt * t * (mode.height - 1) + 32) >> 6;
+            let (js, fs) = (gs >> 4, (gs & 0xf) as u32);
+            let (jt, ft) = (gt >> 4, (gt & 0xf) as u32);
+
+            let v0 = js + jt * mode.width;
+            let w11 = (fs * ft + 8) >> 4;
+            let (w10, w01) = (ft - w11, fs - w11);
+            let w00 = 16 + w11 - fs - ft;
+
+            (weight(v0) * w00
+                + weight(v0 + 1) * w01
+                + weight(v0 + mode.width) * w10
+                + weight(v0 + mode.width + 1) * w11
+                + 8)
+                >> 4
+        })
+        .collect()
+}
+
+/// The partition of texel (`x`, `y`), from the hash of the 10 bit partition `seed`.
+pub(crate) fn select_partition(
+    seed: u32,
+    x: u32,
+    y: u32,
+    partitions: u32,
+    small_block: bool,
+) -> usize {
+    let (x, y) = if small_block {
+        (x << 1, y << 1)
+    } else {
+        (x, y)
+    };
+    let seed = seed + (partitions - 1) * 1024;
+
+    let mut rnum = seed;
+    rnum ^= rnum >> 15;
+    rnum = rnum.wrapping_sub(rnum << 17);
+    rnum = rnum.wrapping_add(rnum << 7);
+    rnum = rnum.wrapping_add(rnum << 4);
+    rnum ^= rnum >> 5;
+    rnum = rnum.wrapping_add(rnum << 16);
+    rnum ^= rnum >> 7;
+    rnum ^= rnum >> 3;
+    rnum ^= rnum << 6;
+    rnum ^= rnum >> 17;
+
+    let mut seeds: [u32; 8] = std::array::from_fn(|i| (rnum >> (i * 4)) & 0xf);
+    seeds.iter_mut().for_each(|s| *s *= *s);
+    let (sh1, sh2) = if seed & 1 != 0 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partitions == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    for (i, s) in seeds.iter_mut().enumerate() {
+        *s >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+
+    // the z terms of 3D blocks are 0
+    let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 0x3f;
+    let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 0x3f;
+    let c = if partitions < 3 {
+        0
+    } else {
+        (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 0x3f
+    };
+    let d = if partitions < 4 {
+        0
+    } else {
+        (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 0x3f
+    };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
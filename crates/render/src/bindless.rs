@@ -0,0 +1,575 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use ash::{vk, Device};
+use bytemuck::{Pod, Zeroable};
+use tracing::debug;
+
+use crate::{
+    error::{RenderError, Result},
+    selector::Feature,
+    Vk,
+};
+
+/// Sampled image slots the global set has at most, fewer if the device supports fewer.
+pub const MAX_SAMPLED_IMAGES: u32 = 16 * 1024;
+/// Sampler slots the global set has at most, fewer if the device supports fewer.
+pub const MAX_SAMPLERS: u32 = 256;
+/// Storage buffer slots the global set has at most, fewer if the device supports fewer.
+pub const MAX_STORAGE_BUFFERS: u32 = 16 * 1024;
+
+/// Size of the push constant range of the bindless pipeline layout, the minimum every device
+/// supports.
+pub const PUSH_CONSTANT_SIZE: u32 = 128;
+
+pub const SAMPLED_IMAGE_BINDING: u32 = 0;
+pub const SAMPLER_BINDING: u32 = 1;
+pub const STORAGE_BUFFER_BINDING: u32 = 2;
+
+/// Declarations of the global set matching [`Bindless`], to be pasted into GLSL shaders.
+///
+/// Storage buffers are declared as `uint` arrays, shaders reinterpreting them declare their own
+/// block on binding 2 instead.
+pub const GLSL_DECLARATIONS: &str = "\
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(set = 0, binding = 0) uniform texture2D bindless_textures[];
+layout(set = 0, binding = 1) uniform sampler bindless_samplers[];
+layout(set = 0, binding = 2) buffer BindlessStorage { uint data[]; } bindless_buffers[];
+";
+
+/// Index of a sampled image in the global set.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct TextureId(u32);
+
+/// Index of a sampler in the global set.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct SamplerId(u32);
+
+/// Index of a storage buffer in the global set.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct StorageBufferId(u32);
+
+impl TextureId {
+    pub fn get_index(self) -> u32 {
+        self.0
+    }
+}
+
+impl SamplerId {
+    pub fn get_index(self) -> u32 {
+        self.0
+    }
+}
+
+impl StorageBufferId {
+    pub fn get_index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Hands out the slots of one binding, only reusing freed slots once the GPU can no longer be
+/// reading them.
+struct SlotAllocator {
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    /// Freed slots with the frame they were freed in.
+    retired: VecDeque<(u64, u32)>,
+    /// Whether each slot handed out so far is in use, so a slot can not be freed twice.
+    live: Vec<bool>,
+}
+
+impl SlotAllocator {
+    fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            next: 0,
+            free: vec![],
+            retired: VecDeque::new(),
+            live: vec![],
+        }
+    }
+
+    fn allocate(&mut self, kind: &str) -> u32 {
+        if let Some(slot) = self.free.pop() {
+            self.live[slot as usize] = true;
+            return slot;
+        }
+
+        assert!(
+            self.next < self.capacity,
+            "All {} bindless {} slots are in use",
+            self.capacity,
+            kind
+        );
+
+        self.live.push(true);
+        self.next += 1;
+        self.next - 1
+    }
+
+    fn retire(&mut self, slot: u32, frame: u64, kind: &str) {
+        let live = self.live.get_mut(slot as usize);
+        assert!(
+            live.as_deref() == Some(&true),
+            "Bindless {} slot {} is not in use, it was removed twice or never added",
+            kind,
+            slot
+        );
+        *live.unwrap() = false;
+
+        self.retired.push_back((frame, slot));
+    }
+
+    fn recycle(&mut self, completed: u64) {
+        while let Some(&(frame, slot)) = self.retired.front() {
+            if frame > completed {
+                break;
+            }
+
+            self.free.push(slot);
+            self.retired.pop_front();
+        }
+    }
+}
+
+/// The one global descriptor set holding every sampled image, sampler and storage buffer.
+///
+/// Resources are registered once and referred to by stable `u32` handles, which shaders get
+/// through push constants and use to index the arrays declared in [`GLSL_DECLARATIONS`]. Every
+/// pipeline uses the same [`Bindless::get_pipeline_layout`], so the set is bound once per command
+/// buffer instead of per draw.
+///
+/// The set is created with update after bind, so resources can be added while frames using it are
+/// in flight. Removed handles are reused only after [`Bindless::next_frame`] was called
+/// `frames_in_flight` times.
+///
+/// The device needs [`Feature::BINDLESS`], which [`crate::selector::DeviceSelector`] requests by
+/// default, check [`Vk::supports_feature`] before creating it on devices that may lack them.
+pub struct Bindless {
+    device: Device,
+    set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    images: SlotAllocator,
+    samplers: SlotAllocator,
+    storage_buffers: SlotAllocator,
+    frames_in_flight: u64,
+    frame: u64,
+}
+
+impl Bindless {
+    /// Creates the global set, sized to the smaller of the `MAX_*` constants and the update after
+    /// bind limits of the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::MissingFeature`] if any of [`Feature::BINDLESS`] is not enabled on
+    /// the device, or another error if the layouts, pool or set could not be created.
+    pub fn new(vk: &Vk, frames_in_flight: usize) -> Result<Self> {
+        if let Some(feature) = Feature::BINDLESS
+            .into_iter()
+            .find(|feature| !vk.supports_feature(*feature))
+        {
+            return Err(RenderError::MissingFeature(feature));
+        }
+
+        let device = vk.get_device().clone();
+
+        let capacity = |ty| runtime_array_capacity(vk, ty).expect("Bindless descriptor type");
+        let image_count = capacity(vk::DescriptorType::SAMPLED_IMAGE);
+        let sampler_count = capacity(vk::DescriptorType::SAMPLER);
+        let storage_buffer_count = capacity(vk::DescriptorType::STORAGE_BUFFER);
+
+        let bindings = [
+            (
+                SAMPLED_IMAGE_BINDING,
+                vk::DescriptorType::SAMPLED_IMAGE,
+                image_count,
+            ),
+            (SAMPLER_BINDING, vk::DescriptorType::SAMPLER, sampler_count),
+            (
+                STORAGE_BUFFER_BINDING,
+                vk::DescriptorType::STORAGE_BUFFER,
+                storage_buffer_count,
+            ),
+        ];
+
+        let layout_bindings = bindings.map(|(binding, ty, count)| {
+            *vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(count)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+        });
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+            3];
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .bindings(&layout_bindings)
+            .push_next(&mut binding_flags_info);
+
+        let pool_sizes = bindings.map(|(_, ty, count)| vk::DescriptorPoolSize {
+            ty,
+            descriptor_count: count,
+        });
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::ALL,
+            offset: 0,
+            size: PUSH_CONSTANT_SIZE,
+        }];
+
+        let (set_layout, pool, set, pipeline_layout) = unsafe {
+            let set_layout = device.create_descriptor_set_layout(&layout_info, None)?;
+            let pool = device.create_descriptor_pool(&pool_info, None)?;
+
+            let set_layouts = [set_layout];
+            let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&set_layouts);
+            let set = device.allocate_descriptor_sets(&alloc_info)?[0];
+
+            let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_info, None)?;
+
+            (set_layout, pool, set, pipeline_layout)
+        };
+
+        vk.set_debug_name(set, "bindless");
+        debug!(
+            "Created bindless set with {} images, {} samplers and {} storage buffers",
+            image_count, sampler_count, storage_buffer_count
+        );
+
+        Ok(Self {
+            device,
+            set_layout,
+            pipeline_layout,
+            pool,
+            set,
+            images: SlotAllocator::new(image_count),
+            samplers: SlotAllocator::new(sampler_count),
+            storage_buffers: SlotAllocator::new(storage_buffer_count),
+            frames_in_flight: frames_in_flight as u64,
+            frame: 0,
+        })
+    }
+
+    /// Registers an image view that is in `layout` whenever shaders sample it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every sampled image slot is in use.
+    pub fn add_image(&mut self, view: vk::ImageView, layout: vk::ImageLayout) -> TextureId {
+        let slot = self.images.allocate("sampled image");
+        self.write_image(TextureId(slot), view, layout);
+
+        TextureId(slot)
+    }
+
+    /// Points `id` at another image view, e.g. after the image was recreated.
+    pub fn write_image(&self, id: TextureId, view: vk::ImageView, layout: vk::ImageLayout) {
+        let image_info = [*vk::DescriptorImageInfo::builder()
+            .image_view(view)
+            .image_layout(layout)];
+
+        self.write(
+            SAMPLED_IMAGE_BINDING,
+            id.0,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            |write| write.image_info(&image_info),
+        );
+    }
+
+    /// # Panics
+    ///
+    /// Panics if every sampler slot is in use.
+    pub fn add_sampler(&mut self, sampler: vk::Sampler) -> SamplerId {
+        let slot = self.samplers.allocate("sampler");
+        let image_info = [*vk::DescriptorImageInfo::builder().sampler(sampler)];
+
+        self.write(
+            SAMPLER_BINDING,
+            slot,
+            vk::DescriptorType::SAMPLER,
+            |write| write.image_info(&image_info),
+        );
+
+        SamplerId(slot)
+    }
+
+    /// Registers `range` bytes of `buffer` starting at `offset`, [`vk::WHOLE_SIZE`] for the rest
+    /// of the buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every storage buffer slot is in use.
+    pub fn add_storage_buffer(
+        &mut self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> StorageBufferId {
+        let slot = self.storage_buffers.allocate("storage buffer");
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer,
+            offset,
+            range,
+        }];
+
+        self.write(
+            STORAGE_BUFFER_BINDING,
+            slot,
+            vk::DescriptorType::STORAGE_BUFFER,
+            |write| write.buffer_info(&buffer_info),
+        );
+
+        StorageBufferId(slot)
+    }
+
+    /// Frees the slot of `id` once the frames in flight are done with it.
+    ///
+    /// The image view has to stay alive until then as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was already removed.
+    pub fn remove_image(&mut self, id: TextureId) {
+        self.images.retire(id.0, self.frame, "sampled image");
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `id` was already removed.
+    pub fn remove_sampler(&mut self, id: SamplerId) {
+        self.samplers.retire(id.0, self.frame, "sampler");
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `id` was already removed.
+    pub fn remove_storage_buffer(&mut self, id: StorageBufferId) {
+        self.storage_buffers
+            .retire(id.0, self.frame, "storage buffer");
+    }
+
+    /// Moves on to the next frame, reusing the slots removed `frames_in_flight` frames ago.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+
+        if let Some(completed) = self.frame.checked_sub(self.frames_in_flight) {
+            self.images.recycle(completed);
+            self.samplers.recycle(completed);
+            self.storage_buffers.recycle(completed);
+        }
+    }
+
+    /// Binds the global set as set 0 for `bind_point`.
+    pub fn bind(&self, cmd: vk::CommandBuffer, bind_point: vk::PipelineBindPoint) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                cmd,
+                bind_point,
+                self.pipeline_layout,
+                0,
+                &[self.set],
+                &[],
+            )
+        };
+    }
+
+    /// Pushes `constants`, usually a struct of handles and [`crate::buffer::BufferAddress`]es, to
+    /// every shader stage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is larger than [`PUSH_CONSTANT_SIZE`].
+    pub fn push_constants<T: Pod>(&self, cmd: vk::CommandBuffer, constants: &T) {
+        assert!(
+            size_of::<T>() <= PUSH_CONSTANT_SIZE as usize,
+            "Push constants of {} bytes exceed the {} bytes of the bindless layout",
+            size_of::<T>(),
+            PUSH_CONSTANT_SIZE
+        );
+
+        unsafe {
+            self.device.cmd_push_constants(
+                cmd,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::ALL,
+                0,
+                bytemuck::bytes_of(constants),
+            )
+        };
+    }
+
+    pub fn get_set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    pub fn get_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    /// The layout every pipeline using the global set has to be created with.
+    pub fn get_pipeline_layout(&self) -> vk::PipelineLayout {
+        self.pipeline_layout
+    }
+
+    fn write<'b>(
+        &self,
+        binding: u32,
+        slot: u32,
+        ty: vk::DescriptorType,
+        info: impl FnOnce(vk::WriteDescriptorSetBuilder<'b>) -> vk::WriteDescriptorSetBuilder<'b>,
+    ) {
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(binding)
+            .dst_array_element(slot)
+            .descriptor_type(ty);
+        let writes = [*info(write)];
+
+        unsafe { self.device.update_descriptor_sets(&writes, &[]) };
+    }
+}
+
+/// How many descriptors a runtime sized array of `ty` gets in an update after bind set, the
+/// smaller of the `MAX_*` constants and the limits of the device, see [`fit_capacities`].
+///
+/// `None` for the types the global set does not hold.
+pub(crate) fn runtime_array_capacity(vk: &Vk, ty: vk::DescriptorType) -> Option<u32> {
+    let mut indexing_props = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+    let mut props = vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_props);
+    unsafe {
+        vk.get_instance()
+            .get_physical_device_properties2(vk.get_physical_device(), &mut props)
+    };
+
+    let images = MAX_SAMPLED_IMAGES
+        .min(indexing_props.max_descriptor_set_update_after_bind_sampled_images)
+        .min(indexing_props.max_per_stage_descriptor_update_after_bind_sampled_images);
+    let samplers = MAX_SAMPLERS
+        .min(indexing_props.max_descriptor_set_update_after_bind_samplers)
+        .min(indexing_props.max_per_stage_descriptor_update_after_bind_samplers);
+    let storage_buffers = MAX_STORAGE_BUFFERS
+        .min(indexing_props.max_descriptor_set_update_after_bind_storage_buffers)
+        .min(indexing_props.max_per_stage_descriptor_update_after_bind_storage_buffers);
+    let [images, samplers, storage_buffers] = fit_capacities(
+        [images, samplers, storage_buffers],
+        indexing_props.max_per_stage_update_after_bind_resources,
+    );
+
+    match ty {
+        vk::DescriptorType::SAMPLED_IMAGE => Some(images),
+        vk::DescriptorType::SAMPLER => Some(samplers),
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER => Some(images.min(samplers)),
+        vk::DescriptorType::STORAGE_BUFFER => Some(storage_buffers),
+        _ => None,
+    }
+}
+
+/// Shrinks the sampled image, sampler and storage buffer counts of the global set to
+/// `per_stage_resources`, the total one stage can access, as every binding is visible to all
+/// stages.
+///
+/// Samplers are few and kept, what is left is split evenly between images and buffers, with the
+/// share one of them does not need going to the other.
+fn fit_capacities(counts: [u32; 3], per_stage_resources: u32) -> [u32; 3] {
+    let [images, samplers, storage_buffers] = counts;
+    if images + samplers + storage_buffers <= per_stage_resources {
+        return counts;
+    }
+
+    let samplers = samplers.min(per_stage_resources);
+    let remaining = per_stage_resources - samplers;
+    let images = images.min((remaining / 2).max(remaining.saturating_sub(storage_buffers)));
+    let storage_buffers = storage_buffers.min(remaining - images);
+
+    [images, samplers, storage_buffers]
+}
+
+impl Drop for Bindless {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // frees the set as well
+            self.device.destroy_descriptor_pool(self.pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_slots_only_after_their_frame_completed() {
+        let mut slots = SlotAllocator::new(4);
+        assert_eq!([slots.allocate("test"), slots.allocate("test")], [0, 1]);
+
+        slots.retire(0, 5, "test");
+        slots.recycle(4);
+        assert_eq!(slots.allocate("test"), 2);
+
+        slots.recycle(5);
+        assert_eq!(slots.allocate("test"), 0);
+        assert_eq!(slots.allocate("test"), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "removed twice")]
+    fn removing_twice_panics() {
+        let mut slots = SlotAllocator::new(4);
+        let slot = slots.allocate("test");
+
+        slots.retire(slot, 0, "test");
+        slots.retire(slot, 1, "test");
+    }
+
+    #[test]
+    #[should_panic(expected = "never added")]
+    fn removing_unallocated_slots_panics() {
+        SlotAllocator::new(4).retire(2, 0, "test");
+    }
+
+    #[test]
+    #[should_panic(expected = "All 1 bindless test slots are in use")]
+    fn running_out_of_slots_panics() {
+        let mut slots = SlotAllocator::new(1);
+        slots.allocate("test");
+        slots.allocate("test");
+    }
+
+    #[test]
+    fn fits_counts_into_the_per_stage_total() {
+        let counts = [MAX_SAMPLED_IMAGES, MAX_SAMPLERS, MAX_STORAGE_BUFFERS];
+        assert_eq!(fit_capacities(counts, u32::MAX), counts);
+        assert_eq!(fit_capacities(counts, 20_256), [10_000, 256, 10_000]);
+        // the share the buffers do not need goes to the images
+        assert_eq!(
+            fit_capacities([16_384, 256, 1000], 10_256),
+            [9000, 256, 1000]
+        );
+        assert_eq!(fit_capacities([100, 256, 16_384], 1256), [100, 256, 900]);
+        assert_eq!(fit_capacities(counts, 100), [0, 100, 0]);
+    }
+}
@@ -0,0 +1,566 @@
+use ash::vk;
+
+use crate::{
+    astc, bptc,
+    error::{RenderError, Result},
+    utils::format_texel_size,
+};
+
+/// The texel footprint and byte size of one block of a block compressed `format`, `None` for
+/// formats that are not block compressed.
+pub fn block_size(format: vk::Format) -> Option<(u32, u32, usize)> {
+    let size = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | vk::Format::EAC_R11_UNORM_BLOCK
+        | vk::Format::EAC_R11_SNORM_BLOCK => (4, 4, 8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::EAC_R11G11_UNORM_BLOCK
+        | vk::Format::EAC_R11G11_SNORM_BLOCK => (4, 4, 16),
+        vk::Format::ASTC_4X4_UNORM_BLOCK | vk::Format::ASTC_4X4_SRGB_BLOCK => (4, 4, 16),
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => (5, 4, 16),
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => (5, 5, 16),
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => (6, 5, 16),
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => (6, 6, 16),
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => (8, 5, 16),
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => (8, 6, 16),
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => (8, 8, 16),
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => (10, 5, 16),
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => (10, 6, 16),
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => (10, 8, 16),
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => (10, 10, 16),
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => (12, 10, 16),
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => (12, 12, 16),
+        _ => return None,
+    };
+
+    Some(size)
+}
+
+/// The uncompressed format [`decompress`] turns `format` into, `None` if it has no CPU decoder.
+///
+/// BC6H becomes half float RGBA, every other block compressed format RGBA8 in its color space.
+/// The signed BC4, BC5 and EAC formats have no decoder, textures in those have to be shipped in
+/// a second format for devices that lack them.
+pub fn decompressed_format(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::R8G8B8_SRGB => Some(vk::Format::R8G8B8A8_SRGB),
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::EAC_R11_UNORM_BLOCK
+        | vk::Format::EAC_R11G11_UNORM_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::R8G8B8_UNORM => Some(vk::Format::R8G8B8A8_UNORM),
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK => {
+            Some(vk::Format::R16G16B16A16_SFLOAT)
+        }
+        format if is_astc(format) => Some(if astc_is_srgb(format) {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        }),
+        _ => None,
+    }
+}
+
+/// Decompresses one `width` by `height` image of `format` into tightly packed texels of
+/// [`decompressed_format`].
+///
+/// Channels the format lacks are 0, alpha is opaque unless the format has it. Besides block
+/// compressed formats, 24 bit RGB is widened, as few devices can sample it.
+///
+/// # Errors
+///
+/// Returns [`RenderError::UnsupportedFormat`] if `format` has no decoder and
+/// [`RenderError::ImageLoad`] if `data` is too short for the image.
+pub fn decompress(format: vk::Format, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>> {
+    if decompressed_format(format).is_none() {
+        return Err(RenderError::UnsupportedFormat(format));
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    if matches!(format, vk::Format::R8G8B8_UNORM | vk::Format::R8G8B8_SRGB) {
+        check_size(format, data, width * height * 3)?;
+
+        return Ok(data
+            .chunks_exact(3)
+            .take(width * height)
+            .flat_map(|texel| [texel[0], texel[1], texel[2], u8::MAX])
+            .collect());
+    }
+
+    let (block_width, block_height, block_bytes) =
+        block_size(format).expect("Decompressible formats are blocks");
+    let (block_width, block_height) = (block_width as usize, block_height as usize);
+    let texel_size = format_texel_size(decompressed_format(format).unwrap()).unwrap() as usize;
+    let (blocks_x, blocks_y) = (width.div_ceil(block_width), height.div_ceil(block_height));
+    check_size(format, data, blocks_x * blocks_y * block_bytes)?;
+
+    let mut texels = vec![0; width * height * texel_size];
+    for (i, block) in data
+        .chunks_exact(block_bytes)
+        .take(blocks_x * blocks_y)
+        .enumerate()
+    {
+        let decoded = decode_block(format, block, block_width, block_height);
+
+        let (block_x, block_y) = (i % blocks_x * block_width, i / blocks_x * block_height);
+        let row_size = block_width.min(width - block_x) * texel_size;
+        for y in 0..block_height.min(height - block_y) {
+            let start = ((block_y + y) * width + block_x) * texel_size;
+            let source = y * block_width * texel_size;
+            texels[start..start + row_size].copy_from_slice(&decoded[source..source + row_size]);
+        }
+    }
+
+    Ok(texels)
+}
+
+fn check_size(format: vk::Format, data: &[u8], size: usize) -> Result<()> {
+    if data.len() < size {
+        return Err(RenderError::ImageLoad {
+            name: format!("{:?} texels", format),
+            message: format!("expected {} bytes, got {}", size, data.len()),
+        });
+    }
+
+    Ok(())
+}
+
+fn is_astc(format: vk::Format) -> bool {
+    (vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw()..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw())
+        .contains(&format.as_raw())
+}
+
+/// The `_SRGB` ASTC formats follow their `_UNORM` twin.
+fn astc_is_srgb(format: vk::Format) -> bool {
+    (format.as_raw() - vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw()) % 2 == 1
+}
+
+/// Decodes the `width` by `height` texels of a block in row major order, as the bytes of its
+/// [`decompressed_format`].
+fn decode_block(format: vk::Format, block: &[u8], width: usize, height: usize) -> Vec<u8> {
+    match format {
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK => {
+            bptc::decode_bc6h(block, format == vk::Format::BC6H_SFLOAT_BLOCK)
+                .iter()
+                .flatten()
+                .flat_map(|half| half.to_le_bytes())
+                .collect()
+        }
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => {
+            bptc::decode_bc7(block).concat()
+        }
+        format if is_astc(format) => {
+            astc::decode_block(block, width, height, astc_is_srgb(format)).concat()
+        }
+        format => decode_4x4(format, block).concat(),
+    }
+}
+
+/// The 16 texels of a block in row major order.
+type Block = [[u8; 4]; 16];
+
+fn decode_4x4(format: vk::Format, block: &[u8]) -> Block {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK => {
+            let mut texels = decode_bc1(block, true);
+            // the transparent black of 3 color blocks is opaque without alpha
+            texels.iter_mut().for_each(|texel| texel[3] = u8::MAX);
+            texels
+        }
+        vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => {
+            decode_bc1(block, true)
+        }
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => {
+            let mut texels = decode_bc1(&block[8..], false);
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (i * 4)) & 0xf) as u8 * 17;
+            }
+            texels
+        }
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => {
+            let mut texels = decode_bc1(&block[8..], false);
+            let alpha = decode_bc4(&block[..8]);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha;
+            }
+            texels
+        }
+        vk::Format::BC4_UNORM_BLOCK => decode_bc4(block).map(|red| [red, 0, 0, u8::MAX]),
+        vk::Format::BC5_UNORM_BLOCK => {
+            let (red, green) = (decode_bc4(&block[..8]), decode_bc4(&block[8..]));
+            std::array::from_fn(|i| [red[i], green[i], 0, u8::MAX])
+        }
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => {
+            decode_etc2(block, false)
+        }
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => {
+            decode_etc2(block, true)
+        }
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+            let mut texels = decode_etc2(&block[8..], false);
+            let alpha = decode_eac(&block[..8], false);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha as u8;
+            }
+            texels
+        }
+        vk::Format::EAC_R11_UNORM_BLOCK => {
+            decode_eac(block, true).map(|red| [unorm11_to_8(red), 0, 0, u8::MAX])
+        }
+        vk::Format::EAC_R11G11_UNORM_BLOCK => {
+            let (red, green) = (decode_eac(&block[..8], true), decode_eac(&block[8..], true));
+            std::array::from_fn(|i| [unorm11_to_8(red[i]), unorm11_to_8(green[i]), 0, u8::MAX])
+        }
+        format => unreachable!("{:?} has no block decoder", format),
+    }
+}
+
+/// Decodes the 8 byte color block of BC1-3. Only BC1 has the 3 color mode with transparent
+/// black, BC2 and BC3 always interpolate 4 colors.
+fn decode_bc1(block: &[u8], three_color_mode: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let mix = |a: u32, b: u32, d: u32| {
+        let channel = |c: usize| ((e0[c] as u32 * a + e1[c] as u32 * b) / d) as u8;
+        [channel(0), channel(1), channel(2), u8::MAX]
+    };
+    let palette = if c0 > c1 || !three_color_mode {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [mix(1, 0, 1), mix(0, 1, 1), mix(1, 1, 2), [0; 4]]
+    };
+
+    std::array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let (r, g, b) = (
+        (color >> 11) as u8,
+        (color >> 5) as u8 & 0x3f,
+        color as u8 & 0x1f,
+    );
+
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// Decodes a single channel block of BC4, also the alpha of BC3 and each channel of BC5.
+fn decode_bc4(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    let palette: [u8; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            i => (((8 - i as u32) * a0 + (i as u32 - 1) * a1) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            6 => 0,
+            7 => u8::MAX,
+            i => (((6 - i as u32) * a0 + (i as u32 - 1) * a1) / 5) as u8,
+        })
+    };
+
+    std::array::from_fn(|i| palette[(indices >> (i * 3)) as usize & 7])
+}
+
+/// The modifiers of the ETC1 individual and differential modes, by table codeword and pixel
+/// index.
+const ETC_MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+/// The distances of the T and H modes.
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// Decodes an 8 byte ETC2 color block. With `punchthrough` the differential bit is the opaque
+/// bit of RGB8A1, and pixel index 2 is transparent black in non opaque blocks.
+fn decode_etc2(block: &[u8], punchthrough: bool) -> Block {
+    let differential = block[3] & 2 != 0;
+    let opaque = !punchthrough || differential;
+    let pixel_indices = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+    // pixels are numbered column major
+    let pixel_index = |x: usize, y: usize| {
+        let bit = x * 4 + y;
+        (((pixel_indices >> (16 + bit)) & 1) << 1 | ((pixel_indices >> bit) & 1)) as usize
+    };
+
+    // the differential mode, and ETC2 modes when its second color overflows
+    let base = |i: usize| (block[i] >> 3) as i32;
+    let second = |i: usize| base(i) + ((block[i] as i8) << 5 >> 5) as i32;
+    if punchthrough || differential {
+        if !(0..32).contains(&second(0)) {
+            return decode_etc2_th(block, false, opaque, pixel_index);
+        }
+        if !(0..32).contains(&second(1)) {
+            return decode_etc2_th(block, true, opaque, pixel_index);
+        }
+        if !(0..32).contains(&second(2)) {
+            return decode_etc2_planar(block);
+        }
+    }
+
+    let (color0, color1) = if punchthrough || differential {
+        (
+            [0, 1, 2].map(|i| extend(base(i) as u8, 5)),
+            [0, 1, 2].map(|i| extend(second(i) as u8, 5)),
+        )
+    } else {
+        (
+            [0, 1, 2].map(|i| extend(block[i] >> 4, 4)),
+            [0, 1, 2].map(|i| extend(block[i] & 0xf, 4)),
+        )
+    };
+    let tables = [(block[3] >> 5) as usize, (block[3] >> 2) as usize & 7];
+    let flip = block[3] & 1 != 0;
+
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let second = if flip { y >= 2 } else { x >= 2 };
+        let (base, table) = if second {
+            (color1, tables[1])
+        } else {
+            (color0, tables[0])
+        };
+
+        let index = pixel_index(x, y);
+        if !opaque && index == 2 {
+            return [0; 4];
+        }
+        // non opaque blocks drop the small modifiers
+        let modifier = if !opaque && index == 0 {
+            0
+        } else {
+            ETC_MODIFIERS[table][index]
+        };
+
+        [
+            clamp(base[0] as i32 + modifier),
+            clamp(base[1] as i32 + modifier),
+            clamp(base[2] as i32 + modifier),
+            u8::MAX,
+        ]
+    })
+}
+
+/// Decodes the T mode, or the H mode if `h_mode` is set, of an ETC2 block.
+fn decode_etc2_th(
+    block: &[u8],
+    h_mode: bool,
+    opaque: bool,
+    pixel_index: impl Fn(usize, usize) -> usize,
+) -> Block {
+    let (color0, color1, distance) = if h_mode {
+        let color0 = [
+            (block[0] >> 3) & 0xf,
+            ((block[0] & 7) << 1) | ((block[1] >> 4) & 1),
+            (block[1] & 8) | ((block[1] & 3) << 1) | (block[2] >> 7),
+        ];
+        let color1 = [
+            (block[2] >> 3) & 0xf,
+            ((block[2] & 7) << 1) | (block[3] >> 7),
+            (block[3] >> 3) & 0xf,
+        ];
+        let value = |c: [u8; 3]| ((c[0] as u32) << 8) | ((c[1] as u32) << 4) | c[2] as u32;
+        let distance =
+            (block[3] & 4) | ((block[3] & 1) << 1) | (value(color0) >= value(color1)) as u8;
+
+        (color0, color1, distance)
+    } else {
+        let color0 = [
+            ((block[0] >> 1) & 0xc) | (block[0] & 3),
+            block[1] >> 4,
+            block[1] & 0xf,
+        ];
+        let color1 = [block[2] >> 4, block[2] & 0xf, block[3] >> 4];
+        let distance = ((block[3] >> 1) & 6) | (block[3] & 1);
+
+        (color0, color1, distance)
+    };
+
+    let (color0, color1) = (color0.map(|c| extend(c, 4)), color1.map(|c| extend(c, 4)));
+    let distance = ETC_DISTANCES[distance as usize];
+    let paint = |color: [u8; 3], offset: i32| {
+        [
+            clamp(color[0] as i32 + offset),
+            clamp(color[1] as i32 + offset),
+            clamp(color[2] as i32 + offset),
+            u8::MAX,
+        ]
+    };
+    let palette = if h_mode {
+        [
+            paint(color0, distance),
+            paint(color0, -distance),
+            paint(color1, distance),
+            paint(color1, -distance),
+        ]
+    } else {
+        [
+            paint(color0, 0),
+            paint(color1, distance),
+            paint(color1, 0),
+            paint(color1, -distance),
+        ]
+    };
+
+    std::array::from_fn(|i| {
+        let index = pixel_index(i % 4, i / 4);
+        if !opaque && index == 2 {
+            [0; 4]
+        } else {
+            palette[index]
+        }
+    })
+}
+
+/// Decodes the planar mode of an ETC2 block, a gradient through 3 colors that is always opaque.
+fn decode_etc2_planar(block: &[u8]) -> Block {
+    let origin = [
+        extend((block[0] >> 1) & 0x3f, 6),
+        extend(((block[0] & 1) << 6) | ((block[1] >> 1) & 0x3f), 7),
+        extend(
+            ((block[1] & 1) << 5) | (block[2] & 0x18) | ((block[2] & 3) << 1) | (block[3] >> 7),
+            6,
+        ),
+    ];
+    let horizontal = [
+        extend(((block[3] >> 1) & 0x3e) | (block[3] & 1), 6),
+        extend((block[4] >> 1) & 0x7f, 7),
+        extend(((block[4] & 1) << 5) | ((block[5] >> 3) & 0x1f), 6),
+    ];
+    let vertical = [
+        extend(((block[5] & 7) << 3) | (block[6] >> 5), 6),
+        extend(((block[6] & 0x1f) << 2) | (block[7] >> 6), 7),
+        extend(block[7] & 0x3f, 6),
+    ];
+
+    std::array::from_fn(|i| {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        let channel = |c: usize| {
+            let (o, h, v) = (origin[c] as i32, horizontal[c] as i32, vertical[c] as i32);
+            clamp((x * (h - o) + y * (v - o) + 4 * o + 2) >> 2)
+        };
+
+        [channel(0), channel(1), channel(2), u8::MAX]
+    })
+}
+
+/// The modifiers of EAC, by table index and pixel index.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Decodes an 8 byte EAC block into 8 bit values, or 11 bit values with `eleven_bit` for the
+/// R11 and RG11 formats.
+fn decode_eac(block: &[u8], eleven_bit: bool) -> [u16; 16] {
+    let base = block[0] as i32;
+    let multiplier = (block[1] >> 4) as i32;
+    let table = EAC_MODIFIERS[(block[1] & 0xf) as usize];
+    let mut bits = [0; 8];
+    bits[2..].copy_from_slice(&block[2..8]);
+    let indices = u64::from_be_bytes(bits);
+
+    std::array::from_fn(|i| {
+        // pixels are numbered column major from the most significant bits
+        let bit = (i % 4) * 4 + i / 4;
+        let modifier = table[(indices >> (45 - bit * 3)) as usize & 7];
+
+        if eleven_bit {
+            let scaled = if multiplier == 0 {
+                modifier
+            } else {
+                modifier * multiplier * 8
+            };
+            (base * 8 + 4 + scaled).clamp(0, 2047) as u16
+        } else {
+            (base + modifier * multiplier).clamp(0, 255) as u16
+        }
+    })
+}
+
+fn unorm11_to_8(value: u16) -> u8 {
+    ((value as u32 * 255 + 1023) / 2047) as u8
+}
+
+/// Widens a `bits` wide channel to 8 bits by repeating its high bits.
+fn extend(value: u8, bits: u32) -> u8 {
+    let value = value as u32;
+
+    ((value << (8 - bits)) | (value >> (2 * bits - 8))) as u8
+}
+
+fn clamp(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
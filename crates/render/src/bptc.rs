@@ -0,0 +1,620 @@
+//! CPU decoders for the BPTC formats, BC7 and BC6H.
+
+/// Subset of each texel in the 64 partitions of 2 subsets, one bit per texel.
+const PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Subset of each texel in the 64 partitions of 3 subsets, two bits per texel.
+const PARTITIONS3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// The texel whose index drops its top bit in the second subset of 2 subset partitions.
+const ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor texels of the second and third subset of 3 subset partitions.
+const ANCHORS3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6,
+        8, 5, 15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8,
+        5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3,
+        15, 6, 10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15,
+        15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(bits: u32) -> &'static [u32] {
+    match bits {
+        2 => &WEIGHTS2,
+        3 => &WEIGHTS3,
+        _ => &WEIGHTS4,
+    }
+}
+
+/// The subset of texel `i` in `partition` of a block with `subsets` subsets.
+fn subset(subsets: u32, partition: usize, i: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (PARTITIONS2[partition] >> i) as usize & 1,
+        _ => (PARTITIONS3[partition] >> (i * 2)) as usize & 3,
+    }
+}
+
+/// Whether texel `i` is the first texel of its subset, whose index is one bit shorter.
+fn is_anchor(subsets: u32, partition: usize, i: usize) -> bool {
+    i == 0
+        || match subsets {
+            2 => ANCHORS2[partition] as usize == i,
+            3 => ANCHORS3[0][partition] as usize == i || ANCHORS3[1][partition] as usize == i,
+            _ => false,
+        }
+}
+
+/// Reads the fields of a block from its least significant bit on.
+struct Bits {
+    bits: u128,
+    position: u32,
+}
+
+impl Bits {
+    fn new(block: &[u8]) -> Self {
+        Self {
+            bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+
+    /// Reads `count` bits into `value` from bit `low` up.
+    fn read_into(&mut self, value: &mut i32, low: u32, count: u32) {
+        *value |= (self.read(count) << low) as i32;
+    }
+
+    /// Reads `count` bits whose first bit is the most significant one.
+    fn read_reversed(&mut self, count: u32) -> u32 {
+        self.read(count).reverse_bits() >> (32 - count)
+    }
+
+    /// Reads the index of each texel, `bits` wide except for the anchors.
+    fn read_indices(&mut self, bits: u32, subsets: u32, partition: usize) -> [u32; 16] {
+        std::array::from_fn(|i| {
+            if is_anchor(subsets, partition, i) {
+                self.read(bits - 1)
+            } else {
+                self.read(bits)
+            }
+        })
+    }
+}
+
+/// Widens a `bits` wide channel to 8 bits by repeating its high bits.
+fn extend(value: u32, bits: u32) -> u32 {
+    let value = value << (8 - bits);
+
+    value | (value >> bits)
+}
+
+/// Decodes a 16 byte BC7 block. Blocks with the reserved mode 8 are transparent black.
+pub(crate) fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = Bits::new(block);
+    let mode = (block[0] as u32).trailing_zeros();
+    if mode >= 8 {
+        return [[0; 4]; 16];
+    }
+    bits.read(mode + 1);
+
+    // subsets, partition bits, rotation bits, index selection bits, color bits, alpha bits,
+    // whether each endpoint or each subset has a p-bit, index bits and secondary index bits
+    let (subsets, partition_bits, rotation_bits, selection_bits) = match mode {
+        0 => (3, 4, 0, 0),
+        1 | 3 | 7 => (2, 6, 0, 0),
+        2 => (3, 6, 0, 0),
+        4 => (1, 0, 2, 1),
+        5 => (1, 0, 2, 0),
+        _ => (1, 0, 0, 0),
+    };
+    let (color_bits, alpha_bits, endpoint_pbits, shared_pbits, index_bits, index_bits2) = match mode
+    {
+        0 => (4, 0, true, false, 3, 0),
+        1 => (6, 0, false, true, 3, 0),
+        2 => (5, 0, false, false, 2, 0),
+        3 => (7, 0, true, false, 2, 0),
+        4 => (5, 6, false, false, 2, 3),
+        5 => (7, 8, false, false, 2, 2),
+        6 => (7, 7, true, false, 4, 0),
+        _ => (5, 5, true, false, 2, 0),
+    };
+
+    let partition = bits.read(partition_bits) as usize;
+    let rotation = bits.read(rotation_bits);
+    let selection = bits.read(selection_bits);
+
+    let endpoints = subsets as usize * 2;
+    let mut colors = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for color in colors.iter_mut().take(endpoints) {
+            color[channel] = bits.read(color_bits);
+        }
+    }
+    for color in colors.iter_mut().take(endpoints) {
+        color[3] = if alpha_bits > 0 {
+            bits.read(alpha_bits)
+        } else {
+            u8::MAX as u32
+        };
+    }
+
+    let pbits: [u32; 6] = if endpoint_pbits {
+        std::array::from_fn(|i| if i < endpoints { bits.read(1) } else { 0 })
+    } else if shared_pbits {
+        let shared = [bits.read(1), bits.read(1)];
+        std::array::from_fn(|i| shared[i / 2 % 2])
+    } else {
+        [0; 6]
+    };
+    let has_pbits = endpoint_pbits || shared_pbits;
+    for (color, pbit) in colors.iter_mut().zip(pbits).take(endpoints) {
+        for (channel, value) in color.iter_mut().enumerate() {
+            let (value_bits, present) = if channel < 3 {
+                (color_bits, true)
+            } else {
+                (alpha_bits, alpha_bits > 0)
+            };
+            if !present {
+                continue;
+            }
+            *value = if has_pbits {
+                extend((*value << 1) | pbit, value_bits + 1)
+            } else {
+                extend(*value, value_bits)
+            };
+        }
+    }
+
+    let indices = bits.read_indices(index_bits, subsets, partition);
+    let indices2 = (index_bits2 > 0).then(|| bits.read_indices(index_bits2, 1, 0));
+
+    std::array::from_fn(|i| {
+        let subset = subset(subsets, partition, i);
+        let (e0, e1) = (colors[subset * 2], colors[subset * 2 + 1]);
+        let interpolate = |channel: usize, weight: u32| {
+            ((e0[channel] * (64 - weight) + e1[channel] * weight + 32) >> 6) as u8
+        };
+
+        let (color_weight, alpha_weight) = match indices2 {
+            Some(indices2) if selection == 1 => (
+                weights(index_bits2)[indices2[i] as usize],
+                weights(index_bits)[indices[i] as usize],
+            ),
+            Some(indices2) => (
+                weights(index_bits)[indices[i] as usize],
+                weights(index_bits2)[indices2[i] as usize],
+            ),
+            None => {
+                let weight = weights(index_bits)[indices[i] as usize];
+                (weight, weight)
+            }
+        };
+
+        let mut texel = [
+            interpolate(0, color_weight),
+            interpolate(1, color_weight),
+            interpolate(2, color_weight),
+            interpolate(3, alpha_weight),
+        ];
+        if rotation > 0 {
+            texel.swap(rotation as usize - 1, 3);
+        }
+        texel
+    })
+}
+
+/// The endpoint bits and per channel delta bits of each BC6H mode, `None` for deltas if the
+/// endpoints are stored as they are.
+const BC6H_MODES: [(u32, Option<[u32; 3]>); 14] = [
+    (10, Some([5, 5, 5])),
+    (7, Some([6, 6, 6])),
+    (11, Some([5, 4, 4])),
+    (11, Some([4, 5, 4])),
+    (11, Some([4, 4, 5])),
+    (9, Some([5, 5, 5])),
+    (8, Some([6, 5, 5])),
+    (8, Some([5, 6, 5])),
+    (8, Some([5, 5, 6])),
+    (6, None),
+    (10, None),
+    (11, Some([9, 9, 9])),
+    (12, Some([8, 8, 8])),
+    (16, Some([4, 4, 4])),
+];
+
+/// Decodes a 16 byte BC6H block into the bits of half floats, alpha is 1. Blocks with a
+/// reserved mode are black.
+pub(crate) fn decode_bc6h(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    const ONE: u16 = 0x3c00;
+
+    let mut bits = Bits::new(block);
+    let mut mode_bits = bits.read(2);
+    if mode_bits >= 2 {
+        mode_bits |= bits.read(3) << 2;
+    }
+    let mode = match mode_bits {
+        0 | 1 => mode_bits as usize,
+        2 | 6 | 10 | 14 | 18 | 22 | 26 | 30 => 2 + (mode_bits as usize >> 2),
+        3 | 7 | 11 | 15 => 10 + (mode_bits as usize >> 2),
+        _ => return [[0, 0, 0, ONE]; 16],
+    };
+
+    // the endpoints of both regions by channel, e[channel][endpoint]
+    let mut e = [[0i32; 4]; 3];
+    let (r, g, b) = (0, 1, 2);
+    // each mode scatters the high bits of its fields differently, these are the D3D layouts as
+    // (channel, endpoint, low bit, bit count) in stream order
+    let layout: &[(usize, usize, u32, u32)] = match mode {
+        0 => &[
+            (g, 2, 4, 1),
+            (b, 2, 4, 1),
+            (b, 3, 4, 1),
+            (r, 0, 0, 10),
+            (g, 0, 0, 10),
+            (b, 0, 0, 10),
+            (r, 1, 0, 5),
+            (g, 3, 4, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 5),
+            (b, 3, 0, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 5),
+            (b, 3, 1, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 5),
+            (b, 3, 2, 1),
+            (r, 3, 0, 5),
+            (b, 3, 3, 1),
+        ],
+        1 => &[
+            (g, 2, 5, 1),
+            (g, 3, 4, 1),
+            (g, 3, 5, 1),
+            (r, 0, 0, 7),
+            (b, 3, 0, 1),
+            (b, 3, 1, 1),
+            (b, 2, 4, 1),
+            (g, 0, 0, 7),
+            (b, 2, 5, 1),
+            (b, 3, 2, 1),
+            (g, 2, 4, 1),
+            (b, 0, 0, 7),
+            (b, 3, 3, 1),
+            (b, 3, 5, 1),
+            (b, 3, 4, 1),
+            (r, 1, 0, 6),
+            (g, 2, 0, 4),
+            (g, 1, 0, 6),
+            (g, 3, 0, 4),
+            (b, 1, 0, 6),
+            (b, 2, 0, 4),
+            (r, 2, 0, 6),
+            (r, 3, 0, 6),
+        ],
+        2 => &[
+            (r, 0, 0, 10),
+            (g, 0, 0, 10),
+            (b, 0, 0, 10),
+            (r, 1, 0, 5),
+            (r, 0, 10, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 4),
+            (g, 0, 10, 1),
+            (b, 3, 0, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 4),
+            (b, 0, 10, 1),
+            (b, 3, 1, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 5),
+            (b, 3, 2, 1),
+            (r, 3, 0, 5),
+            (b, 3, 3, 1),
+        ],
+        3 => &[
+            (r, 0, 0, 10),
+            (g, 0, 0, 10),
+            (b, 0, 0, 10),
+            (r, 1, 0, 4),
+            (r, 0, 10, 1),
+            (g, 3, 4, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 5),
+            (g, 0, 10, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 4),
+            (b, 0, 10, 1),
+            (b, 3, 1, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 4),
+            (b, 3, 0, 1),
+            (b, 3, 2, 1),
+            (r, 3, 0, 4),
+            (g, 2, 4, 1),
+            (b, 3, 3, 1),
+        ],
+        4 => &[
+            (r, 0, 0, 10),
+            (g, 0, 0, 10),
+            (b, 0, 0, 10),
+            (r, 1, 0, 4),
+            (r, 0, 10, 1),
+            (b, 2, 4, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 4),
+            (g, 0, 10, 1),
+            (b, 3, 0, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 5),
+            (b, 0, 10, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 4),
+            (b, 3, 1, 1),
+            (b, 3, 2, 1),
+            (r, 3, 0, 4),
+            (b, 3, 4, 1),
+            (b, 3, 3, 1),
+        ],
+        5 => &[
+            (r, 0, 0, 9),
+            (b, 2, 4, 1),
+            (g, 0, 0, 9),
+            (g, 2, 4, 1),
+            (b, 0, 0, 9),
+            (b, 3, 4, 1),
+            (r, 1, 0, 5),
+            (g, 3, 4, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 5),
+            (b, 3, 0, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 5),
+            (b, 3, 1, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 5),
+            (b, 3, 2, 1),
+            (r, 3, 0, 5),
+            (b, 3, 3, 1),
+        ],
+        6 => &[
+            (r, 0, 0, 8),
+            (g, 3, 4, 1),
+            (b, 2, 4, 1),
+            (g, 0, 0, 8),
+            (b, 3, 2, 1),
+            (g, 2, 4, 1),
+            (b, 0, 0, 8),
+            (b, 3, 3, 1),
+            (b, 3, 4, 1),
+            (r, 1, 0, 6),
+            (g, 2, 0, 4),
+            (g, 1, 0, 5),
+            (b, 3, 0, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 5),
+            (b, 3, 1, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 6),
+            (r, 3, 0, 6),
+        ],
+        7 => &[
+            (r, 0, 0, 8),
+            (b, 3, 0, 1),
+            (b, 2, 4, 1),
+            (g, 0, 0, 8),
+            (g, 2, 5, 1),
+            (g, 2, 4, 1),
+            (b, 0, 0, 8),
+            (g, 3, 5, 1),
+            (b, 3, 4, 1),
+            (r, 1, 0, 5),
+            (g, 3, 4, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 6),
+            (g, 3, 0, 4),
+            (b, 1, 0, 5),
+            (b, 3, 1, 1),
+            (b, 2, 0, 4),
+            (r, 2, 0, 5),
+            (b, 3, 2, 1),
+            (r, 3, 0, 5),
+            (b, 3, 3, 1),
+        ],
+        8 => &[
+            (r, 0, 0, 8),
+            (b, 3, 1, 1),
+            (b, 2, 4, 1),
+            (g, 0, 0, 8),
+            (b, 2, 5, 1),
+            (g, 2, 4, 1),
+            (b, 0, 0, 8),
+            (b, 3, 5, 1),
+            (b, 3, 4, 1),
+            (r, 1, 0, 5),
+            (g, 3, 4, 1),
+            (g, 2, 0, 4),
+            (g, 1, 0, 5),
+            (b, 3, 0, 1),
+            (g, 3, 0, 4),
+            (b, 1, 0, 6),
+            (b, 2, 0, 4),
+            (r, 2, 0, 5),
+            (b, 3, 2, 1),
+            (r, 3, 0, 5),
+            (b, 3, 3, 1),
+        ],
+        9 => &[
+            (r, 0, 0, 6),
+            (g, 3, 4, 1),
+            (b, 3, 0, 1),
+            (b, 3, 1, 1),
+            (b, 2, 4, 1),
+            (g, 0, 0, 6),
+            (g, 2, 5, 1),
+            (b, 2, 5, 1),
+            (b, 3, 2, 1),
+            (g, 2, 4, 1),
+            (b, 0, 0, 6),
+            (g, 3, 5, 1),
+            (b, 3, 3, 1),
+            (b, 3, 5, 1),
+            (b, 3, 4, 1),
+            (r, 1, 0, 6),
+            (g, 2, 0, 4),
+            (g, 1, 0, 6),
+            (g, 3, 0, 4),
+            (b, 1, 0, 6),
+            (b, 2, 0, 4),
+            (r, 2, 0, 6),
+            (r, 3, 0, 6),
+        ],
+        10 => &[
+            (r, 0, 0, 10),
+            (g, 0, 0, 10),
+            (b, 0, 0, 10),
+            (r, 1, 0, 10),
+            (g, 1, 0, 10),
+            (b, 1, 0, 10),
+        ],
+        11 => &[
+            (r, 0, 0, 10),
+            (g, 0, 0, 10),
+            (b, 0, 0, 10),
+            (r, 1, 0, 9),
+            (r, 0, 10, 1),
+            (g, 1, 0, 9),
+            (g, 0, 10, 1),
+            (b, 1, 0, 9),
+            (b, 0, 10, 1),
+        ],
+        _ => &[(r, 0, 0, 10), (g, 0, 0, 10), (b, 0, 0, 10)],
+    };
+    for &(channel, endpoint, low, count) in layout {
+        bits.read_into(&mut e[channel][endpoint], low, count);
+    }
+    // the two highest precision modes store the high bits of the base reversed
+    if mode >= 12 {
+        let (delta_bits, high_bits) = if mode == 12 { (8, 2) } else { (4, 6) };
+        for channel in e.iter_mut() {
+            channel[1] |= bits.read(delta_bits) as i32;
+            channel[0] |= (bits.read_reversed(high_bits) << 10) as i32;
+        }
+    }
+
+    let regions = if mode < 10 { 2 } else { 1 };
+    let partition = if regions == 2 {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let (endpoint_bits, deltas) = BC6H_MODES[mode];
+    let sign_extend = |value: i32, bits: u32| (value << (32 - bits)) >> (32 - bits);
+    for (channel, values) in e.iter_mut().enumerate() {
+        if signed {
+            values[0] = sign_extend(values[0], endpoint_bits);
+        }
+        let base = values[0];
+        for value in values.iter_mut().take(regions * 2).skip(1) {
+            // the other endpoints are deltas to the base in all but two modes
+            if let Some(deltas) = deltas {
+                *value = (base + sign_extend(*value, deltas[channel])) & ((1 << endpoint_bits) - 1);
+            }
+            if signed {
+                *value = sign_extend(*value, endpoint_bits);
+            }
+        }
+        for value in values.iter_mut() {
+            *value = unquantize(*value, endpoint_bits, signed);
+        }
+    }
+
+    let indices = if regions == 2 {
+        bits.read_indices(3, 2, partition)
+    } else {
+        bits.read_indices(4, 1, 0)
+    };
+
+    std::array::from_fn(|i| {
+        let region = subset(regions as u32, partition, i);
+        let weight = weights(if regions == 2 { 3 } else { 4 })[indices[i] as usize] as i32;
+        let channel = |c: usize| {
+            let (e0, e1) = (e[c][region * 2], e[c][region * 2 + 1]);
+            finish((e0 * (64 - weight) + e1 * weight + 32) >> 6, signed)
+        };
+
+        [channel(0), channel(1), channel(2), ONE]
+    })
+}
+
+/// Scales an endpoint of `bits` to the full 16 bit range that is interpolated.
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if signed {
+        if bits >= 16 {
+            return value;
+        }
+        let magnitude = value.abs();
+        let magnitude = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        magnitude * value.signum()
+    } else if bits >= 15 {
+        value
+    } else if value == 0 {
+        0
+    } else if value == (1 << bits) - 1 {
+        0xffff
+    } else {
+        ((value << 16) + 0x8000) >> bits
+    }
+}
+
+/// Turns an interpolated value into the bits of a half float.
+fn finish(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | ((-value * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
@@ -0,0 +1,312 @@
+use std::{fmt, hash::Hash, marker::PhantomData, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    error::Result,
+    memory::{GpuBuffer, MemoryLocation},
+    Vk,
+};
+
+/// What a [`Buffer`] is bound as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+    Vertex,
+    /// Element type has to be `u16` or `u32`, see [`IndexElement`].
+    Index,
+    /// Kept host visible, so updates are written directly instead of going through staging.
+    Uniform,
+    Storage,
+    /// Draw or dispatch parameters, e.g. [`DrawIndexedIndirectCommand`].
+    Indirect,
+}
+
+impl BufferUsage {
+    pub fn get_flags(self) -> vk::BufferUsageFlags {
+        let usage = match self {
+            BufferUsage::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+            BufferUsage::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+            BufferUsage::Uniform => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            BufferUsage::Storage => {
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC
+            }
+            // compute shaders commonly write the draw parameters
+            BufferUsage::Indirect => {
+                vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER
+            }
+        };
+
+        // every buffer can be read through its address, see `Buffer::get_device_address`
+        usage | vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+    }
+
+    pub fn get_location(self) -> MemoryLocation {
+        match self {
+            BufferUsage::Uniform => MemoryLocation::Upload,
+            _ => MemoryLocation::GpuOnly,
+        }
+    }
+}
+
+/// An element type that can be used with [`BufferUsage::Index`].
+pub trait IndexElement: Pod {
+    const INDEX_TYPE: vk::IndexType;
+}
+
+impl IndexElement for u16 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT16;
+}
+
+impl IndexElement for u32 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT32;
+}
+
+/// [`vk::DrawIndirectCommand`] that can be put in a [`Buffer`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct DrawIndirectCommand {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    pub first_instance: u32,
+}
+
+/// [`vk::DrawIndexedIndirectCommand`] that can be put in a [`Buffer`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct DrawIndexedIndirectCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+/// [`vk::DispatchIndirectCommand`] that can be put in a [`Buffer`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct DispatchIndirectCommand {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+/// The device address of a buffer of `T`, which can be put into push constants or other buffers
+/// and dereferenced in shaders as a `GL_EXT_buffer_reference` pointer.
+///
+/// Is plain old data, so it can be a field of a [`crate::pipeline::PushConstants`] block.
+#[repr(transparent)]
+pub struct BufferAddress<T> {
+    address: vk::DeviceAddress,
+    marker: PhantomData<T>,
+}
+
+impl<T> BufferAddress<T> {
+    /// An address that must not be dereferenced, for optional data.
+    pub const NULL: Self = Self {
+        address: 0,
+        marker: PhantomData,
+    };
+
+    pub fn get_address(self) -> vk::DeviceAddress {
+        self.address
+    }
+
+    pub fn is_null(self) -> bool {
+        self.address == 0
+    }
+
+    /// The address of the element `index` elements past this one.
+    pub fn offset(self, index: usize) -> Self {
+        Self {
+            address: self.address + (index * size_of::<T>()) as vk::DeviceAddress,
+            marker: PhantomData,
+        }
+    }
+}
+
+// derived impls would require the same traits of `T`
+impl<T> Clone for BufferAddress<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BufferAddress<T> {}
+
+impl<T> PartialEq for BufferAddress<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> Eq for BufferAddress<T> {}
+
+impl<T> Hash for BufferAddress<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.address.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for BufferAddress<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BufferAddress({:#x})", self.address)
+    }
+}
+
+// a single `u64`, the marker takes no space
+unsafe impl<T: 'static> Zeroable for BufferAddress<T> {}
+unsafe impl<T: 'static> Pod for BufferAddress<T> {}
+
+/// A GPU buffer holding `len` elements of `T`.
+///
+/// Buffers other than [`BufferUsage::Uniform`] live in device local memory and are filled through
+/// the staging ring of [`Vk`], see [`Vk::upload_to_buffer`].
+pub struct Buffer<T: Pod> {
+    buffer: GpuBuffer,
+    len: usize,
+    usage: BufferUsage,
+    marker: PhantomData<T>,
+}
+
+impl<T: Pod> Buffer<T> {
+    /// Creates a buffer for `len` elements without initializing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer could not be allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is zero, as Vulkan has no empty buffers.
+    pub fn new(vk: &Vk, name: &str, usage: BufferUsage, len: usize) -> Result<Self> {
+        assert!(len > 0, "Buffer {} must hold at least one element", name);
+
+        let buffer = GpuBuffer::new(
+            vk,
+            name,
+            (len * size_of::<T>()) as vk::DeviceSize,
+            usage.get_flags(),
+            usage.get_location(),
+        )?;
+
+        Ok(Self {
+            buffer,
+            len,
+            usage,
+            marker: PhantomData,
+        })
+    }
+
+    /// Creates a buffer holding a copy of `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer could not be allocated or the upload failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is empty.
+    pub fn from_slice(vk: &Vk, name: &str, usage: BufferUsage, data: &[T]) -> Result<Self> {
+        let mut buffer = Self::new(vk, name, usage, data.len())?;
+        buffer.update(vk, 0, data)?;
+
+        Ok(buffer)
+    }
+
+    /// Overwrites the elements starting at `first` with `data`, leaving the rest of the buffer
+    /// as it is.
+    ///
+    /// Blocks until the copy has finished for device local buffers, so the GPU must not be using
+    /// the buffer at the same time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the upload failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not fit at `first`.
+    pub fn update(&mut self, vk: &Vk, first: usize, data: &[T]) -> Result<()> {
+        assert!(
+            first + data.len() <= self.len,
+            "Elements {}..{} are out of bounds of buffer {} of {} elements",
+            first,
+            first + data.len(),
+            self.buffer.get_name(),
+            self.len
+        );
+
+        let offset = (first * size_of::<T>()) as vk::DeviceSize;
+        let bytes = bytemuck::cast_slice(data);
+
+        match self.buffer.get_location() {
+            MemoryLocation::GpuOnly => vk.upload_to_buffer(self.buffer.get_buffer(), offset, bytes),
+            _ => self.buffer.write(offset, bytes),
+        }
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer {
+        self.buffer.get_buffer()
+    }
+
+    pub fn get_gpu_buffer(&self) -> &GpuBuffer {
+        &self.buffer
+    }
+
+    pub fn get_usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Size of the buffer in bytes.
+    pub fn get_size(&self) -> vk::DeviceSize {
+        self.buffer.get_size()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Address of the first element, for reading the buffer in shaders without a descriptor.
+    pub fn get_device_address(&self) -> BufferAddress<T> {
+        let address = self
+            .buffer
+            .get_device_address()
+            .expect("Buffers are always created with device addresses");
+
+        BufferAddress {
+            address,
+            marker: PhantomData,
+        }
+    }
+
+    /// Describes the whole buffer for a descriptor write.
+    pub fn get_descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.get_buffer(),
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }
+    }
+}
+
+impl<T: IndexElement> Buffer<T> {
+    pub fn get_index_type(&self) -> vk::IndexType {
+        T::INDEX_TYPE
+    }
+}
+
+impl<T: Pod> fmt::Debug for Buffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("buffer", &self.buffer)
+            .field("len", &self.len)
+            .field("usage", &self.usage)
+            .finish()
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use ash::vk;
+
+use crate::error::{RenderError, Result};
+
+/// Converts tightly packed texels of `format` into RGBA8, the layout of a PNG.
+///
+/// The bytes are kept as they are, so sRGB formats stay sRGB encoded, which is what a PNG holds.
+/// Swapchain images of UNORM formats hold sRGB encoded values as well, as the presentation engine
+/// interprets them in the sRGB color space. Alpha is made opaque, since swapchain images are
+/// presented opaque whatever they hold.
+///
+/// # Errors
+///
+/// Returns [`RenderError::UnsupportedFormat`] if `format` is not a 32 bit RGBA, BGRA or
+/// A2B10G10R10 format.
+pub fn to_rgba8(format: vk::Format, texels: &[u8]) -> Result<Vec<u8>> {
+    let rgba = match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => texels
+            .chunks_exact(4)
+            .flat_map(|texel| [texel[0], texel[1], texel[2], u8::MAX])
+            .collect(),
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => texels
+            .chunks_exact(4)
+            .flat_map(|texel| [texel[2], texel[1], texel[0], u8::MAX])
+            .collect(),
+        vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => texels
+            .chunks_exact(4)
+            .flat_map(|texel| {
+                let packed = u32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]);
+                // keep the top 8 of the 10 bits
+                let channel = |shift: u32| (packed >> (shift + 2)) as u8;
+
+                if format == vk::Format::A2B10G10R10_UNORM_PACK32 {
+                    [channel(0), channel(10), channel(20), u8::MAX]
+                } else {
+                    [channel(20), channel(10), channel(0), u8::MAX]
+                }
+            })
+            .collect(),
+        format => return Err(RenderError::UnsupportedFormat(format)),
+    };
+
+    Ok(rgba)
+}
+
+/// Writes tightly packed RGBA8 `pixels` of `extent` to `path` as a PNG, which viewers treat as
+/// sRGB.
+///
+/// # Errors
+///
+/// Returns an error if the file could not be written.
+///
+/// # Panics
+///
+/// Panics if `pixels` does not hold `extent` RGBA8 pixels.
+pub fn save_png(path: impl AsRef<Path>, extent: vk::Extent2D, pixels: &[u8]) -> Result<()> {
+    assert_eq!(
+        pixels.len(),
+        extent.width as usize * extent.height as usize * 4,
+        "Pixels do not match the extent {}x{}",
+        extent.width,
+        extent.height
+    );
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, extent.width, extent.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(std::io::Error::other)?;
+
+    Ok(())
+}
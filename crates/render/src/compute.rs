@@ -0,0 +1,320 @@
+use std::mem::size_of;
+
+use ash::{vk, Device};
+use bytemuck::Pod;
+
+use crate::{
+    buffer::{Buffer, BufferUsage, DispatchIndirectCommand},
+    error::Result,
+    memory::{GpuBuffer, MemoryLocation},
+    reflect::{DescriptorSets, PipelineReflection, ReflectedLayout, ShaderReflection},
+    shader::{CompiledShader, ShaderSource, ShaderStage},
+    Vk,
+};
+
+/// A compute pipeline whose layout is reflected from its shader, or given.
+///
+/// Storage buffers and images are bound through [`DescriptorSets`] from
+/// [`ComputePipeline::create_descriptor_sets`], or through their addresses in push constants with
+/// a layout given to [`ComputePipeline::with_layout`].
+pub struct ComputePipeline {
+    device: Device,
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    /// `None` if the layout was given.
+    reflected_layout: Option<ReflectedLayout>,
+    push_constants: Option<vk::PushConstantRange>,
+    reflection: ShaderReflection,
+}
+
+impl ComputePipeline {
+    /// Creates the pipeline with the layout reflected from `shader`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader can not be reflected or the pipeline could not be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shader` is not a compute shader.
+    pub fn new(vk: &Vk, name: &str, shader: &CompiledShader) -> Result<Self> {
+        let reflection = shader.reflect()?;
+        let layout =
+            PipelineReflection::new(std::slice::from_ref(&reflection))?.create_layouts(vk)?;
+
+        Self::create(
+            vk,
+            name,
+            shader,
+            reflection,
+            layout.get_pipeline_layout(),
+            layout.get_push_constant_range(),
+            Some(layout),
+        )
+    }
+
+    /// Creates the pipeline with `layout` and its `push_constants` range instead of reflecting
+    /// them, e.g. the layout of [`crate::bindless::Bindless`] or of
+    /// [`crate::pipeline::create_pipeline_layout`] with a
+    /// [`crate::pipeline::PushConstants::get_range`].
+    ///
+    /// `layout` has to outlive the pipeline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader can not be reflected or the pipeline could not be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shader` is not a compute shader.
+    pub fn with_layout(
+        vk: &Vk,
+        name: &str,
+        shader: &CompiledShader,
+        layout: vk::PipelineLayout,
+        push_constants: Option<vk::PushConstantRange>,
+    ) -> Result<Self> {
+        let reflection = shader.reflect()?;
+
+        Self::create(vk, name, shader, reflection, layout, push_constants, None)
+    }
+
+    fn create(
+        vk: &Vk,
+        name: &str,
+        shader: &CompiledShader,
+        reflection: ShaderReflection,
+        layout: vk::PipelineLayout,
+        push_constants: Option<vk::PushConstantRange>,
+        reflected_layout: Option<ReflectedLayout>,
+    ) -> Result<Self> {
+        assert_eq!(
+            shader.stage,
+            ShaderStage::Compute,
+            "Compute pipeline {} needs a compute shader",
+            name
+        );
+
+        let device = vk.get_device();
+
+        let module = shader.create_module(vk)?;
+        let stage = *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(module)
+            .name(c"main");
+        let pipeline_info = [*vk::ComputePipelineCreateInfo::builder()
+            .stage(stage)
+            .layout(layout)];
+
+        let pipeline = unsafe {
+            device.create_compute_pipelines(vk::PipelineCache::null(), &pipeline_info, None)
+        };
+        unsafe { device.destroy_shader_module(module, None) };
+        let pipeline = pipeline.map_err(|(_, e)| e)?[0];
+        vk.set_debug_name(pipeline, name);
+
+        Ok(Self {
+            device: device.clone(),
+            pipeline,
+            layout,
+            reflected_layout,
+            push_constants,
+            reflection,
+        })
+    }
+
+    /// Compiles `source` and creates the pipeline of it, named after the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader does not compile, see [`ComputePipeline::new`].
+    pub fn from_source(vk: &Vk, source: &ShaderSource) -> Result<Self> {
+        Self::new(vk, &source.path.display().to_string(), &source.compile()?)
+    }
+
+    pub fn get_pipeline(&self) -> vk::Pipeline {
+        self.pipeline
+    }
+
+    pub fn get_layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    pub fn get_reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+
+    /// The `local_size` of the shader.
+    pub fn get_workgroup_size(&self) -> [u32; 3] {
+        self.reflection.workgroup_size
+    }
+
+    /// Number of workgroups along x needed to cover `invocations` invocations.
+    pub fn get_group_count(&self, invocations: u32) -> u32 {
+        invocations.div_ceil(self.reflection.workgroup_size[0].max(1))
+    }
+
+    /// Allocates a descriptor set for every set the shader uses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sets could not be allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout was given, whose owner allocates the sets.
+    pub fn create_descriptor_sets(&self) -> Result<DescriptorSets> {
+        self.reflected_layout
+            .as_ref()
+            .expect("Descriptor sets of a given layout are allocated by its owner")
+            .create_descriptor_sets()
+    }
+
+    /// Records binding the pipeline and `sets`.
+    pub fn bind(&self, cmd: vk::CommandBuffer, sets: Option<&DescriptorSets>) {
+        unsafe {
+            self.device
+                .cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+
+            if let Some(sets) = sets.filter(|sets| !sets.get_sets().is_empty()) {
+                self.device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.get_layout(),
+                    0,
+                    sets.get_sets(),
+                    &[],
+                );
+            }
+        }
+    }
+
+    /// Records pushing `constants` for the bound pipeline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout has no push constant range.
+    pub fn push_constants<T: Pod>(&self, cmd: vk::CommandBuffer, constants: &T) {
+        let range = self
+            .push_constants
+            .expect("The layout has no push constants");
+
+        unsafe {
+            self.device.cmd_push_constants(
+                cmd,
+                self.get_layout(),
+                range.stage_flags,
+                0,
+                bytemuck::bytes_of(constants),
+            )
+        };
+    }
+
+    /// Records a dispatch of `group_count` workgroups, the pipeline has to be bound.
+    pub fn dispatch(&self, cmd: vk::CommandBuffer, group_count: [u32; 3]) {
+        let [x, y, z] = group_count;
+        unsafe { self.device.cmd_dispatch(cmd, x, y, z) };
+    }
+
+    /// Records a dispatch whose group count is read from element `index` of `commands` when it
+    /// executes, e.g. written by an earlier dispatch. The pipeline has to be bound.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn dispatch_indirect(
+        &self,
+        cmd: vk::CommandBuffer,
+        commands: &Buffer<DispatchIndirectCommand>,
+        index: usize,
+    ) {
+        assert!(
+            index < commands.len(),
+            "Dispatch {} is out of bounds of {} commands",
+            index,
+            commands.len()
+        );
+
+        let offset = (index * size_of::<DispatchIndirectCommand>()) as vk::DeviceSize;
+        unsafe {
+            self.device
+                .cmd_dispatch_indirect(cmd, commands.get_buffer(), offset)
+        };
+    }
+
+    /// Runs the shader once per element of `data` and returns what it left in the buffer.
+    ///
+    /// `data` is bound as the storage buffer at set 0, binding 0, which the shader reads and
+    /// overwrites in place. Invocations past the end, from rounding up to whole workgroups, have
+    /// to check the length of the buffer themselves. Blocks until the results are read back, so
+    /// this is meant for tools and tests rather than per frame work.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffers could not be created or the submission failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is empty, the layout was given or the shader has no storage buffer at set
+    /// 0, binding 0.
+    pub fn run<T: Pod>(&self, vk: &Vk, data: &[T]) -> Result<Vec<T>> {
+        let buffer = Buffer::from_slice(vk, "compute data", BufferUsage::Storage, data)?;
+        let readback = GpuBuffer::new(
+            vk,
+            "compute readback",
+            buffer.get_size(),
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::Readback,
+        )?;
+
+        let mut sets = self.create_descriptor_sets()?;
+        sets.write_buffer(0, 0, buffer.get_descriptor_info());
+
+        let group_count = self.get_group_count(data.len() as u32);
+        vk.immediate_submit(|device, cmd| {
+            self.bind(cmd, Some(&sets));
+            self.dispatch(cmd, [group_count, 1, 1]);
+
+            let to_copy = [*vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+                .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)];
+            let to_host = [*vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                .dst_access_mask(vk::AccessFlags2::HOST_READ)];
+            let region = [vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size: buffer.get_size(),
+            }];
+
+            unsafe {
+                device.cmd_pipeline_barrier2(
+                    cmd,
+                    &vk::DependencyInfo::builder().memory_barriers(&to_copy),
+                );
+                device.cmd_copy_buffer(cmd, buffer.get_buffer(), readback.get_buffer(), &region);
+                device.cmd_pipeline_barrier2(
+                    cmd,
+                    &vk::DependencyInfo::builder().memory_barriers(&to_host),
+                );
+            }
+        })?;
+
+        let bytes = readback.read(0, buffer.get_size() as usize)?;
+        // the bytes are not necessarily aligned for `T`
+        Ok(bytes
+            .chunks_exact(size_of::<T>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect())
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_pipeline(self.pipeline, None) };
+    }
+}
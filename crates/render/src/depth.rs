@@ -0,0 +1,109 @@
+use ash::{vk, Device};
+
+use crate::{
+    error::Result,
+    offscreen::{create_attachment, find_depth_format, image_barrier, Attachment},
+    utils::aspect_of,
+    Vk,
+};
+
+/// A depth attachment for rendering into the swapchain, see
+/// [`crate::Frame::begin_rendering_with_depth`].
+///
+/// One depth buffer is shared by every frame in flight, as its contents are cleared at the start
+/// of every frame and never read afterwards. It has to be resized with the swapchain.
+pub struct DepthBuffer {
+    device: Device,
+    attachment: Attachment,
+    extent: vk::Extent2D,
+}
+
+impl DepthBuffer {
+    /// Creates a depth buffer of `extent` in the format from [`find_depth_format`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device supports no depth format or the image could not be created.
+    pub fn new(vk: &Vk, extent: vk::Extent2D) -> Result<Self> {
+        let format = find_depth_format(vk)?;
+        let attachment = create_attachment(
+            vk,
+            "depth buffer",
+            extent,
+            format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            aspect_of(format),
+        )?;
+
+        Ok(Self {
+            device: vk.get_device().clone(),
+            attachment,
+            extent,
+        })
+    }
+
+    /// Recreates the image for `extent`, e.g. after the swapchain was recreated. Does nothing if
+    /// the extent did not change.
+    ///
+    /// The caller has to make sure no frame in flight still uses the old image, which
+    /// [`Vk::recreate_swapchain`] does by waiting for the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new image could not be created.
+    pub fn resize(&mut self, vk: &Vk, extent: vk::Extent2D) -> Result<()> {
+        if extent == self.extent {
+            return Ok(());
+        }
+
+        *self = Self::new(vk, extent)?;
+
+        Ok(())
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.attachment.image.get_format()
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn get_image(&self) -> vk::Image {
+        self.attachment.image.get_image()
+    }
+
+    pub fn get_view(&self) -> vk::ImageView {
+        self.attachment.view
+    }
+
+    /// The barrier into `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` before the buffer is cleared, waiting
+    /// for the depth tests of the previous frame.
+    ///
+    /// The combined layout is used as the format may have a stencil aspect, see
+    /// [`crate::offscreen::DEPTH_FORMATS`].
+    pub(crate) fn get_begin_barrier(&self) -> vk::ImageMemoryBarrier2 {
+        let tests = vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+            | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS;
+
+        // the contents are cleared, so the old layout does not matter
+        image_barrier(
+            self.get_image(),
+            aspect_of(self.get_format()),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            tests,
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            tests,
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        )
+    }
+}
+
+impl Drop for DepthBuffer {
+    fn drop(&mut self) {
+        // the image frees itself
+        unsafe { self.device.destroy_image_view(self.attachment.view, None) };
+    }
+}
@@ -1,22 +1,26 @@
-use std::sync::Arc;
+use std::ffi::{c_char, c_void, CStr};
 
 use ash::{
+    extensions::khr,
     vk::{self, PhysicalDevice},
     Device, Instance,
 };
 use tracing::info;
 
-use crate::utils::vk_to_str;
+use crate::{
+    error::{RenderError, Result},
+    selector::{Feature, Features},
+    utils::vk_to_str,
+};
 
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
     pub present_family: Option<u32>,
-}
-
-struct SwapChainSupportDetail {
-    capabilities: vk::SurfaceCapabilitiesKHR,
-    formats: Vec<vk::SurfaceFormatKHR>,
-    present_modes: Vec<vk::PresentModeKHR>,
+    /// A family with compute but without graphics support, for async compute.
+    pub compute_family: Option<u32>,
+    /// A family with transfer but without graphics or compute support, usually backed by a DMA
+    /// engine.
+    pub transfer_family: Option<u32>,
 }
 
 impl QueueFamilyIndices {
@@ -24,277 +28,223 @@ impl QueueFamilyIndices {
         QueueFamilyIndices {
             graphics_family: None,
             present_family: None,
+            compute_family: None,
+            transfer_family: None,
         }
     }
+}
 
-    pub fn is_complete(&self) -> bool {
-        self.graphics_family.is_some() && self.present_family.is_some()
-    }
+/// A queue together with the family it was created from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Queue {
+    pub queue: vk::Queue,
+    pub family: u32,
 }
 
-pub fn select_physical_device(instance: &Instance) -> Option<PhysicalDevice> {
-    let mut physical_devices = unsafe {
-        let devs = instance
-            .enumerate_physical_devices()
-            .expect("Could not enumerate physical devices");
+/// The queues created with the device.
+///
+/// Every role always has a queue. When the device has no dedicated compute or transfer family the
+/// queue falls back to a more general family, so `compute` and `transfer` may be the same queue as
+/// `graphics`. Compare [`Queue::family`] to find out whether ownership transfers are needed.
+#[derive(Debug, Clone, Copy)]
+pub struct Queues {
+    pub graphics: Queue,
+    /// Only present when the device was created for a surface.
+    pub present: Option<Queue>,
+    pub compute: Queue,
+    pub transfer: Queue,
+}
 
-        info!("Found {} devices with vulkan support", devs.len());
-        devs
+/// Finds the queue families of `p_dev`.
+///
+/// Presenting is only looked up if a `surface` is given, preferring the graphics family so the
+/// swapchain images do not have to be shared between families. The compute and transfer families
+/// are only set if the device has dedicated families for them.
+pub fn find_queue_families(
+    instance: &Instance,
+    p_dev: vk::PhysicalDevice,
+    surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+) -> Result<QueueFamilyIndices> {
+    let queue_families = unsafe { instance.get_physical_device_queue_family_properties(p_dev) };
+
+    let find = |required: vk::QueueFlags, excluded: vk::QueueFlags| {
+        queue_families
+            .iter()
+            .position(|family| {
+                family.queue_count > 0
+                    && family.queue_flags.contains(required)
+                    && !family.queue_flags.intersects(excluded)
+            })
+            .map(|i| i as u32)
     };
 
-    physical_devices.sort_by_key(|device| {
-        let device_props = unsafe { instance.get_physical_device_properties(*device) };
-        match device_props.device_type {
-            vk::PhysicalDeviceType::DISCRETE_GPU => 0,
-            vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
-            _ => 2,
+    let mut res = QueueFamilyIndices::new();
+
+    res.graphics_family = find(vk::QueueFlags::GRAPHICS, vk::QueueFlags::empty());
+    res.compute_family = find(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS);
+    res.transfer_family = find(
+        vk::QueueFlags::TRANSFER,
+        vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+    );
+
+    if let Some((surface_loader, surface)) = surface {
+        let mut present_families = Vec::new();
+        for i in 0..queue_families.len() as u32 {
+            if queue_families[i as usize].queue_count > 0
+                && unsafe { surface_loader.get_physical_device_surface_support(p_dev, i, surface)? }
+            {
+                present_families.push(i);
+            }
         }
-    });
 
-    // physical_devices.iter().find(|device| {
-    //     let device = *device;
-
-    //     let props  = unsafe {instance.get_physical_device_queue_family_properties(device)};
-    //     // for (index, family) in
-    //     props.iter().filter(|f| f.queue_count > 0).enumerate().fold((None, None), |acc, (idx,
-    // fam)| {         acc.0 = None;
-    //         acc.1 = None;
-
-    //         if fam.queue_flags.contains(vk::QueueFlags::GRAPHICS) &&
-    // fam.queue_flags.contains(vk::QueueFlags::COMPUTE) {             acc.0 = Some(idx);
-    //         };
-
-    //         let present_support = unsafe {
-
-    //         };
-    //     });
-    //     //     {
-    //     //     let index = index as u32;
-
-    //     //     if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) &&
-    // family.queue_flags.contains(vk::QueueFlags::COMPUTE) && gra     // }
-    // });
-
-    Some(
-        *physical_devices
-            .iter()
-            // .filter(|device| is_p_device_suitable(instance, device))
-            .map(|device| (device, rate_device_suitability(instance, device)))
-            .max_by(|(_, x), (_, y)| x.cmp(y))
-            .expect("Could not find any suitable GPU!")
-            .0,
-    )
-}
+        res.present_family = res
+            .graphics_family
+            .filter(|graphics| present_families.contains(graphics))
+            .or_else(|| present_families.first().copied());
+    }
 
-pub fn create_device(instance: Instance, p_dev: vk::PhysicalDevice) -> vk::Device {
-    todo!()
+    Ok(res)
 }
 
-// fn is_p_device_suitable(instance: &Instance, device: &PhysicalDevice) -> bool {
-//     let device_features = unsafe { instance.get_physical_device_features(*device) };
-//     // let queue_families = find_queue_families(instance, device);
-
-//     let is_extensions_supported = check_device_extension_support(instance, device);
-//     // let swapchain_support = if is_extensions_supported {
-//     //     let swapchain_support = self.query_spawnchain_support(device);
-//     //     !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty()
-//     // } else {
-//     //     false
-//     // };
-
-//     // match device_props.device_type {
-//     //     vk::PhysicalDeviceType::DISCRETE_GPU | vk::PhysicalDeviceType::INTEGRATED_GPU => {
-//     //         info!("Found a GPU");
-
-//     //         device_features.geometry_shader > 0
-//     //             // && queue_families.is_complete()
-//     //             && is_extensions_supported
-//     //         // && swapchain_support
-//     //     }
-//     //     _ => false,
-//     // }
-// }
-
-fn rate_device_suitability(instance: &Instance, device: &PhysicalDevice) -> i32 {
-    let device_props = unsafe { instance.get_physical_device_properties(*device) };
-    let device_features = unsafe { instance.get_physical_device_features(*device) };
-
-    let mut score = 0;
-
-    // big plus that it's a GPU
-    if device_props.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-        score += 1000;
+/// Creates the logical device together with a queue for every role in `indices`.
+///
+/// Roles without a dedicated family share the graphics family. Shared families hand out separate
+/// queues as long as the family has enough of them, otherwise the roles share a queue. Presenting
+/// always uses the graphics queue if the graphics family can present.
+pub fn create_logical_device(
+    instance: &Instance,
+    p_dev: vk::PhysicalDevice,
+    indices: &QueueFamilyIndices,
+    features: &[Feature],
+    extensions: &[*const c_char],
+    mesh_shader: bool,
+) -> Result<(Device, Queues)> {
+    let mut enabled = Features::enabled(features);
+    enabled.vk12.p_next =
+        &mut enabled.vk13 as *mut vk::PhysicalDeviceVulkan13Features as *mut c_void;
+
+    let mut mesh_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::builder()
+        .task_shader(true)
+        .mesh_shader(true);
+    if mesh_shader {
+        enabled.vk13.p_next = &mut *mesh_features as *mut _ as *mut c_void;
     }
+    let mut features = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut enabled.vk12)
+        .build();
 
-    // small plus that it's a integrated
-    if device_props.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU {
-        score += 100;
+    let family_props = unsafe { instance.get_physical_device_queue_family_properties(p_dev) };
+
+    let graphics_family = indices
+        .graphics_family
+        .ok_or(RenderError::NoSuitableDevice)?;
+    let compute_family = indices.compute_family.unwrap_or(graphics_family);
+    let transfer_family = indices.transfer_family.unwrap_or(compute_family);
+
+    // (family, index in family) of the graphics, compute and transfer queue
+    let mut slots = [(graphics_family, 0); 3];
+    let mut queue_counts: Vec<u32> = vec![0; family_props.len()];
+    for (slot, family) in slots
+        .iter_mut()
+        .zip([graphics_family, compute_family, transfer_family])
+    {
+        let count = &mut queue_counts[family as usize];
+        let index = (*count).min(family_props[family as usize].queue_count - 1);
+        *count = index + 1;
+        *slot = (family, index);
     }
-
-    // add add max image dimensions to score
-    score += device_props.limits.max_image_dimension2_d as i32;
-
-    // need geometry shader
-    if device_features.geometry_shader == 0 {
-        return 0;
+    // presenting from another family uses its first queue, which has to be created as well
+    if let Some(present_family) = indices.present_family {
+        let count = &mut queue_counts[present_family as usize];
+        *count = (*count).max(1);
     }
 
-    score
-}
-
-pub fn create_logical_device(instance: &Instance, p_dev: vk::PhysicalDevice) -> Device {
-    let mut features13 = vk::PhysicalDeviceVulkan13Features::builder()
-        .dynamic_rendering(true)
-        .synchronization2(true)
-        .build();
-    let mut features12 = vk::PhysicalDeviceVulkan12Features::builder()
-        .buffer_device_address(true)
-        .descriptor_indexing(true)
-        .build();
-    features12.p_next =
-        &mut features13 as *mut vk::PhysicalDeviceVulkan13Features as *mut std::ffi::c_void;
-    let mut features = vk::PhysicalDeviceFeatures2::builder()
-        .push_next(&mut features12)
-        .build();
+    let queue_priorities: Vec<Vec<f32>> = queue_counts
+        .iter()
+        .map(|count| vec![1.0; *count as usize])
+        .collect();
+    let queue_infos: Vec<vk::DeviceQueueCreateInfo> = queue_counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(family, _)| {
+            *vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(family as u32)
+                .queue_priorities(&queue_priorities[family])
+        })
+        .collect();
 
     let device_info = vk::DeviceCreateInfo::builder()
-        // .enabled_extension_names(enumerate_required_extensions())
+        .queue_create_infos(&queue_infos)
+        .enabled_extension_names(extensions)
         // .enabled_features(&mut features)
         .push_next(&mut features)
         .build();
 
-    let device = unsafe {
-        let Ok(d) = instance.create_device(p_dev, &device_info, None) else {
-            panic!("Could not create device!");
-        };
-        d
-    };
-
-    device
-}
-
-pub fn create_physical_devices(instance: &Instance) -> vk::PhysicalDevice {
-    let mut features13_check = vk::PhysicalDeviceVulkan13Features::builder().build();
-    let mut features12_check = vk::PhysicalDeviceVulkan12Features::builder().build();
-    features12_check.p_next =
-        &mut features13_check as *mut vk::PhysicalDeviceVulkan13Features as *mut std::ffi::c_void;
-    let mut features_check = vk::PhysicalDeviceFeatures2::builder()
-        .push_next(&mut features12_check)
-        .build();
-
-    unsafe {
-        instance
-            .enumerate_physical_devices()
-            .expect("Physical device error!")
-    }
-    .iter()
-    .find_map(|p_dev| unsafe {
-        let p_props = instance.get_physical_device_properties(*p_dev);
-        // let p_features = instance.get_physical_device_features(*p_dev);
-        instance.get_physical_device_features2(*p_dev, &mut features_check);
-        // find a discrete gpu
-        match p_props.device_type {
-            vk::PhysicalDeviceType::DISCRETE_GPU | vk::PhysicalDeviceType::INTEGRATED_GPU => {
-                if features13_check.dynamic_rendering == vk::TRUE
-                    || features13_check.synchronization2 == vk::TRUE
-                    || features12_check.buffer_device_address == vk::TRUE
-                    || features12_check.descriptor_indexing == vk::TRUE
-                {
-                    Some(*p_dev)
-                } else {
-                    None
-                }
+    let device =
+        unsafe { instance.create_device(p_dev, &device_info, None) }.map_err(|e| match e {
+            vk::Result::ERROR_EXTENSION_NOT_PRESENT => {
+                RenderError::MissingExtension(first_missing_extension(instance, p_dev, extensions))
             }
-            _ => None,
-        }
-    })
-    .expect("No suitable device found!")
-}
+            e => e.into(),
+        })?;
 
-fn check_device_extension_support(instance: &Instance, device: &PhysicalDevice) -> bool {
-    let extensions = unsafe {
-        instance
-            .clone()
-            .enumerate_device_extension_properties(*device)
-    }
-    .expect("Could not enumerate device extension properties");
+    let get_queue = |(family, index): (u32, u32)| Queue {
+        queue: unsafe { device.get_device_queue(family, index) },
+        family,
+    };
 
-    let req_extension = get_swap_required_extensions();
+    let graphics = get_queue(slots[0]);
+    let queues = Queues {
+        graphics,
+        present: indices.present_family.map(|family| {
+            if family == graphics_family {
+                graphics
+            } else {
+                get_queue((family, 0))
+            }
+        }),
+        compute: get_queue(slots[1]),
+        transfer: get_queue(slots[2]),
+    };
 
-    let res = req_extension.iter().all(|extension| {
-        extensions
-            .iter()
-            .map(|elem| vk_to_str(&elem.extension_name))
-            .any(|x| x == *extension)
-    });
+    info!(
+        "Created queues: graphics family {}, present family {:?}, compute family {}, transfer family {}",
+        graphics_family, indices.present_family, compute_family, transfer_family
+    );
 
-    res
+    Ok((device, queues))
 }
 
-// fn find_queue_families(instance: &Instance, device: &PhysicalDevice) -> QueueFamilyIndices {
-//     let queue_familys = unsafe { instance.get_physical_device_queue_family_properties(*device) };
-
-//     let mut res = QueueFamilyIndices::new();
-
-//     for (i, family) in queue_familys.iter().enumerate() {
-//         if family.queue_count > 0 && family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-//             res.graphics_family = Some(i as u32);
-//         }
-
-//         let is_present_support = unsafe {
-//             self.surface
-//                 .clone()
-//                 .unwrap()
-//                 .surface_loader
-//                 .get_physical_device_surface_support(
-//                     *device,
-//                     i as u32,
-//                     self.surface.clone().unwrap().surface,
-//                 )
-//                 .unwrap()
-//         };
+/// Whether `p_dev` supports task and mesh shaders, only valid to ask if it has
+/// `VK_EXT_mesh_shader`.
+pub fn supports_mesh_shaders(instance: &Instance, p_dev: PhysicalDevice) -> bool {
+    let mut mesh_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder().push_next(&mut mesh_features);
+    unsafe { instance.get_physical_device_features2(p_dev, &mut features2) };
 
-//         if family.queue_count > 0 && is_present_support {
-//             res.present_family = Some(i as u32);
-//         }
-
-//         if res.is_complete() {
-//             break;
-//         }
-//     }
-
-//     res
-// }
-
-// fn query_spawnchain_support(
-//     instance: &Instance,
-//     physical_device: &vk::PhysicalDevice,
-// ) -> SwapChainSupportDetail {
-//     let surface_info = self.surface.clone().unwrap();
-//     unsafe {
-//         let capabilities = surface_info
-//             .surface_loader
-//             .get_physical_device_surface_capabilities(*physical_device, surface_info.surface)
-//             .expect("Failed to query for surface capabilites");
-
-//         let formats = surface_info
-//             .surface_loader
-//             .get_physical_device_surface_formats(*physical_device, surface_info.surface)
-//             .expect("Failed to query for surface formats");
-
-//         let present_modes = surface_info
-//             .surface_loader
-//             .get_physical_device_surface_present_modes(*physical_device, surface_info.surface)
-//             .expect("Failed to query for surface present modes.");
-
-//         SwapChainSupportDetail {
-//             capabilities,
-//             formats,
-//             present_modes,
-//         }
-//     }
-// }
+    mesh_features.task_shader == vk::TRUE && mesh_features.mesh_shader == vk::TRUE
+}
 
-pub fn get_swap_required_extensions() -> Vec<String> {
-    ["VK_KHR_swapchain".to_owned()].to_vec()
+/// Returns the name of the first extension in `extensions` the device does not support.
+fn first_missing_extension(
+    instance: &Instance,
+    p_dev: PhysicalDevice,
+    extensions: &[*const c_char],
+) -> String {
+    let available: Vec<String> = unsafe { instance.enumerate_device_extension_properties(p_dev) }
+        .unwrap_or_default()
+        .iter()
+        .map(|props| vk_to_str(&props.extension_name))
+        .collect();
+
+    extensions
+        .iter()
+        .map(|name| {
+            unsafe { CStr::from_ptr(*name) }
+                .to_string_lossy()
+                .into_owned()
+        })
+        .find(|name| !available.contains(name))
+        .unwrap_or_else(|| "unknown".to_owned())
 }
@@ -0,0 +1,70 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::selector::Feature;
+
+pub type Result<T> = std::result::Result<T, RenderError>;
+
+/// Errors that can occur while setting up or driving the renderer.
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error("no suitable GPU found")]
+    NoSuitableDevice,
+    #[error("required extension {0} is not available")]
+    MissingExtension(String),
+    #[error("required feature {0} is not enabled")]
+    MissingFeature(Feature),
+    #[error("required layer {0} is not available")]
+    MissingLayer(String),
+    #[error("could not create surface: {0}")]
+    SurfaceCreationFailed(vk::Result),
+    #[error("out of {0} memory")]
+    OutOfMemory(MemoryKind),
+    #[error("the device was lost")]
+    DeviceLost,
+    #[error("vulkan call failed: {0}")]
+    Vulkan(vk::Result),
+    #[error("could not compile shader {name}:\n{message}")]
+    ShaderCompilation { name: String, message: String },
+    #[error("could not reflect shader: {0}")]
+    ShaderReflection(String),
+    #[error("vertex type {vertex} does not match the shader: {message}")]
+    VertexMismatch { vertex: String, message: String },
+    #[error("could not load image {name}: {message}")]
+    ImageLoad { name: String, message: String },
+    #[error("could not load model {name}: {message}")]
+    ModelLoad { name: String, message: String },
+    #[error("format {0:?} is not supported")]
+    UnsupportedFormat(vk::Format),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    Host,
+    Device,
+}
+
+impl std::fmt::Display for MemoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryKind::Host => write!(f, "host"),
+            MemoryKind::Device => write!(f, "device"),
+        }
+    }
+}
+
+impl From<vk::Result> for RenderError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY => RenderError::OutOfMemory(MemoryKind::Host),
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => RenderError::OutOfMemory(MemoryKind::Device),
+            vk::Result::ERROR_DEVICE_LOST => RenderError::DeviceLost,
+            vk::Result::ERROR_INCOMPATIBLE_DRIVER | vk::Result::ERROR_FEATURE_NOT_PRESENT => {
+                RenderError::NoSuitableDevice
+            }
+            result => RenderError::Vulkan(result),
+        }
+    }
+}
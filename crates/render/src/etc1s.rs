@@ -0,0 +1,462 @@
+//! A transcoder from ETC1S, the BasisLZ supercompressed format of Basis Universal, to ETC1.
+//!
+//! ETC1S blocks are ETC1 blocks with one color and modifier table for both halves. Colors and
+//! selectors come from codebooks shared by every slice of a container, and each slice Huffman
+//! codes which entries its blocks use, predicted from their neighbors.
+
+/// The modifiers of ETC1, ordered by ETC1S selector from darkest to brightest.
+const MODIFIERS: [[i32; 4]; 8] = [
+    [-8, -2, 2, 8],
+    [-17, -5, 5, 17],
+    [-29, -9, 9, 29],
+    [-42, -13, 13, 42],
+    [-60, -18, 18, 60],
+    [-80, -24, 24, 80],
+    [-106, -33, 33, 106],
+    [-183, -47, 47, 183],
+];
+
+/// The ETC1 pixel index of each ETC1S selector.
+const ETC1_INDICES: [u32; 4] = [3, 2, 0, 1];
+
+/// The order the lengths of the code length code are stored in.
+const CODE_LENGTH_ORDER: [usize; 21] = [
+    17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+];
+
+/// The endpoint prediction symbol repeating the previous one.
+const REPEAT_PREDICTIONS: u32 = 256;
+
+/// Runs of the most recent selector in the history shorter than this are not run length coded.
+const MIN_SELECTOR_RUN: u32 = 3;
+
+/// The color and modifier table of a block.
+#[derive(Clone, Copy, Default)]
+struct Endpoint {
+    /// RGB with 5 bits per channel.
+    color: [u8; 3],
+    table: u8,
+}
+
+/// The 2 bit selectors of a block, a byte per row with the selector of column x at bit 2x.
+type Selectors = [u8; 4];
+
+/// A decoded ETC1S block.
+#[derive(Clone, Copy)]
+pub(crate) struct Block {
+    endpoint: Endpoint,
+    selectors: Selectors,
+}
+
+impl Block {
+    /// The block as an ETC1 block in differential mode, which ETC2 decoders read as well.
+    pub(crate) fn to_etc1(self) -> [u8; 8] {
+        let [r, g, b] = self.endpoint.color.map(|c| c << 3);
+        let table = self.endpoint.table;
+
+        // pixel indices are column major, split into a plane of high and one of low bits
+        let mut indices = 0;
+        for (x, y) in (0..4).flat_map(|y| (0..4).map(move |x| (x, y))) {
+            let index = ETC1_INDICES[(self.selectors[y] >> (x * 2)) as usize & 3];
+            let bit = x * 4 + y;
+            indices |= (index & 1) << bit | (index >> 1) << (16 + bit);
+        }
+        let [i0, i1, i2, i3] = u32::to_be_bytes(indices);
+
+        [r, g, b, table << 5 | table << 2 | 2, i0, i1, i2, i3]
+    }
+
+    /// The RGB of the 16 texels in row major order.
+    pub(crate) fn texels(self) -> [[u8; 3]; 16] {
+        let base = self.endpoint.color.map(|c| (c << 3 | c >> 2) as i32);
+        let modifiers = MODIFIERS[self.endpoint.table as usize];
+
+        std::array::from_fn(|i| {
+            let modifier = modifiers[(self.selectors[i / 4] >> (i % 4 * 2)) as usize & 3];
+            base.map(|c| (c + modifier).clamp(0, 255) as u8)
+        })
+    }
+}
+
+/// The codebooks and Huffman codes of a BasisLZ container, shared by all its slices.
+pub(crate) struct Codebooks {
+    endpoints: Vec<Endpoint>,
+    selectors: Vec<Selectors>,
+    endpoint_predictions: Huffman,
+    endpoint_deltas: Huffman,
+    selectors_code: Huffman,
+    selector_runs: Huffman,
+    history_size: usize,
+}
+
+impl Codebooks {
+    /// Reads the codebooks of `endpoint_count` endpoints and `selector_count` selectors and the
+    /// Huffman codes of the slices, `None` if they are malformed.
+    pub(crate) fn read(
+        endpoint_count: usize,
+        selector_count: usize,
+        endpoints: &[u8],
+        selectors: &[u8],
+        tables: &[u8],
+    ) -> Option<Self> {
+        let mut tables = Bits::new(tables);
+
+        Some(Self {
+            endpoints: read_endpoints(endpoint_count, &mut Bits::new(endpoints))?,
+            selectors: read_selectors(selector_count, &mut Bits::new(selectors))?,
+            endpoint_predictions: tables.read_huffman()?,
+            endpoint_deltas: tables.read_huffman()?,
+            selectors_code: tables.read_huffman()?,
+            selector_runs: tables.read_huffman()?,
+            history_size: tables.read(13)? as usize,
+        })
+    }
+
+    /// Decodes a slice of `blocks_x` by `blocks_y` blocks in row major order, `None` if it is
+    /// malformed.
+    pub(crate) fn decode_slice(
+        &self,
+        slice: &[u8],
+        blocks_x: usize,
+        blocks_y: usize,
+    ) -> Option<Vec<Block>> {
+        let mut bits = Bits::new(slice);
+        let endpoint_count = self.endpoints.len();
+        let selector_count = self.selectors.len();
+        let run_symbol = selector_count + self.history_size;
+        let mut history = History::new(self.history_size);
+
+        // the endpoint of each block and the prediction bits for the row below of every even
+        // row, and the same for the odd rows
+        let mut rows = [vec![(0, 0); blocks_x], vec![(0, 0); blocks_x]];
+        let (mut predictions, mut previous_predictions, mut repeats) = (0, 0, 0);
+        let mut previous_endpoint = 0;
+        let mut selector_run = 0;
+        let mut blocks = Vec::with_capacity(blocks_x * blocks_y);
+
+        for y in 0..blocks_y {
+            let (current, above) = (y & 1, (y & 1) ^ 1);
+
+            for x in 0..blocks_x {
+                // each 2x2 group of blocks shares a symbol of 4 predictions, 2 bits each
+                if x & 1 == 0 {
+                    if y & 1 == 0 {
+                        if repeats > 0 {
+                            repeats -= 1;
+                            predictions = previous_predictions;
+                        } else {
+                            predictions = bits.decode(&self.endpoint_predictions)?;
+                            if predictions == REPEAT_PREDICTIONS {
+                                repeats = bits.read_vlc(4)? + 2;
+                                predictions = previous_predictions;
+                            }
+                            previous_predictions = predictions;
+                        }
+                        rows[above][x].1 = predictions >> 4;
+                    } else {
+                        predictions = rows[current][x].1;
+                    }
+                }
+
+                let endpoint = match predictions & 3 {
+                    0 if x > 0 => previous_endpoint,
+                    1 if y > 0 => rows[above][x].0,
+                    2 if x > 0 && y > 0 => rows[above][x - 1].0,
+                    3 => {
+                        let endpoint =
+                            previous_endpoint + bits.decode(&self.endpoint_deltas)? as usize;
+                        if endpoint >= endpoint_count {
+                            endpoint - endpoint_count
+                        } else {
+                            endpoint
+                        }
+                    }
+                    _ => return None,
+                };
+                predictions >>= 2;
+                rows[current][x].0 = endpoint;
+                previous_endpoint = endpoint;
+
+                let symbol = if selector_run > 0 {
+                    selector_run -= 1;
+                    selector_count
+                } else {
+                    let symbol = bits.decode(&self.selectors_code)? as usize;
+                    if symbol == run_symbol {
+                        let run = bits.decode(&self.selector_runs)?;
+                        let run = match run {
+                            63 => bits.read_vlc(7)?,
+                            run => run,
+                        } + MIN_SELECTOR_RUN;
+                        if run as usize > blocks_x * blocks_y {
+                            return None;
+                        }
+                        selector_run = run - 1;
+                        selector_count
+                    } else {
+                        symbol
+                    }
+                };
+                let selector = match symbol.checked_sub(selector_count) {
+                    Some(index) => history.take(index)?,
+                    None => {
+                        history.add(symbol);
+                        symbol
+                    }
+                };
+
+                blocks.push(Block {
+                    endpoint: *self.endpoints.get(endpoint)?,
+                    selectors: *self.selectors.get(selector)?,
+                });
+            }
+        }
+
+        Some(blocks)
+    }
+}
+
+fn read_endpoints(count: usize, bits: &mut Bits) -> Option<Vec<Endpoint>> {
+    // the color deltas are coded by the range the previous value is in
+    let color_deltas = [
+        bits.read_huffman()?,
+        bits.read_huffman()?,
+        bits.read_huffman()?,
+    ];
+    let table_deltas = bits.read_huffman()?;
+    let grayscale = bits.read(1)? == 1;
+
+    let mut previous = [16; 3];
+    let mut previous_table = 0;
+    (0..count)
+        .map(|_| {
+            let table = (previous_table + bits.decode(&table_deltas)?) & 7;
+            previous_table = table;
+
+            let mut color = [0; 3];
+            for c in 0..if grayscale { 1 } else { 3 } {
+                let deltas = match previous[c] {
+                    0..=9 => &color_deltas[0],
+                    10..=21 => &color_deltas[1],
+                    _ => &color_deltas[2],
+                };
+                previous[c] = (previous[c] + bits.decode(deltas)?) & 31;
+                color[c] = previous[c] as u8;
+            }
+            if grayscale {
+                color = [color[0]; 3];
+            }
+
+            Some(Endpoint {
+                color,
+                table: table as u8,
+            })
+        })
+        .collect()
+}
+
+fn read_selectors(count: usize, bits: &mut Bits) -> Option<Vec<Selectors>> {
+    // the global and hybrid selector codebooks of older encoders
+    if bits.read(1)? == 1 || bits.read(1)? == 1 {
+        return None;
+    }
+
+    if bits.read(1)? == 1 {
+        return (0..count)
+            .map(|_| {
+                Some([
+                    bits.read(8)? as u8,
+                    bits.read(8)? as u8,
+                    bits.read(8)? as u8,
+                    bits.read(8)? as u8,
+                ])
+            })
+            .collect();
+    }
+
+    // every row is xored with the row of the previous selectors
+    let deltas = bits.read_huffman()?;
+    let mut previous = [0; 4];
+    (0..count)
+        .map(|i| {
+            for row in &mut previous {
+                *row ^= if i == 0 {
+                    bits.read(8)?
+                } else {
+                    bits.decode(&deltas)?
+                } as u8;
+            }
+            Some(previous)
+        })
+        .collect()
+}
+
+/// Recently used selectors, where used ones move halfway to the front.
+struct History {
+    selectors: Vec<usize>,
+    /// Where the next new selector goes, cycling through the back half.
+    next: usize,
+}
+
+impl History {
+    fn new(size: usize) -> Self {
+        Self {
+            selectors: vec![0; size],
+            next: size / 2,
+        }
+    }
+
+    fn add(&mut self, selector: usize) {
+        if self.selectors.is_empty() {
+            return;
+        }
+
+        self.selectors[self.next] = selector;
+        self.next += 1;
+        if self.next == self.selectors.len() {
+            self.next = self.selectors.len() / 2;
+        }
+    }
+
+    fn take(&mut self, index: usize) -> Option<usize> {
+        let selector = *self.selectors.get(index)?;
+        self.selectors.swap(index / 2, index);
+
+        Some(selector)
+    }
+}
+
+/// A canonical Huffman code, decoded a bit at a time.
+#[derive(Default)]
+struct Huffman {
+    /// The number of codes of each length.
+    counts: [u32; 17],
+    /// The symbols ordered by code length.
+    symbols: Vec<u32>,
+}
+
+impl Huffman {
+    /// `None` if the lengths do not fit into a prefix code.
+    fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0; 17];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = left * 2 - *count as i32;
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut symbols: Vec<u32> = (0..lengths.len() as u32)
+            .filter(|&symbol| lengths[symbol as usize] > 0)
+            .collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+
+        Some(Self { counts, symbols })
+    }
+}
+
+/// Reads the bits of a byte slice from the lowest bit of the first byte up.
+struct Bits<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// `None` past the end.
+    fn read(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.bytes.get(self.position / 8)?;
+            value |= ((byte >> (self.position % 8)) as u32 & 1) << i;
+            self.position += 1;
+        }
+
+        Some(value)
+    }
+
+    /// Reads a value stored in chunks of `chunk_bits`, each followed by a bit telling whether
+    /// another one follows.
+    fn read_vlc(&mut self, chunk_bits: u32) -> Option<u32> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let chunk = self.read(chunk_bits + 1)?;
+            value |= (chunk & ((1 << chunk_bits) - 1)).checked_shl(shift)?;
+            if chunk >> chunk_bits == 0 {
+                return Some(value);
+            }
+            shift += chunk_bits;
+        }
+    }
+
+    /// Codes are stored from their highest bit, so the first bit read is the top one.
+    fn decode(&mut self, huffman: &Huffman) -> Option<u32> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &huffman.counts[1..] {
+            code |= self.read(1)?;
+            if code - first < count {
+                return huffman
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        None
+    }
+
+    /// Reads the code lengths of a Huffman code, themselves Huffman coded with runs of zeros and
+    /// repeats.
+    fn read_huffman(&mut self) -> Option<Huffman> {
+        let symbol_count = self.read(14)? as usize;
+        if symbol_count == 0 {
+            return Some(Huffman::default());
+        }
+
+        let code_length_count = self.read(5)? as usize;
+        if !(1..=CODE_LENGTH_ORDER.len()).contains(&code_length_count) {
+            return None;
+        }
+        let mut code_lengths = [0; 21];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = self.read(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(symbol_count);
+        while lengths.len() < symbol_count {
+            match self.decode(&code_lengths)? {
+                length @ 0..=16 => lengths.push(length as u8),
+                17 => lengths.resize(lengths.len() + self.read(3)? as usize + 3, 0),
+                18 => lengths.resize(lengths.len() + self.read(7)? as usize + 11, 0),
+                symbol => {
+                    let previous = *lengths.last().filter(|&&length| length > 0)?;
+                    let repeats = if symbol == 19 {
+                        self.read(2)? + 3
+                    } else {
+                        self.read(7)? + 7
+                    };
+                    lengths.resize(lengths.len() + repeats as usize, previous);
+                }
+            }
+        }
+        if lengths.len() != symbol_count {
+            return None;
+        }
+
+        Huffman::new(&lengths)
+    }
+}
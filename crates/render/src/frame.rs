@@ -0,0 +1,805 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use ash::{vk, Device};
+use tracing::{debug, info, warn};
+
+use crate::{
+    capture::{save_png, to_rgba8},
+    depth::DepthBuffer,
+    error::{RenderError, Result},
+    memory::{GpuBuffer, MemoryLocation},
+    offscreen::image_barrier,
+    swapchain::Swapchain,
+    utils::format_texel_size,
+    Vk,
+};
+
+/// How many frames the CPU may record ahead of the GPU by default.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// What one frame in flight needs, reused every `frames_in_flight` frames.
+struct FrameSlot {
+    command_pool: vk::CommandPool,
+    cmd: vk::CommandBuffer,
+    image_available: vk::Semaphore,
+    in_flight: vk::Fence,
+    /// Created on the first capture, recreated when the swapchain grows.
+    readback: Option<GpuBuffer>,
+    /// Copied into `readback` by the last frame of the slot, saved once the slot is free again.
+    pending_capture: Option<PendingCapture>,
+}
+
+struct PendingCapture {
+    path: PathBuf,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
+/// Captures every frame, see [`FrameLoop::start_image_sequence`].
+struct ImageSequence {
+    directory: PathBuf,
+    timestep: Duration,
+    next_frame: u64,
+}
+
+/// Drives rendering into the swapchain of a [`Vk`] with up to N frames in flight.
+///
+/// Every frame goes through [`FrameLoop::begin`], which waits until the slot of the frame is free
+/// and acquires a swapchain image, recording into the returned [`Frame`], and [`FrameLoop::end`],
+/// which submits with synchronization2 and presents. Rendering uses dynamic rendering, so there
+/// are no render passes or framebuffers to recreate with the swapchain.
+///
+/// Has to be dropped before the [`Vk`] it was created from.
+pub struct FrameLoop {
+    device: Device,
+    slots: Vec<FrameSlot>,
+    /// One per swapchain image, as an image is only presented again after being acquired again.
+    render_finished: Vec<vk::Semaphore>,
+    frame_number: u64,
+    /// Where to save the next frame, see [`FrameLoop::capture_frame`].
+    capture_request: Option<PathBuf>,
+    sequence: Option<ImageSequence>,
+}
+
+/// A frame that is being recorded, returned by [`FrameLoop::begin`].
+///
+/// The swapchain image is already in `COLOR_ATTACHMENT_OPTIMAL` layout and the command buffer is
+/// recording. Every frame has to be handed back to [`FrameLoop::end`], otherwise its slot is never
+/// freed.
+pub struct Frame<'a> {
+    device: &'a Device,
+    cmd: vk::CommandBuffer,
+    image_index: u32,
+    image: vk::Image,
+    image_view: vk::ImageView,
+    format: vk::Format,
+    extent: vk::Extent2D,
+    slot: usize,
+    frame_number: u64,
+    suboptimal: bool,
+    wait_semaphores: Vec<vk::SemaphoreSubmitInfo>,
+}
+
+impl FrameLoop {
+    /// Creates a frame loop allowing `frames_in_flight` frames to be recorded while the GPU is
+    /// still busy with earlier ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command buffers or synchronization primitives could not be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames_in_flight` is zero.
+    pub fn new(vk: &Vk, frames_in_flight: usize) -> Result<Self> {
+        assert!(
+            frames_in_flight > 0,
+            "At least one frame has to be in flight"
+        );
+
+        let mut frame_loop = Self {
+            device: vk.get_device().clone(),
+            slots: Vec::with_capacity(frames_in_flight),
+            render_finished: vec![],
+            frame_number: 0,
+            capture_request: None,
+            sequence: None,
+        };
+
+        for i in 0..frames_in_flight {
+            let slot = create_slot(vk, i)?;
+            frame_loop.slots.push(slot);
+        }
+
+        Ok(frame_loop)
+    }
+
+    /// Waits until the next frame slot is free, acquires a swapchain image and begins recording.
+    ///
+    /// Returns `None` if the swapchain is out of date, in which case it has to be recreated with
+    /// [`Vk::recreate_swapchain`] before trying again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::SurfaceCreationFailed`] if `vk` has no swapchain, or an error if
+    /// waiting, acquiring or beginning the command buffer failed, or a capture the slot made
+    /// earlier could not be saved.
+    ///
+    /// Returns [`RenderError::UnsupportedFormat`] if the frame is to be captured but the swapchain
+    /// format can not be read back, or an error if the readback buffer of the capture could not be
+    /// created. In both cases the capture request and image sequence are dropped so the next frame
+    /// can be rendered.
+    pub fn begin<'a>(&mut self, vk: &'a Vk) -> Result<Option<Frame<'a>>> {
+        let swapchain = get_swapchain(vk)?;
+        let capturing = self.capture_request.is_some() || self.sequence.is_some();
+        if capturing && format_texel_size(swapchain.get_format()).is_none() {
+            self.capture_request = None;
+            self.sequence = None;
+            return Err(RenderError::UnsupportedFormat(swapchain.get_format()));
+        }
+        let slot_index = (self.frame_number % self.slots.len() as u64) as usize;
+
+        unsafe {
+            self.device
+                .wait_for_fences(&[self.slots[slot_index].in_flight], true, u64::MAX)?
+        };
+        self.save_capture(slot_index)?;
+        // before acquiring, so failing leaves neither a signaled semaphore nor an unsignaled fence
+        // behind
+        if capturing && swapchain.supports_capture() {
+            self.prepare_capture(
+                vk,
+                slot_index,
+                swapchain.get_extent(),
+                swapchain.get_format(),
+            )?;
+        }
+        let slot = &self.slots[slot_index];
+
+        let (image_index, suboptimal) =
+            match swapchain.acquire(slot.image_available, vk::Fence::null()) {
+                Ok(acquired) => acquired,
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+        // only reset once something is going to be submitted, or the next wait never returns
+        unsafe {
+            self.device.reset_fences(&[slot.in_flight])?;
+            self.device
+                .reset_command_pool(slot.command_pool, vk::CommandPoolResetFlags::empty())?;
+
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            self.device.begin_command_buffer(slot.cmd, &begin_info)?;
+        }
+
+        let image = swapchain.get_images()[image_index as usize];
+        let to_attachment = [image_barrier(
+            image,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::NONE,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        )];
+        let dependency_info = vk::DependencyInfo::builder().image_memory_barriers(&to_attachment);
+        unsafe {
+            self.device
+                .cmd_pipeline_barrier2(slot.cmd, &dependency_info)
+        };
+
+        Ok(Some(Frame {
+            device: vk.get_device(),
+            cmd: slot.cmd,
+            image_index,
+            image,
+            image_view: swapchain.get_image_views()[image_index as usize],
+            format: swapchain.get_format(),
+            extent: swapchain.get_extent(),
+            slot: slot_index,
+            frame_number: self.frame_number,
+            suboptimal,
+            wait_semaphores: vec![],
+        }))
+    }
+
+    /// Transitions the swapchain image of `frame` for presenting, submits it to the graphics queue
+    /// and presents it. A present queue of another family presents without an ownership transfer,
+    /// as the swapchain images are then shared concurrently between both families.
+    ///
+    /// If the frame is captured, the image is copied into the readback buffer [`FrameLoop::begin`]
+    /// prepared on the way, see [`FrameLoop::capture_frame`].
+    ///
+    /// Returns whether the swapchain is out of date or suboptimal and should be recreated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if submitting or presenting failed.
+    pub fn end(&mut self, vk: &Vk, frame: Frame) -> Result<bool> {
+        let swapchain = get_swapchain(vk)?;
+        let queues = vk.get_queues();
+        let present_queue = queues.present.unwrap_or(queues.graphics);
+        let (image_available, in_flight) = {
+            let slot = &self.slots[frame.slot];
+            (slot.image_available, slot.in_flight)
+        };
+
+        let capturing = self.capture_request.is_some() || self.sequence.is_some();
+        let captured = if !capturing {
+            false
+        } else if !swapchain.supports_capture() {
+            self.next_capture_path();
+            warn!("The swapchain images can not be copied, the frame is not captured");
+            false
+        } else if !self.readback_fits(frame.slot, frame.extent, frame.format) {
+            // requested while the frame was recorded, so the next frame is captured instead
+            false
+        } else {
+            match self.next_capture_path() {
+                Some(path) => {
+                    self.record_capture(&frame, path);
+                    true
+                }
+                None => false,
+            }
+        };
+
+        let to_present = [if captured {
+            image_barrier(
+                frame.image,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::NONE,
+                vk::PipelineStageFlags2::NONE,
+                vk::AccessFlags2::NONE,
+            )
+        } else {
+            image_barrier(
+                frame.image,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags2::NONE,
+                vk::AccessFlags2::NONE,
+            )
+        }];
+        let dependency_info = vk::DependencyInfo::builder().image_memory_barriers(&to_present);
+
+        unsafe {
+            self.device
+                .cmd_pipeline_barrier2(frame.cmd, &dependency_info);
+            self.device.end_command_buffer(frame.cmd)?;
+        }
+
+        let render_finished = self.get_render_finished(vk, frame.image_index)?;
+
+        let mut wait_semaphores = frame.wait_semaphores;
+        wait_semaphores.push(
+            *vk::SemaphoreSubmitInfo::builder()
+                .semaphore(image_available)
+                .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT),
+        );
+        let command_buffers = [*vk::CommandBufferSubmitInfo::builder().command_buffer(frame.cmd)];
+        let signal_semaphores = [*vk::SemaphoreSubmitInfo::builder()
+            .semaphore(render_finished)
+            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
+        let submit_info = [*vk::SubmitInfo2::builder()
+            .wait_semaphore_infos(&wait_semaphores)
+            .command_buffer_infos(&command_buffers)
+            .signal_semaphore_infos(&signal_semaphores)];
+
+        unsafe {
+            self.device
+                .queue_submit2(queues.graphics.queue, &submit_info, in_flight)?
+        };
+
+        self.frame_number += 1;
+
+        match swapchain.present(present_queue.queue, frame.image_index, &[render_finished]) {
+            Ok(suboptimal) => Ok(suboptimal || frame.suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Blocks until every frame in flight has finished on the GPU.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting failed, e.g. because the device was lost.
+    pub fn wait_idle(&self) -> Result<()> {
+        let fences: Vec<vk::Fence> = self.slots.iter().map(|slot| slot.in_flight).collect();
+
+        unsafe { self.device.wait_for_fences(&fences, true, u64::MAX)? };
+
+        Ok(())
+    }
+
+    /// Saves the next frame handed to [`FrameLoop::end`] to `path` as a PNG.
+    ///
+    /// The swapchain image is copied into a readback buffer when the frame ends and saved once the
+    /// GPU is done with it, when its slot is reused by [`FrameLoop::begin`] or in
+    /// [`FrameLoop::flush_captures`], so capturing never stalls the frame it is taken in.
+    pub fn capture_frame(&mut self, path: impl Into<PathBuf>) {
+        self.capture_request = Some(path.into());
+    }
+
+    /// Captures every frame from now on into `directory` as `frame_00000.png`, `frame_00001.png`
+    /// and so on, for making videos of a scene.
+    ///
+    /// The frames are meant to be played back at `frames_per_second`, so while the sequence runs
+    /// the scene has to be advanced by [`FrameLoop::get_fixed_timestep`] every frame instead of
+    /// by the time that passed, which capturing slows down.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `directory` could not be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames_per_second` is zero.
+    pub fn start_image_sequence(
+        &mut self,
+        directory: impl Into<PathBuf>,
+        frames_per_second: u32,
+    ) -> Result<()> {
+        assert!(frames_per_second > 0, "The sequence needs a frame rate");
+
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        info!(
+            "Capturing an image sequence at {} fps into {}",
+            frames_per_second,
+            directory.display()
+        );
+
+        self.sequence = Some(ImageSequence {
+            directory,
+            timestep: Duration::from_secs(1) / frames_per_second,
+            next_frame: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Stops the image sequence, returning how many frames it captured.
+    ///
+    /// The last frames are saved once they finished, see [`FrameLoop::flush_captures`].
+    pub fn stop_image_sequence(&mut self) -> Option<u64> {
+        self.sequence.take().map(|sequence| sequence.next_frame)
+    }
+
+    /// The time every frame of a running image sequence stands for, `None` if no sequence runs.
+    pub fn get_fixed_timestep(&self) -> Option<Duration> {
+        self.sequence.as_ref().map(|sequence| sequence.timestep)
+    }
+
+    /// Waits until every frame in flight has finished and saves their captures.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting failed or a capture could not be saved.
+    pub fn flush_captures(&mut self) -> Result<()> {
+        self.wait_idle()?;
+
+        for index in 0..self.slots.len() {
+            self.save_capture(index)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_frames_in_flight(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// How many frames have been submitted so far.
+    pub fn get_frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// Where the frame that is ending is captured to, if anywhere.
+    fn next_capture_path(&mut self) -> Option<PathBuf> {
+        if let Some(path) = self.capture_request.take() {
+            return Some(path);
+        }
+
+        let sequence = self.sequence.as_mut()?;
+        let path = sequence
+            .directory
+            .join(format!("frame_{:05}.png", sequence.next_frame));
+        sequence.next_frame += 1;
+
+        Some(path)
+    }
+
+    /// Makes sure the free slot `index` has a readback buffer for a capture of `extent` texels
+    /// of `format`, replacing one too small for a grown swapchain.
+    ///
+    /// Drops the capture request and image sequence if it could not be created.
+    fn prepare_capture(
+        &mut self,
+        vk: &Vk,
+        index: usize,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<()> {
+        if self.readback_fits(index, extent, format) {
+            return Ok(());
+        }
+
+        let slot = &mut self.slots[index];
+        // dropped first, so the old and new buffer are never alive at once
+        slot.readback = None;
+        let readback = capture_size(extent, format)
+            .ok_or(RenderError::UnsupportedFormat(format))
+            .and_then(|size| {
+                GpuBuffer::new(
+                    vk,
+                    &format!("frame capture {}", index),
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    MemoryLocation::Readback,
+                )
+            });
+
+        match readback {
+            Ok(readback) => {
+                slot.readback = Some(readback);
+                Ok(())
+            }
+            Err(e) => {
+                self.capture_request = None;
+                self.sequence = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether the readback buffer of slot `index` can hold a capture of `extent` texels of
+    /// `format`.
+    fn readback_fits(&self, index: usize, extent: vk::Extent2D, format: vk::Format) -> bool {
+        match (&self.slots[index].readback, capture_size(extent, format)) {
+            (Some(readback), Some(size)) => readback.get_size() >= size,
+            _ => false,
+        }
+    }
+
+    /// Records copying the swapchain image of `frame` into the readback buffer of its slot,
+    /// leaving the image in `TRANSFER_SRC_OPTIMAL`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slot has no readback buffer, see [`FrameLoop::prepare_capture`].
+    fn record_capture(&mut self, frame: &Frame, path: PathBuf) {
+        let device = &self.device;
+        let slot = &mut self.slots[frame.slot];
+        let buffer = slot
+            .readback
+            .as_ref()
+            .expect("The readback buffer is prepared when the frame begins")
+            .get_buffer();
+
+        let to_transfer = [image_barrier(
+            frame.image,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags2::COPY,
+            vk::AccessFlags2::TRANSFER_READ,
+        )];
+        let region = [*vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: frame.extent.width,
+                height: frame.extent.height,
+                depth: 1,
+            })];
+        let to_host = [*vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)];
+
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                frame.cmd,
+                &vk::DependencyInfo::builder().image_memory_barriers(&to_transfer),
+            );
+            device.cmd_copy_image_to_buffer(
+                frame.cmd,
+                frame.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer,
+                &region,
+            );
+            device.cmd_pipeline_barrier2(
+                frame.cmd,
+                &vk::DependencyInfo::builder().buffer_memory_barriers(&to_host),
+            );
+        }
+
+        slot.pending_capture = Some(PendingCapture {
+            path,
+            extent: frame.extent,
+            format: frame.format,
+        });
+    }
+
+    /// Saves the capture the last frame of slot `index` made, the slot has to be free.
+    fn save_capture(&mut self, index: usize) -> Result<()> {
+        let slot = &mut self.slots[index];
+        let (Some(capture), Some(readback)) = (slot.pending_capture.take(), &slot.readback) else {
+            return Ok(());
+        };
+
+        let texel_size = format_texel_size(capture.format)
+            .ok_or(RenderError::UnsupportedFormat(capture.format))?;
+        let size =
+            capture.extent.width as usize * capture.extent.height as usize * texel_size as usize;
+        let pixels = to_rgba8(capture.format, &readback.read(0, size)?)?;
+        save_png(&capture.path, capture.extent, &pixels)?;
+        debug!("Captured frame to {}", capture.path.display());
+
+        Ok(())
+    }
+
+    /// Returns the semaphore signaled when rendering to `image_index` is done, creating semaphores
+    /// for images a recreated swapchain added.
+    fn get_render_finished(&mut self, vk: &Vk, image_index: u32) -> Result<vk::Semaphore> {
+        while self.render_finished.len() <= image_index as usize {
+            let semaphore = unsafe {
+                self.device
+                    .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)?
+            };
+            vk.set_debug_name(
+                semaphore,
+                &format!("render finished {}", self.render_finished.len()),
+            );
+            self.render_finished.push(semaphore);
+        }
+
+        Ok(self.render_finished[image_index as usize])
+    }
+}
+
+/// The bytes a capture of `extent` texels of `format` takes, `None` if the format can not be
+/// captured.
+fn capture_size(extent: vk::Extent2D, format: vk::Format) -> Option<vk::DeviceSize> {
+    let texel_size = format_texel_size(format)?;
+
+    Some(
+        extent.width as vk::DeviceSize
+            * extent.height as vk::DeviceSize
+            * texel_size as vk::DeviceSize,
+    )
+}
+
+impl Drop for FrameLoop {
+    fn drop(&mut self) {
+        unsafe {
+            // nothing sensible can be done about a lost device while dropping, and presenting
+            // may still wait on the semaphores
+            let _ = self.device.device_wait_idle();
+        }
+
+        for index in 0..self.slots.len() {
+            if let Err(e) = self.save_capture(index) {
+                warn!("Could not save frame capture: {}", e);
+            }
+        }
+
+        unsafe {
+            for slot in self.slots.drain(..) {
+                self.device.destroy_fence(slot.in_flight, None);
+                self.device.destroy_semaphore(slot.image_available, None);
+                self.device.destroy_command_pool(slot.command_pool, None);
+            }
+            for semaphore in self.render_finished.drain(..) {
+                self.device.destroy_semaphore(semaphore, None);
+            }
+        }
+    }
+}
+
+impl Frame<'_> {
+    /// Begins dynamic rendering into the swapchain image, clearing it to `clear_color`.
+    pub fn begin_rendering(&self, clear_color: [f32; 4]) {
+        self.begin(clear_color, None);
+    }
+
+    /// Like [`Frame::begin_rendering`], with `depth` as the depth attachment cleared to 1.0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is not the size of the swapchain image.
+    pub fn begin_rendering_with_depth(&self, clear_color: [f32; 4], depth: &DepthBuffer) {
+        assert_eq!(
+            depth.get_extent(),
+            self.extent,
+            "The depth buffer has to be resized with the swapchain"
+        );
+
+        self.begin(clear_color, Some(depth));
+    }
+
+    fn begin(&self, clear_color: [f32; 4], depth: Option<&DepthBuffer>) {
+        let color_attachments = [*vk::RenderingAttachmentInfo::builder()
+            .image_view(self.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color,
+                },
+            })];
+
+        let mut rendering_info = vk::RenderingInfo::builder()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent,
+            })
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        let depth_attachment;
+        if let Some(depth) = depth {
+            depth_attachment = vk::RenderingAttachmentInfo::builder()
+                .image_view(depth.get_view())
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 1.,
+                        stencil: 0,
+                    },
+                });
+            rendering_info = rendering_info.depth_attachment(&depth_attachment);
+
+            let to_attachment = [depth.get_begin_barrier()];
+            let dependency_info =
+                vk::DependencyInfo::builder().image_memory_barriers(&to_attachment);
+            unsafe {
+                self.device
+                    .cmd_pipeline_barrier2(self.cmd, &dependency_info)
+            };
+        }
+
+        unsafe { self.device.cmd_begin_rendering(self.cmd, &rendering_info) };
+    }
+
+    pub fn end_rendering(&self) {
+        unsafe { self.device.cmd_end_rendering(self.cmd) };
+    }
+
+    /// Makes the submit of the frame wait on `wait`, e.g. the semaphore returned by
+    /// [`crate::upload::UploadManager::record_acquires`].
+    pub fn wait_semaphore(&mut self, wait: vk::SemaphoreSubmitInfo) {
+        self.wait_semaphores.push(wait);
+    }
+
+    pub fn get_command_buffer(&self) -> vk::CommandBuffer {
+        self.cmd
+    }
+
+    pub fn get_image_index(&self) -> u32 {
+        self.image_index
+    }
+
+    pub fn get_image(&self) -> vk::Image {
+        self.image
+    }
+
+    pub fn get_image_view(&self) -> vk::ImageView {
+        self.image_view
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Which of the frames in flight this is, for indexing per frame resources.
+    pub fn get_frame_index(&self) -> usize {
+        self.slot
+    }
+
+    pub fn get_frame_number(&self) -> u64 {
+        self.frame_number
+    }
+}
+
+fn get_swapchain(vk: &Vk) -> Result<&Swapchain> {
+    vk.get_swapchain().ok_or(RenderError::SurfaceCreationFailed(
+        vk::Result::ERROR_SURFACE_LOST_KHR,
+    ))
+}
+
+fn create_slot(vk: &Vk, index: usize) -> Result<FrameSlot> {
+    let device = vk.get_device();
+
+    let pool_info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(vk.get_queues().graphics.family);
+
+    let alloc_info = |command_pool| {
+        *vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(command_pool)
+            .command_buffer_count(1)
+    };
+
+    // signaled, so the first wait on a slot returns immediately
+    let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+
+    unsafe {
+        let command_pool = device.create_command_pool(&pool_info, None)?;
+        let cmd = device.allocate_command_buffers(&alloc_info(command_pool))?[0];
+        let image_available = device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)?;
+        let in_flight = device.create_fence(&fence_info, None)?;
+
+        vk.set_debug_name(cmd, &format!("frame {}", index));
+        vk.set_debug_name(image_available, &format!("image available {}", index));
+        debug!("Created frame slot {}", index);
+
+        Ok(FrameSlot {
+            command_pool,
+            cmd,
+            image_available,
+            in_flight,
+            readback: None,
+            pending_capture: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_readback_drops_the_capture_and_keeps_the_slot_free() {
+        let vk = Vk::headless().expect("Could not create headless Vulkan device");
+        let mut frame_loop = FrameLoop::new(&vk, 1).expect("Could not create frame loop");
+        frame_loop.capture_frame("never-saved.png");
+
+        // 4 EiB, more than any heap holds
+        let extent = vk::Extent2D {
+            width: 1 << 30,
+            height: 1 << 30,
+        };
+        let prepared = frame_loop.prepare_capture(&vk, 0, extent, vk::Format::B8G8R8A8_SRGB);
+
+        assert!(prepared.is_err(), "The readback buffer should not fit");
+        assert!(frame_loop.capture_request.is_none());
+        assert!(frame_loop.slots[0].readback.is_none());
+        // the next begin would wait forever on an unsignaled fence
+        let signaled = unsafe {
+            vk.get_device()
+                .get_fence_status(frame_loop.slots[0].in_flight)
+        };
+        assert_eq!(signaled, Ok(true));
+    }
+}
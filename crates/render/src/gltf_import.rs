@@ -0,0 +1,450 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use ash::vk;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use glam::Mat4;
+use gltf::{
+    buffer,
+    camera::Projection as GltfProjection,
+    image,
+    khr_lights_punctual::Kind,
+    material::AlphaMode as GltfAlphaMode,
+    mesh::Mode,
+    texture::{MagFilter, MinFilter, WrappingMode},
+    Document, Gltf,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    error::{RenderError, Result},
+    sampler::SamplerDesc,
+    scene::{
+        AlphaMode, Camera, Light, LightKind, Material, Mesh, Node, Primitive, Projection, Scene,
+        SceneTexture,
+    },
+    texture::{ColorSpace, TextureData},
+    vertex::MeshVertex,
+};
+
+/// Imports the `.gltf` or `.glb` file at `path`, resolving external buffers and images relative
+/// to it.
+///
+/// # Errors
+///
+/// Returns [`RenderError::ModelLoad`] if the file or anything it refers to could not be read or
+/// is invalid, and [`RenderError::ImageLoad`] if an image could not be decoded.
+pub fn load(path: impl AsRef<Path>) -> Result<Scene> {
+    let path = path.as_ref();
+
+    Importer {
+        name: path.display().to_string(),
+        base: path.parent().map(Path::to_path_buf),
+    }
+    .import(&std::fs::read(path)?)
+}
+
+/// Imports a `.gltf` or `.glb` file in memory. Buffers and images have to be embedded, as there
+/// is no directory to resolve other files in.
+///
+/// # Errors
+///
+/// Returns [`RenderError::ModelLoad`] if `bytes` are not valid glTF or refer to external files,
+/// and [`RenderError::ImageLoad`] if an image could not be decoded.
+pub fn from_memory(bytes: &[u8]) -> Result<Scene> {
+    Importer {
+        name: "in memory".to_owned(),
+        base: None,
+    }
+    .import(bytes)
+}
+
+struct Importer {
+    name: String,
+    /// The directory relative URIs are resolved in.
+    base: Option<PathBuf>,
+}
+
+impl Importer {
+    fn error(&self, message: impl ToString) -> RenderError {
+        RenderError::ModelLoad {
+            name: self.name.clone(),
+            message: message.to_string(),
+        }
+    }
+
+    fn import(&self, bytes: &[u8]) -> Result<Scene> {
+        let Gltf { document, blob } = Gltf::from_slice(bytes).map_err(|e| self.error(e))?;
+
+        let buffers = document
+            .buffers()
+            .map(|buffer| match buffer.source() {
+                buffer::Source::Bin => blob
+                    .clone()
+                    .ok_or_else(|| self.error("binary chunk is missing")),
+                buffer::Source::Uri(uri) => self.read_uri(uri),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| {
+                let primitives = mesh
+                    .primitives()
+                    .filter_map(|primitive| self.import_primitive(&primitive, &buffers))
+                    .collect();
+
+                Mesh {
+                    name: mesh.name().map(str::to_owned),
+                    primitives,
+                }
+            })
+            .collect();
+
+        let scene = Scene {
+            meshes,
+            materials: document.materials().map(import_material).collect(),
+            textures: self.import_textures(&document, &buffers)?,
+            nodes: document.nodes().map(import_node).collect(),
+            roots: document
+                .default_scene()
+                .or_else(|| document.scenes().next())
+                .map_or_else(Vec::new, |scene| {
+                    scene.nodes().map(|node| node.index()).collect()
+                }),
+            cameras: document.cameras().map(import_camera).collect(),
+            lights: document
+                .lights()
+                .map_or_else(Vec::new, |lights| lights.map(import_light).collect()),
+        };
+        debug!(
+            "Imported {} with {} meshes, {} materials and {} nodes",
+            self.name,
+            scene.meshes.len(),
+            scene.materials.len(),
+            scene.nodes.len()
+        );
+
+        Ok(scene)
+    }
+
+    /// Reads a `data:` URI or a file relative to the imported file.
+    fn read_uri(&self, uri: &str) -> Result<Vec<u8>> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let Some((_, encoded)) = data.split_once(";base64,") else {
+                return Err(self.error("data URIs have to be base64 encoded"));
+            };
+
+            return STANDARD.decode(encoded).map_err(|e| self.error(e));
+        }
+
+        let Some(base) = &self.base else {
+            return Err(self.error(format!("can not resolve {} without a directory", uri)));
+        };
+
+        Ok(std::fs::read(base.join(percent_decode(uri)))?)
+    }
+
+    /// Converts a primitive into our vertex layout, `None` for points and lines, which are
+    /// skipped.
+    fn import_primitive(
+        &self,
+        primitive: &gltf::Primitive,
+        buffers: &[Vec<u8>],
+    ) -> Option<Primitive> {
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let Some(positions) = reader.read_positions() else {
+            warn!("Skipping primitive without positions in {}", self.name);
+            return None;
+        };
+
+        let mut vertices: Vec<MeshVertex> = positions
+            .map(|position| MeshVertex::new(position, [0.; 3], [0.; 2]))
+            .collect();
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                vertex.uv = uv;
+            }
+        }
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        let indices = match primitive.mode() {
+            Mode::Triangles => indices,
+            Mode::TriangleStrip => indices
+                .windows(3)
+                .enumerate()
+                .flat_map(|(i, w)| {
+                    // every other triangle is wound the other way round
+                    if i % 2 == 0 {
+                        [w[0], w[1], w[2]]
+                    } else {
+                        [w[1], w[0], w[2]]
+                    }
+                })
+                .collect(),
+            Mode::TriangleFan => indices
+                .windows(2)
+                .skip(1)
+                .flat_map(|w| [indices[0], w[0], w[1]])
+                .collect(),
+            mode => {
+                warn!("Skipping {:?} primitive in {}", mode, self.name);
+                return None;
+            }
+        };
+        if indices
+            .iter()
+            .any(|&index| index as usize >= vertices.len())
+        {
+            warn!(
+                "Skipping primitive with out of bounds indices in {}",
+                self.name
+            );
+            return None;
+        }
+
+        let mut primitive_data = Primitive {
+            vertices,
+            indices,
+            material: primitive.material().index(),
+        };
+
+        match reader.read_normals() {
+            Some(normals) => {
+                for (vertex, normal) in primitive_data.vertices.iter_mut().zip(normals) {
+                    vertex.normal = normal;
+                }
+            }
+            None => primitive_data.generate_normals(),
+        }
+        match reader.read_tangents() {
+            Some(tangents) => {
+                for (vertex, tangent) in primitive_data.vertices.iter_mut().zip(tangents) {
+                    vertex.tangent = tangent;
+                }
+            }
+            None if primitive.material().normal_texture().is_some() => {
+                primitive_data.generate_tangents();
+            }
+            None => {}
+        }
+
+        Some(primitive_data)
+    }
+
+    /// Decodes the image of every texture, in sRGB if a material uses it for colors.
+    fn import_textures(
+        &self,
+        document: &Document,
+        buffers: &[Vec<u8>],
+    ) -> Result<Vec<SceneTexture>> {
+        let srgb: HashSet<usize> = document
+            .materials()
+            .flat_map(|material| {
+                [
+                    material.pbr_metallic_roughness().base_color_texture(),
+                    material.emissive_texture(),
+                ]
+            })
+            .flatten()
+            .map(|info| info.texture().index())
+            .collect();
+
+        document
+            .textures()
+            .map(|texture| {
+                let image = texture.source();
+                let bytes = match image.source() {
+                    image::Source::View { view, .. } => {
+                        let buffer = &buffers[view.buffer().index()];
+                        buffer
+                            .get(view.offset()..view.offset() + view.length())
+                            .ok_or_else(|| self.error("buffer view is out of bounds"))?
+                            .to_vec()
+                    }
+                    image::Source::Uri { uri, .. } => self.read_uri(uri)?,
+                };
+
+                let color_space = if srgb.contains(&texture.index()) {
+                    ColorSpace::Srgb
+                } else {
+                    ColorSpace::Linear
+                };
+                let data = TextureData::from_memory(&bytes, color_space).map_err(|e| match e {
+                    RenderError::ImageLoad { message, .. } => RenderError::ImageLoad {
+                        name: format!("{} image {}", self.name, image.index()),
+                        message,
+                    },
+                    e => e,
+                })?;
+
+                Ok(SceneTexture {
+                    name: texture.name().or(image.name()).map(str::to_owned),
+                    data,
+                    sampler: import_sampler(&texture.sampler()),
+                })
+            })
+            .collect()
+    }
+}
+
+fn import_material(material: gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let defaults = Material::default();
+
+    Material {
+        name: material.name().map(str::to_owned),
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|info| info.texture().index()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|info| info.texture().index()),
+        normal_texture: material
+            .normal_texture()
+            .map(|normal| normal.texture().index()),
+        normal_scale: material
+            .normal_texture()
+            .map_or(defaults.normal_scale, |normal| normal.scale()),
+        occlusion_texture: material
+            .occlusion_texture()
+            .map(|occlusion| occlusion.texture().index()),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map_or(defaults.occlusion_strength, |occlusion| {
+                occlusion.strength()
+            }),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: material
+            .emissive_texture()
+            .map(|info| info.texture().index()),
+        alpha_mode: match material.alpha_mode() {
+            GltfAlphaMode::Opaque => AlphaMode::Opaque,
+            GltfAlphaMode::Mask => AlphaMode::Mask {
+                cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            },
+            GltfAlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn import_sampler(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let address_mode = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+    // unset filters are up to the implementation, so use the best
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR)
+        }
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::LinearMipmapLinear) | None => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR)
+        }
+    };
+
+    SamplerDesc {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            Some(MagFilter::Linear) | None => vk::Filter::LINEAR,
+        },
+        min_filter,
+        mipmap_mode,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+    }
+}
+
+fn import_node(node: gltf::Node) -> Node {
+    Node {
+        name: node.name().map(str::to_owned),
+        transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
+        children: node.children().map(|child| child.index()).collect(),
+        mesh: node.mesh().map(|mesh| mesh.index()),
+        camera: node.camera().map(|camera| camera.index()),
+        light: node.light().map(|light| light.index()),
+    }
+}
+
+fn import_camera(camera: gltf::Camera) -> Camera {
+    let projection = match camera.projection() {
+        GltfProjection::Perspective(perspective) => Projection::Perspective {
+            yfov: perspective.yfov(),
+            aspect_ratio: perspective.aspect_ratio(),
+            znear: perspective.znear(),
+            zfar: perspective.zfar(),
+        },
+        GltfProjection::Orthographic(orthographic) => Projection::Orthographic {
+            xmag: orthographic.xmag(),
+            ymag: orthographic.ymag(),
+            znear: orthographic.znear(),
+            zfar: orthographic.zfar(),
+        },
+    };
+
+    Camera {
+        name: camera.name().map(str::to_owned),
+        projection,
+    }
+}
+
+fn import_light(light: gltf::khr_lights_punctual::Light) -> Light {
+    Light {
+        name: light.name().map(str::to_owned),
+        kind: match light.kind() {
+            Kind::Directional => LightKind::Directional,
+            Kind::Point => LightKind::Point,
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        },
+        color: light.color(),
+        intensity: light.intensity(),
+        range: light.range(),
+    }
+}
+
+/// Undoes the `%XX` escapes of a relative URI, e.g. `%20` for spaces in file names.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,1084 @@
+use std::collections::HashSet;
+
+use ash::{vk, Device};
+use tracing::debug;
+
+use crate::{
+    error::Result,
+    memory::{GpuImage, MemoryLocation},
+    profile::GpuProfiler,
+    utils::aspect_of,
+    Frame, Vk,
+};
+
+/// How a pass uses an image or buffer, which decides the pipeline stages, access flags and image
+/// layout the graph synchronizes for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    ColorAttachment,
+    DepthAttachment,
+    /// Depth testing without depth writes.
+    DepthRead,
+    /// Sampled in a fragment or compute shader.
+    Sampled,
+    /// Storage image or buffer read in a fragment or compute shader.
+    StorageRead,
+    /// Storage image or buffer written in a fragment or compute shader.
+    StorageWrite,
+    TransferRead,
+    TransferWrite,
+    VertexBuffer,
+    IndexBuffer,
+    Uniform,
+    Indirect,
+    Present,
+}
+
+impl Access {
+    pub fn get_stage(self) -> vk::PipelineStageFlags2 {
+        match self {
+            Access::ColorAttachment => vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            Access::DepthAttachment | Access::DepthRead => {
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+            }
+            Access::Sampled | Access::StorageRead | Access::StorageWrite => {
+                vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER
+            }
+            Access::TransferRead | Access::TransferWrite => vk::PipelineStageFlags2::ALL_TRANSFER,
+            Access::VertexBuffer => vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+            Access::IndexBuffer => vk::PipelineStageFlags2::INDEX_INPUT,
+            Access::Uniform => {
+                vk::PipelineStageFlags2::VERTEX_SHADER
+                    | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags2::COMPUTE_SHADER
+            }
+            Access::Indirect => vk::PipelineStageFlags2::DRAW_INDIRECT,
+            Access::Present => vk::PipelineStageFlags2::NONE,
+        }
+    }
+
+    pub fn get_access(self) -> vk::AccessFlags2 {
+        match self {
+            Access::ColorAttachment => {
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+            }
+            Access::DepthAttachment => {
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            Access::DepthRead => vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            Access::Sampled => vk::AccessFlags2::SHADER_SAMPLED_READ,
+            Access::StorageRead => vk::AccessFlags2::SHADER_STORAGE_READ,
+            Access::StorageWrite => {
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE
+            }
+            Access::TransferRead => vk::AccessFlags2::TRANSFER_READ,
+            Access::TransferWrite => vk::AccessFlags2::TRANSFER_WRITE,
+            Access::VertexBuffer => vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+            Access::IndexBuffer => vk::AccessFlags2::INDEX_READ,
+            Access::Uniform => vk::AccessFlags2::UNIFORM_READ,
+            Access::Indirect => vk::AccessFlags2::INDIRECT_COMMAND_READ,
+            Access::Present => vk::AccessFlags2::NONE,
+        }
+    }
+
+    /// The layout an image has to be in for this access. Depth uses the combined depth stencil
+    /// layouts, which need no `separateDepthStencilLayouts`.
+    pub fn get_layout(self) -> vk::ImageLayout {
+        match self {
+            Access::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Access::DepthAttachment => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            Access::DepthRead => vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            Access::Sampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            Access::TransferRead => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            Access::TransferWrite => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            Access::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+            _ => vk::ImageLayout::GENERAL,
+        }
+    }
+
+    /// The usage an image needs to be created with for this access.
+    pub fn get_image_usage(self) -> vk::ImageUsageFlags {
+        match self {
+            Access::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Access::DepthAttachment | Access::DepthRead => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            Access::Sampled => vk::ImageUsageFlags::SAMPLED,
+            Access::StorageRead | Access::StorageWrite => vk::ImageUsageFlags::STORAGE,
+            Access::TransferRead => vk::ImageUsageFlags::TRANSFER_SRC,
+            Access::TransferWrite => vk::ImageUsageFlags::TRANSFER_DST,
+            _ => vk::ImageUsageFlags::empty(),
+        }
+    }
+
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            Access::ColorAttachment
+                | Access::DepthAttachment
+                | Access::StorageWrite
+                | Access::TransferWrite
+        )
+    }
+}
+
+/// An image declared in a [`RenderGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+/// A buffer declared in a [`RenderGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+/// An image only living for the duration of a graph, created and reused by a [`TransientPool`].
+///
+/// The usage is derived from how the passes access the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+/// An image owned outside the graph, e.g. a swapchain image.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+/// What happens to an attachment when a pass begins rendering.
+#[derive(Clone, Copy)]
+pub enum LoadOp {
+    Clear(vk::ClearValue),
+    Load,
+    DontCare,
+}
+
+enum ImageSource {
+    Imported(ImportedImage),
+    Transient(TransientImageDesc),
+}
+
+struct ImageResource {
+    name: String,
+    source: ImageSource,
+    /// How the image was accessed before the graph, `None` if its contents do not matter.
+    initial: Option<Access>,
+    /// The access the image is transitioned to after the graph.
+    last: Option<Access>,
+    usage: vk::ImageUsageFlags,
+}
+
+struct BufferResource {
+    buffer: vk::Buffer,
+    initial: Option<Access>,
+}
+
+struct Attachment {
+    image: ImageHandle,
+    load: LoadOp,
+}
+
+/// Records the commands of a pass, looking up the resources it declared in the [`PassContext`].
+type RecordFn<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+struct Pass<'a> {
+    name: String,
+    images: Vec<(ImageHandle, Access)>,
+    buffers: Vec<(BufferHandle, Access)>,
+    color_attachments: Vec<Attachment>,
+    depth_attachment: Option<Attachment>,
+    side_effects: bool,
+    record: Option<RecordFn<'a>>,
+}
+
+/// A frame worth of passes that declare the images and buffers they read and write.
+///
+/// From the declarations the graph culls passes whose results are never used, inserts the
+/// synchronization2 barriers and layout transitions between passes and places transient images in
+/// a [`TransientPool`], letting images whose lifetimes do not overlap share memory. Passes with
+/// attachments are wrapped in dynamic rendering.
+///
+/// Passes run in the order they were added. A pass is kept if it writes an imported resource, is
+/// marked with [`PassBuilder::side_effects`], or writes something a kept pass reads.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<ImageResource>,
+    buffers: Vec<BufferResource>,
+    passes: Vec<Pass<'a>>,
+}
+
+/// Declares the resources of one pass, added to the graph with [`PassBuilder::execute`].
+#[must_use = "the pass is only added by PassBuilder::execute"]
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: Pass<'a>,
+}
+
+/// Passed to the record function of a pass, which is called between the barriers of the pass and,
+/// if it has attachments, inside dynamic rendering.
+pub struct PassContext<'r> {
+    device: &'r Device,
+    cmd: vk::CommandBuffer,
+    images: &'r [PhysicalImage],
+    buffers: &'r [vk::Buffer],
+    render_area: vk::Extent2D,
+}
+
+#[derive(Clone, Copy)]
+struct PhysicalImage {
+    image: vk::Image,
+    view: vk::ImageView,
+    format: vk::Format,
+    extent: vk::Extent2D,
+}
+
+/// The barrier needed before an access, with the layout transition for images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Barrier {
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    pub dst_stage: vk::PipelineStageFlags2,
+    pub dst_access: vk::AccessFlags2,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+/// What has happened to an image or buffer since it was last written, which decides the barrier
+/// the next access needs.
+///
+/// Reads only wait on the last write, so a read is merged with earlier reads without a barrier if
+/// the write was already made visible to its stages and accesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceState {
+    layout: vk::ImageLayout,
+    /// Stages of the last write or layout transition, empty if the contents were made visible
+    /// before the graph.
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    /// Stages and accesses the last write has been made visible to.
+    visible_stage: vk::PipelineStageFlags2,
+    visible_access: vk::AccessFlags2,
+    /// Stages that read since the last write, which the next write has to wait for.
+    read_stage: vk::PipelineStageFlags2,
+}
+
+impl ResourceState {
+    /// The state of a resource last accessed as `initial` before the graph, `None` if its
+    /// contents do not matter. A write there is waited on by the first access, while a read means
+    /// earlier writes are already visible.
+    pub fn new(initial: Option<Access>) -> Self {
+        let mut state = Self {
+            layout: vk::ImageLayout::UNDEFINED,
+            write_stage: vk::PipelineStageFlags2::NONE,
+            write_access: vk::AccessFlags2::NONE,
+            visible_stage: vk::PipelineStageFlags2::NONE,
+            visible_access: vk::AccessFlags2::NONE,
+            read_stage: vk::PipelineStageFlags2::NONE,
+        };
+
+        if let Some(access) = initial {
+            state.layout = access.get_layout();
+            if access.is_write() {
+                state.write_stage = access.get_stage();
+                state.write_access = access.get_access();
+            } else {
+                state.read_stage = access.get_stage();
+            }
+        }
+
+        state
+    }
+
+    pub fn get_layout(&self) -> vk::ImageLayout {
+        self.layout
+    }
+
+    /// Moves an image to `access`, returning the barrier that is needed first, if any.
+    ///
+    /// Without `keep_contents` the old layout is undefined, so the contents may be discarded.
+    pub fn transition(&mut self, access: Access, keep_contents: bool) -> Option<Barrier> {
+        self.transition_to(access, access.get_layout(), keep_contents)
+    }
+
+    /// Moves a buffer to `access` like [`ResourceState::transition`], ignoring layouts as buffers
+    /// have none.
+    pub fn transition_buffer(&mut self, access: Access) -> Option<Barrier> {
+        self.transition_to(access, self.layout, true)
+    }
+
+    fn transition_to(
+        &mut self,
+        access: Access,
+        layout: vk::ImageLayout,
+        keep_contents: bool,
+    ) -> Option<Barrier> {
+        let (stage, access_flags) = (access.get_stage(), access.get_access());
+
+        if !access.is_write() && layout == self.layout {
+            let visible = self.write_stage.is_empty()
+                || (self.visible_stage.contains(stage)
+                    && self.visible_access.contains(access_flags));
+            let barrier = (!visible).then(|| Barrier {
+                // waiting on earlier barriers chains them to the last write
+                src_stage: self.write_stage | self.visible_stage,
+                src_access: self.write_access,
+                dst_stage: stage,
+                dst_access: access_flags,
+                old_layout: layout,
+                new_layout: layout,
+            });
+
+            if !self.write_stage.is_empty() {
+                self.visible_stage |= stage;
+                self.visible_access |= access_flags;
+            }
+            self.read_stage |= stage;
+
+            return barrier;
+        }
+
+        // writes and layout transitions wait on every access since the last write as well
+        let barrier = Barrier {
+            src_stage: self.write_stage | self.visible_stage | self.read_stage,
+            src_access: self.write_access,
+            dst_stage: stage,
+            dst_access: access_flags,
+            old_layout: if keep_contents {
+                self.layout
+            } else {
+                vk::ImageLayout::UNDEFINED
+            },
+            new_layout: layout,
+        };
+
+        self.layout = layout;
+        if access.is_write() {
+            self.write_stage = stage;
+            self.write_access = access_flags;
+            self.visible_stage = vk::PipelineStageFlags2::NONE;
+            self.visible_access = vk::AccessFlags2::NONE;
+            self.read_stage = vk::PipelineStageFlags2::NONE;
+        } else {
+            // later reads in other stages have to wait on the transition
+            self.write_stage |= stage;
+            self.visible_stage = stage;
+            self.visible_access = access_flags;
+            self.read_stage = stage;
+        }
+
+        Some(barrier)
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares an image owned outside the graph, which is in the state of `initial` before the
+    /// graph runs and transitioned to `last` after it.
+    ///
+    /// An `initial` of `None` means the contents can be discarded.
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: ImportedImage,
+        initial: Option<Access>,
+        last: Access,
+    ) -> ImageHandle {
+        self.images.push(ImageResource {
+            name: name.to_owned(),
+            source: ImageSource::Imported(image),
+            initial,
+            last: Some(last),
+            usage: vk::ImageUsageFlags::empty(),
+        });
+
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// Declares the swapchain image of `frame`, which [`crate::FrameLoop`] hands out and takes
+    /// back as a color attachment.
+    pub fn import_frame(&mut self, frame: &Frame) -> ImageHandle {
+        let image = ImportedImage {
+            image: frame.get_image(),
+            view: frame.get_image_view(),
+            format: frame.get_format(),
+            extent: frame.get_extent(),
+        };
+
+        self.import_image(
+            "swapchain",
+            image,
+            Some(Access::ColorAttachment),
+            Access::ColorAttachment,
+        )
+    }
+
+    /// Declares an image that is created by the graph and only lives until it has run.
+    pub fn create_image(&mut self, name: &str, desc: TransientImageDesc) -> ImageHandle {
+        self.images.push(ImageResource {
+            name: name.to_owned(),
+            source: ImageSource::Transient(desc),
+            initial: None,
+            last: None,
+            usage: vk::ImageUsageFlags::empty(),
+        });
+
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// Declares a buffer owned outside the graph, last accessed as `initial` before the graph
+    /// runs.
+    pub fn import_buffer(&mut self, buffer: vk::Buffer, initial: Option<Access>) -> BufferHandle {
+        self.buffers.push(BufferResource { buffer, initial });
+
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Starts declaring a pass called `name`.
+    pub fn add_pass<'g>(&'g mut self, name: &str) -> PassBuilder<'g, 'a> {
+        PassBuilder {
+            graph: self,
+            pass: Pass {
+                name: name.to_owned(),
+                images: vec![],
+                buffers: vec![],
+                color_attachments: vec![],
+                depth_attachment: None,
+                side_effects: false,
+                record: None,
+            },
+        }
+    }
+
+    /// Records every pass that contributes to the result into `cmd`, together with the barriers
+    /// between them, taking transient images from `pool`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transient image could not be created.
+    pub fn execute(self, vk: &Vk, pool: &mut TransientPool, cmd: vk::CommandBuffer) -> Result<()> {
+        self.record(vk, pool, cmd, None)
+    }
+
+    /// Like [`RenderGraph::execute`], measuring every pass, with its barriers, in a GPU scope
+    /// named after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transient image could not be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no frame was begun with [`GpuProfiler::begin_frame`] on a device with timestamps.
+    pub fn execute_profiled(
+        self,
+        vk: &Vk,
+        pool: &mut TransientPool,
+        cmd: vk::CommandBuffer,
+        profiler: &mut GpuProfiler,
+    ) -> Result<()> {
+        self.record(vk, pool, cmd, Some(profiler))
+    }
+
+    fn record(
+        self,
+        vk: &Vk,
+        pool: &mut TransientPool,
+        cmd: vk::CommandBuffer,
+        mut profiler: Option<&mut GpuProfiler>,
+    ) -> Result<()> {
+        let device = vk.get_device();
+        let kept = self.cull();
+
+        let images = self.place_images(vk, pool, &kept)?;
+        let buffers: Vec<vk::Buffer> = self.buffers.iter().map(|buffer| buffer.buffer).collect();
+
+        let mut image_states: Vec<ResourceState> = self
+            .images
+            .iter()
+            .map(|image| ResourceState::new(image.initial))
+            .collect();
+        let mut buffer_states: Vec<ResourceState> = self
+            .buffers
+            .iter()
+            .map(|buffer| ResourceState::new(buffer.initial))
+            .collect();
+        // aliased transient images share the state of the memory they live in
+        let mut physical_states = vec![ResourceState::new(None); pool.images.len()];
+
+        let mut passes = self.passes;
+        for (index, pass) in passes.iter_mut().enumerate() {
+            if !kept[index] {
+                debug!("Culled pass {}", pass.name);
+                continue;
+            }
+            if let Some(profiler) = profiler.as_deref_mut() {
+                profiler.begin_scope(cmd, &pass.name);
+            }
+
+            let mut image_barriers = vec![];
+            for &(handle, access) in &pass.images {
+                let resource = &self.images[handle.0];
+                let state = match images.slots[handle.0] {
+                    Slot::Own => &mut image_states[handle.0],
+                    Slot::Pooled(slot) => &mut physical_states[slot],
+                };
+
+                // transient images start out undefined, whatever lived in their memory before
+                let keep_contents = images.first_use[handle.0] != Some(index)
+                    || matches!(resource.source, ImageSource::Imported(_));
+                if let Some(barrier) = state.transition(access, keep_contents) {
+                    image_barriers.push(image_barrier(&images.physical[handle.0], barrier));
+                }
+            }
+
+            let mut buffer_barriers = vec![];
+            for &(handle, access) in &pass.buffers {
+                let state = &mut buffer_states[handle.0];
+
+                if let Some(barrier) = state.transition_buffer(access) {
+                    buffer_barriers.push(
+                        *vk::BufferMemoryBarrier2::builder()
+                            .src_stage_mask(barrier.src_stage)
+                            .src_access_mask(barrier.src_access)
+                            .dst_stage_mask(barrier.dst_stage)
+                            .dst_access_mask(barrier.dst_access)
+                            .buffer(buffers[handle.0])
+                            .offset(0)
+                            .size(vk::WHOLE_SIZE),
+                    );
+                }
+            }
+
+            if !image_barriers.is_empty() || !buffer_barriers.is_empty() {
+                let dependency_info = vk::DependencyInfo::builder()
+                    .image_memory_barriers(&image_barriers)
+                    .buffer_memory_barriers(&buffer_barriers);
+                unsafe { device.cmd_pipeline_barrier2(cmd, &dependency_info) };
+            }
+
+            let rendering = !pass.color_attachments.is_empty() || pass.depth_attachment.is_some();
+            let render_area = pass
+                .color_attachments
+                .iter()
+                .chain(&pass.depth_attachment)
+                .map(|attachment| images.physical[attachment.image.0].extent)
+                .next()
+                .unwrap_or_default();
+
+            if rendering {
+                begin_rendering(device, cmd, pass, &images.physical, render_area);
+            }
+
+            if let Some(record) = pass.record.take() {
+                record(&PassContext {
+                    device,
+                    cmd,
+                    images: &images.physical,
+                    buffers: &buffers,
+                    render_area,
+                });
+            }
+
+            if rendering {
+                unsafe { device.cmd_end_rendering(cmd) };
+            }
+            if let Some(profiler) = profiler.as_deref_mut() {
+                profiler.end_scope(cmd);
+            }
+        }
+
+        // hand imported images back in the state they are expected in
+        let final_barriers: Vec<vk::ImageMemoryBarrier2> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter_map(|(i, image)| {
+                let last = image.last?;
+                let barrier = image_states[i].transition(last, true)?;
+
+                Some(image_barrier(&images.physical[i], barrier))
+            })
+            .collect();
+        if !final_barriers.is_empty() {
+            let dependency_info =
+                vk::DependencyInfo::builder().image_memory_barriers(&final_barriers);
+            unsafe { device.cmd_pipeline_barrier2(cmd, &dependency_info) };
+        }
+
+        pool.trim();
+
+        Ok(())
+    }
+
+    /// Names of the passes the graph skips when it executes, as nothing they write is used.
+    pub fn get_culled_passes(&self) -> Vec<&str> {
+        self.passes
+            .iter()
+            .zip(self.cull())
+            .filter(|(_, kept)| !kept)
+            .map(|(pass, _)| pass.name.as_str())
+            .collect()
+    }
+
+    /// The slot every transient image would get in an empty [`TransientPool`], where images in the
+    /// same slot share an image. `None` for imported images and images no kept pass uses.
+    pub fn get_transient_slots(&self) -> Vec<Option<usize>> {
+        let lifetimes = self.get_lifetimes(&self.cull());
+        let mut slots: Vec<(TransientImageDesc, vk::ImageUsageFlags, usize)> = vec![];
+
+        self.images
+            .iter()
+            .zip(lifetimes)
+            .map(|(image, lifetime)| {
+                let (ImageSource::Transient(desc), Some((first, last))) = (&image.source, lifetime)
+                else {
+                    return None;
+                };
+
+                let free = slots.iter().position(|&(slot_desc, usage, busy_until)| {
+                    is_free(
+                        slot_desc,
+                        usage,
+                        Some(busy_until),
+                        *desc,
+                        image.usage,
+                        first,
+                    )
+                });
+                let slot = free.unwrap_or_else(|| {
+                    slots.push((*desc, image.usage, last));
+                    slots.len() - 1
+                });
+                slots[slot].2 = last;
+
+                Some(slot)
+            })
+            .collect()
+    }
+
+    /// Returns which passes contribute to an imported resource or have side effects.
+    fn cull(&self) -> Vec<bool> {
+        let mut needed_images: HashSet<usize> = HashSet::new();
+        let mut kept = vec![false; self.passes.len()];
+
+        for (index, pass) in self.passes.iter().enumerate().rev() {
+            let writes_needed = pass.images.iter().any(|(handle, access)| {
+                access.is_write()
+                    && (needed_images.contains(&handle.0)
+                        || matches!(self.images[handle.0].source, ImageSource::Imported(_)))
+            }) || pass.buffers.iter().any(|(_, access)| access.is_write());
+
+            if !(pass.side_effects || writes_needed) {
+                continue;
+            }
+
+            kept[index] = true;
+            needed_images.extend(pass.images.iter().map(|(handle, _)| handle.0));
+        }
+
+        kept
+    }
+
+    /// Resolves every image to a physical image, placing transient images in `pool`.
+    fn place_images(
+        &self,
+        vk: &Vk,
+        pool: &mut TransientPool,
+        kept: &[bool],
+    ) -> Result<PlacedImages> {
+        let lifetimes = self.get_lifetimes(kept);
+
+        pool.reset();
+
+        let mut slots = Vec::with_capacity(self.images.len());
+        let mut physical = Vec::with_capacity(self.images.len());
+        for (i, image) in self.images.iter().enumerate() {
+            match &image.source {
+                ImageSource::Imported(imported) => {
+                    slots.push(Slot::Own);
+                    physical.push(PhysicalImage {
+                        image: imported.image,
+                        view: imported.view,
+                        format: imported.format,
+                        extent: imported.extent,
+                    });
+                }
+                ImageSource::Transient(desc) => {
+                    let Some((first, last)) = lifetimes[i] else {
+                        // never used by a kept pass, so never touched
+                        slots.push(Slot::Own);
+                        physical.push(PhysicalImage {
+                            image: vk::Image::null(),
+                            view: vk::ImageView::null(),
+                            format: desc.format,
+                            extent: desc.extent,
+                        });
+                        continue;
+                    };
+
+                    let slot = pool.acquire(vk, &image.name, *desc, image.usage, first, last)?;
+                    let pooled = &pool.images[slot];
+                    slots.push(Slot::Pooled(slot));
+                    physical.push(PhysicalImage {
+                        image: pooled.image.get_image(),
+                        view: pooled.view,
+                        format: desc.format,
+                        extent: desc.extent,
+                    });
+                }
+            }
+        }
+
+        Ok(PlacedImages {
+            slots,
+            physical,
+            first_use: lifetimes
+                .iter()
+                .map(|lifetime| lifetime.map(|(first, _)| first))
+                .collect(),
+        })
+    }
+
+    /// The first and last kept pass using every image.
+    fn get_lifetimes(&self, kept: &[bool]) -> Vec<Option<(usize, usize)>> {
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.images.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            if !kept[index] {
+                continue;
+            }
+            for (handle, _) in &pass.images {
+                let (first, _) = lifetimes[handle.0].get_or_insert((index, index));
+                lifetimes[handle.0] = Some((*first, index));
+            }
+        }
+
+        lifetimes
+    }
+}
+
+/// Where the state of an image is tracked.
+#[derive(Clone, Copy)]
+enum Slot {
+    /// By the image itself, as nothing else lives in its memory.
+    Own,
+    Pooled(usize),
+}
+
+struct PlacedImages {
+    slots: Vec<Slot>,
+    physical: Vec<PhysicalImage>,
+    first_use: Vec<Option<usize>>,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    /// Declares that the pass accesses `image` as `access`.
+    pub fn image(mut self, image: ImageHandle, access: Access) -> Self {
+        self.graph.images[image.0].usage |= access.get_image_usage();
+        self.pass.images.push((image, access));
+        self
+    }
+
+    /// Declares that the pass accesses `buffer` as `access`.
+    pub fn buffer(mut self, buffer: BufferHandle, access: Access) -> Self {
+        self.pass.buffers.push((buffer, access));
+        self
+    }
+
+    /// Renders into `image` as the next color attachment.
+    pub fn color_attachment(mut self, image: ImageHandle, load: LoadOp) -> Self {
+        self.pass.color_attachments.push(Attachment { image, load });
+        self.image(image, Access::ColorAttachment)
+    }
+
+    /// Uses `image` as the depth attachment, writing depth.
+    pub fn depth_attachment(mut self, image: ImageHandle, load: LoadOp) -> Self {
+        self.pass.depth_attachment = Some(Attachment { image, load });
+        self.image(image, Access::DepthAttachment)
+    }
+
+    /// Keeps the pass even if nothing reads what it writes, e.g. because it writes to the host.
+    pub fn side_effects(mut self) -> Self {
+        self.pass.side_effects = true;
+        self
+    }
+
+    /// Adds the pass to the graph, recording its commands with `record` once the graph executes.
+    pub fn execute(mut self, record: impl FnOnce(&PassContext) + 'a) {
+        self.pass.record = Some(Box::new(record));
+        self.graph.passes.push(self.pass);
+    }
+}
+
+impl PassContext<'_> {
+    pub fn get_device(&self) -> &Device {
+        self.device
+    }
+
+    pub fn get_command_buffer(&self) -> vk::CommandBuffer {
+        self.cmd
+    }
+
+    pub fn get_image(&self, image: ImageHandle) -> vk::Image {
+        self.images[image.0].image
+    }
+
+    pub fn get_image_view(&self, image: ImageHandle) -> vk::ImageView {
+        self.images[image.0].view
+    }
+
+    pub fn get_format(&self, image: ImageHandle) -> vk::Format {
+        self.images[image.0].format
+    }
+
+    pub fn get_extent(&self, image: ImageHandle) -> vk::Extent2D {
+        self.images[image.0].extent
+    }
+
+    pub fn get_buffer(&self, buffer: BufferHandle) -> vk::Buffer {
+        self.buffers[buffer.0]
+    }
+
+    /// Extent of the attachments of the pass, zero if it has none.
+    pub fn get_render_area(&self) -> vk::Extent2D {
+        self.render_area
+    }
+}
+
+struct PooledImage {
+    desc: TransientImageDesc,
+    usage: vk::ImageUsageFlags,
+    image: GpuImage,
+    view: vk::ImageView,
+    /// Last pass of the current graph using the image, `None` if the graph does not use it yet.
+    busy_until: Option<usize>,
+    used: bool,
+}
+
+/// The images backing transient images of [`RenderGraph`]s, kept between executions so they are
+/// only created once.
+///
+/// The images are reused by the next graph executed with the pool, so every frame in flight needs
+/// its own pool. Images the last graph did not use are destroyed.
+pub struct TransientPool {
+    device: Device,
+    images: Vec<PooledImage>,
+}
+
+impl TransientPool {
+    pub fn new(vk: &Vk) -> Self {
+        Self {
+            device: vk.get_device().clone(),
+            images: vec![],
+        }
+    }
+
+    /// Number of images currently held by the pool.
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    fn reset(&mut self) {
+        for image in &mut self.images {
+            image.busy_until = None;
+            image.used = false;
+        }
+    }
+
+    /// Returns an image matching `desc` and `usage` that is free from pass `first` to `last`,
+    /// creating one if there is none.
+    fn acquire(
+        &mut self,
+        vk: &Vk,
+        name: &str,
+        desc: TransientImageDesc,
+        usage: vk::ImageUsageFlags,
+        first: usize,
+        last: usize,
+    ) -> Result<usize> {
+        let free = self.images.iter().position(|image| {
+            is_free(
+                image.desc,
+                image.usage,
+                image.busy_until,
+                desc,
+                usage,
+                first,
+            )
+        });
+
+        let slot = match free {
+            Some(slot) => slot,
+            None => {
+                self.images
+                    .push(create_pooled_image(vk, name, desc, usage)?);
+                self.images.len() - 1
+            }
+        };
+
+        self.images[slot].busy_until = Some(last);
+        self.images[slot].used = true;
+
+        Ok(slot)
+    }
+
+    fn trim(&mut self) {
+        let device = &self.device;
+        self.images.retain(|image| {
+            if !image.used {
+                unsafe { device.destroy_image_view(image.view, None) };
+            }
+
+            image.used
+        });
+    }
+}
+
+impl Drop for TransientPool {
+    fn drop(&mut self) {
+        // the images free themselves
+        for image in &self.images {
+            unsafe { self.device.destroy_image_view(image.view, None) };
+        }
+    }
+}
+
+/// Whether an image of `slot_desc` and `slot_usage`, used until pass `busy_until`, can hold an
+/// image of `desc` and `usage` from pass `first` on.
+fn is_free(
+    slot_desc: TransientImageDesc,
+    slot_usage: vk::ImageUsageFlags,
+    busy_until: Option<usize>,
+    desc: TransientImageDesc,
+    usage: vk::ImageUsageFlags,
+    first: usize,
+) -> bool {
+    slot_desc == desc && slot_usage == usage && busy_until.is_none_or(|busy| busy < first)
+}
+
+fn create_pooled_image(
+    vk: &Vk,
+    name: &str,
+    desc: TransientImageDesc,
+    usage: vk::ImageUsageFlags,
+) -> Result<PooledImage> {
+    let image_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(desc.format)
+        .extent(vk::Extent3D {
+            width: desc.extent.width,
+            height: desc.extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let image = GpuImage::new(vk, name, &image_info, MemoryLocation::GpuOnly)?;
+
+    let view_info = vk::ImageViewCreateInfo::builder()
+        .image(image.get_image())
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(desc.format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: aspect_of(desc.format),
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        });
+
+    let view = unsafe { vk.get_device().create_image_view(&view_info, None)? };
+
+    Ok(PooledImage {
+        desc,
+        usage,
+        image,
+        view,
+        busy_until: None,
+        used: false,
+    })
+}
+
+fn begin_rendering(
+    device: &Device,
+    cmd: vk::CommandBuffer,
+    pass: &Pass,
+    images: &[PhysicalImage],
+    render_area: vk::Extent2D,
+) {
+    let attachment_info = |attachment: &Attachment, layout| {
+        let (load_op, clear_value) = match attachment.load {
+            LoadOp::Clear(value) => (vk::AttachmentLoadOp::CLEAR, value),
+            LoadOp::Load => (vk::AttachmentLoadOp::LOAD, vk::ClearValue::default()),
+            LoadOp::DontCare => (vk::AttachmentLoadOp::DONT_CARE, vk::ClearValue::default()),
+        };
+
+        *vk::RenderingAttachmentInfo::builder()
+            .image_view(images[attachment.image.0].view)
+            .image_layout(layout)
+            .load_op(load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(clear_value)
+    };
+
+    let color_attachments: Vec<vk::RenderingAttachmentInfo> = pass
+        .color_attachments
+        .iter()
+        .map(|attachment| attachment_info(attachment, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+        .collect();
+    let depth_attachment = pass.depth_attachment.as_ref().map(|attachment| {
+        attachment_info(
+            attachment,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        )
+    });
+
+    let mut rendering_info = vk::RenderingInfo::builder()
+        .render_area(vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: render_area,
+        })
+        .layer_count(1)
+        .color_attachments(&color_attachments);
+    if let Some(depth_attachment) = &depth_attachment {
+        rendering_info = rendering_info.depth_attachment(depth_attachment);
+    }
+
+    unsafe { device.cmd_begin_rendering(cmd, &rendering_info) };
+}
+
+fn image_barrier(image: &PhysicalImage, barrier: Barrier) -> vk::ImageMemoryBarrier2 {
+    *vk::ImageMemoryBarrier2::builder()
+        .src_stage_mask(barrier.src_stage)
+        .src_access_mask(barrier.src_access)
+        .dst_stage_mask(barrier.dst_stage)
+        .dst_access_mask(barrier.dst_access)
+        .old_layout(barrier.old_layout)
+        .new_layout(barrier.new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image.image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: aspect_of(image.format),
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+}
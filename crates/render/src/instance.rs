@@ -8,37 +8,34 @@ use ash::{
     Entry, Instance,
 };
 
-use crate::utils::{check_validation_layer_support, VALIDATION_LAYERS};
+use tracing::{debug, error, info, warn};
 
-/// .
-///
-/// # Panics
+use crate::{
+    error::{RenderError, Result},
+    utils::{check_validation_layer_support, vk_to_str, VALIDATION_LAYERS},
+};
+
+/// Creates the Vulkan instance with the extensions in `ext` enabled.
 ///
-/// Panics if .
-pub fn init(entry: &Entry, ext: &[*const i8]) -> Instance {
-    #[cfg(debug_assertions)]
-    assert!(
-        check_validation_layer_support(entry),
-        "Validation layers requested but none were found!"
-    );
+/// In debug builds the validation layers are enabled if they are available, otherwise a warning is
+/// logged and the instance is created without them.
+pub fn init(entry: &Entry, ext: &[*const i8]) -> Result<Instance> {
+    let validation = cfg!(debug_assertions)
+        && match check_validation_layer_support(entry) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("{}, continuing without validation", e);
+                false
+            }
+        };
 
     let engine_name =
         CString::new("Youniverse Engine").expect("Could not make the C String for the engine");
 
-    // these unwraps are safe because the environment variables are set by cargo and are guaranteed
-    // to be valid
-    let major = std::env::var("CARGO_PKG_VERSION_MAJOR")
-        .unwrap()
-        .parse()
-        .unwrap();
-    let minor = std::env::var("CARGO_PKG_VERSION_MINOR")
-        .unwrap()
-        .parse()
-        .unwrap();
-    let patch = std::env::var("CARGO_PKG_VERSION_PATCH")
-        .unwrap()
-        .parse()
-        .unwrap();
+    // cargo sets these at compile time, so they are guaranteed to be valid numbers
+    let major = env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap();
+    let minor = env!("CARGO_PKG_VERSION_MINOR").parse().unwrap();
+    let patch = env!("CARGO_PKG_VERSION_PATCH").parse().unwrap();
 
     let app_info = vk::ApplicationInfo::builder()
         .engine_name(&engine_name)
@@ -50,31 +47,55 @@ pub fn init(entry: &Entry, ext: &[*const i8]) -> Instance {
         .map(|s| CString::new(*s).unwrap())
         .collect();
 
-    let layers = if cfg!(debug_assert) {
+    let layers = if validation {
         tmp.iter().map(|s| s.as_ptr()).collect()
     } else {
         vec![]
     };
-    // let ext = crate::raw::enumerate_required_extensions(entry, window);
-    // let ext = window.
 
     let mut create_info = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
         .enabled_extension_names(ext)
         .enabled_layer_names(&layers);
 
-    create_info.p_next = if cfg!(debug_assert) {
-        let debug_info = populate_debug_messenger_create_info();
+    // has to outlive the create call, as it is only referenced through `p_next`
+    let debug_info = populate_debug_messenger_create_info();
+    create_info.p_next = if validation {
         std::ptr::addr_of!(debug_info).cast::<c_void>()
     } else {
         ptr::null()
     };
 
-    unsafe {
-        entry
-            .create_instance(&create_info, None)
-            .expect("Could not create instance")
-    }
+    let instance = unsafe { entry.create_instance(&create_info, None) }.map_err(|e| match e {
+        vk::Result::ERROR_EXTENSION_NOT_PRESENT => {
+            RenderError::MissingExtension(first_missing_extension(entry, ext))
+        }
+        vk::Result::ERROR_LAYER_NOT_PRESENT => {
+            RenderError::MissingLayer(VALIDATION_LAYERS.join(", "))
+        }
+        e => e.into(),
+    })?;
+
+    Ok(instance)
+}
+
+/// Returns the name of the first extension in `ext` the instance does not support.
+fn first_missing_extension(entry: &Entry, ext: &[*const i8]) -> String {
+    let available: Vec<String> = entry
+        .enumerate_instance_extension_properties(None)
+        .unwrap_or_default()
+        .iter()
+        .map(|props| vk_to_str(&props.extension_name))
+        .collect();
+
+    ext.iter()
+        .map(|name| {
+            unsafe { CStr::from_ptr(*name) }
+                .to_string_lossy()
+                .into_owned()
+        })
+        .find(|name| !available.contains(name))
+        .unwrap_or_else(|| "unknown".to_owned())
 }
 
 #[inline(never)]
@@ -98,23 +119,20 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
     message_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
     _user_data: *mut c_void,
 ) -> vk::Bool32 {
-    let serverity = match message_severity {
-        DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "[\x1b[34mVerbose\x1b[0m]",
-        DebugUtilsMessageSeverityFlagsEXT::INFO => "[\x1b[36mInfo\x1b[0m]",
-        DebugUtilsMessageSeverityFlagsEXT::WARNING => "[\x1b[33mWarning\x1b[0m]",
-        DebugUtilsMessageSeverityFlagsEXT::ERROR => "[\x1b[31mError\x1b[0m]",
-        _ => "[Unknown]",
-    };
-
     let m_type = match message_type {
-        DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
-        DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[Performance]",
-        DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
-        _ => "[Unknown]",
+        DebugUtilsMessageTypeFlagsEXT::VALIDATION => "Validation",
+        DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "Performance",
+        DebugUtilsMessageTypeFlagsEXT::GENERAL => "General",
+        _ => "Unknown",
     };
 
-    let message = CStr::from_ptr((*message_data).p_message);
-    println!("[Debug]{}{}{:?}", serverity, m_type, message);
+    let message = CStr::from_ptr((*message_data).p_message).to_string_lossy();
+    match message_severity {
+        DebugUtilsMessageSeverityFlagsEXT::ERROR => error!("[{}] {}", m_type, message),
+        DebugUtilsMessageSeverityFlagsEXT::WARNING => warn!("[{}] {}", m_type, message),
+        DebugUtilsMessageSeverityFlagsEXT::INFO => info!("[{}] {}", m_type, message),
+        _ => debug!("[{}] {}", m_type, message),
+    }
 
     vk::FALSE
 }
@@ -0,0 +1,322 @@
+use std::path::Path;
+
+use ash::vk;
+use ktx2::{ColorModel, DfdBlockBasic, SupercompressionScheme, TransferFunction};
+use tracing::warn;
+
+use crate::{
+    block::{decompress, decompressed_format},
+    error::{RenderError, Result},
+    etc1s::{self, Codebooks},
+    texture::{supports_format, Texture, TextureData},
+    uastc, Vk,
+};
+
+/// The data format descriptor channel of two channel ETC1S textures that holds green.
+const ETC1S_GREEN_CHANNEL: u8 = 4;
+
+/// Loads the KTX2 container at `path` into a texture the device can sample, see [`negotiate`].
+///
+/// Containers with a single level get a generated mip chain if the format can be blitted.
+///
+/// # Errors
+///
+/// Returns an error if the file could not be read or decoded, the device can not sample its
+/// format or the upload failed.
+pub fn load(vk: &Vk, path: impl AsRef<Path>) -> Result<Texture> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let data = decode_named(&name, &std::fs::read(path)?)?;
+
+    Texture::new(vk, &name, &negotiate(vk, data)?, true)
+}
+
+/// Reads the levels of a KTX2 container in the format they are stored in, undoing Zstandard
+/// supercompression.
+///
+/// Basis Universal payloads, which have no Vulkan format of their own, are transcoded without
+/// loss to formats [`negotiate`] can decompress. UASTC becomes ASTC 4x4 and ETC1S becomes ETC2
+/// RGB, or RGBA8 if it has a second slice for alpha or green.
+///
+/// Every level holds all layers and cubemap faces, in the order of the array layers of the image.
+///
+/// # Errors
+///
+/// Returns [`RenderError::ImageLoad`] if `bytes` are not a valid container or hold something
+/// other than 2D images, cubemaps or arrays of them, including ETC1S video.
+pub fn decode(bytes: &[u8]) -> Result<TextureData> {
+    decode_named("in memory", bytes)
+}
+
+fn decode_named(name: &str, bytes: &[u8]) -> Result<TextureData> {
+    let load_error = |message: String| RenderError::ImageLoad {
+        name: name.to_owned(),
+        message,
+    };
+
+    let reader = ktx2::Reader::new(bytes).map_err(|e| load_error(e.to_string()))?;
+    let header = reader.header();
+
+    if header.pixel_depth > 1 {
+        return Err(load_error("3D textures are not supported".to_owned()));
+    }
+    let layers = header.layer_count.max(1) * header.face_count;
+
+    let (format, levels) = match header.format {
+        Some(format) => inflate_levels(&reader)
+            .map(|levels| (vk::Format::from_raw(format.value() as i32), levels)),
+        None => transcode_basis(&reader, layers),
+    }
+    .map_err(load_error)?;
+
+    Ok(TextureData {
+        format,
+        width: header.pixel_width,
+        height: header.pixel_height.max(1),
+        layers,
+        cube: header.face_count == 6,
+        levels,
+    })
+}
+
+/// The levels of `reader` with Zstandard supercompression undone.
+fn inflate_levels(reader: &ktx2::Reader<&[u8]>) -> std::result::Result<Vec<Vec<u8>>, String> {
+    reader
+        .levels()
+        .map(|level| match reader.header().supercompression_scheme {
+            None => Ok(level.data.to_vec()),
+            Some(SupercompressionScheme::Zstandard) => {
+                let mut texels = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                ruzstd::FrameDecoder::new()
+                    .decode_all_to_vec(level.data, &mut texels)
+                    .map_err(|e| e.to_string())?;
+
+                Ok(texels)
+            }
+            Some(scheme) => Err(format!("{:?} supercompression is not supported", scheme)),
+        })
+        .collect()
+}
+
+/// Transcodes the Basis Universal payload of a container without a format, which one is told by
+/// the color model of its data format descriptor, see [`decode`].
+fn transcode_basis(
+    reader: &ktx2::Reader<&[u8]>,
+    layers: u32,
+) -> std::result::Result<(vk::Format, Vec<Vec<u8>>), String> {
+    let dfd = reader
+        .dfd_blocks()
+        .next()
+        .and_then(|block| DfdBlockBasic::parse(block.data).ok())
+        .ok_or("Containers without a format need a data format descriptor")?;
+    let srgb = dfd.header.transfer_function == Some(TransferFunction::SRGB);
+
+    match dfd.header.color_model {
+        Some(ColorModel::UASTC) => {
+            let levels = inflate_levels(reader)?
+                .iter()
+                .map(|level| {
+                    level
+                        .chunks_exact(16)
+                        .flat_map(uastc::transcode_block)
+                        .collect()
+                })
+                .collect();
+            let format = if srgb {
+                vk::Format::ASTC_4X4_SRGB_BLOCK
+            } else {
+                vk::Format::ASTC_4X4_UNORM_BLOCK
+            };
+
+            Ok((format, levels))
+        }
+        Some(ColorModel::ETC1S) => {
+            // a second sample is a slice for alpha, or for green in two channel textures
+            let second_channel = dfd.sample_information().nth(1).map(|sample| {
+                if sample.channel_type == ETC1S_GREEN_CHANNEL {
+                    1
+                } else {
+                    3
+                }
+            });
+            let levels = transcode_etc1s(reader, layers, second_channel)?;
+            let format = match (second_channel, srgb) {
+                (None, false) => vk::Format::ETC2_R8G8B8_UNORM_BLOCK,
+                (None, true) => vk::Format::ETC2_R8G8B8_SRGB_BLOCK,
+                (Some(_), false) => vk::Format::R8G8B8A8_UNORM,
+                (Some(_), true) => vk::Format::R8G8B8A8_SRGB,
+            };
+
+            Ok((format, levels))
+        }
+        model => Err(format!(
+            "{:?} payloads without a format are not supported",
+            model
+        )),
+    }
+}
+
+/// Decodes the BasisLZ supercompressed ETC1S slices of every level and layer into ETC1 blocks,
+/// or into RGBA8 texels with the second slices in `second_channel`.
+fn transcode_etc1s(
+    reader: &ktx2::Reader<&[u8]>,
+    layers: u32,
+    second_channel: Option<usize>,
+) -> std::result::Result<Vec<Vec<u8>>, String> {
+    let header = reader.header();
+    if header.supercompression_scheme != Some(SupercompressionScheme::BasisLZ) {
+        return Err("ETC1S needs BasisLZ supercompression".to_owned());
+    }
+    let malformed = || "Malformed BasisLZ data".to_owned();
+
+    // the counts and byte lengths of the codebooks, followed by a description of every image
+    // from the first level on and the codebooks
+    let global = reader.supercompression_global_data();
+    let field = |offset: usize, size: usize| {
+        let bytes = global.get(offset..offset + size).ok_or_else(malformed)?;
+        Ok::<_, String>(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | byte as usize),
+        )
+    };
+    let image_count = header.level_count.max(1) as usize * layers as usize;
+    let mut start = 20 + image_count * 20;
+    let mut section = |length_offset: usize| {
+        let length = field(length_offset, 4)?;
+        start += length;
+        global.get(start - length..start).ok_or_else(malformed)
+    };
+    let codebooks = Codebooks::read(
+        field(0, 2)?,
+        field(2, 2)?,
+        section(4)?,
+        section(8)?,
+        section(12)?,
+    )
+    .ok_or_else(malformed)?;
+
+    reader
+        .levels()
+        .enumerate()
+        .map(|(level, data)| {
+            let width = (header.pixel_width >> level).max(1) as usize;
+            let height = (header.pixel_height.max(1) >> level).max(1) as usize;
+            let (blocks_x, blocks_y) = (width.div_ceil(4), height.div_ceil(4));
+
+            let mut texels = vec![];
+            for layer in 0..layers as usize {
+                let image = 20 + (level * layers as usize + layer) * 20;
+                if field(image, 4)? & 2 != 0 {
+                    return Err("ETC1S video is not supported".to_owned());
+                }
+                let slice = |description: usize| {
+                    let (offset, length) = (field(description, 4)?, field(description + 4, 4)?);
+                    data.data
+                        .get(offset..offset + length)
+                        .and_then(|slice| codebooks.decode_slice(slice, blocks_x, blocks_y))
+                        .ok_or_else(malformed)
+                };
+
+                let color = slice(image + 4)?;
+                match second_channel {
+                    None => texels.extend(color.into_iter().flat_map(etc1s::Block::to_etc1)),
+                    Some(channel) => texels.extend(etc1s_texels(
+                        &color,
+                        &slice(image + 12)?,
+                        channel,
+                        width,
+                        height,
+                    )),
+                }
+            }
+
+            Ok(texels)
+        })
+        .collect()
+}
+
+/// The RGBA8 texels of an image of `width` by `height` texels from the blocks of its color
+/// slice, with `channel` taken from the green of the blocks of its second slice.
+fn etc1s_texels(
+    color: &[etc1s::Block],
+    second: &[etc1s::Block],
+    channel: usize,
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let blocks_x = width.div_ceil(4);
+    let mut texels = vec![u8::MAX; width * height * 4];
+
+    for (i, (color, second)) in color.iter().zip(second).enumerate() {
+        let (color, second) = (color.texels(), second.texels());
+        let (block_x, block_y) = (i % blocks_x * 4, i / blocks_x * 4);
+
+        for (j, (rgb, second)) in color.iter().zip(second).enumerate() {
+            let (x, y) = (block_x + j % 4, block_y + j / 4);
+            if x < width && y < height {
+                let texel = &mut texels[(y * width + x) * 4..][..4];
+                texel[..3].copy_from_slice(rgb);
+                texel[channel] = second[1];
+            }
+        }
+    }
+
+    texels
+}
+
+/// Returns `data` as it is if the device can sample its format, otherwise decompressed with
+/// [`decompress_levels`].
+///
+/// # Errors
+///
+/// Returns [`RenderError::UnsupportedFormat`] if the device lacks the format and it has no CPU
+/// decoder, see [`decompressed_format`].
+pub fn negotiate(vk: &Vk, data: TextureData) -> Result<TextureData> {
+    if supports_format(vk, data.format, vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+        return Ok(data);
+    }
+
+    let texels = decompress_levels(&data)?;
+    warn!(
+        "Device can not sample {:?}, decompressed to {:?}",
+        data.format, texels.format
+    );
+
+    Ok(texels)
+}
+
+/// Decompresses every level and layer of `data` to the format of [`decompressed_format`].
+///
+/// # Errors
+///
+/// Returns [`RenderError::UnsupportedFormat`] if the format has no CPU decoder and
+/// [`RenderError::ImageLoad`] if a level is too short.
+pub fn decompress_levels(data: &TextureData) -> Result<TextureData> {
+    let format =
+        decompressed_format(data.format).ok_or(RenderError::UnsupportedFormat(data.format))?;
+
+    let levels = data
+        .levels
+        .iter()
+        .enumerate()
+        .map(|(level, texels)| {
+            let extent = data.get_level_extent(level as u32);
+            let layer_size = texels.len() / data.layers as usize;
+
+            let mut decompressed = Vec::new();
+            for layer in texels.chunks_exact(layer_size.max(1)) {
+                decompressed.extend(decompress(data.format, extent.width, extent.height, layer)?);
+            }
+
+            Ok(decompressed)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(TextureData {
+        format,
+        levels,
+        ..*data
+    })
+}
@@ -13,38 +13,423 @@
 //     }
 // }
 
+mod astc;
+pub mod bindless;
+pub mod block;
+mod bptc;
+pub mod buffer;
+pub mod capture;
+pub mod compute;
+pub mod depth;
 mod device;
+pub mod error;
+mod etc1s;
+pub mod frame;
+pub mod gltf_import;
+pub mod graph;
 mod instance;
-mod swapchain;
+pub mod ktx;
+pub mod memory;
+pub mod meshlet;
+pub mod obj_import;
+pub mod offscreen;
+pub mod pipeline;
+pub mod profile;
+pub mod reflect;
+pub mod sampler;
+pub mod scene;
+pub mod selector;
+pub mod shader;
+pub mod staging;
+pub mod swapchain;
+pub mod texture;
+mod uastc;
+pub mod upload;
 mod utils;
+pub mod vertex;
 
-use ash::{vk::PhysicalDevice, Entry, Instance};
-use device::{create_logical_device, create_physical_devices};
+use std::{
+    ffi::{CStr, CString},
+    mem::ManuallyDrop,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use ash::{
+    extensions::{ext, khr},
+    vk::{self, Handle, PhysicalDevice},
+    Device, Entry, Instance,
+};
+use device::{create_logical_device, find_queue_families, supports_mesh_shaders};
+pub use device::{Queue, Queues};
+pub use error::{RenderError, Result};
+pub use frame::{Frame, FrameLoop};
+use offscreen::OffscreenTarget;
+use selector::{DeviceReport, DeviceSelector, Feature, Features};
+use staging::{StagingRing, DEFAULT_STAGING_SIZE};
+use swapchain::Swapchain;
+use tracing::{info, warn};
+use vk_mem::Allocator;
 
 pub struct Vk {
     entry: Entry,
     instance: Instance,
     physical_device: PhysicalDevice,
-    device: ash::Device,
+    device_report: DeviceReport,
+    device: Device,
+    queues: Queues,
+    /// The required features and the requested ones the device supports.
+    features: Vec<Feature>,
+    /// Dropped by hand before the device is destroyed.
+    allocator: ManuallyDrop<Arc<Allocator>>,
+    /// Only loaded if the instance was created with the debug utils extension.
+    debug_utils: Option<ext::DebugUtils>,
+    /// Only loaded if the device supports task and mesh shaders.
+    mesh_shader: Option<ext::MeshShader>,
+    command_pool: vk::CommandPool,
+    /// Created on the first upload.
+    staging: Mutex<Option<StagingRing>>,
+    /// The surface passed to [`Vk::new`] until it is handed to the swapchain.
+    surface: Option<vk::SurfaceKHR>,
+    swapchain: Option<Swapchain>,
 }
 
 impl Vk {
-    /// Creates a new [`Vk`].
+    /// Creates a new [`Vk`] able to present, `ext` being the instance extensions required by the
+    /// window system.
+    ///
+    /// `create_surface` is called with the new instance before the device is picked, so a queue
+    /// family that can present to the surface is known when the queues are created. The surface
+    /// is kept until [`Vk::create_swapchain`] is called.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no device supports presenting or any of the extensions is missing.
+    pub fn new(
+        ext: &[*const i8],
+        create_surface: impl FnOnce(&Entry, &Instance) -> Result<vk::SurfaceKHR>,
+    ) -> Result<Self> {
+        Self::with_selector(ext, &DeviceSelector::new(), create_surface)
+    }
+
+    /// Like [`Vk::new`], picking the device with `selector`.
+    ///
+    /// The swapchain extension is required on top of what `selector` requires.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if .
-    pub fn new(ext: &[*const i8]) -> Self {
+    /// Returns an error if `selector` finds no suitable device or the instance could not be
+    /// created.
+    pub fn with_selector(
+        ext: &[*const i8],
+        selector: &DeviceSelector,
+        create_surface: impl FnOnce(&Entry, &Instance) -> Result<vk::SurfaceKHR>,
+    ) -> Result<Self> {
         let entry = Entry::linked();
-        let instance = instance::init(&entry, ext);
-        let physical_device = create_physical_devices(&instance);
-        let device = create_logical_device(&instance, physical_device);
-        // let device = todo!();
-        Vk {
+        let instance = instance::init(&entry, ext)?;
+        let surface = create_surface(&entry, &instance)?;
+
+        let selector = selector.clone().require_extension(khr::Swapchain::name());
+        let debug_utils = ext
+            .iter()
+            .any(|name| unsafe { CStr::from_ptr(*name) } == ext::DebugUtils::name());
+
+        Self::init(entry, instance, Some(surface), &selector, debug_utils)
+    }
+
+    /// Creates a new [`Vk`] without any window system integration, for rendering into an
+    /// [`OffscreenTarget`] on machines without a display.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no Vulkan capable device.
+    pub fn headless() -> Result<Self> {
+        Self::headless_with_selector(&DeviceSelector::new())
+    }
+
+    /// Like [`Vk::headless`], picking the device with `selector`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `selector` finds no suitable device.
+    pub fn headless_with_selector(selector: &DeviceSelector) -> Result<Self> {
+        let ext = if cfg!(debug_assertions) {
+            vec![ext::DebugUtils::name().as_ptr()]
+        } else {
+            vec![]
+        };
+
+        let entry = Entry::linked();
+        let instance = instance::init(&entry, &ext)?;
+
+        Self::init(entry, instance, None, selector, cfg!(debug_assertions))
+    }
+
+    fn init(
+        entry: Entry,
+        instance: Instance,
+        surface: Option<vk::SurfaceKHR>,
+        selector: &DeviceSelector,
+        debug_utils: bool,
+    ) -> Result<Self> {
+        let surface_loader = khr::Surface::new(&entry, &instance);
+        let surface = surface.map(|surface| (&surface_loader, surface));
+
+        let (physical_device, device_report) = selector.select(&instance, surface)?;
+        let queue_families = find_queue_families(&instance, physical_device, surface)?;
+
+        let available: Vec<String> =
+            unsafe { instance.enumerate_device_extension_properties(physical_device)? }
+                .iter()
+                .map(|props| utils::vk_to_str(&props.extension_name))
+                .collect();
+        let mut enabled: Vec<&CStr> = selector
+            .get_extensions()
+            .iter()
+            .map(CString::as_c_str)
+            .collect();
+        enabled.extend(
+            selector
+                .get_optional_extensions()
+                .iter()
+                .map(CString::as_c_str)
+                .filter(|extension| {
+                    available
+                        .iter()
+                        .any(|name| *name == extension.to_string_lossy())
+                }),
+        );
+
+        // the extension alone does not guarantee the features, e.g. for task shaders
+        let mesh_shader = enabled.contains(&ext::MeshShader::name())
+            && supports_mesh_shaders(&instance, physical_device);
+        if !mesh_shader {
+            enabled.retain(|extension| *extension != ext::MeshShader::name());
+        }
+        info!(
+            "Mesh shaders are {}",
+            if mesh_shader {
+                "supported"
+            } else {
+                "not supported"
+            }
+        );
+
+        let supported = Features::query(&instance, physical_device);
+        let mut features = selector.get_features().to_vec();
+        for feature in selector.get_optional_features() {
+            if features.contains(feature) {
+                continue;
+            }
+            if supported.supports(*feature) {
+                features.push(*feature);
+            } else {
+                info!("Optional feature {} is not supported", feature);
+            }
+        }
+
+        let extensions: Vec<_> = enabled.iter().map(|extension| extension.as_ptr()).collect();
+        let (device, queues) = create_logical_device(
+            &instance,
+            physical_device,
+            &queue_families,
+            &features,
+            &extensions,
+            mesh_shader,
+        )?;
+
+        let pool_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(queues.graphics.family);
+
+        let command_pool = unsafe { device.create_command_pool(&pool_info, None)? };
+
+        let allocator = memory::create_allocator(&instance, &device, physical_device)?;
+        let debug_utils = debug_utils.then(|| ext::DebugUtils::new(&entry, &instance));
+        let mesh_shader = mesh_shader.then(|| ext::MeshShader::new(&instance, &device));
+
+        Ok(Vk {
             entry,
             instance,
             physical_device,
+            device_report,
             device,
+            queues,
+            features,
+            allocator: ManuallyDrop::new(Arc::new(allocator)),
+            debug_utils,
+            mesh_shader,
+            command_pool,
+            staging: Mutex::new(None),
+            surface: surface.map(|(_, surface)| surface),
+            swapchain: None,
+        })
+    }
+
+    /// Records commands with `record` into a one time command buffer, submits it to the graphics
+    /// queue and blocks until it has finished executing.
+    pub fn immediate_submit(&self, record: impl FnOnce(&Device, vk::CommandBuffer)) -> Result<()> {
+        let alloc_info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(self.command_pool)
+            .command_buffer_count(1);
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            let cmd = self.device.allocate_command_buffers(&alloc_info)?[0];
+            let command_buffers = [cmd];
+
+            let result = (|| {
+                self.device.begin_command_buffer(cmd, &begin_info)?;
+                record(&self.device, cmd);
+                self.device.end_command_buffer(cmd)?;
+
+                let fence = self
+                    .device
+                    .create_fence(&vk::FenceCreateInfo::default(), None)?;
+
+                let submit_info = [*vk::SubmitInfo::builder().command_buffers(&command_buffers)];
+                let submitted = self
+                    .device
+                    .queue_submit(self.queues.graphics.queue, &submit_info, fence)
+                    .and_then(|_| self.device.wait_for_fences(&[fence], true, u64::MAX));
+
+                self.device.destroy_fence(fence, None);
+                submitted
+            })();
+
+            self.device
+                .free_command_buffers(self.command_pool, &command_buffers);
+
+            Ok(result?)
+        }
+    }
+
+    /// Copies `data` into `dst` at `dst_offset` bytes through the staging ring, blocking until the
+    /// copy has finished.
+    ///
+    /// Data larger than the ring is uploaded in several submits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the staging ring could not be created or the copy failed.
+    pub fn upload_to_buffer(
+        &self,
+        dst: vk::Buffer,
+        dst_offset: vk::DeviceSize,
+        data: &[u8],
+    ) -> Result<()> {
+        let mut staging = self.staging.lock().unwrap_or_else(PoisonError::into_inner);
+        if staging.is_none() {
+            *staging = Some(StagingRing::new(self, DEFAULT_STAGING_SIZE)?);
+        }
+        let ring = staging.as_mut().unwrap();
+
+        let capacity = ring.get_capacity() as usize;
+        for (i, chunk) in data.chunks(capacity).enumerate() {
+            // every submit is waited on, so the ring is empty and the chunk always fits
+            let src_offset = ring.push(chunk, 4)?.expect("Staging ring is not empty");
+            let submission = ring.submit();
+
+            let region = [vk::BufferCopy {
+                src_offset,
+                dst_offset: dst_offset + (i * capacity) as vk::DeviceSize,
+                size: chunk.len() as vk::DeviceSize,
+            }];
+            let barrier = [*vk::BufferMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .buffer(dst)
+                .offset(region[0].dst_offset)
+                .size(region[0].size)];
+            let dependency_info = vk::DependencyInfo::builder().buffer_memory_barriers(&barrier);
+
+            let result = self.immediate_submit(|device, cmd| unsafe {
+                device.cmd_copy_buffer(cmd, ring.get_buffer(), dst, &region);
+                device.cmd_pipeline_barrier2(cmd, &dependency_info);
+            });
+            ring.release(submission);
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders a single frame into `target` and reads the color attachment back to the CPU.
+    ///
+    /// `record` is called between beginning and ending dynamic rendering, so it only needs to bind
+    /// pipelines and issue draws. The returned bytes are tightly packed rows in the color format of
+    /// `target`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if submitting the frame or reading it back failed.
+    pub fn render_offscreen(
+        &self,
+        target: &OffscreenTarget,
+        clear_color: [f32; 4],
+        record: impl FnOnce(&Device, vk::CommandBuffer),
+    ) -> Result<Vec<u8>> {
+        self.immediate_submit(|device, cmd| {
+            target.begin(cmd, clear_color);
+            record(device, cmd);
+            target.end(cmd);
+        })?;
+
+        target.read_back()
+    }
+
+    /// Creates the [`Swapchain`] presenting to the surface passed to [`Vk::new`], which is owned
+    /// by the swapchain from then on. If creating fails, e.g. because the window is minimized, the
+    /// surface is kept and creating can be tried again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::SurfaceCreationFailed`] if there is no surface, because the [`Vk`]
+    /// is headless or the swapchain was already created, or any error from creating the swapchain.
+    pub fn create_swapchain(
+        &mut self,
+        extent: vk::Extent2D,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<()> {
+        let surface = self.surface.ok_or(RenderError::SurfaceCreationFailed(
+            vk::Result::ERROR_SURFACE_LOST_KHR,
+        ))?;
+        let surface_loader = khr::Surface::new(&self.entry, &self.instance);
+        let mut queue_families = vec![self.queues.graphics.family];
+        queue_families.extend(self.queues.present.map(|present| present.family));
+
+        self.swapchain = Some(Swapchain::new(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            surface_loader,
+            surface,
+            extent,
+            present_mode,
+            &queue_families,
+        )?);
+        self.surface = None;
+
+        Ok(())
+    }
+
+    /// Recreates the swapchain for a new surface extent without touching the device.
+    ///
+    /// Returns whether the swapchain was recreated. Does nothing if there is no swapchain or the
+    /// extent is zero, e.g. while the window is minimized. The surface can report a zero extent of
+    /// its own, which [`Swapchain::recreate`] checks for.
+    pub fn recreate_swapchain(&mut self, extent: vk::Extent2D) -> Result<bool> {
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(false);
+        }
+
+        match self.swapchain.as_mut() {
+            Some(swapchain) => swapchain.recreate(extent),
+            None => Ok(false),
         }
     }
 
@@ -55,4 +440,117 @@ impl Vk {
     pub fn get_instance(&self) -> &Instance {
         &self.instance
     }
+
+    pub fn get_device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn get_physical_device(&self) -> PhysicalDevice {
+        self.physical_device
+    }
+
+    /// The allocator backing every [`memory::GpuBuffer`] and [`memory::GpuImage`].
+    ///
+    /// Those have to be dropped before the [`Vk`], which panics otherwise.
+    pub fn get_allocator(&self) -> &Arc<Allocator> {
+        &self.allocator
+    }
+
+    /// Names `object` in validation messages and graphics debuggers.
+    ///
+    /// Does nothing if the instance was created without the debug utils extension.
+    pub fn set_debug_name<T: Handle>(&self, object: T, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+
+        // a name with an interior nul is cut off there
+        let name = CString::new(name.split('\0').next().unwrap_or_default()).unwrap_or_default();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(T::TYPE)
+            .object_handle(object.as_raw())
+            .object_name(&name);
+
+        if let Err(e) =
+            unsafe { debug_utils.set_debug_utils_object_name(self.device.handle(), &name_info) }
+        {
+            warn!(
+                "Could not name {:?} {}: {}",
+                T::TYPE,
+                name.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    /// The devices that were considered and why the selected one was picked.
+    pub fn get_device_report(&self) -> &DeviceReport {
+        &self.device_report
+    }
+
+    /// Whether `feature` is enabled on the device, either because the selector required it or
+    /// because it requested it and the device supports it.
+    pub fn supports_feature(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// The `VK_EXT_mesh_shader` functions, `None` if the device does not support task and mesh
+    /// shaders.
+    pub fn get_mesh_shader(&self) -> Option<&ext::MeshShader> {
+        self.mesh_shader.as_ref()
+    }
+
+    pub fn get_queues(&self) -> &Queues {
+        &self.queues
+    }
+
+    pub fn get_command_pool(&self) -> vk::CommandPool {
+        self.command_pool
+    }
+
+    pub fn get_swapchain(&self) -> Option<&Swapchain> {
+        self.swapchain.as_ref()
+    }
+
+    pub fn get_swapchain_mut(&mut self) -> Option<&mut Swapchain> {
+        self.swapchain.as_mut()
+    }
+}
+
+impl Drop for Vk {
+    fn drop(&mut self) {
+        unsafe {
+            // nothing sensible can be done about a lost device while dropping
+            let _ = self.device.device_wait_idle();
+
+            // the swapchain has to go before the device it was created from
+            std::mem::drop(self.swapchain.take());
+            if let Some(surface) = self.surface.take() {
+                khr::Surface::new(&self.entry, &self.instance).destroy_surface(surface, None);
+            }
+
+            self.device.destroy_command_pool(self.command_pool, None);
+            std::mem::drop(
+                self.staging
+                    .get_mut()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .take(),
+            );
+
+            let allocator = ManuallyDrop::take(&mut self.allocator);
+            if Arc::strong_count(&allocator) > 1 {
+                // their drops would free memory of a destroyed device, so the device is leaked
+                // with them instead, and the panic is skipped if already unwinding from another
+                std::mem::forget(allocator);
+                if !std::thread::panicking() {
+                    panic!("Vk dropped while GPU buffers or images are still alive");
+                }
+                return;
+            }
+            std::mem::drop(allocator);
+
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
 }
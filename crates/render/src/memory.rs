@@ -0,0 +1,324 @@
+use std::{fmt, ptr::NonNull, sync::Arc};
+
+use ash::{vk, Device, Instance};
+use tracing::debug;
+use vk_mem::{
+    Alloc, Allocation, AllocationCreateFlags, AllocationCreateInfo, Allocator,
+    AllocatorCreateFlags, AllocatorCreateInfo, MemoryUsage,
+};
+
+use crate::{error::Result, Vk};
+
+/// Where the memory of a [`GpuBuffer`] or [`GpuImage`] lives, chosen by how it is accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Device local memory the CPU can not access, for everything only the GPU reads and writes.
+    GpuOnly,
+    /// Host visible memory the CPU writes sequentially and the GPU reads, e.g. staging buffers and
+    /// per frame uniforms. Stays mapped for its whole lifetime.
+    Upload,
+    /// Host visible, cached memory the GPU writes and the CPU reads back. Stays mapped for its
+    /// whole lifetime.
+    Readback,
+}
+
+impl MemoryLocation {
+    fn allocation_info(self) -> AllocationCreateInfo {
+        let (usage, flags) = match self {
+            MemoryLocation::GpuOnly => (
+                MemoryUsage::AutoPreferDevice,
+                AllocationCreateFlags::empty(),
+            ),
+            MemoryLocation::Upload => (
+                MemoryUsage::Auto,
+                AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE | AllocationCreateFlags::MAPPED,
+            ),
+            MemoryLocation::Readback => (
+                MemoryUsage::Auto,
+                AllocationCreateFlags::HOST_ACCESS_RANDOM | AllocationCreateFlags::MAPPED,
+            ),
+        };
+
+        AllocationCreateInfo {
+            usage,
+            flags,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_host_visible(self) -> bool {
+        self != MemoryLocation::GpuOnly
+    }
+}
+
+/// Creates the allocator [`Vk`] hands out to every [`GpuBuffer`] and [`GpuImage`].
+pub(crate) fn create_allocator(
+    instance: &Instance,
+    device: &Device,
+    p_dev: vk::PhysicalDevice,
+) -> Result<Allocator> {
+    let create_info = AllocatorCreateInfo::new(instance, device, p_dev)
+        .vulkan_api_version(vk::API_VERSION_1_3)
+        .flags(AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS);
+
+    Ok(Allocator::new(create_info)?)
+}
+
+/// A [`vk::Buffer`] with its own allocation, both freed when it is dropped.
+pub struct GpuBuffer {
+    allocator: Arc<Allocator>,
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    size: vk::DeviceSize,
+    location: MemoryLocation,
+    mapped: Option<NonNull<u8>>,
+    /// Only queried for buffers created with `SHADER_DEVICE_ADDRESS` usage.
+    device_address: Option<vk::DeviceAddress>,
+    name: String,
+}
+
+// the mapped pointer is only dereferenced through `&self`/`&mut self`
+unsafe impl Send for GpuBuffer {}
+unsafe impl Sync for GpuBuffer {}
+
+impl GpuBuffer {
+    /// Creates a buffer of `size` bytes in `location`, naming it `name` in debug tools.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer could not be created or there is no memory left in
+    /// `location`.
+    pub fn new(
+        vk: &Vk,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> Result<Self> {
+        let allocator = vk.get_allocator().clone();
+
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let (buffer, allocation) =
+            unsafe { allocator.create_buffer(&buffer_info, &location.allocation_info())? };
+
+        let mapped = NonNull::new(
+            allocator
+                .get_allocation_info(&allocation)
+                .mapped_data
+                .cast(),
+        );
+        let device_address = usage
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+            .then(|| {
+                let address_info = vk::BufferDeviceAddressInfo::builder().buffer(buffer);
+                unsafe { vk.get_device().get_buffer_device_address(&address_info) }
+            });
+
+        vk.set_debug_name(buffer, name);
+        debug!(
+            "Allocated buffer {} of {} bytes in {:?}",
+            name, size, location
+        );
+
+        Ok(Self {
+            allocator,
+            buffer,
+            allocation,
+            size,
+            location,
+            mapped,
+            device_address,
+            name: name.to_owned(),
+        })
+    }
+
+    /// Copies `data` into the buffer at `offset` bytes and flushes it, so the GPU sees it once the
+    /// next submit happens.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not host visible or `data` does not fit at `offset`.
+    pub fn write(&mut self, offset: vk::DeviceSize, data: &[u8]) -> Result<()> {
+        let ptr = self.mapped_range(offset, data.len());
+
+        unsafe { ptr.copy_from_nonoverlapping(data.as_ptr(), data.len()) };
+        self.allocator
+            .flush_allocation(&self.allocation, offset as usize, data.len())?;
+
+        Ok(())
+    }
+
+    /// Reads `len` bytes at `offset` after invalidating the host caches, so writes of finished GPU
+    /// work are visible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is not host visible or the range is out of bounds.
+    pub fn read(&self, offset: vk::DeviceSize, len: usize) -> Result<Vec<u8>> {
+        let ptr = self.mapped_range(offset, len);
+
+        self.allocator
+            .invalidate_allocation(&self.allocation, offset as usize, len)?;
+
+        let mut data = vec![0u8; len];
+        unsafe { ptr.copy_to_nonoverlapping(data.as_mut_ptr(), len) };
+
+        Ok(data)
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn get_size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn get_location(&self) -> MemoryLocation {
+        self.location
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Address shaders can read the buffer through, `None` unless the buffer was created with
+    /// `SHADER_DEVICE_ADDRESS` usage.
+    pub fn get_device_address(&self) -> Option<vk::DeviceAddress> {
+        self.device_address
+    }
+
+    /// Pointer to the start of the mapped memory, `None` for [`MemoryLocation::GpuOnly`].
+    pub fn get_mapped(&self) -> Option<NonNull<u8>> {
+        self.mapped
+    }
+
+    fn mapped_range(&self, offset: vk::DeviceSize, len: usize) -> *mut u8 {
+        let mapped = self
+            .mapped
+            .unwrap_or_else(|| panic!("Buffer {} is not host visible", self.name));
+        assert!(
+            offset + len as vk::DeviceSize <= self.size,
+            "Range {}..{} is out of bounds of buffer {} of {} bytes",
+            offset,
+            offset + len as vk::DeviceSize,
+            self.name,
+            self.size
+        );
+
+        unsafe { mapped.as_ptr().add(offset as usize) }
+    }
+}
+
+impl fmt::Debug for GpuBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GpuBuffer")
+            .field("name", &self.name)
+            .field("buffer", &self.buffer)
+            .field("size", &self.size)
+            .field("location", &self.location)
+            .finish()
+    }
+}
+
+impl Drop for GpuBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.allocator
+                .destroy_buffer(self.buffer, &mut self.allocation)
+        };
+    }
+}
+
+/// A [`vk::Image`] with its own allocation, both freed when it is dropped.
+///
+/// Views are not part of the image, as they depend on how it is used.
+pub struct GpuImage {
+    allocator: Arc<Allocator>,
+    image: vk::Image,
+    allocation: Allocation,
+    format: vk::Format,
+    extent: vk::Extent3D,
+    mip_levels: u32,
+    name: String,
+}
+
+impl GpuImage {
+    /// Creates an image from `image_info` in `location`, naming it `name` in debug tools.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image could not be created or there is no memory left in
+    /// `location`.
+    pub fn new(
+        vk: &Vk,
+        name: &str,
+        image_info: &vk::ImageCreateInfo,
+        location: MemoryLocation,
+    ) -> Result<Self> {
+        let allocator = vk.get_allocator().clone();
+
+        let (image, allocation) =
+            unsafe { allocator.create_image(image_info, &location.allocation_info())? };
+
+        vk.set_debug_name(image, name);
+        debug!(
+            "Allocated image {} of {}x{} {:?} in {:?}",
+            name, image_info.extent.width, image_info.extent.height, image_info.format, location
+        );
+
+        Ok(Self {
+            allocator,
+            image,
+            allocation,
+            format: image_info.format,
+            extent: image_info.extent,
+            mip_levels: image_info.mip_levels,
+            name: name.to_owned(),
+        })
+    }
+
+    pub fn get_image(&self) -> vk::Image {
+        self.image
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn get_extent(&self) -> vk::Extent3D {
+        self.extent
+    }
+
+    pub fn get_mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for GpuImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GpuImage")
+            .field("name", &self.name)
+            .field("image", &self.image)
+            .field("format", &self.format)
+            .field("extent", &self.extent)
+            .finish()
+    }
+}
+
+impl Drop for GpuImage {
+    fn drop(&mut self) {
+        unsafe {
+            self.allocator
+                .destroy_image(self.image, &mut self.allocation)
+        };
+    }
+}
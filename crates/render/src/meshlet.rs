@@ -0,0 +1,623 @@
+use ash::{vk, Device};
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use tracing::info;
+
+use crate::{
+    buffer::{Buffer, BufferAddress, BufferUsage},
+    error::Result,
+    pipeline::{create_graphics_pipeline, GraphicsPipeline, PipelineDesc, PushConstants},
+    shader::{ShaderSource, ShaderStage},
+    vertex::MeshVertex,
+    Vk,
+};
+
+/// Most vertices of a meshlet, the `max_vertices` of mesh shaders drawing them.
+pub const MAX_MESHLET_VERTICES: usize = 64;
+/// Most triangles of a meshlet, the `max_primitives` of mesh shaders drawing them.
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+/// Meshlets culled by one task shader workgroup, the `local_size_x` of task shaders.
+pub const TASK_WORKGROUP_SIZE: u32 = 32;
+
+/// Declarations of [`Meshlet`] and [`MeshletConstants`] shared by the meshlet shaders, to be
+/// pasted into GLSL shaders after `#version 460`.
+///
+/// `is_meshlet_visible` culls meshlets outside the frustum of `view_projection` or facing away
+/// from `camera`, see [`Meshlet::is_backfacing`].
+pub const MESHLET_GLSL_DECLARATIONS: &str = "\
+#extension GL_EXT_buffer_reference : require
+
+struct Meshlet {
+    uint vertex_offset;
+    uint triangle_offset;
+    uint vertex_count;
+    uint triangle_count;
+    vec3 center;
+    float radius;
+    vec3 cone_axis;
+    float cone_cutoff;
+};
+
+// the meshlets a task shader workgroup found visible
+struct MeshletTask {
+    uint meshlets[32];
+};
+
+layout(buffer_reference, std430) readonly buffer Meshlets { Meshlet meshlets[]; };
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer MeshletIndices {
+    uint indices[];
+};
+// 12 floats per vertex, position, normal, tangent and uv
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer MeshVertices {
+    float vertices[];
+};
+
+layout(push_constant) uniform MeshletConstants {
+    mat4 view_projection;
+    vec3 camera;
+    Meshlets meshlets;
+    MeshletIndices meshlet_vertices;
+    MeshletIndices meshlet_triangles;
+    uint meshlet_count;
+    MeshVertices vertices;
+} constants;
+
+bool is_meshlet_visible(Meshlet meshlet) {
+    vec3 to_center = meshlet.center - constants.camera;
+    if (dot(to_center, meshlet.cone_axis) >=
+            meshlet.cone_cutoff * length(to_center) + meshlet.radius) {
+        return false;
+    }
+
+    // the left, right, top, bottom and near planes from the rows of the matrix
+    mat4 rows = transpose(constants.view_projection);
+    vec4 planes[5] = vec4[](
+        rows[3] + rows[0], rows[3] - rows[0], rows[3] + rows[1], rows[3] - rows[1], rows[2]);
+    for (int i = 0; i < 5; i++) {
+        if (dot(planes[i], vec4(meshlet.center, 1.0)) < -meshlet.radius * length(planes[i].xyz)) {
+            return false;
+        }
+    }
+
+    return true;
+}
+";
+
+/// A task shader launching a mesh shader workgroup per visible meshlet, to be compiled after
+/// `#version 460` and [`MESHLET_GLSL_DECLARATIONS`].
+pub const MESHLET_TASK_GLSL: &str = "\
+#extension GL_EXT_mesh_shader : require
+
+layout(local_size_x = 32) in;
+
+taskPayloadSharedEXT MeshletTask task;
+
+shared uint visible_count;
+
+void main() {
+    if (gl_LocalInvocationIndex == 0) {
+        visible_count = 0;
+    }
+    barrier();
+
+    uint index = gl_GlobalInvocationID.x;
+    if (index < constants.meshlet_count && is_meshlet_visible(constants.meshlets.meshlets[index])) {
+        task.meshlets[atomicAdd(visible_count, 1)] = index;
+    }
+    barrier();
+
+    EmitMeshTasksEXT(visible_count, 1, 1);
+}
+";
+
+/// A mesh shader drawing the meshlets of [`MESHLET_TASK_GLSL`] with [`MeshVertex`]es, to be
+/// compiled after `#version 460` and [`MESHLET_GLSL_DECLARATIONS`].
+///
+/// Passes the normal, tangent and uv to the fragment shader at locations 0, 1 and 2, like
+/// [`MESHLET_VERTEX_GLSL`].
+pub const MESHLET_MESH_GLSL: &str = "\
+#extension GL_EXT_mesh_shader : require
+
+layout(local_size_x = 32) in;
+layout(triangles, max_vertices = 64, max_primitives = 124) out;
+
+layout(location = 0) out vec3 out_normal[];
+layout(location = 1) out vec4 out_tangent[];
+layout(location = 2) out vec2 out_uv[];
+
+taskPayloadSharedEXT MeshletTask task;
+
+void main() {
+    Meshlet meshlet = constants.meshlets.meshlets[task.meshlets[gl_WorkGroupID.x]];
+    SetMeshOutputsEXT(meshlet.vertex_count, meshlet.triangle_count);
+
+    for (uint i = gl_LocalInvocationIndex; i < meshlet.vertex_count; i += 32) {
+        uint first = constants.meshlet_vertices.indices[meshlet.vertex_offset + i] * 12;
+        float v[12];
+        for (uint j = 0; j < 12; j++) {
+            v[j] = constants.vertices.vertices[first + j];
+        }
+
+        gl_MeshVerticesEXT[i].gl_Position = constants.view_projection * vec4(v[0], v[1], v[2], 1.0);
+        out_normal[i] = vec3(v[3], v[4], v[5]);
+        out_tangent[i] = vec4(v[6], v[7], v[8], v[9]);
+        out_uv[i] = vec2(v[10], v[11]);
+    }
+
+    for (uint i = gl_LocalInvocationIndex; i < meshlet.triangle_count; i += 32) {
+        uint triangle = constants.meshlet_triangles.indices[meshlet.triangle_offset + i];
+        gl_PrimitiveTriangleIndicesEXT[i] =
+            uvec3(triangle & 0xff, (triangle >> 8) & 0xff, (triangle >> 16) & 0xff);
+    }
+}
+";
+
+/// A vertex shader for the vertex path of [`MeshletPipeline`] with the outputs of
+/// [`MESHLET_MESH_GLSL`], to be compiled after `#version 460` and [`MESHLET_GLSL_DECLARATIONS`].
+pub const MESHLET_VERTEX_GLSL: &str = "\
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec4 out_tangent;
+layout(location = 2) out vec2 out_uv;
+
+void main() {
+    gl_Position = constants.view_projection * vec4(position, 1.0);
+    out_normal = normal;
+    out_tangent = tangent;
+    out_uv = uv;
+}
+";
+
+/// A small cluster of triangles, culled as a whole by task shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Meshlet {
+    /// First element of [`Meshlets::vertices`].
+    pub vertex_offset: u32,
+    /// First element of [`Meshlets::triangles`].
+    pub triangle_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_count: u32,
+    /// Center of the bounding sphere.
+    pub center: [f32; 3],
+    pub radius: f32,
+    /// Axis of the cone containing the normals of every triangle, see [`Meshlet::is_backfacing`].
+    pub cone_axis: [f32; 3],
+    pub cone_cutoff: f32,
+}
+
+impl Meshlet {
+    /// Whether every triangle faces away from a camera at `camera`, the test
+    /// [`MESHLET_TASK_GLSL`] does besides frustum culling.
+    pub fn is_backfacing(&self, camera: Vec3) -> bool {
+        let to_center = Vec3::from(self.center) - camera;
+
+        to_center.dot(self.cone_axis.into()) >= self.cone_cutoff * to_center.length() + self.radius
+    }
+}
+
+/// An indexed mesh split into [`Meshlet`]s.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Meshlets {
+    pub meshlets: Vec<Meshlet>,
+    /// Indices into the vertex buffer of the mesh, [`Meshlet::vertex_count`] per meshlet.
+    pub vertices: Vec<u32>,
+    /// Triangles as three 8 bit indices into the vertices of their meshlet, packed into the low
+    /// 24 bits of a `u32`.
+    pub triangles: Vec<u32>,
+}
+
+impl Meshlets {
+    /// Splits the triangle list `indices` into meshlets, `positions` being the positions of the
+    /// vertices they index.
+    ///
+    /// Triangles are taken in order, so a mesh already optimized for the vertex cache makes
+    /// meshlets that share few vertices. Degenerate triangles are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `indices` is not a list of triangles or an index is out of bounds of
+    /// `positions`.
+    pub fn build(indices: &[u32], positions: &[[f32; 3]]) -> Self {
+        assert!(
+            indices.len().is_multiple_of(3),
+            "{} indices are not a list of triangles",
+            indices.len()
+        );
+
+        let mut meshlets = Meshlets::default();
+        // index of every vertex in the current meshlet, `u8::MAX` if it is not part of it
+        let mut local = vec![u8::MAX; positions.len()];
+        let mut current = MeshletBuilder::default();
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+            if a == b || b == c || a == c {
+                continue;
+            }
+
+            let new_vertices = triangle
+                .iter()
+                .filter(|&&vertex| local[vertex as usize] == u8::MAX)
+                .count();
+            if current.vertices.len() + new_vertices > MAX_MESHLET_VERTICES
+                || current.triangles.len() == MAX_MESHLET_TRIANGLES
+            {
+                current.finish(&mut meshlets, &mut local, positions);
+            }
+
+            let mut packed = 0;
+            for (i, &vertex) in triangle.iter().enumerate() {
+                if local[vertex as usize] == u8::MAX {
+                    local[vertex as usize] = current.vertices.len() as u8;
+                    current.vertices.push(vertex);
+                }
+                packed |= (local[vertex as usize] as u32) << (i * 8);
+            }
+            current.triangles.push(packed);
+        }
+        current.finish(&mut meshlets, &mut local, positions);
+
+        meshlets
+    }
+
+    /// The triangles of every meshlet as a triangle list into the vertex buffer of the mesh, for
+    /// drawing them without mesh shaders.
+    pub fn to_indices(&self) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(self.triangles.len() * 3);
+        for meshlet in &self.meshlets {
+            let vertices = &self.vertices[meshlet.vertex_offset as usize..];
+            let start = meshlet.triangle_offset as usize;
+
+            for &triangle in &self.triangles[start..start + meshlet.triangle_count as usize] {
+                indices.extend((0..3).map(|i| vertices[(triangle >> (i * 8)) as usize & 0xff]));
+            }
+        }
+
+        indices
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshlets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshlets.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct MeshletBuilder {
+    vertices: Vec<u32>,
+    triangles: Vec<u32>,
+}
+
+impl MeshletBuilder {
+    /// Adds the meshlet to `meshlets` and starts over, resetting `local` for the next meshlet.
+    fn finish(&mut self, meshlets: &mut Meshlets, local: &mut [u8], positions: &[[f32; 3]]) {
+        if self.triangles.is_empty() {
+            return;
+        }
+
+        let (center, radius) = self.get_bounding_sphere(positions);
+        let (cone_axis, cone_cutoff) = self.get_normal_cone(positions);
+
+        meshlets.meshlets.push(Meshlet {
+            vertex_offset: meshlets.vertices.len() as u32,
+            triangle_offset: meshlets.triangles.len() as u32,
+            vertex_count: self.vertices.len() as u32,
+            triangle_count: self.triangles.len() as u32,
+            center,
+            radius,
+            cone_axis,
+            cone_cutoff,
+        });
+
+        for &vertex in &self.vertices {
+            local[vertex as usize] = u8::MAX;
+        }
+        meshlets.vertices.append(&mut self.vertices);
+        meshlets.triangles.append(&mut self.triangles);
+    }
+
+    /// The sphere around the center of the bounding box, not the smallest one but cheap.
+    fn get_bounding_sphere(&self, positions: &[[f32; 3]]) -> ([f32; 3], f32) {
+        let positions = || {
+            self.vertices
+                .iter()
+                .map(|&vertex| Vec3::from(positions[vertex as usize]))
+        };
+        let min = positions().fold(Vec3::MAX, Vec3::min);
+        let max = positions().fold(Vec3::MIN, Vec3::max);
+
+        let center = (min + max) * 0.5;
+        let radius = positions()
+            .map(|position| position.distance(center))
+            .fold(0., f32::max);
+
+        (center.into(), radius)
+    }
+
+    /// The average normal and the cutoff of the cone around it containing every normal, with a
+    /// cutoff of 1 if the normals spread too far for the meshlet to ever be culled.
+    fn get_normal_cone(&self, positions: &[[f32; 3]]) -> ([f32; 3], f32) {
+        let normals: Vec<Vec3> = self
+            .triangles
+            .iter()
+            .filter_map(|&triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| {
+                    Vec3::from(
+                        positions[self.vertices[(triangle >> (i * 8)) as usize & 0xff] as usize],
+                    )
+                });
+                (b - a).cross(c - a).try_normalize()
+            })
+            .collect();
+
+        let Some(axis) = normals.iter().sum::<Vec3>().try_normalize() else {
+            return ([0., 0., 1.], 1.);
+        };
+
+        let min_dot = normals
+            .iter()
+            .map(|normal| normal.dot(axis))
+            .fold(1., f32::min);
+        // the cone is wider than a hemisphere, some triangle always faces the camera
+        if min_dot <= 0.1 {
+            return (axis.into(), 1.);
+        }
+
+        (axis.into(), (1. - min_dot * min_dot).sqrt())
+    }
+}
+
+/// Addresses of the buffers of a [`MeshletMesh`], pushed to task and mesh shaders as part of
+/// their push constants.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+pub struct MeshletAddresses {
+    pub meshlets: BufferAddress<Meshlet>,
+    pub vertices: BufferAddress<u32>,
+    pub triangles: BufferAddress<u32>,
+    pub meshlet_count: u32,
+    pub _pad: u32,
+}
+
+/// The push constants of the meshlet shaders, see [`MESHLET_GLSL_DECLARATIONS`].
+///
+/// Positions are only transformed by `view_projection`, so `camera` is in the space of the
+/// mesh.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct MeshletConstants {
+    /// Column major, as from [`glam::Mat4::to_cols_array_2d`].
+    pub view_projection: [[f32; 4]; 4],
+    pub camera: [f32; 3],
+    pub _pad: u32,
+    /// Zeroed on the vertex path.
+    pub addresses: MeshletAddresses,
+    /// The vertex buffer of the mesh, zeroed on the vertex path.
+    pub vertices: BufferAddress<MeshVertex>,
+}
+
+impl PushConstants for MeshletConstants {}
+
+struct MeshShadingBuffers {
+    meshlets: Buffer<Meshlet>,
+    vertices: Buffer<u32>,
+    triangles: Buffer<u32>,
+}
+
+enum MeshletBuffers {
+    MeshShading(Box<MeshShadingBuffers>),
+    Vertex(Buffer<u32>),
+}
+
+/// [`Meshlets`] uploaded for the path the device supports, as storage buffers read by mesh
+/// shaders or as an index buffer for the vertex pipeline.
+pub struct MeshletMesh {
+    buffers: MeshletBuffers,
+    meshlet_count: u32,
+    index_count: u32,
+}
+
+impl MeshletMesh {
+    /// # Errors
+    ///
+    /// Returns an error if the buffers could not be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `meshlets` is empty.
+    pub fn new(vk: &Vk, name: &str, meshlets: &Meshlets) -> Result<Self> {
+        let buffers = if vk.get_mesh_shader().is_some() {
+            MeshletBuffers::MeshShading(Box::new(MeshShadingBuffers {
+                meshlets: Buffer::from_slice(
+                    vk,
+                    &format!("{} meshlets", name),
+                    BufferUsage::Storage,
+                    &meshlets.meshlets,
+                )?,
+                vertices: Buffer::from_slice(
+                    vk,
+                    &format!("{} meshlet vertices", name),
+                    BufferUsage::Storage,
+                    &meshlets.vertices,
+                )?,
+                triangles: Buffer::from_slice(
+                    vk,
+                    &format!("{} meshlet triangles", name),
+                    BufferUsage::Storage,
+                    &meshlets.triangles,
+                )?,
+            }))
+        } else {
+            MeshletBuffers::Vertex(Buffer::from_slice(
+                vk,
+                &format!("{} indices", name),
+                BufferUsage::Index,
+                &meshlets.to_indices(),
+            )?)
+        };
+
+        Ok(Self {
+            buffers,
+            meshlet_count: meshlets.len() as u32,
+            index_count: meshlets.triangles.len() as u32 * 3,
+        })
+    }
+
+    /// The addresses for the task and mesh shaders, `None` on the vertex path.
+    pub fn get_addresses(&self) -> Option<MeshletAddresses> {
+        match &self.buffers {
+            MeshletBuffers::MeshShading(buffers) => Some(MeshletAddresses {
+                meshlets: buffers.meshlets.get_device_address(),
+                vertices: buffers.vertices.get_device_address(),
+                triangles: buffers.triangles.get_device_address(),
+                meshlet_count: self.meshlet_count,
+                _pad: 0,
+            }),
+            MeshletBuffers::Vertex(_) => None,
+        }
+    }
+
+    pub fn get_meshlet_count(&self) -> u32 {
+        self.meshlet_count
+    }
+
+    /// Records drawing the mesh with the bound [`MeshletPipeline`], launching a task shader
+    /// workgroup per [`TASK_WORKGROUP_SIZE`] meshlets or drawing the index buffer.
+    ///
+    /// On the vertex path the vertex buffer of the mesh has to be bound at binding 0.
+    pub fn draw(&self, vk: &Vk, cmd: vk::CommandBuffer) {
+        match &self.buffers {
+            MeshletBuffers::MeshShading(_) => {
+                let mesh_shader = vk
+                    .get_mesh_shader()
+                    .expect("Mesh buffers are only made when mesh shaders are supported");
+                let group_count = self.meshlet_count.div_ceil(TASK_WORKGROUP_SIZE);
+
+                unsafe { mesh_shader.cmd_draw_mesh_tasks(cmd, group_count, 1, 1) };
+            }
+            MeshletBuffers::Vertex(indices) => unsafe {
+                let device = vk.get_device();
+                device.cmd_bind_index_buffer(
+                    cmd,
+                    indices.get_buffer(),
+                    0,
+                    indices.get_index_type(),
+                );
+                device.cmd_draw_indexed(cmd, self.index_count, 1, 0, 0, 0);
+            },
+        }
+    }
+}
+
+/// Draws [`MeshletMesh`]es with task and mesh shaders where the device supports them, and with
+/// a classic vertex pipeline everywhere else.
+///
+/// The mesh path uses the given layout, usually the one of [`crate::bindless::Bindless`] with
+/// [`MeshletAddresses`] in the push constants. [`MESHLET_TASK_GLSL`] and [`MESHLET_MESH_GLSL`]
+/// cull and draw meshlets of [`MeshVertex`]es with [`MeshletConstants`], and
+/// [`MESHLET_VERTEX_GLSL`] draws them the same on the vertex path.
+pub struct MeshletPipeline {
+    device: Device,
+    /// The pipeline and the layout it was made with, `None` on the vertex path.
+    mesh: Option<(vk::Pipeline, vk::PipelineLayout)>,
+    /// Only made on the vertex path.
+    fallback: Option<GraphicsPipeline>,
+}
+
+impl MeshletPipeline {
+    /// Creates the pipeline of `mesh_desc`, a task, mesh and fragment shader, with `mesh_layout`
+    /// if the device supports mesh shaders, and the one of `fallback_desc` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shaders of the chosen path do not compile or the pipeline could not
+    /// be created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mesh_desc` has no mesh shader.
+    pub fn new(
+        vk: &Vk,
+        mesh_desc: &PipelineDesc,
+        mesh_layout: vk::PipelineLayout,
+        fallback_desc: &PipelineDesc,
+        cache: vk::PipelineCache,
+    ) -> Result<Self> {
+        assert!(
+            mesh_desc
+                .shaders
+                .iter()
+                .any(|shader| shader.stage == ShaderStage::Mesh),
+            "The mesh shading pipeline needs a mesh shader"
+        );
+
+        let device = vk.get_device().clone();
+        if vk.get_mesh_shader().is_none() {
+            info!("Drawing meshlets with the vertex pipeline, mesh shaders are not supported");
+            return Ok(Self {
+                device,
+                mesh: None,
+                fallback: Some(GraphicsPipeline::new(vk, fallback_desc, cache)?),
+            });
+        }
+
+        let shaders = mesh_desc
+            .shaders
+            .iter()
+            .map(ShaderSource::compile)
+            .collect::<Result<Vec<_>>>()?;
+        let pipeline = create_graphics_pipeline(vk, mesh_desc, &shaders, mesh_layout, cache)?;
+
+        Ok(Self {
+            device,
+            mesh: Some((pipeline, mesh_layout)),
+            fallback: None,
+        })
+    }
+
+    /// Whether meshlets are drawn by mesh shaders rather than the vertex pipeline.
+    pub fn is_mesh_shading(&self) -> bool {
+        self.mesh.is_some()
+    }
+
+    pub fn get_pipeline(&self) -> vk::Pipeline {
+        match (&self.mesh, &self.fallback) {
+            (Some((pipeline, _)), _) => *pipeline,
+            (None, Some(fallback)) => fallback.get_pipeline(),
+            (None, None) => unreachable!("One of the paths is always made"),
+        }
+    }
+
+    pub fn get_layout(&self) -> vk::PipelineLayout {
+        match (&self.mesh, &self.fallback) {
+            (Some((_, layout)), _) => *layout,
+            (None, Some(fallback)) => fallback.get_layout(),
+            (None, None) => unreachable!("One of the paths is always made"),
+        }
+    }
+
+    /// Records binding the pipeline of the chosen path.
+    pub fn bind(&self, cmd: vk::CommandBuffer) {
+        unsafe {
+            self.device
+                .cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.get_pipeline())
+        };
+    }
+}
+
+impl Drop for MeshletPipeline {
+    fn drop(&mut self) {
+        // the layout belongs to the caller, the fallback destroys itself
+        if let Some((pipeline, _)) = self.mesh {
+            unsafe { self.device.destroy_pipeline(pipeline, None) };
+        }
+    }
+}
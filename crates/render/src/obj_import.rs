@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use tracing::{debug, warn};
+
+use crate::{
+    error::{RenderError, Result},
+    sampler::SamplerDesc,
+    scene::{AlphaMode, Material, Mesh, Node, Primitive, Scene, SceneTexture},
+    texture::{ColorSpace, TextureData},
+    vertex::MeshVertex,
+};
+
+/// Polygons are fanned into triangles and every distinct position, normal and texture
+/// coordinate combination becomes one vertex.
+const LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    single_index: true,
+    triangulate: true,
+    ignore_points: true,
+    ignore_lines: true,
+};
+
+/// Imports the Wavefront `.obj` file at `path` and the `.mtl` files it refers to, resolving
+/// both and the texture maps relative to it.
+///
+/// Every object or group becomes a mesh under its own root node, with a primitive per material.
+/// Missing material files and texture maps are skipped with a warning.
+///
+/// # Errors
+///
+/// Returns [`RenderError::ModelLoad`] if the file could not be read or parsed.
+pub fn load(path: impl AsRef<Path>) -> Result<Scene> {
+    let path = path.as_ref();
+    let importer = Importer {
+        name: path.display().to_string(),
+        base: path.parent().map(Path::to_path_buf),
+    };
+
+    let (models, materials) = tobj::load_obj(path, &LOAD_OPTIONS).map_err(|e| importer.error(e))?;
+    importer.import(models, materials)
+}
+
+/// Imports a Wavefront `.obj` file in memory, with `mtl` as the contents of every material
+/// file it refers to. Texture maps are skipped, as there is no directory to resolve them in.
+///
+/// # Errors
+///
+/// Returns [`RenderError::ModelLoad`] if `obj` could not be parsed.
+pub fn from_memory(obj: &[u8], mtl: Option<&[u8]>) -> Result<Scene> {
+    let importer = Importer {
+        name: "in memory".to_owned(),
+        base: None,
+    };
+
+    let (models, materials) =
+        tobj::load_obj_buf(&mut BufReader::new(obj), &LOAD_OPTIONS, |_| match mtl {
+            Some(mtl) => tobj::load_mtl_buf(&mut BufReader::new(mtl)),
+            None => Err(tobj::LoadError::OpenFileFailed),
+        })
+        .map_err(|e| importer.error(e))?;
+    importer.import(models, materials)
+}
+
+struct Importer {
+    name: String,
+    /// The directory texture maps are resolved in.
+    base: Option<PathBuf>,
+}
+
+impl Importer {
+    fn error(&self, message: impl ToString) -> RenderError {
+        RenderError::ModelLoad {
+            name: self.name.clone(),
+            message: message.to_string(),
+        }
+    }
+
+    fn import(
+        &self,
+        models: Vec<tobj::Model>,
+        materials: std::result::Result<Vec<tobj::Material>, tobj::LoadError>,
+    ) -> Result<Scene> {
+        let materials = materials.unwrap_or_else(|e| {
+            warn!("Could not load the materials of {}: {}", self.name, e);
+            Vec::new()
+        });
+
+        let mut scene = Scene::default();
+        let mut textures = HashMap::new();
+        for material in &materials {
+            let material = self.import_material(material, &mut scene.textures, &mut textures);
+            scene.materials.push(material);
+        }
+
+        for model in models {
+            let Some(primitive) = self.import_primitive(&model.mesh, &scene.materials) else {
+                continue;
+            };
+
+            // a material change within an object starts a model of the same name
+            match scene.meshes.last_mut() {
+                Some(mesh) if mesh.name.as_deref() == Some(model.name.as_str()) => {
+                    mesh.primitives.push(primitive);
+                }
+                _ => {
+                    scene.roots.push(scene.nodes.len());
+                    scene.nodes.push(Node {
+                        name: Some(model.name.clone()),
+                        mesh: Some(scene.meshes.len()),
+                        ..Default::default()
+                    });
+                    scene.meshes.push(Mesh {
+                        name: Some(model.name),
+                        primitives: vec![primitive],
+                    });
+                }
+            }
+        }
+        debug!(
+            "Imported {} with {} meshes, {} materials and {} textures",
+            self.name,
+            scene.meshes.len(),
+            scene.materials.len(),
+            scene.textures.len()
+        );
+
+        Ok(scene)
+    }
+
+    fn import_primitive(&self, mesh: &tobj::Mesh, materials: &[Material]) -> Option<Primitive> {
+        if mesh.indices.is_empty() {
+            return None;
+        }
+
+        let material = mesh.material_id.filter(|&id| id < materials.len());
+        let mut primitive = Primitive {
+            vertices: mesh
+                .positions
+                .chunks_exact(3)
+                .enumerate()
+                .map(|(i, position)| {
+                    let normal = mesh.normals.get(3 * i..3 * i + 3).unwrap_or(&[0.; 3]);
+                    // the origin of OBJ texture coordinates is the bottom left of the image
+                    let uv = mesh
+                        .texcoords
+                        .get(2 * i..2 * i + 2)
+                        .map_or([0.; 2], |uv| [uv[0], 1. - uv[1]]);
+
+                    MeshVertex::new(
+                        [position[0], position[1], position[2]],
+                        [normal[0], normal[1], normal[2]],
+                        uv,
+                    )
+                })
+                .collect(),
+            indices: mesh.indices.clone(),
+            material,
+        };
+
+        if primitive
+            .indices
+            .iter()
+            .any(|&index| index as usize >= primitive.vertices.len())
+        {
+            warn!(
+                "Skipping primitive with out of bounds indices in {}",
+                self.name
+            );
+            return None;
+        }
+
+        if mesh.normals.is_empty() {
+            primitive.generate_normals();
+        }
+        if material.is_some_and(|id| materials[id].normal_texture.is_some()) {
+            primitive.generate_tangents();
+        }
+
+        Some(primitive)
+    }
+
+    /// Maps the Phong parameters of an MTL material onto a metallic roughness material.
+    ///
+    /// The diffuse map and color become the base color and the dissolve its alpha. `norm` or
+    /// else `bump` is taken as a tangent space normal map, as most exporters write them there.
+    fn import_material(
+        &self,
+        material: &tobj::Material,
+        textures: &mut Vec<SceneTexture>,
+        cache: &mut HashMap<(PathBuf, ColorSpace), Option<usize>>,
+    ) -> Material {
+        let defaults = Material::default();
+        let [r, g, b] = material.diffuse.unwrap_or([1.; 3]);
+        let alpha = material.dissolve.unwrap_or(1.);
+
+        let mut texture = |map: Option<&String>, color_space| {
+            self.import_texture(map?, color_space, textures, cache)
+        };
+        let base_color_texture = texture(material.diffuse_texture.as_ref(), ColorSpace::Srgb);
+        let normal_texture = texture(
+            material
+                .unknown_param
+                .get("norm")
+                .or(material.normal_texture.as_ref()),
+            ColorSpace::Linear,
+        );
+
+        Material {
+            name: Some(material.name.clone()),
+            base_color_factor: [r, g, b, alpha],
+            base_color_texture,
+            metallic_factor: 0.,
+            // the usual fit of a Blinn-Phong exponent to a GGX roughness
+            roughness_factor: material
+                .shininess
+                .map_or(defaults.roughness_factor, |shininess| {
+                    (2. / (shininess.max(0.) + 2.)).sqrt()
+                }),
+            normal_texture,
+            alpha_mode: if alpha < 1. {
+                AlphaMode::Blend
+            } else {
+                AlphaMode::Opaque
+            },
+            ..defaults
+        }
+    }
+
+    /// Decodes a texture map once per color space, returning its index in the scene.
+    fn import_texture(
+        &self,
+        map: &str,
+        color_space: ColorSpace,
+        textures: &mut Vec<SceneTexture>,
+        cache: &mut HashMap<(PathBuf, ColorSpace), Option<usize>>,
+    ) -> Option<usize> {
+        let Some(base) = &self.base else {
+            warn!("Skipping texture map {} of {}", map, self.name);
+            return None;
+        };
+        let path = base.join(map_file(map));
+
+        *cache
+            .entry((path, color_space))
+            .or_insert_with_key(|(path, _)| match TextureData::load(path, color_space) {
+                Ok(data) => {
+                    textures.push(SceneTexture {
+                        name: Some(path.display().to_string()),
+                        data,
+                        sampler: SamplerDesc::default(),
+                    });
+                    Some(textures.len() - 1)
+                }
+                Err(e) => {
+                    warn!("Skipping texture map of {}: {}", self.name, e);
+                    None
+                }
+            })
+    }
+}
+
+/// The file name of a texture map statement, which may start with options such as
+/// `-bm 0.5 normal.png`.
+fn map_file(map: &str) -> &str {
+    if map.starts_with('-') {
+        map.split_whitespace().last().unwrap_or(map)
+    } else {
+        map
+    }
+}
@@ -0,0 +1,349 @@
+use ash::{vk, Device};
+
+use crate::{
+    error::{RenderError, Result},
+    memory::{GpuBuffer, GpuImage, MemoryLocation},
+    utils::{aspect_of, format_texel_size},
+    Vk,
+};
+
+/// Depth formats in order of preference. The fallbacks have a stencil aspect too, so depth
+/// attachments are transitioned with [`aspect_of`] into the combined depth stencil layouts, which
+/// unlike the depth only layouts need no `separateDepthStencilLayouts`.
+pub const DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
+
+pub(crate) struct Attachment {
+    pub(crate) image: GpuImage,
+    pub(crate) view: vk::ImageView,
+}
+
+/// A color and depth attachment pair that is rendered to instead of a swapchain image, together
+/// with a host visible buffer the color image is copied into so it can be read back on the CPU.
+///
+/// Rendering uses dynamic rendering, so no render pass or framebuffer is needed.
+pub struct OffscreenTarget {
+    device: Device,
+    extent: vk::Extent2D,
+    color: Attachment,
+    depth: Attachment,
+    readback: GpuBuffer,
+}
+
+impl OffscreenTarget {
+    /// Creates a new [`OffscreenTarget`] of `extent` with a `color_format` color attachment and the
+    /// depth format from [`find_depth_format`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::UnsupportedFormat`] if `color_format` can not be read back, or an
+    /// error if the device supports none of the depth formats or the images or readback buffer
+    /// could not be created.
+    pub fn new(vk: &Vk, extent: vk::Extent2D, color_format: vk::Format) -> Result<Self> {
+        let texel_size =
+            format_texel_size(color_format).ok_or(RenderError::UnsupportedFormat(color_format))?;
+        let depth_format = find_depth_format(vk)?;
+
+        let color = create_attachment(
+            vk,
+            "offscreen color",
+            extent,
+            color_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageAspectFlags::COLOR,
+        )?;
+        let depth = create_attachment(
+            vk,
+            "offscreen depth",
+            extent,
+            depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            aspect_of(depth_format),
+        )?;
+
+        let readback_size = extent.width as vk::DeviceSize
+            * extent.height as vk::DeviceSize
+            * texel_size as vk::DeviceSize;
+
+        let readback = GpuBuffer::new(
+            vk,
+            "offscreen readback",
+            readback_size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::Readback,
+        )?;
+
+        Ok(Self {
+            device: vk.get_device().clone(),
+            extent,
+            color,
+            depth,
+            readback,
+        })
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn get_color_format(&self) -> vk::Format {
+        self.color.image.get_format()
+    }
+
+    pub fn get_depth_format(&self) -> vk::Format {
+        self.depth.image.get_format()
+    }
+
+    pub fn get_color_view(&self) -> vk::ImageView {
+        self.color.view
+    }
+
+    pub fn get_depth_view(&self) -> vk::ImageView {
+        self.depth.view
+    }
+
+    /// Records the layout transitions into attachment layouts and begins dynamic rendering into
+    /// the target, clearing color to `clear_color` and depth to 1.0.
+    pub fn begin(&self, cmd: vk::CommandBuffer, clear_color: [f32; 4]) {
+        let barriers = [
+            image_barrier(
+                self.color.image.get_image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::TOP_OF_PIPE,
+                vk::AccessFlags2::NONE,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            ),
+            image_barrier(
+                self.depth.image.get_image(),
+                aspect_of(self.depth.image.get_format()),
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::TOP_OF_PIPE,
+                vk::AccessFlags2::NONE,
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+        ];
+        let dependency_info = vk::DependencyInfo::builder().image_memory_barriers(&barriers);
+
+        let color_attachments = [*vk::RenderingAttachmentInfo::builder()
+            .image_view(self.color.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color,
+                },
+            })];
+        let depth_attachment = vk::RenderingAttachmentInfo::builder()
+            .image_view(self.depth.view)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.,
+                    stencil: 0,
+                },
+            });
+
+        let rendering_info = vk::RenderingInfo::builder()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent,
+            })
+            .layer_count(1)
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment);
+
+        unsafe {
+            self.device.cmd_pipeline_barrier2(cmd, &dependency_info);
+            self.device.cmd_begin_rendering(cmd, &rendering_info);
+        }
+    }
+
+    /// Ends dynamic rendering and records the copy of the color attachment into the readback
+    /// buffer.
+    pub fn end(&self, cmd: vk::CommandBuffer) {
+        let to_transfer = [image_barrier(
+            self.color.image.get_image(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            vk::PipelineStageFlags2::COPY,
+            vk::AccessFlags2::TRANSFER_READ,
+        )];
+        let to_transfer = vk::DependencyInfo::builder().image_memory_barriers(&to_transfer);
+
+        let region = [*vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            })];
+
+        let to_host = [*vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ)
+            .buffer(self.readback.get_buffer())
+            .offset(0)
+            .size(vk::WHOLE_SIZE)];
+        let to_host = vk::DependencyInfo::builder().buffer_memory_barriers(&to_host);
+
+        unsafe {
+            self.device.cmd_end_rendering(cmd);
+            self.device.cmd_pipeline_barrier2(cmd, &to_transfer);
+            self.device.cmd_copy_image_to_buffer(
+                cmd,
+                self.color.image.get_image(),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.readback.get_buffer(),
+                &region,
+            );
+            self.device.cmd_pipeline_barrier2(cmd, &to_host);
+        }
+    }
+
+    /// Copies the contents of the readback buffer into a tightly packed, row major `Vec<u8>` in
+    /// the color format of the target.
+    ///
+    /// Only valid once the commands recorded by [`OffscreenTarget::end`] have finished executing.
+    pub fn read_back(&self) -> Result<Vec<u8>> {
+        self.readback.read(0, self.readback.get_size() as usize)
+    }
+}
+
+impl Drop for OffscreenTarget {
+    fn drop(&mut self) {
+        // the images and the readback buffer free themselves
+        unsafe {
+            for attachment in [&self.color, &self.depth] {
+                self.device.destroy_image_view(attachment.view, None);
+            }
+        }
+    }
+}
+
+/// Returns the first format in `candidates` that supports `features` with optimal tiling.
+pub fn find_supported_format(
+    vk: &Vk,
+    candidates: &[vk::Format],
+    features: vk::FormatFeatureFlags,
+) -> Option<vk::Format> {
+    candidates.iter().copied().find(|format| {
+        let props = unsafe {
+            vk.get_instance()
+                .get_physical_device_format_properties(vk.get_physical_device(), *format)
+        };
+
+        props.optimal_tiling_features.contains(features)
+    })
+}
+
+/// Returns the first format in [`DEPTH_FORMATS`] the device can use as a depth attachment.
+///
+/// # Errors
+///
+/// Returns [`RenderError::NoSuitableDevice`] if the device supports none of them.
+pub fn find_depth_format(vk: &Vk) -> Result<vk::Format> {
+    find_supported_format(
+        vk,
+        &DEPTH_FORMATS,
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+    )
+    .ok_or(RenderError::NoSuitableDevice)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn image_barrier(
+    image: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_stage_mask: vk::PipelineStageFlags2,
+    src_access_mask: vk::AccessFlags2,
+    dst_stage_mask: vk::PipelineStageFlags2,
+    dst_access_mask: vk::AccessFlags2,
+) -> vk::ImageMemoryBarrier2 {
+    *vk::ImageMemoryBarrier2::builder()
+        .src_stage_mask(src_stage_mask)
+        .src_access_mask(src_access_mask)
+        .dst_stage_mask(dst_stage_mask)
+        .dst_access_mask(dst_access_mask)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+}
+
+pub(crate) fn create_attachment(
+    vk: &Vk,
+    name: &str,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect_mask: vk::ImageAspectFlags,
+) -> Result<Attachment> {
+    let image_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let image = GpuImage::new(vk, name, &image_info, MemoryLocation::GpuOnly)?;
+
+    let view_info = vk::ImageViewCreateInfo::builder()
+        .image(image.get_image())
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        });
+
+    let view = unsafe { vk.get_device().create_image_view(&view_info, None)? };
+
+    Ok(Attachment { image, view })
+}
@@ -0,0 +1,709 @@
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    mem::size_of,
+    path::PathBuf,
+};
+
+use ash::{vk, Device};
+use bytemuck::Pod;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    error::Result,
+    reflect::{DescriptorSets, PipelineReflection, ReflectedLayout},
+    shader::{CompiledShader, ShaderSource, ShaderStage},
+    vertex::VertexLayout,
+    Vk,
+};
+
+/// A `#[repr(C)]` block pushed to shaders before a draw or dispatch.
+///
+/// Per draw data is meant to live in storage buffers that the block points at with
+/// [`crate::buffer::BufferAddress`] fields, so changing it needs no descriptor updates:
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod, Zeroable)]
+/// struct DrawConstants {
+///     transforms: BufferAddress<Mat4>,
+///     materials: BufferAddress<Material>,
+///     instance: u32,
+///     _pad: u32,
+/// }
+///
+/// impl PushConstants for DrawConstants {}
+/// ```
+///
+/// which matches a GLSL block using `GL_EXT_buffer_reference`:
+///
+/// ```glsl
+/// layout(buffer_reference, std430) readonly buffer Transforms { mat4 transforms[]; };
+/// layout(buffer_reference, std430) readonly buffer Materials { Material materials[]; };
+///
+/// layout(push_constant) uniform DrawConstants {
+///     Transforms transforms;
+///     Materials materials;
+///     uint instance;
+/// };
+/// ```
+pub trait PushConstants: Pod {
+    /// The stages the block is visible to.
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::ALL;
+
+    fn get_range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: Self::STAGES,
+            offset: 0,
+            size: size_of::<Self>() as u32,
+        }
+    }
+
+    /// Records pushing the block for pipelines created with `layout`.
+    fn push(&self, device: &Device, cmd: vk::CommandBuffer, layout: vk::PipelineLayout) {
+        unsafe {
+            device.cmd_push_constants(cmd, layout, Self::STAGES, 0, bytemuck::bytes_of(self))
+        };
+    }
+}
+
+/// Creates a pipeline layout with `set_layouts` and the push constant block `P`.
+///
+/// # Errors
+///
+/// Returns an error if the layout could not be created.
+///
+/// # Panics
+///
+/// Panics if `P` is larger than the push constants the device supports.
+pub fn create_pipeline_layout<P: PushConstants>(
+    vk: &Vk,
+    set_layouts: &[vk::DescriptorSetLayout],
+) -> Result<vk::PipelineLayout> {
+    let limits = unsafe {
+        vk.get_instance()
+            .get_physical_device_properties(vk.get_physical_device())
+            .limits
+    };
+    assert!(
+        size_of::<P>() as u32 <= limits.max_push_constants_size,
+        "Push constants of {} bytes exceed the {} bytes the device supports",
+        size_of::<P>(),
+        limits.max_push_constants_size
+    );
+
+    let push_constant_ranges = [P::get_range()];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    Ok(unsafe { vk.get_device().create_pipeline_layout(&layout_info, None)? })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Topology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    LineList,
+    LineStrip,
+    PointList,
+}
+
+impl From<Topology> for vk::PrimitiveTopology {
+    fn from(topology: Topology) -> Self {
+        match topology {
+            Topology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            Topology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            Topology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            Topology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            Topology::PointList => vk::PrimitiveTopology::POINT_LIST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum CullMode {
+    None,
+    Front,
+    #[default]
+    Back,
+}
+
+impl From<CullMode> for vk::CullModeFlags {
+    fn from(cull_mode: CullMode) -> Self {
+        match cull_mode {
+            CullMode::None => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum FrontFace {
+    #[default]
+    CounterClockwise,
+    Clockwise,
+}
+
+impl From<FrontFace> for vk::FrontFace {
+    fn from(front_face: FrontFace) -> Self {
+        match front_face {
+            FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+            FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    Line,
+}
+
+impl From<PolygonMode> for vk::PolygonMode {
+    fn from(polygon_mode: PolygonMode) -> Self {
+        match polygon_mode {
+            PolygonMode::Fill => vk::PolygonMode::FILL,
+            PolygonMode::Line => vk::PolygonMode::LINE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct RasterState {
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    pub polygon_mode: PolygonMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum CompareOp {
+    Never,
+    #[default]
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+impl From<CompareOp> for vk::CompareOp {
+    fn from(compare_op: CompareOp) -> Self {
+        match compare_op {
+            CompareOp::Never => vk::CompareOp::NEVER,
+            CompareOp::Less => vk::CompareOp::LESS,
+            CompareOp::Equal => vk::CompareOp::EQUAL,
+            CompareOp::LessOrEqual => vk::CompareOp::LESS_OR_EQUAL,
+            CompareOp::Greater => vk::CompareOp::GREATER,
+            CompareOp::NotEqual => vk::CompareOp::NOT_EQUAL,
+            CompareOp::GreaterOrEqual => vk::CompareOp::GREATER_OR_EQUAL,
+            CompareOp::Always => vk::CompareOp::ALWAYS,
+        }
+    }
+}
+
+/// Depth testing of a pipeline, which needs a depth attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: CompareOp,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: CompareOp::Less,
+        }
+    }
+}
+
+/// How the output of the fragment shader is combined with every color attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Blend {
+    #[default]
+    Opaque,
+    /// Blends by the alpha of straight, not premultiplied, colors.
+    Alpha,
+    Premultiplied,
+    Additive,
+}
+
+impl Blend {
+    pub fn get_attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color, dst_color) = match self {
+            Blend::Opaque => {
+                return vk::PipelineColorBlendAttachmentState {
+                    color_write_mask: vk::ColorComponentFlags::RGBA,
+                    ..Default::default()
+                }
+            }
+            Blend::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            Blend::Premultiplied => (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            Blend::Additive => (vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+        };
+
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: src_color,
+            dst_color_blend_factor: dst_color,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }
+    }
+}
+
+/// Everything a graphics pipeline is made of, so materials can ask [`Pipelines`] for one by
+/// description and share it with every material asking for the same.
+///
+/// Pipelines are made for dynamic rendering into `color_formats` and `depth_format`, with dynamic
+/// viewport and scissor. The layout is reflected from the shaders, see
+/// [`PipelineReflection::create_layouts`], unless `layout` is given.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PipelineDesc {
+    pub shaders: Vec<ShaderSource>,
+    /// The vertex read from vertex buffer 0, `None` for shaders without vertex inputs.
+    pub vertex: Option<VertexLayout>,
+    pub topology: Topology,
+    pub raster: RasterState,
+    /// `None` disables depth testing.
+    pub depth: Option<DepthState>,
+    pub blend: Blend,
+    #[serde(with = "crate::utils::serde_formats")]
+    pub color_formats: Vec<vk::Format>,
+    /// `UNDEFINED` without a depth attachment.
+    #[serde(with = "crate::utils::serde_format")]
+    pub depth_format: vk::Format,
+    /// The layout to create the pipeline with instead of the reflected one, e.g. the one of
+    /// [`crate::bindless::Bindless`] or of [`create_pipeline_layout`] with the set layout of it,
+    /// for shaders reading everything through addresses in their push constants.
+    ///
+    /// Not serialized, and it has to outlive the pipeline.
+    #[serde(skip)]
+    pub layout: Option<vk::PipelineLayout>,
+}
+
+impl PipelineDesc {
+    /// An opaque triangle list pipeline without depth, culling back faces.
+    pub fn new(shaders: Vec<ShaderSource>, color_formats: Vec<vk::Format>) -> Self {
+        Self {
+            shaders,
+            vertex: None,
+            topology: Topology::default(),
+            raster: RasterState::default(),
+            depth: None,
+            blend: Blend::default(),
+            color_formats,
+            depth_format: vk::Format::UNDEFINED,
+            layout: None,
+        }
+    }
+
+    /// A hash of the description, equal for equal descriptions within one build of the engine.
+    pub fn get_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A graphics pipeline built from a [`PipelineDesc`], destroyed when dropped.
+pub struct GraphicsPipeline {
+    device: Device,
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    /// `None` if the layout was given by the description.
+    reflected_layout: Option<ReflectedLayout>,
+    reflection: PipelineReflection,
+}
+
+impl GraphicsPipeline {
+    /// Compiles the shaders of `desc` and creates the pipeline with the layout of `desc`, or
+    /// their reflected one if it has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a shader does not compile, the vertex of `desc` does not match the
+    /// vertex shader, the layout can not be reflected or the pipeline could not be created.
+    pub fn new(vk: &Vk, desc: &PipelineDesc, cache: vk::PipelineCache) -> Result<Self> {
+        let shaders = desc
+            .shaders
+            .iter()
+            .map(ShaderSource::compile)
+            .collect::<Result<Vec<_>>>()?;
+        let reflection = PipelineReflection::from_shaders(&shaders)?;
+        if let Some(vertex) = &desc.vertex {
+            reflection.check_vertex_layout(vertex)?;
+        }
+
+        let (layout, reflected_layout) = match desc.layout {
+            Some(layout) => (layout, None),
+            None => {
+                let reflected = reflection.create_layouts(vk)?;
+                (reflected.get_pipeline_layout(), Some(reflected))
+            }
+        };
+        let pipeline = create_graphics_pipeline(vk, desc, &shaders, layout, cache)?;
+
+        Ok(Self {
+            device: vk.get_device().clone(),
+            pipeline,
+            layout,
+            reflected_layout,
+            reflection,
+        })
+    }
+
+    pub fn get_pipeline(&self) -> vk::Pipeline {
+        self.pipeline
+    }
+
+    pub fn get_layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    /// The reflected descriptor set layouts, indexed by set number, empty if the layout was given
+    /// by the description.
+    pub fn get_set_layouts(&self) -> &[vk::DescriptorSetLayout] {
+        self.reflected_layout
+            .as_ref()
+            .map_or(&[], ReflectedLayout::get_set_layouts)
+    }
+
+    pub fn get_reflection(&self) -> &PipelineReflection {
+        &self.reflection
+    }
+
+    /// Allocates a descriptor set for every set the shaders use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sets could not be allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout was given by the description, whose owner allocates the sets.
+    pub fn create_descriptor_sets(&self) -> Result<DescriptorSets> {
+        self.reflected_layout
+            .as_ref()
+            .expect("Descriptor sets of a given layout are allocated by its owner")
+            .create_descriptor_sets()
+    }
+}
+
+impl Drop for GraphicsPipeline {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_pipeline(self.pipeline, None) };
+    }
+}
+
+/// Creates the pipeline of `desc` from `shaders`, which were compiled from its sources.
+///
+/// Pipelines with a mesh shader ignore the vertex and topology of `desc`.
+pub(crate) fn create_graphics_pipeline(
+    vk: &Vk,
+    desc: &PipelineDesc,
+    shaders: &[CompiledShader],
+    layout: vk::PipelineLayout,
+    cache: vk::PipelineCache,
+) -> Result<vk::Pipeline> {
+    let device = vk.get_device();
+    let (binding_descriptions, attribute_descriptions) = match &desc.vertex {
+        Some(vertex) => (
+            vec![vertex.get_binding_description(0)],
+            vertex.get_attribute_descriptions(0),
+        ),
+        None => (vec![], vec![]),
+    };
+
+    let mut modules = Vec::with_capacity(shaders.len());
+    for shader in shaders {
+        match shader.create_module(vk) {
+            Ok(module) => modules.push(module),
+            Err(e) => {
+                destroy_modules(device, &modules);
+                return Err(e);
+            }
+        }
+    }
+
+    let stages: Vec<_> = shaders
+        .iter()
+        .zip(&modules)
+        .map(|(shader, &module)| {
+            *vk::PipelineShaderStageCreateInfo::builder()
+                .stage(shader.stage.get_flags())
+                .module(module)
+                .name(c"main")
+        })
+        .collect();
+
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&binding_descriptions)
+        .vertex_attribute_descriptions(&attribute_descriptions);
+
+    let input_assembly_info =
+        vk::PipelineInputAssemblyStateCreateInfo::builder().topology(desc.topology.into());
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(desc.raster.polygon_mode.into())
+        .cull_mode(desc.raster.cull_mode.into())
+        .front_face(desc.raster.front_face.into())
+        .line_width(1.);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth = desc.depth.unwrap_or(DepthState {
+        test: false,
+        write: false,
+        compare_op: CompareOp::Always,
+    });
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(depth.test)
+        .depth_write_enable(depth.write)
+        .depth_compare_op(depth.compare_op.into());
+
+    let color_blend_attachments = vec![desc.blend.get_attachment_state(); desc.color_formats.len()];
+    let color_blending =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&desc.color_formats)
+        .depth_attachment_format(desc.depth_format);
+
+    let mut pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .push_next(&mut rendering_info)
+        .stages(&stages)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_state_info)
+        .layout(layout);
+    // mesh shaders generate their primitives themselves
+    if !shaders
+        .iter()
+        .any(|shader| shader.stage == ShaderStage::Mesh)
+    {
+        pipeline_info = pipeline_info
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info);
+    }
+    let pipeline_info = [*pipeline_info];
+
+    let pipeline = unsafe { device.create_graphics_pipelines(cache, &pipeline_info, None) };
+    destroy_modules(device, &modules);
+    let pipeline = pipeline.map_err(|(_, e)| e)?[0];
+
+    let name: Vec<_> = desc
+        .shaders
+        .iter()
+        .filter_map(|shader| shader.path.file_name())
+        .map(|name| name.to_string_lossy())
+        .collect();
+    vk.set_debug_name(pipeline, &name.join(" + "));
+
+    Ok(pipeline)
+}
+
+fn destroy_modules(device: &Device, modules: &[vk::ShaderModule]) {
+    for &module in modules {
+        unsafe { device.destroy_shader_module(module, None) };
+    }
+}
+
+/// Size of the header `vkGetPipelineCacheData` starts with.
+const CACHE_HEADER_SIZE: usize = 32;
+
+/// A `VkPipelineCache`, loaded from and saved to a file so pipelines are compiled by the driver
+/// only on the first run.
+pub struct PipelineCache {
+    device: Device,
+    cache: vk::PipelineCache,
+    path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+    /// A cache that is not persisted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cache could not be created.
+    pub fn new(vk: &Vk) -> Result<Self> {
+        Self::with_data(vk, &[], None)
+    }
+
+    /// Loads the cache saved at `path`, which is saved again by [`PipelineCache::save`] and when
+    /// the cache is dropped.
+    ///
+    /// Starts empty if there is no file yet or it was written by another device or driver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but can not be read, or the cache could not be
+    /// created.
+    pub fn load(vk: &Vk, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+
+        let properties = unsafe {
+            vk.get_instance()
+                .get_physical_device_properties(vk.get_physical_device())
+        };
+        let data = if data.is_empty() || is_compatible(&data, &properties) {
+            data
+        } else {
+            info!(
+                "Discarding pipeline cache {} of another device or driver",
+                path.display()
+            );
+            vec![]
+        };
+
+        Self::with_data(vk, &data, Some(path))
+    }
+
+    fn with_data(vk: &Vk, data: &[u8], path: Option<PathBuf>) -> Result<Self> {
+        let cache_info = vk::PipelineCacheCreateInfo::builder().initial_data(data);
+        let cache = unsafe { vk.get_device().create_pipeline_cache(&cache_info, None)? };
+
+        Ok(Self {
+            device: vk.get_device().clone(),
+            cache,
+            path,
+        })
+    }
+
+    /// Writes the cache to the file it was loaded from, does nothing for caches that are not
+    /// persisted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data could not be retrieved or written.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data = unsafe { self.device.get_pipeline_cache_data(self.cache)? };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // written next to it and renamed, so a crash while saving does not leave half a cache
+        let partial = path.with_extension("partial");
+        fs::write(&partial, data)?;
+        fs::rename(&partial, path)?;
+
+        Ok(())
+    }
+
+    pub fn get_cache(&self) -> vk::PipelineCache {
+        self.cache
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            warn!("Could not save the pipeline cache: {}", e);
+        }
+
+        unsafe { self.device.destroy_pipeline_cache(self.cache, None) };
+    }
+}
+
+/// Whether `data` has the header of a cache of the device of `properties`, drivers should reject
+/// others by themselves but not all of them do.
+fn is_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < CACHE_HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| {
+        u32::from_le_bytes(
+            data[offset..offset + 4]
+                .try_into()
+                .expect("Slice of 4 bytes"),
+        )
+    };
+
+    read_u32(0) as usize >= CACHE_HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..CACHE_HEADER_SIZE] == properties.pipeline_cache_uuid
+}
+
+/// Graphics pipelines by description, so everything asking for the same [`PipelineDesc`] shares
+/// one pipeline.
+pub struct Pipelines {
+    pipelines: HashMap<PipelineDesc, GraphicsPipeline>,
+    cache: PipelineCache,
+}
+
+impl Pipelines {
+    pub fn new(cache: PipelineCache) -> Self {
+        Self {
+            pipelines: HashMap::new(),
+            cache,
+        }
+    }
+
+    /// The pipeline of `desc`, created with the cache the first time it is asked for.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline could not be created, see [`GraphicsPipeline::new`].
+    pub fn get(&mut self, vk: &Vk, desc: &PipelineDesc) -> Result<&GraphicsPipeline> {
+        if !self.pipelines.contains_key(desc) {
+            let pipeline = GraphicsPipeline::new(vk, desc, self.cache.get_cache())?;
+            debug!("Created pipeline {:016x}", desc.get_hash());
+            self.pipelines.insert(desc.clone(), pipeline);
+        }
+
+        Ok(&self.pipelines[desc])
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    pub fn get_cache(&self) -> &PipelineCache {
+        &self.cache
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use ash::{vk, Device};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::{error::Result, FrameLoop, Vk};
+
+/// How many scopes one frame can have by default, a scope taking two queries.
+pub const DEFAULT_MAX_SCOPES: u32 = 128;
+
+/// How long a scope took on the GPU, resolved by [`GpuProfiler`] a few frames after it ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeTiming {
+    pub name: String,
+    /// How many scopes it is nested in.
+    pub depth: u32,
+    /// Since the profiler was created, on the CPU clock.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+struct PendingScope {
+    name: String,
+    depth: u32,
+    start_query: u32,
+    end_query: Option<u32>,
+}
+
+/// The queries of one frame in flight.
+struct QuerySlot {
+    pool: vk::QueryPool,
+    scopes: Vec<PendingScope>,
+    used: u32,
+    frame_number: u64,
+}
+
+/// One complete event of the Chrome trace event format, times in microseconds.
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u32,
+}
+
+const CPU_THREAD: u32 = 1;
+const GPU_THREAD: u32 = 2;
+
+/// Measures named scopes of the command buffers of a [`crate::FrameLoop`] with timestamp queries.
+///
+/// Every frame in flight has its own query pool. Its results are read back when the slot comes
+/// around again in [`GpuProfiler::begin_frame`], after [`crate::FrameLoop::begin`] has waited for
+/// the frame that used it, so reading them never stalls. [`GpuProfiler::get_last_frame`] is
+/// therefore `frames_in_flight` frames behind.
+///
+/// GPU timestamps are converted to the CPU clock with an offset measured in
+/// [`GpuProfiler::calibrate`], so GPU scopes line up with CPU scopes from
+/// [`GpuProfiler::begin_cpu_scope`] in a captured trace to within the submission latency.
+///
+/// ```ignore
+/// let frame = frame_loop.begin(&vk)?.unwrap();
+/// let cmd = frame.get_command_buffer();
+/// profiler.begin_frame(cmd, frame.get_frame_index(), frame.get_frame_number())?;
+/// profiler.begin_scope(cmd, "opaque");
+/// // record the pass
+/// profiler.end_scope(cmd);
+/// profiler.end_frame();
+/// frame_loop.end(&vk, frame)?;
+/// ```
+///
+/// Devices whose graphics queue has no timestamps get a profiler that only records CPU scopes.
+/// Has to be dropped before the [`Vk`] it was created from.
+pub struct GpuProfiler {
+    device: Device,
+    slots: Vec<QuerySlot>,
+    max_scopes: u32,
+    /// The slot of the frame being recorded.
+    current: Option<usize>,
+    /// Indices into the scopes of the current slot, `None` for scopes that did not fit.
+    open: Vec<Option<usize>>,
+    /// Nanoseconds per tick.
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    epoch: Instant,
+    /// A GPU timestamp and the time since `epoch` it was taken at.
+    calibration: (u64, Duration),
+    last_frame: Vec<ScopeTiming>,
+    last_frame_number: Option<u64>,
+    cpu_open: Vec<(String, Instant)>,
+    /// Events since [`GpuProfiler::start_capture`], `None` when not capturing.
+    capture: Option<Vec<TraceEvent>>,
+}
+
+impl GpuProfiler {
+    /// Creates a query pool for each of the `frames_in_flight` frames, each fitting `max_scopes`
+    /// scopes.
+    ///
+    /// `frames_in_flight` has to be at least that of the [`FrameLoop`] the frames come from, as a
+    /// slot is only free once the loop has waited for the frame that used it. See
+    /// [`GpuProfiler::for_frame_loop`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query pools could not be created or calibrating failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames_in_flight` or `max_scopes` is zero.
+    pub fn new(vk: &Vk, frames_in_flight: usize, max_scopes: u32) -> Result<Self> {
+        assert!(
+            frames_in_flight > 0 && max_scopes > 0,
+            "The profiler needs at least one frame and scope"
+        );
+
+        let instance = vk.get_instance();
+        let physical_device = vk.get_physical_device();
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+        let family = vk.get_queues().graphics.family as usize;
+        let valid_bits = unsafe {
+            instance.get_physical_device_queue_family_properties(physical_device)[family]
+                .timestamp_valid_bits
+        };
+
+        let mut profiler = Self {
+            device: vk.get_device().clone(),
+            slots: Vec::with_capacity(frames_in_flight),
+            max_scopes,
+            current: None,
+            open: vec![],
+            timestamp_period: limits.timestamp_period as f64,
+            timestamp_mask: match valid_bits {
+                64 => u64::MAX,
+                bits => (1 << bits) - 1,
+            },
+            epoch: Instant::now(),
+            calibration: (0, Duration::ZERO),
+            last_frame: vec![],
+            last_frame_number: None,
+            cpu_open: vec![],
+            capture: None,
+        };
+
+        if valid_bits == 0 {
+            warn!("The graphics queue has no timestamps, only CPU scopes are profiled");
+            return Ok(profiler);
+        }
+
+        for i in 0..frames_in_flight {
+            let pool = profiler.create_query_pool(max_scopes * 2)?;
+            vk.set_debug_name(pool, &format!("profiler queries {}", i));
+
+            profiler.slots.push(QuerySlot {
+                pool,
+                scopes: vec![],
+                used: 0,
+                frame_number: 0,
+            });
+        }
+        profiler.calibrate(vk)?;
+
+        Ok(profiler)
+    }
+
+    /// Creates a profiler with a query pool for each frame in flight of `frame_loop`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query pools could not be created or calibrating failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_scopes` is zero.
+    pub fn for_frame_loop(vk: &Vk, frame_loop: &FrameLoop, max_scopes: u32) -> Result<Self> {
+        Self::new(vk, frame_loop.get_frames_in_flight(), max_scopes)
+    }
+
+    /// Whether GPU scopes are measured, `false` if the graphics queue has no timestamps.
+    pub fn is_supported(&self) -> bool {
+        !self.slots.is_empty()
+    }
+
+    /// Measures the offset between the GPU and CPU clocks again, which drift apart over time.
+    ///
+    /// Blocks until a timestamp written by the GPU has been read back.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query pool could not be created or the submission failed.
+    pub fn calibrate(&mut self, vk: &Vk) -> Result<()> {
+        if !self.is_supported() {
+            return Ok(());
+        }
+
+        let pool = self.create_query_pool(1)?;
+        let before = self.epoch.elapsed();
+        let submitted = vk.immediate_submit(|device, cmd| unsafe {
+            device.cmd_reset_query_pool(cmd, pool, 0, 1);
+            device.cmd_write_timestamp2(cmd, vk::PipelineStageFlags2::ALL_COMMANDS, pool, 0);
+        });
+        let after = self.epoch.elapsed();
+
+        let mut timestamp = [0u64];
+        let read = submitted.and_then(|_| unsafe {
+            Ok(self.device.get_query_pool_results(
+                pool,
+                0,
+                1,
+                &mut timestamp,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+            )?)
+        });
+        unsafe { self.device.destroy_query_pool(pool, None) };
+        read?;
+
+        // the timestamp was written somewhere between submitting and the fence being signaled
+        self.calibration = (timestamp[0], (before + after) / 2);
+        debug!("Calibrated GPU timestamps to {:?}", self.calibration);
+
+        Ok(())
+    }
+
+    /// Resolves the scopes the slot `slot_index` measured last time and resets its queries in
+    /// `cmd`, starting frame `frame_number`.
+    ///
+    /// With a [`FrameLoop`] these are the [`crate::Frame::get_command_buffer`],
+    /// [`crate::Frame::get_frame_index`] and [`crate::Frame::get_frame_number`] of a frame, and it
+    /// has to be called right after [`crate::FrameLoop::begin`], before any scope is recorded. In
+    /// general the submission that last used the slot has to have finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the results failed, e.g. because the device was lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous frame was not ended with [`GpuProfiler::end_frame`], or if
+    /// `slot_index` is not a slot of the profiler, e.g. because the frame loop has more frames in
+    /// flight, which would reset queries a pending submission still writes.
+    pub fn begin_frame(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        slot_index: usize,
+        frame_number: u64,
+    ) -> Result<()> {
+        assert!(self.current.is_none(), "The previous frame was not ended");
+        if !self.is_supported() {
+            return Ok(());
+        }
+
+        assert!(
+            slot_index < self.slots.len(),
+            "Slot {} is out of the {} slots of the profiler, fewer than the frames in flight",
+            slot_index,
+            self.slots.len()
+        );
+        self.resolve(slot_index)?;
+
+        let slot = &mut self.slots[slot_index];
+        unsafe {
+            self.device
+                .cmd_reset_query_pool(cmd, slot.pool, 0, self.max_scopes * 2)
+        };
+        slot.scopes.clear();
+        slot.used = 0;
+        slot.frame_number = frame_number;
+        self.current = Some(slot_index);
+
+        Ok(())
+    }
+
+    /// Ends the frame begun with [`GpuProfiler::begin_frame`], before it is handed to
+    /// [`crate::FrameLoop::end`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a scope is still open.
+    pub fn end_frame(&mut self) {
+        assert!(
+            self.open.is_empty(),
+            "{} GPU scopes are still open",
+            self.open.len()
+        );
+
+        self.current = None;
+    }
+
+    /// Records a timestamp starting the scope `name`, ended by the next [`GpuProfiler::end_scope`].
+    ///
+    /// Scopes can be nested. Scopes past the `max_scopes` of a frame are not measured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no frame was begun with [`GpuProfiler::begin_frame`] on a device with timestamps.
+    pub fn begin_scope(&mut self, cmd: vk::CommandBuffer, name: &str) {
+        if !self.is_supported() {
+            return;
+        }
+
+        let index = self
+            .current
+            .expect("GPU scopes have to be inside GpuProfiler::begin_frame");
+        let slot = &mut self.slots[index];
+        // the end query of every open scope has to fit as well
+        if slot.used + self.open.len() as u32 + 2 > self.max_scopes * 2 {
+            debug!("Out of queries, scope {} is not measured", name);
+            self.open.push(None);
+            return;
+        }
+
+        unsafe {
+            self.device.cmd_write_timestamp2(
+                cmd,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                slot.pool,
+                slot.used,
+            )
+        };
+        slot.scopes.push(PendingScope {
+            name: name.to_owned(),
+            depth: self.open.len() as u32,
+            start_query: slot.used,
+            end_query: None,
+        });
+        slot.used += 1;
+        self.open.push(Some(slot.scopes.len() - 1));
+    }
+
+    /// Records a timestamp ending the innermost open scope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no scope is open on a device with timestamps.
+    pub fn end_scope(&mut self, cmd: vk::CommandBuffer) {
+        if !self.is_supported() {
+            return;
+        }
+
+        let Some(scope) = self.open.pop().expect("No GPU scope is open") else {
+            return;
+        };
+        let slot = &mut self.slots[self.current.expect("Scopes only open inside a frame")];
+
+        unsafe {
+            self.device.cmd_write_timestamp2(
+                cmd,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                slot.pool,
+                slot.used,
+            )
+        };
+        slot.scopes[scope].end_query = Some(slot.used);
+        slot.used += 1;
+    }
+
+    /// Starts measuring the CPU scope `name`, ended by the next [`GpuProfiler::end_cpu_scope`].
+    ///
+    /// CPU scopes only end up in captures.
+    pub fn begin_cpu_scope(&mut self, name: &str) {
+        self.cpu_open.push((name.to_owned(), Instant::now()));
+    }
+
+    /// # Panics
+    ///
+    /// Panics if no CPU scope is open.
+    pub fn end_cpu_scope(&mut self) {
+        let (name, start) = self.cpu_open.pop().expect("No CPU scope is open");
+
+        if let Some(events) = &mut self.capture {
+            events.push(TraceEvent {
+                name,
+                cat: "cpu",
+                ph: "X",
+                ts: micros(start - self.epoch),
+                dur: micros(start.elapsed()),
+                pid: 1,
+                tid: CPU_THREAD,
+            });
+        }
+    }
+
+    /// The GPU scopes of the last resolved frame, in the order they began.
+    pub fn get_last_frame(&self) -> &[ScopeTiming] {
+        &self.last_frame
+    }
+
+    /// The frame number of [`GpuProfiler::get_last_frame`], `None` before the first frame is
+    /// resolved.
+    pub fn get_last_frame_number(&self) -> Option<u64> {
+        self.last_frame_number
+    }
+
+    /// Starts collecting CPU and GPU scopes for [`GpuProfiler::write_chrome_trace`], dropping what
+    /// was collected so far.
+    pub fn start_capture(&mut self) {
+        self.capture = Some(vec![]);
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Stops capturing and writes the collected scopes to `path` in the Chrome trace event format,
+    /// which `chrome://tracing` and Perfetto load. CPU and GPU scopes are two threads of one
+    /// process.
+    ///
+    /// GPU scopes of the last `frames_in_flight` frames are not resolved yet and are missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no capture was started.
+    pub fn write_chrome_trace(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let events = self
+            .capture
+            .take()
+            .expect("No capture was started with GpuProfiler::start_capture");
+
+        // metadata events naming the threads
+        let mut trace_events: Vec<serde_json::Value> = [(CPU_THREAD, "CPU"), (GPU_THREAD, "GPU")]
+            .into_iter()
+            .map(|(tid, name)| {
+                serde_json::json!({
+                    "name": "thread_name",
+                    "ph": "M",
+                    "pid": 1,
+                    "tid": tid,
+                    "args": { "name": name },
+                })
+            })
+            .collect();
+        let event_count = events.len();
+        for event in events {
+            trace_events.push(serde_json::to_value(event).map_err(std::io::Error::from)?);
+        }
+
+        let trace = serde_json::json!({
+            "traceEvents": trace_events,
+            "displayTimeUnit": "ms",
+        });
+        fs::write(
+            path.as_ref(),
+            serde_json::to_vec(&trace).map_err(std::io::Error::from)?,
+        )?;
+        debug!(
+            "Wrote {} trace events to {}",
+            event_count,
+            path.as_ref().display()
+        );
+
+        Ok(())
+    }
+
+    /// Reads back the queries of the slot `index` and keeps them as the last frame.
+    fn resolve(&mut self, index: usize) -> Result<()> {
+        let slot = &self.slots[index];
+        if slot.used == 0 {
+            return Ok(());
+        }
+
+        let mut timestamps = vec![0u64; slot.used as usize];
+        let read = unsafe {
+            self.device.get_query_pool_results(
+                slot.pool,
+                0,
+                slot.used,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        match read {
+            Ok(()) => {}
+            // e.g. the frame was never submitted
+            Err(vk::Result::NOT_READY) => {
+                debug!("Timestamps of frame {} are not ready", slot.frame_number);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let last_frame: Vec<ScopeTiming> = slot
+            .scopes
+            .iter()
+            .filter_map(|scope| {
+                let start = timestamps[scope.start_query as usize];
+                let end = timestamps[scope.end_query? as usize];
+
+                Some(ScopeTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    start: self.to_cpu_time(start),
+                    duration: self.to_duration(end.wrapping_sub(start)),
+                })
+            })
+            .collect();
+
+        if let Some(events) = &mut self.capture {
+            events.extend(last_frame.iter().map(|timing| TraceEvent {
+                name: timing.name.clone(),
+                cat: "gpu",
+                ph: "X",
+                ts: micros(timing.start),
+                dur: micros(timing.duration),
+                pid: 1,
+                tid: GPU_THREAD,
+            }));
+        }
+        self.last_frame_number = Some(slot.frame_number);
+        self.last_frame = last_frame;
+
+        Ok(())
+    }
+
+    fn to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_nanos(((ticks & self.timestamp_mask) as f64 * self.timestamp_period) as u64)
+    }
+
+    /// The time since `epoch` at which the GPU wrote `timestamp`, saturating at the epoch.
+    fn to_cpu_time(&self, timestamp: u64) -> Duration {
+        let (calibrated, at) = self.calibration;
+        let ticks = timestamp.wrapping_sub(calibrated) & self.timestamp_mask;
+
+        // timestamps from before calibrating wrap around to huge tick counts
+        if ticks > self.timestamp_mask / 2 {
+            at.saturating_sub(self.to_duration(calibrated.wrapping_sub(timestamp)))
+        } else {
+            at + self.to_duration(ticks)
+        }
+    }
+
+    fn create_query_pool(&self, count: u32) -> Result<vk::QueryPool> {
+        let pool_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(count);
+
+        Ok(unsafe { self.device.create_query_pool(&pool_info, None)? })
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        for slot in self.slots.drain(..) {
+            unsafe { self.device.destroy_query_pool(slot.pool, None) };
+        }
+    }
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
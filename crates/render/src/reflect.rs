@@ -0,0 +1,1049 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ash::{vk, Device};
+use spirv::{Decoration, Dim, ExecutionMode, ExecutionModel, Op, StorageClass};
+
+use crate::{
+    bindless::runtime_array_capacity,
+    error::{RenderError, Result},
+    selector::Feature,
+    shader::{CompiledShader, ShaderStage},
+    vertex::{Vertex, VertexLayout},
+    Vk,
+};
+
+/// An input of a vertex shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub name: Option<String>,
+    /// The 32 bit format matching the type of the input, e.g. `R32G32B32_SFLOAT` for a `vec3`.
+    pub format: vk::Format,
+}
+
+/// A resource bound through a descriptor set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Number of descriptors in the binding, `None` for runtime sized arrays.
+    pub count: Option<u32>,
+    pub stages: vk::ShaderStageFlags,
+    pub name: Option<String>,
+}
+
+/// The interface of one shader stage, as found in its SPIR-V.
+///
+/// Push constant blocks may hold `GL_EXT_buffer_reference` addresses, which count as 8 bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderReflection {
+    pub stage: ShaderStage,
+    /// Sorted by location, empty unless `stage` is [`ShaderStage::Vertex`].
+    pub inputs: Vec<VertexInput>,
+    /// Sorted by set and binding.
+    pub bindings: Vec<DescriptorBinding>,
+    /// Size of the push constant block in bytes, up to the end of its last member, 0 if there is
+    /// none.
+    pub push_constant_size: u32,
+    /// The `local_size` of compute, task and mesh shaders, zero for other stages.
+    pub workgroup_size: [u32; 3],
+}
+
+impl ShaderReflection {
+    /// Reflects the entry point of `stage` in `spirv`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::ShaderReflection`] if `spirv` can not be parsed, has no entry point
+    /// of `stage` or uses a resource or input type that has no Vulkan equivalent.
+    pub fn new(spirv: &[u32], stage: ShaderStage) -> Result<Self> {
+        let module = Module::parse(spirv)?;
+        let entry_point = module
+            .entry_points
+            .iter()
+            .find(|entry_point| entry_point.model == stage.to_execution_model())
+            .ok_or_else(|| RenderError::ShaderReflection(format!("no {:?} entry point", stage)))?;
+
+        let mut inputs = vec![];
+        if stage == ShaderStage::Vertex {
+            for variable in &module.variables {
+                if variable.class == StorageClass::Input
+                    && entry_point.interface.contains(&variable.id)
+                {
+                    module.push_input(&mut inputs, variable)?;
+                }
+            }
+        }
+        inputs.sort_by_key(|input| input.location);
+
+        let mut bindings = vec![];
+        let mut push_constant_size = 0;
+        for variable in &module.variables {
+            if variable.class == StorageClass::PushConstant {
+                push_constant_size = module.size_of(module.pointee(variable.ty)?, None)?;
+                continue;
+            }
+
+            let decorations = module.decorations(variable.id);
+            let (Some(set), Some(binding)) = (decorations.set, decorations.binding) else {
+                continue;
+            };
+            let (descriptor_type, count) = module.descriptor_type(variable)?;
+
+            bindings.push(DescriptorBinding {
+                set,
+                binding,
+                descriptor_type,
+                count,
+                stages: stage.get_flags(),
+                name: module.name(variable.id),
+            });
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        Ok(Self {
+            stage,
+            inputs,
+            bindings,
+            push_constant_size,
+            workgroup_size: module.workgroup_size(entry_point.id),
+        })
+    }
+}
+
+/// The interface of all stages of a pipeline, from which its layouts are built.
+#[derive(Debug, Clone)]
+pub struct PipelineReflection {
+    inputs: Vec<VertexInput>,
+    bindings: Vec<DescriptorBinding>,
+    push_constants: Option<vk::PushConstantRange>,
+}
+
+impl PipelineReflection {
+    /// Merges the reflection of the stages of a pipeline.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::ShaderReflection`] if two stages declare different resources at
+    /// the same set and binding.
+    pub fn new(stages: &[ShaderReflection]) -> Result<Self> {
+        let mut inputs = vec![];
+        let mut bindings: BTreeMap<(u32, u32), DescriptorBinding> = BTreeMap::new();
+        let mut push_constants: Option<vk::PushConstantRange> = None;
+
+        for stage in stages {
+            if stage.stage == ShaderStage::Vertex {
+                inputs.clone_from(&stage.inputs);
+            }
+
+            for binding in &stage.bindings {
+                let Some(existing) = bindings.get_mut(&(binding.set, binding.binding)) else {
+                    bindings.insert((binding.set, binding.binding), binding.clone());
+                    continue;
+                };
+
+                if existing.descriptor_type != binding.descriptor_type
+                    || existing.count != binding.count
+                {
+                    return Err(RenderError::ShaderReflection(format!(
+                        "binding {}.{} is a {:?} x {:?} in {:?} but a {:?} x {:?} in {:?}",
+                        binding.set,
+                        binding.binding,
+                        existing.descriptor_type,
+                        existing.count,
+                        existing.stages,
+                        binding.descriptor_type,
+                        binding.count,
+                        binding.stages,
+                    )));
+                }
+                existing.stages |= binding.stages;
+            }
+
+            if stage.push_constant_size > 0 {
+                let range = push_constants.get_or_insert(vk::PushConstantRange::default());
+                range.stage_flags |= stage.stage.get_flags();
+                range.size = range.size.max(stage.push_constant_size);
+            }
+        }
+
+        Ok(Self {
+            inputs,
+            bindings: bindings.into_values().collect(),
+            push_constants,
+        })
+    }
+
+    /// Reflects and merges the stages of `shaders`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the shaders can not be reflected or they do not agree.
+    pub fn from_shaders(shaders: &[CompiledShader]) -> Result<Self> {
+        let stages = shaders
+            .iter()
+            .map(CompiledShader::reflect)
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(&stages)
+    }
+
+    pub fn get_inputs(&self) -> &[VertexInput] {
+        &self.inputs
+    }
+
+    pub fn get_bindings(&self) -> &[DescriptorBinding] {
+        &self.bindings
+    }
+
+    pub fn get_push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constants
+    }
+
+    /// The vertex input of a pipeline reading `V` from the vertex buffer at `binding`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::VertexMismatch`] if `V` does not match the vertex shader, see
+    /// [`PipelineReflection::check_vertex_layout`].
+    pub fn vertex_input<V: Vertex>(&self, binding: u32) -> Result<VertexInputLayout> {
+        let layout = VertexLayout::of::<V>();
+        self.check_vertex_layout(&layout)?;
+
+        Ok(VertexInputLayout {
+            bindings: vec![layout.get_binding_description(binding)],
+            attributes: layout.get_attribute_descriptions(binding),
+        })
+    }
+
+    /// Checks that `layout` has an attribute for every input of the vertex shader, with a format
+    /// of the type of the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::VertexMismatch`] naming the first input that is missing or has the
+    /// wrong type.
+    pub fn check_vertex_layout(&self, layout: &VertexLayout) -> Result<()> {
+        let mismatch = |message: String| RenderError::VertexMismatch {
+            vertex: layout.name.clone(),
+            message,
+        };
+
+        for input in &self.inputs {
+            let name = input.name.as_deref().unwrap_or("<unnamed>");
+            let Some(attribute) = layout
+                .attributes
+                .iter()
+                .find(|attribute| attribute.location == input.location)
+            else {
+                return Err(mismatch(format!(
+                    "no attribute for input {} at location {}",
+                    name, input.location
+                )));
+            };
+
+            if get_format_type(attribute.format) != get_format_type(input.format) {
+                return Err(mismatch(format!(
+                    "attribute at location {} is {:?}, but input {} needs a format like {:?}",
+                    input.location, attribute.format, name, input.format
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates a descriptor set layout for every set up to the highest one used, and the
+    /// pipeline layout of them and the push constant range.
+    ///
+    /// Runtime sized arrays like the ones of [`crate::bindless::GLSL_DECLARATIONS`] are partially
+    /// bound and update after bind, with as many descriptors as [`crate::bindless::Bindless`]
+    /// makes room for.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::MissingFeature`] for runtime sized arrays if any of
+    /// [`Feature::BINDLESS`] is not enabled on the device, [`RenderError::ShaderReflection`] for
+    /// runtime sized arrays of other types than sampled images, samplers and storage buffers, or
+    /// another error if a layout could not be created.
+    pub fn create_layouts(&self, vk: &Vk) -> Result<ReflectedLayout> {
+        let device = vk.get_device();
+        let set_count = self
+            .bindings
+            .iter()
+            .map(|binding| binding.set + 1)
+            .max()
+            .unwrap_or(0);
+
+        let update_after_bind = self.bindings.iter().any(|binding| binding.count.is_none());
+        if update_after_bind {
+            if let Some(feature) = Feature::BINDLESS
+                .into_iter()
+                .find(|feature| !vk.supports_feature(*feature))
+            {
+                return Err(RenderError::MissingFeature(feature));
+            }
+        }
+
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|binding| {
+                let count = match binding.count {
+                    Some(count) => count,
+                    None => {
+                        runtime_array_capacity(vk, binding.descriptor_type).ok_or_else(|| {
+                            RenderError::ShaderReflection(format!(
+                                "binding {}.{} is a runtime sized array of {:?}",
+                                binding.set, binding.binding, binding.descriptor_type
+                            ))
+                        })?
+                    }
+                };
+
+                Ok(DescriptorBinding {
+                    count: Some(count),
+                    ..binding.clone()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut layout = ReflectedLayout {
+            device: device.clone(),
+            set_layouts: Vec::with_capacity(set_count as usize),
+            pipeline_layout: vk::PipelineLayout::null(),
+            bindings,
+            push_constants: self.push_constants,
+            update_after_bind,
+        };
+
+        for set in 0..set_count {
+            let (layout_bindings, binding_flags): (Vec<_>, Vec<_>) = self
+                .bindings
+                .iter()
+                .zip(&layout.bindings)
+                .filter(|(_, binding)| binding.set == set)
+                .map(|(reflected, binding)| {
+                    let flags = match reflected.count {
+                        Some(_) => vk::DescriptorBindingFlags::empty(),
+                        None => {
+                            vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                                | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING
+                        }
+                    };
+                    let layout_binding = *vk::DescriptorSetLayoutBinding::builder()
+                        .binding(binding.binding)
+                        .descriptor_type(binding.descriptor_type)
+                        .descriptor_count(binding.count.unwrap_or(0))
+                        .stage_flags(binding.stages);
+
+                    (layout_binding, flags)
+                })
+                .unzip();
+
+            let layout_flags = if binding_flags.iter().any(|flags| !flags.is_empty()) {
+                vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL
+            } else {
+                vk::DescriptorSetLayoutCreateFlags::empty()
+            };
+            let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+                .binding_flags(&binding_flags);
+            let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+                .flags(layout_flags)
+                .bindings(&layout_bindings)
+                .push_next(&mut binding_flags_info);
+            // pushed right away, so the ones already created are destroyed on error
+            layout
+                .set_layouts
+                .push(unsafe { device.create_descriptor_set_layout(&layout_info, None)? });
+        }
+
+        let push_constant_ranges: Vec<_> = self.push_constants.into_iter().collect();
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&layout.set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        layout.pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+
+        Ok(layout)
+    }
+}
+
+/// Vertex buffer bindings and attributes for `vk::PipelineVertexInputStateCreateInfo`.
+#[derive(Debug, Clone, Default)]
+pub struct VertexInputLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+/// Layouts made by [`PipelineReflection::create_layouts`], destroyed when dropped.
+pub struct ReflectedLayout {
+    device: Device,
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    pipeline_layout: vk::PipelineLayout,
+    /// With the descriptor count runtime sized arrays were given.
+    bindings: Vec<DescriptorBinding>,
+    push_constants: Option<vk::PushConstantRange>,
+    /// Whether a set has runtime sized arrays, which need an update after bind pool.
+    update_after_bind: bool,
+}
+
+impl ReflectedLayout {
+    /// The layout of every set, indexed by set number.
+    pub fn get_set_layouts(&self) -> &[vk::DescriptorSetLayout] {
+        &self.set_layouts
+    }
+
+    pub fn get_pipeline_layout(&self) -> vk::PipelineLayout {
+        self.pipeline_layout
+    }
+
+    pub fn get_push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constants
+    }
+
+    /// Allocates one descriptor set of every set layout from a pool of their own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool or sets could not be created.
+    pub fn create_descriptor_sets(&self) -> Result<DescriptorSets> {
+        let mut sets = DescriptorSets {
+            device: self.device.clone(),
+            pool: vk::DescriptorPool::null(),
+            sets: vec![],
+            bindings: self.bindings.clone(),
+        };
+        if self.set_layouts.is_empty() {
+            return Ok(sets);
+        }
+
+        let mut pool_sizes: Vec<vk::DescriptorPoolSize> = vec![];
+        for binding in &self.bindings {
+            let count = binding.count.unwrap_or(0);
+            match pool_sizes
+                .iter_mut()
+                .find(|size| size.ty == binding.descriptor_type)
+            {
+                Some(size) => size.descriptor_count += count,
+                None => pool_sizes.push(vk::DescriptorPoolSize {
+                    ty: binding.descriptor_type,
+                    descriptor_count: count,
+                }),
+            }
+        }
+
+        let pool_flags = if self.update_after_bind {
+            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+        } else {
+            vk::DescriptorPoolCreateFlags::empty()
+        };
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(pool_flags)
+            .max_sets(self.set_layouts.len() as u32)
+            .pool_sizes(&pool_sizes);
+        sets.pool = unsafe { self.device.create_descriptor_pool(&pool_info, None)? };
+
+        let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(sets.pool)
+            .set_layouts(&self.set_layouts);
+        sets.sets = unsafe { self.device.allocate_descriptor_sets(&alloc_info)? };
+
+        Ok(sets)
+    }
+}
+
+impl Drop for ReflectedLayout {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            for &set_layout in &self.set_layouts {
+                self.device.destroy_descriptor_set_layout(set_layout, None);
+            }
+        }
+    }
+}
+
+/// One descriptor set for every set of a [`ReflectedLayout`], freed when dropped.
+///
+/// Writes look up the descriptor type in the reflected bindings, so only the resource has to be
+/// given.
+pub struct DescriptorSets {
+    device: Device,
+    pool: vk::DescriptorPool,
+    sets: Vec<vk::DescriptorSet>,
+    bindings: Vec<DescriptorBinding>,
+}
+
+impl DescriptorSets {
+    /// The sets, indexed by set number.
+    pub fn get_sets(&self) -> &[vk::DescriptorSet] {
+        &self.sets
+    }
+
+    /// Points the buffer binding at `set` and `binding` to `info`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the shaders have no such binding.
+    pub fn write_buffer(&mut self, set: u32, binding: u32, info: vk::DescriptorBufferInfo) {
+        let buffer_info = [info];
+        let write = *vk::WriteDescriptorSet::builder()
+            .dst_set(self.sets[set as usize])
+            .dst_binding(binding)
+            .descriptor_type(self.get_descriptor_type(set, binding))
+            .buffer_info(&buffer_info);
+
+        unsafe { self.device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    /// Points the image or sampler binding at `set` and `binding` to `info`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the shaders have no such binding.
+    pub fn write_image(&mut self, set: u32, binding: u32, info: vk::DescriptorImageInfo) {
+        let image_info = [info];
+        let write = *vk::WriteDescriptorSet::builder()
+            .dst_set(self.sets[set as usize])
+            .dst_binding(binding)
+            .descriptor_type(self.get_descriptor_type(set, binding))
+            .image_info(&image_info);
+
+        unsafe { self.device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    fn get_descriptor_type(&self, set: u32, binding: u32) -> vk::DescriptorType {
+        self.bindings
+            .iter()
+            .find(|b| b.set == set && b.binding == binding)
+            .unwrap_or_else(|| panic!("The shaders have no binding {}.{}", set, binding))
+            .descriptor_type
+    }
+}
+
+impl Drop for DescriptorSets {
+    fn drop(&mut self) {
+        // frees the sets as well
+        unsafe { self.device.destroy_descriptor_pool(self.pool, None) };
+    }
+}
+
+/// The parts of a SPIR-V module reflection looks at, gathered from the instructions before the
+/// first function.
+#[derive(Default)]
+struct Module {
+    entry_points: Vec<EntryPoint>,
+    /// The `LocalSize` or `LocalSizeId` execution mode of each entry point.
+    local_sizes: HashMap<u32, LocalSize>,
+    names: HashMap<u32, String>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    types: HashMap<u32, Type>,
+    /// The lowest word of every scalar constant, enough for array lengths and workgroup sizes.
+    constants: HashMap<u32, u32>,
+    /// Every variable outside of functions.
+    variables: Vec<Variable>,
+}
+
+struct EntryPoint {
+    model: ExecutionModel,
+    id: u32,
+    interface: Vec<u32>,
+}
+
+enum LocalSize {
+    Literal([u32; 3]),
+    /// Ids of constants.
+    Id([u32; 3]),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Decorations {
+    location: Option<u32>,
+    set: Option<u32>,
+    binding: Option<u32>,
+    array_stride: Option<u32>,
+    /// Marks storage buffers declared in the `Uniform` storage class, as SPIR-V before 1.3 does.
+    buffer_block: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+    row_major: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Bool,
+    Int {
+        width: u32,
+        signed: bool,
+    },
+    Float {
+        width: u32,
+    },
+    Vector {
+        component: u32,
+        count: u32,
+    },
+    Matrix {
+        column: u32,
+        count: u32,
+    },
+    Image {
+        dim: Option<Dim>,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage,
+    /// `length` is the id of a constant.
+    Array {
+        element: u32,
+        length: u32,
+    },
+    RuntimeArray {
+        element: u32,
+    },
+    Struct {
+        members: Vec<u32>,
+    },
+    Pointer {
+        class: Option<StorageClass>,
+        pointee: u32,
+    },
+    AccelerationStructure,
+}
+
+struct Variable {
+    id: u32,
+    /// A pointer type.
+    ty: u32,
+    class: StorageClass,
+}
+
+impl Module {
+    fn parse(spirv: &[u32]) -> Result<Self> {
+        if spirv.len() < 5 || spirv[0] != spirv::MAGIC_NUMBER {
+            return Err(RenderError::ShaderReflection(
+                "the code has no SPIR-V header".to_owned(),
+            ));
+        }
+
+        let mut module = Self::default();
+        let mut words = &spirv[5..];
+        while let Some(&first) = words.first() {
+            let word_count = (first >> 16) as usize;
+            if word_count == 0 || word_count > words.len() {
+                return Err(RenderError::ShaderReflection(format!(
+                    "instruction at word {} is truncated",
+                    spirv.len() - words.len()
+                )));
+            }
+            let (instruction, rest) = words.split_at(word_count);
+            words = rest;
+
+            let Some(op) = Op::from_u32(first & 0xffff) else {
+                continue;
+            };
+            match (op, &instruction[1..]) {
+                // types and variables are all declared before the first function
+                (Op::Function, _) => break,
+                (Op::EntryPoint, &[model, id, ref rest @ ..]) => {
+                    // the name is padded with zeros to a whole word, the interface follows
+                    let name_words = rest
+                        .iter()
+                        .position(|word| word.to_le_bytes().contains(&0))
+                        .map_or(rest.len(), |i| i + 1);
+                    if let Some(model) = ExecutionModel::from_u32(model) {
+                        module.entry_points.push(EntryPoint {
+                            model,
+                            id,
+                            interface: rest[name_words..].to_vec(),
+                        });
+                    }
+                }
+                (Op::ExecutionMode, &[entry_point, mode, x, y, z])
+                    if ExecutionMode::from_u32(mode) == Some(ExecutionMode::LocalSize) =>
+                {
+                    module
+                        .local_sizes
+                        .insert(entry_point, LocalSize::Literal([x, y, z]));
+                }
+                (Op::ExecutionModeId, &[entry_point, mode, x, y, z])
+                    if ExecutionMode::from_u32(mode) == Some(ExecutionMode::LocalSizeId) =>
+                {
+                    module
+                        .local_sizes
+                        .insert(entry_point, LocalSize::Id([x, y, z]));
+                }
+                (Op::Name, &[id, ref name @ ..]) => {
+                    module.names.insert(id, literal_string(name));
+                }
+                (Op::Decorate, &[id, decoration, ref literals @ ..]) => {
+                    let decorations = module.decorations.entry(id).or_default();
+                    match (Decoration::from_u32(decoration), literals.first().copied()) {
+                        (Some(Decoration::Location), location) => decorations.location = location,
+                        (Some(Decoration::DescriptorSet), set) => decorations.set = set,
+                        (Some(Decoration::Binding), binding) => decorations.binding = binding,
+                        (Some(Decoration::ArrayStride), stride) => {
+                            decorations.array_stride = stride
+                        }
+                        (Some(Decoration::BufferBlock), _) => decorations.buffer_block = true,
+                        _ => {}
+                    }
+                }
+                (Op::MemberDecorate, &[id, member, decoration, ref literals @ ..]) => {
+                    let decorations = module.member_decorations.entry((id, member)).or_default();
+                    match (Decoration::from_u32(decoration), literals.first().copied()) {
+                        (Some(Decoration::Offset), offset) => decorations.offset = offset,
+                        (Some(Decoration::MatrixStride), stride) => {
+                            decorations.matrix_stride = stride;
+                        }
+                        (Some(Decoration::RowMajor), _) => decorations.row_major = true,
+                        _ => {}
+                    }
+                }
+                (Op::TypeBool, &[id]) => {
+                    module.types.insert(id, Type::Bool);
+                }
+                (Op::TypeInt, &[id, width, signed]) => {
+                    let signed = signed != 0;
+                    module.types.insert(id, Type::Int { width, signed });
+                }
+                (Op::TypeFloat, &[id, width, ..]) => {
+                    module.types.insert(id, Type::Float { width });
+                }
+                (Op::TypeVector, &[id, component, count]) => {
+                    module.types.insert(id, Type::Vector { component, count });
+                }
+                (Op::TypeMatrix, &[id, column, count]) => {
+                    module.types.insert(id, Type::Matrix { column, count });
+                }
+                (Op::TypeImage, &[id, _, dim, _, _, _, sampled, ..]) => {
+                    let dim = Dim::from_u32(dim);
+                    module.types.insert(id, Type::Image { dim, sampled });
+                }
+                (Op::TypeSampler, &[id]) => {
+                    module.types.insert(id, Type::Sampler);
+                }
+                (Op::TypeSampledImage, &[id, _]) => {
+                    module.types.insert(id, Type::SampledImage);
+                }
+                (Op::TypeArray, &[id, element, length]) => {
+                    module.types.insert(id, Type::Array { element, length });
+                }
+                (Op::TypeRuntimeArray, &[id, element]) => {
+                    module.types.insert(id, Type::RuntimeArray { element });
+                }
+                (Op::TypeStruct, &[id, ref members @ ..]) => {
+                    let members = members.to_vec();
+                    module.types.insert(id, Type::Struct { members });
+                }
+                // also declares pointers first named by `OpTypeForwardPointer`, e.g. buffer
+                // references, whose pointee is never looked at
+                (Op::TypePointer, &[id, class, pointee]) => {
+                    let class = StorageClass::from_u32(class);
+                    module.types.insert(id, Type::Pointer { class, pointee });
+                }
+                (Op::TypeAccelerationStructureKHR, &[id]) => {
+                    module.types.insert(id, Type::AccelerationStructure);
+                }
+                (Op::Constant | Op::SpecConstant, &[_, id, value, ..]) => {
+                    module.constants.insert(id, value);
+                }
+                (Op::Variable, &[ty, id, class, ..]) => {
+                    if let Some(class) = StorageClass::from_u32(class) {
+                        module.variables.push(Variable { id, ty, class });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn decorations(&self, id: u32) -> Decorations {
+        self.decorations.get(&id).copied().unwrap_or_default()
+    }
+
+    /// The name of `id`, `None` if it has none or an empty one like anonymous blocks.
+    fn name(&self, id: u32) -> Option<String> {
+        self.names.get(&id).filter(|name| !name.is_empty()).cloned()
+    }
+
+    fn get_type(&self, id: u32) -> Result<&Type> {
+        self.types
+            .get(&id)
+            .ok_or_else(|| RenderError::ShaderReflection(format!("type %{} is not declared", id)))
+    }
+
+    fn pointee(&self, pointer: u32) -> Result<u32> {
+        match self.get_type(pointer)? {
+            Type::Pointer { pointee, .. } => Ok(*pointee),
+            ty => Err(RenderError::ShaderReflection(format!(
+                "variable of type {:?} is not a pointer",
+                ty
+            ))),
+        }
+    }
+
+    fn constant(&self, id: u32) -> Result<u32> {
+        self.constants.get(&id).copied().ok_or_else(|| {
+            RenderError::ShaderReflection(format!("constant %{} is not declared", id))
+        })
+    }
+
+    fn workgroup_size(&self, entry_point: u32) -> [u32; 3] {
+        match self.local_sizes.get(&entry_point) {
+            Some(LocalSize::Literal(size)) => *size,
+            Some(LocalSize::Id(ids)) => ids.map(|id| self.constant(id).unwrap_or(0)),
+            None => [0; 3],
+        }
+    }
+
+    /// Size of `ty` in a block, where it is the type of a member decorated with `member`.
+    fn size_of(&self, ty: u32, member: Option<MemberDecorations>) -> Result<u32> {
+        Ok(match self.get_type(ty)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => count * self.size_of(*component, None)?,
+            Type::Matrix { column, count } => {
+                let member = member.unwrap_or_default();
+                match (member.matrix_stride, self.get_type(*column)?) {
+                    (Some(stride), Type::Vector { count: rows, .. }) if member.row_major => {
+                        rows * stride
+                    }
+                    (Some(stride), _) => count * stride,
+                    (None, _) => count * self.size_of(*column, None)?,
+                }
+            }
+            Type::Array { element, length } => {
+                let stride = match self.decorations(ty).array_stride {
+                    Some(stride) => stride,
+                    None => self.size_of(*element, member)?,
+                };
+                self.constant(*length)? * stride
+            }
+            Type::RuntimeArray { .. } => 0,
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (index, &member_ty) in members.iter().enumerate() {
+                    let decorations = self
+                        .member_decorations
+                        .get(&(ty, index as u32))
+                        .copied()
+                        .unwrap_or_default();
+                    let offset = decorations.offset.unwrap_or(size);
+                    size = size.max(offset + self.size_of(member_ty, Some(decorations))?);
+                }
+                size
+            }
+            // a `GL_EXT_buffer_reference`, stored as its 64 bit address
+            Type::Pointer {
+                class: Some(StorageClass::PhysicalStorageBuffer),
+                ..
+            } => 8,
+            ty => {
+                return Err(RenderError::ShaderReflection(format!(
+                    "{:?} can not be in a block",
+                    ty
+                )))
+            }
+        })
+    }
+
+    /// The descriptor type of `variable` and the number of descriptors, `None` for runtime sized
+    /// arrays.
+    fn descriptor_type(&self, variable: &Variable) -> Result<(vk::DescriptorType, Option<u32>)> {
+        let pointee = self.pointee(variable.ty)?;
+        let (ty, count) = match *self.get_type(pointee)? {
+            Type::Array { element, length } => (element, Some(self.constant(length)?)),
+            Type::RuntimeArray { element } => (element, None),
+            _ => (pointee, Some(1)),
+        };
+
+        let descriptor_type = match (variable.class, self.get_type(ty)?) {
+            (StorageClass::Uniform, _) if self.decorations(ty).buffer_block => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (StorageClass::Uniform, _) => vk::DescriptorType::UNIFORM_BUFFER,
+            (StorageClass::StorageBuffer, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (StorageClass::UniformConstant, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (StorageClass::UniformConstant, Type::SampledImage) => {
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            }
+            (StorageClass::UniformConstant, Type::Image { dim, sampled }) => match (dim, sampled) {
+                (Some(Dim::DimSubpassData), _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (Some(Dim::DimBuffer), 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (Some(Dim::DimBuffer), _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (StorageClass::UniformConstant, Type::AccelerationStructure) => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
+            (class, ty) => {
+                return Err(RenderError::ShaderReflection(format!(
+                    "unsupported resource {:?} in {:?}",
+                    ty, class
+                )))
+            }
+        };
+
+        Ok((descriptor_type, count))
+    }
+
+    fn push_input(&self, inputs: &mut Vec<VertexInput>, variable: &Variable) -> Result<()> {
+        // built-ins like gl_VertexIndex are not read from vertex buffers
+        let Some(location) = self.decorations(variable.id).location else {
+            return Ok(());
+        };
+        let name = self.name(variable.id);
+
+        let ty = self.get_type(self.pointee(variable.ty)?)?;
+        let (scalar, components) = match ty {
+            Type::Vector { component, count } => (self.get_type(*component)?, *count),
+            scalar => (scalar, 1),
+        };
+
+        let format = match (scalar, components) {
+            (Type::Float { width: 32 }, 1) => vk::Format::R32_SFLOAT,
+            (Type::Float { width: 32 }, 2) => vk::Format::R32G32_SFLOAT,
+            (Type::Float { width: 32 }, 3) => vk::Format::R32G32B32_SFLOAT,
+            (Type::Float { width: 32 }, _) => vk::Format::R32G32B32A32_SFLOAT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                1,
+            ) => vk::Format::R32_SINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                2,
+            ) => vk::Format::R32G32_SINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                3,
+            ) => vk::Format::R32G32B32_SINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                },
+                _,
+            ) => vk::Format::R32G32B32A32_SINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                1,
+            ) => vk::Format::R32_UINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                2,
+            ) => vk::Format::R32G32_UINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                3,
+            ) => vk::Format::R32G32B32_UINT,
+            (
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                },
+                _,
+            ) => vk::Format::R32G32B32A32_UINT,
+            _ => {
+                return Err(RenderError::ShaderReflection(format!(
+                    "vertex input {} at location {} has the unsupported type {:?}",
+                    name.as_deref().unwrap_or("<unnamed>"),
+                    location,
+                    ty
+                )))
+            }
+        };
+
+        inputs.push(VertexInput {
+            location,
+            name,
+            format,
+        });
+
+        Ok(())
+    }
+}
+
+/// Decodes a zero terminated SPIR-V string literal.
+fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumericType {
+    Float,
+    Sint,
+    Uint,
+}
+
+/// How a vertex attribute of `format` is read by a shader, as numeric type and component count.
+fn get_format_type(format: vk::Format) -> Option<(NumericType, u32)> {
+    use vk::Format as F;
+    use NumericType::{Float, Sint, Uint};
+
+    Some(match format {
+        F::R32_SFLOAT | F::R16_SFLOAT | F::R8_UNORM | F::R8_SNORM | F::R16_UNORM | F::R16_SNORM => {
+            (Float, 1)
+        }
+        F::R32G32_SFLOAT
+        | F::R16G16_SFLOAT
+        | F::R8G8_UNORM
+        | F::R8G8_SNORM
+        | F::R16G16_UNORM
+        | F::R16G16_SNORM => (Float, 2),
+        F::R32G32B32_SFLOAT | F::R16G16B16_SFLOAT | F::R8G8B8_UNORM | F::R8G8B8_SNORM => (Float, 3),
+        F::R32G32B32A32_SFLOAT
+        | F::R16G16B16A16_SFLOAT
+        | F::R8G8B8A8_UNORM
+        | F::R8G8B8A8_SNORM
+        | F::B8G8R8A8_UNORM
+        | F::R16G16B16A16_UNORM
+        | F::R16G16B16A16_SNORM
+        | F::A2B10G10R10_UNORM_PACK32
+        | F::A2B10G10R10_SNORM_PACK32 => (Float, 4),
+        F::R32_SINT | F::R16_SINT | F::R8_SINT => (Sint, 1),
+        F::R32G32_SINT | F::R16G16_SINT | F::R8G8_SINT => (Sint, 2),
+        F::R32G32B32_SINT | F::R16G16B16_SINT | F::R8G8B8_SINT => (Sint, 3),
+        F::R32G32B32A32_SINT | F::R16G16B16A16_SINT | F::R8G8B8A8_SINT => (Sint, 4),
+        F::R32_UINT | F::R16_UINT | F::R8_UINT => (Uint, 1),
+        F::R32G32_UINT | F::R16G16_UINT | F::R8G8_UINT => (Uint, 2),
+        F::R32G32B32_UINT | F::R16G16B16_UINT | F::R8G8B8_UINT => (Uint, 3),
+        F::R32G32B32A32_UINT | F::R16G16B16A16_UINT | F::R8G8B8A8_UINT => (Uint, 4),
+        _ => return None,
+    })
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use tracing::debug;
+
+use crate::{
+    bindless::{Bindless, SamplerId},
+    error::Result,
+    Vk,
+};
+
+/// The filtering and addressing of a sampler, the key of the [`SamplerCache`].
+///
+/// The W address mode is the U mode, as textures are 2D.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl SamplerDesc {
+    /// Trilinear filtering, repeating in both directions.
+    pub const LINEAR_REPEAT: Self = Self::new(
+        vk::Filter::LINEAR,
+        vk::SamplerMipmapMode::LINEAR,
+        vk::SamplerAddressMode::REPEAT,
+    );
+    /// Trilinear filtering, clamped to the edges, e.g. for post processing.
+    pub const LINEAR_CLAMP: Self = Self::new(
+        vk::Filter::LINEAR,
+        vk::SamplerMipmapMode::LINEAR,
+        vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    );
+    /// Nearest filtering, repeating in both directions, e.g. for pixel art.
+    pub const NEAREST_REPEAT: Self = Self::new(
+        vk::Filter::NEAREST,
+        vk::SamplerMipmapMode::NEAREST,
+        vk::SamplerAddressMode::REPEAT,
+    );
+    /// Nearest filtering, clamped to the edges, e.g. for lookup tables.
+    pub const NEAREST_CLAMP: Self = Self::new(
+        vk::Filter::NEAREST,
+        vk::SamplerMipmapMode::NEAREST,
+        vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    );
+
+    /// Uses `filter` for magnification and minification and `address_mode` in both directions.
+    pub const fn new(
+        filter: vk::Filter,
+        mipmap_mode: vk::SamplerMipmapMode,
+        address_mode: vk::SamplerAddressMode,
+    ) -> Self {
+        Self {
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_mode,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+        }
+    }
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self::LINEAR_REPEAT
+    }
+}
+
+/// Creates every distinct sampler once and hands out the same one for equal descriptions.
+///
+/// Samplers are never freed before the cache is dropped, as there are only ever a handful.
+pub struct SamplerCache {
+    device: Device,
+    samplers: HashMap<SamplerDesc, (vk::Sampler, Option<SamplerId>)>,
+}
+
+impl SamplerCache {
+    pub fn new(vk: &Vk) -> Self {
+        Self {
+            device: vk.get_device().clone(),
+            samplers: HashMap::new(),
+        }
+    }
+
+    /// The sampler for `desc`, created on first use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sampler could not be created.
+    pub fn get(&mut self, desc: SamplerDesc) -> Result<vk::Sampler> {
+        Ok(self.get_entry(desc)?.0)
+    }
+
+    /// The index of the sampler for `desc` in the global set, added on first use.
+    ///
+    /// The cache has to be used with a single [`Bindless`] set, as the index is remembered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sampler could not be created.
+    pub fn get_id(&mut self, bindless: &mut Bindless, desc: SamplerDesc) -> Result<SamplerId> {
+        let (sampler, id) = self.get_entry(desc)?;
+
+        Ok(*id.get_or_insert_with(|| bindless.add_sampler(*sampler)))
+    }
+
+    /// How many samplers have been created.
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    fn get_entry(&mut self, desc: SamplerDesc) -> Result<&mut (vk::Sampler, Option<SamplerId>)> {
+        if !self.samplers.contains_key(&desc) {
+            let sampler_info = vk::SamplerCreateInfo::builder()
+                .mag_filter(desc.mag_filter)
+                .min_filter(desc.min_filter)
+                .mipmap_mode(desc.mipmap_mode)
+                .address_mode_u(desc.address_mode_u)
+                .address_mode_v(desc.address_mode_v)
+                .address_mode_w(desc.address_mode_u)
+                .min_lod(0.)
+                .max_lod(vk::LOD_CLAMP_NONE)
+                // the sampler anisotropy feature is not enabled
+                .anisotropy_enable(false)
+                .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK);
+            let sampler = unsafe { self.device.create_sampler(&sampler_info, None)? };
+            debug!("Created sampler {:?}", desc);
+
+            self.samplers.insert(desc, (sampler, None));
+        }
+
+        Ok(self
+            .samplers
+            .get_mut(&desc)
+            .expect("Sampler was just inserted"))
+    }
+}
+
+impl Drop for SamplerCache {
+    fn drop(&mut self) {
+        // the set keeps the ids, but they must not be sampled after this
+        for (sampler, _) in self.samplers.values() {
+            unsafe { self.device.destroy_sampler(*sampler, None) };
+        }
+    }
+}
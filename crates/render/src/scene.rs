@@ -0,0 +1,408 @@
+use ash::vk;
+use glam::{Mat4, Vec3};
+
+use crate::{
+    buffer::{Buffer, BufferUsage},
+    error::Result,
+    sampler::SamplerDesc,
+    texture::{Texture, TextureData},
+    vertex::MeshVertex,
+    Vk,
+};
+
+/// An imported scene, e.g. from [`crate::gltf_import::load`], with everything still on the CPU.
+///
+/// Everything refers to each other by index into the vectors of the scene.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<SceneTexture>,
+    pub nodes: Vec<Node>,
+    /// The nodes at the top of the hierarchy.
+    pub roots: Vec<usize>,
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+}
+
+impl Scene {
+    /// The transform from the space of every node to the space of the scene, by node index.
+    ///
+    /// Nodes that can not be reached from [`Scene::roots`] keep their local transform.
+    pub fn get_world_transforms(&self) -> Vec<Mat4> {
+        let mut world: Vec<Mat4> = self.nodes.iter().map(|node| node.transform).collect();
+
+        let mut stack: Vec<(usize, Mat4)> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Mat4::IDENTITY))
+            .collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            world[index] = parent * node.transform;
+            stack.extend(node.children.iter().map(|&child| (child, world[index])));
+        }
+
+        world
+    }
+
+    /// Uploads every texture with a full mip chain, in the order of [`Scene::textures`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a texture could not be uploaded.
+    pub fn upload_textures(&self, vk: &Vk) -> Result<Vec<Texture>> {
+        self.textures
+            .iter()
+            .enumerate()
+            .map(|(i, texture)| {
+                let name = texture
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("texture {}", i));
+
+                Texture::new(vk, &name, &texture.data, true)
+            })
+            .collect()
+    }
+}
+
+/// A part of the scene hierarchy, placed relative to its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub name: Option<String>,
+    /// From the space of the node to the space of its parent.
+    pub transform: Mat4,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub light: Option<usize>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            name: None,
+            transform: Mat4::IDENTITY,
+            children: vec![],
+            mesh: None,
+            camera: None,
+            light: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    pub name: Option<String>,
+    pub primitives: Vec<Primitive>,
+}
+
+/// Indexed triangles drawn with a single material.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Primitive {
+    pub vertices: Vec<MeshVertex>,
+    /// Three per triangle, counter clockwise.
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+}
+
+impl Primitive {
+    /// Sets the normal of every vertex to the average of the triangles using it, weighted by
+    /// their area, for meshes that come without normals.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vec3::from(self.vertices[triangle[i] as usize].position));
+            // the length of the cross product is twice the area
+            let normal = (b - a).cross(c - a);
+
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.try_normalize().unwrap_or(Vec3::Z).into();
+        }
+    }
+
+    /// Derives the tangent of every vertex from the direction its texture coordinates increase
+    /// in, for meshes with normal maps that come without tangents.
+    ///
+    /// Normals have to be set first, the tangents are made orthogonal to them.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &self.vertices[triangle[i] as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|vertex| Vec3::from(vertex.position));
+            let (edge1, edge2) = (pb - pa, pc - pa);
+            let (du1, dv1) = (b.uv[0] - a.uv[0], b.uv[1] - a.uv[1]);
+            let (du2, dv2) = (c.uv[0] - a.uv[0], c.uv[1] - a.uv[1]);
+
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1. / determinant;
+            let tangent = (edge1 * dv2 - edge2 * dv1) * r;
+            let bitangent = (edge2 * du1 - edge1 * du2) * r;
+
+            for &index in triangle {
+                tangents[index as usize] += tangent;
+                bitangents[index as usize] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = Vec3::from(vertex.normal);
+            let orthogonal = tangent - normal * tangent.dot(normal);
+
+            let Some(tangent) = orthogonal.try_normalize() else {
+                vertex.tangent = [1., 0., 0., 1.];
+                continue;
+            };
+            let handedness = normal.cross(tangent).dot(bitangent);
+
+            vertex.tangent = tangent
+                .extend(if handedness < 0. { -1. } else { 1. })
+                .into();
+        }
+    }
+}
+
+/// How the alpha of the base color is used.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with an alpha below `cutoff` are discarded, the rest are opaque.
+    Mask { cutoff: f32 },
+    /// Blended over what is behind.
+    Blend,
+}
+
+/// A metallic roughness material, as in glTF. Textures are indices into [`Scene::textures`] and
+/// are multiplied by their factors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: Option<String>,
+    /// Linear RGBA.
+    pub base_color_factor: [f32; 4],
+    /// sRGB.
+    pub base_color_texture: Option<usize>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in green and metalness in blue.
+    pub metallic_roughness_texture: Option<usize>,
+    /// Tangent space normals.
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    /// Ambient occlusion in red.
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    /// sRGB.
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: [1.; 4],
+            base_color_texture: None,
+            metallic_factor: 1.,
+            roughness_factor: 1.,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.,
+            occlusion_texture: None,
+            occlusion_strength: 1.,
+            emissive_factor: [0.; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+/// A decoded image and how it is sampled, uploaded by [`Scene::upload_textures`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneTexture {
+    pub name: Option<String>,
+    pub data: TextureData,
+    pub sampler: SamplerDesc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective {
+        /// Vertical field of view in radians.
+        yfov: f32,
+        /// Width over height, `None` to use the aspect ratio of the viewport.
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        /// `None` for an infinite projection.
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        /// Half the width of the view.
+        xmag: f32,
+        /// Half the height of the view.
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+/// Looks down the -Z axis of its node, with +Y up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    /// Shines down the -Z axis of its node from infinitely far away.
+    Directional,
+    /// Shines from the origin of its node in every direction.
+    Point,
+    /// Shines down the -Z axis of its node in a cone, with angles in radians from the axis.
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+    pub name: Option<String>,
+    pub kind: LightKind,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    /// Lux for directional lights, candela for the others.
+    pub intensity: f32,
+    /// Distance after which the light has no effect, `None` for no limit.
+    pub range: Option<f32>,
+}
+
+/// Where a [`Primitive`] lives in [`MeshBuffers`], the parameters of its indexed draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrimitiveRange {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+    pub material: Option<usize>,
+}
+
+/// The vertices and indices of many meshes in one vertex and one index buffer.
+pub struct MeshBuffers {
+    vertices: Buffer<MeshVertex>,
+    indices: Buffer<u32>,
+    /// By mesh index.
+    primitives: Vec<Vec<PrimitiveRange>>,
+}
+
+impl MeshBuffers {
+    /// Uploads every primitive of `meshes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffers could not be created or the upload failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `meshes` have no vertices or indices at all.
+    pub fn new(vk: &Vk, name: &str, meshes: &[Mesh]) -> Result<Self> {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        let primitives = meshes
+            .iter()
+            .map(|mesh| {
+                mesh.primitives
+                    .iter()
+                    .map(|primitive| {
+                        let range = PrimitiveRange {
+                            first_index: indices.len() as u32,
+                            index_count: primitive.indices.len() as u32,
+                            vertex_offset: vertices.len() as i32,
+                            material: primitive.material,
+                        };
+                        vertices.extend_from_slice(&primitive.vertices);
+                        indices.extend_from_slice(&primitive.indices);
+
+                        range
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            vertices: Buffer::from_slice(
+                vk,
+                &format!("{} vertices", name),
+                BufferUsage::Vertex,
+                &vertices,
+            )?,
+            indices: Buffer::from_slice(
+                vk,
+                &format!("{} indices", name),
+                BufferUsage::Index,
+                &indices,
+            )?,
+            primitives,
+        })
+    }
+
+    pub fn get_vertex_buffer(&self) -> &Buffer<MeshVertex> {
+        &self.vertices
+    }
+
+    pub fn get_index_buffer(&self) -> &Buffer<u32> {
+        &self.indices
+    }
+
+    /// The primitives of mesh `mesh`, in the order of [`Mesh::primitives`].
+    pub fn get_primitives(&self, mesh: usize) -> &[PrimitiveRange] {
+        &self.primitives[mesh]
+    }
+
+    /// Binds the vertex buffer to binding 0 and the index buffer.
+    pub fn bind(&self, vk: &Vk, cmd: vk::CommandBuffer) {
+        unsafe {
+            let device = vk.get_device();
+            device.cmd_bind_vertex_buffers(cmd, 0, &[self.vertices.get_buffer()], &[0]);
+            device.cmd_bind_index_buffer(
+                cmd,
+                self.indices.get_buffer(),
+                0,
+                self.indices.get_index_type(),
+            );
+        }
+    }
+
+    /// Draws one primitive, after [`MeshBuffers::bind`] and with its material bound.
+    pub fn draw(&self, vk: &Vk, cmd: vk::CommandBuffer, primitive: &PrimitiveRange) {
+        unsafe {
+            vk.get_device().cmd_draw_indexed(
+                cmd,
+                primitive.index_count,
+                1,
+                primitive.first_index,
+                primitive.vertex_offset,
+                0,
+            )
+        };
+    }
+}
@@ -0,0 +1,756 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    fmt,
+};
+
+use ash::{
+    extensions::{ext, khr},
+    vk::{self, PhysicalDevice},
+    Instance,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    device::find_queue_families,
+    error::{RenderError, Result},
+    utils::vk_to_str,
+};
+
+/// Environment variable overriding the selected device, either by index or by name.
+pub const DEVICE_ENV_VAR: &str = "YOUNIVERSE_DEVICE";
+
+/// The renderer is written against Vulkan 1.3, dynamic rendering and sync2 being core there.
+pub const MIN_API_VERSION: u32 = vk::API_VERSION_1_3;
+
+/// A device feature that can be required by a [`DeviceSelector`] and is enabled on the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    DynamicRendering,
+    Synchronization2,
+    BufferDeviceAddress,
+    DescriptorIndexing,
+    TimelineSemaphore,
+    /// Unsized descriptor arrays, as used by [`crate::bindless::Bindless`].
+    RuntimeDescriptorArray,
+    /// Descriptor arrays with slots that are never written.
+    DescriptorBindingPartiallyBound,
+    /// Writing descriptors of a set that is bound in command buffers still being executed.
+    DescriptorBindingUpdateUnusedWhilePending,
+    DescriptorBindingSampledImageUpdateAfterBind,
+    DescriptorBindingStorageBufferUpdateAfterBind,
+    /// Indexing image arrays with values that differ between invocations.
+    ShaderSampledImageArrayNonUniformIndexing,
+}
+
+impl Feature {
+    /// The features the renderer can not run without.
+    pub const REQUIRED: [Feature; 5] = [
+        Feature::DynamicRendering,
+        Feature::Synchronization2,
+        Feature::BufferDeviceAddress,
+        Feature::DescriptorIndexing,
+        Feature::TimelineSemaphore,
+    ];
+
+    /// The features [`crate::bindless::Bindless`] needs on top of [`Feature::REQUIRED`].
+    pub const BINDLESS: [Feature; 6] = [
+        Feature::RuntimeDescriptorArray,
+        Feature::DescriptorBindingPartiallyBound,
+        Feature::DescriptorBindingUpdateUnusedWhilePending,
+        Feature::DescriptorBindingSampledImageUpdateAfterBind,
+        Feature::DescriptorBindingStorageBufferUpdateAfterBind,
+        Feature::ShaderSampledImageArrayNonUniformIndexing,
+    ];
+
+    /// Name of the feature as in the Vulkan specification.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::DynamicRendering => "dynamicRendering",
+            Feature::Synchronization2 => "synchronization2",
+            Feature::BufferDeviceAddress => "bufferDeviceAddress",
+            Feature::DescriptorIndexing => "descriptorIndexing",
+            Feature::TimelineSemaphore => "timelineSemaphore",
+            Feature::RuntimeDescriptorArray => "runtimeDescriptorArray",
+            Feature::DescriptorBindingPartiallyBound => "descriptorBindingPartiallyBound",
+            Feature::DescriptorBindingUpdateUnusedWhilePending => {
+                "descriptorBindingUpdateUnusedWhilePending"
+            }
+            Feature::DescriptorBindingSampledImageUpdateAfterBind => {
+                "descriptorBindingSampledImageUpdateAfterBind"
+            }
+            Feature::DescriptorBindingStorageBufferUpdateAfterBind => {
+                "descriptorBindingStorageBufferUpdateAfterBind"
+            }
+            Feature::ShaderSampledImageArrayNonUniformIndexing => {
+                "shaderSampledImageArrayNonUniformIndexing"
+            }
+        }
+    }
+
+    fn field(self, features: &mut Features) -> &mut vk::Bool32 {
+        match self {
+            Feature::DynamicRendering => &mut features.vk13.dynamic_rendering,
+            Feature::Synchronization2 => &mut features.vk13.synchronization2,
+            Feature::BufferDeviceAddress => &mut features.vk12.buffer_device_address,
+            Feature::DescriptorIndexing => &mut features.vk12.descriptor_indexing,
+            Feature::TimelineSemaphore => &mut features.vk12.timeline_semaphore,
+            Feature::RuntimeDescriptorArray => &mut features.vk12.runtime_descriptor_array,
+            Feature::DescriptorBindingPartiallyBound => {
+                &mut features.vk12.descriptor_binding_partially_bound
+            }
+            Feature::DescriptorBindingUpdateUnusedWhilePending => {
+                &mut features.vk12.descriptor_binding_update_unused_while_pending
+            }
+            Feature::DescriptorBindingSampledImageUpdateAfterBind => {
+                &mut features
+                    .vk12
+                    .descriptor_binding_sampled_image_update_after_bind
+            }
+            Feature::DescriptorBindingStorageBufferUpdateAfterBind => {
+                &mut features
+                    .vk12
+                    .descriptor_binding_storage_buffer_update_after_bind
+            }
+            Feature::ShaderSampledImageArrayNonUniformIndexing => {
+                &mut features
+                    .vk12
+                    .shader_sampled_image_array_non_uniform_indexing
+            }
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The Vulkan 1.2 and 1.3 feature structs, with their `p_next` left null so they can be copied.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Features {
+    pub vk12: vk::PhysicalDeviceVulkan12Features,
+    pub vk13: vk::PhysicalDeviceVulkan13Features,
+}
+
+impl Features {
+    /// Queries the features supported by `p_dev`.
+    pub fn query(instance: &Instance, p_dev: PhysicalDevice) -> Self {
+        let mut features = Features::default();
+        features.vk12.p_next = &mut features.vk13 as *mut _ as *mut c_void;
+
+        {
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut features.vk12);
+            unsafe { instance.get_physical_device_features2(p_dev, &mut features2) };
+        }
+
+        features.vk12.p_next = std::ptr::null_mut();
+        features.vk13.p_next = std::ptr::null_mut();
+        features
+    }
+
+    /// Features with only `enabled` turned on, for creating a device.
+    pub fn enabled(enabled: &[Feature]) -> Self {
+        let mut features = Features::default();
+        for feature in enabled {
+            *feature.field(&mut features) = vk::TRUE;
+        }
+
+        features
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        let mut features = *self;
+        *feature.field(&mut features) == vk::TRUE
+    }
+}
+
+/// Forces a specific device instead of the highest scoring one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceOverride {
+    /// Index in the order the driver enumerates the devices.
+    Index(usize),
+    /// Case insensitive substring of the device name.
+    Name(String),
+}
+
+impl DeviceOverride {
+    /// Reads [`DEVICE_ENV_VAR`], which is an index if it parses as one and a name otherwise.
+    pub fn from_env() -> Option<Self> {
+        Self::parse(&std::env::var(DEVICE_ENV_VAR).ok()?)
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+
+        Some(match value.parse() {
+            Ok(index) => DeviceOverride::Index(index),
+            Err(_) => DeviceOverride::Name(value.to_owned()),
+        })
+    }
+
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            DeviceOverride::Index(i) => *i == index,
+            DeviceOverride::Name(n) => name.to_lowercase().contains(&n.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for DeviceOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceOverride::Index(i) => write!(f, "index {}", i),
+            DeviceOverride::Name(n) => write!(f, "name \"{}\"", n),
+        }
+    }
+}
+
+/// Why a device was not selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// The device only supports an older Vulkan version than [`MIN_API_VERSION`].
+    ApiVersion(u32),
+    MissingFeature(Feature),
+    MissingExtension(String),
+    NoGraphicsQueue,
+    /// No queue family can present to the surface.
+    CannotPresent,
+    /// The device is suitable, but the override picked a different one.
+    Overridden,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::ApiVersion(version) => write!(
+                f,
+                "Vulkan {} is older than {}",
+                format_version(*version),
+                format_version(MIN_API_VERSION)
+            ),
+            Rejection::MissingFeature(feature) => write!(f, "missing feature {}", feature),
+            Rejection::MissingExtension(extension) => {
+                write!(f, "missing extension {}", extension)
+            }
+            Rejection::NoGraphicsQueue => write!(f, "no graphics queue"),
+            Rejection::CannotPresent => write!(f, "can not present to the surface"),
+            Rejection::Overridden => write!(f, "not picked by the override"),
+        }
+    }
+}
+
+/// A device as seen by the [`DeviceSelector`].
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub physical_device: PhysicalDevice,
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    pub score: u64,
+    /// Every reason the device was rejected, empty if it is suitable.
+    pub rejections: Vec<Rejection>,
+}
+
+impl Candidate {
+    pub fn is_suitable(&self) -> bool {
+        self.rejections.is_empty()
+    }
+}
+
+/// Every device the [`DeviceSelector`] looked at and why it was or was not selected.
+#[derive(Debug, Clone)]
+pub struct DeviceReport {
+    pub candidates: Vec<Candidate>,
+    /// Index into `candidates` of the selected device.
+    pub selected: Option<usize>,
+    /// The override that was in effect, if any.
+    pub device_override: Option<DeviceOverride>,
+}
+
+impl DeviceReport {
+    pub fn get_selected(&self) -> Option<&Candidate> {
+        self.selected.map(|i| &self.candidates[i])
+    }
+}
+
+impl fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Found {} Vulkan devices", self.candidates.len())?;
+        if let Some(device_override) = &self.device_override {
+            write!(f, ", overridden by {}", device_override)?;
+        }
+
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            write!(
+                f,
+                "\n  [{}] {} ({:?}, Vulkan {}) score {}",
+                candidate.index,
+                candidate.name,
+                candidate.device_type,
+                format_version(candidate.api_version),
+                candidate.score
+            )?;
+
+            if self.selected == Some(i) {
+                write!(f, ": selected")?;
+            } else if !candidate.rejections.is_empty() {
+                let reasons: Vec<String> = candidate
+                    .rejections
+                    .iter()
+                    .map(|rejection| rejection.to_string())
+                    .collect();
+                write!(f, ": rejected, {}", reasons.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Picks the physical device to run on.
+///
+/// A device has to support every required feature and extension to be considered, CPU
+/// implementations included so software drivers like lavapipe work on machines without a GPU. Of
+/// the remaining devices the one with the highest score is picked, preferring discrete over
+/// integrated GPUs and more device local memory, unless an override picks a device by index or
+/// name. [`DEVICE_ENV_VAR`] takes precedence over [`DeviceSelector::device`].
+#[derive(Debug, Clone)]
+pub struct DeviceSelector {
+    features: Vec<Feature>,
+    optional_features: Vec<Feature>,
+    extensions: Vec<CString>,
+    optional_extensions: Vec<CString>,
+    device_override: Option<DeviceOverride>,
+}
+
+impl Default for DeviceSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceSelector {
+    /// Creates a selector requiring [`Feature::REQUIRED`] and no extensions, requesting
+    /// [`Feature::BINDLESS`] and `VK_EXT_mesh_shader`.
+    pub fn new() -> Self {
+        Self {
+            features: Feature::REQUIRED.to_vec(),
+            optional_features: Feature::BINDLESS.to_vec(),
+            extensions: vec![],
+            optional_extensions: vec![ext::MeshShader::name().to_owned()],
+            device_override: None,
+        }
+    }
+
+    pub fn require_feature(mut self, feature: Feature) -> Self {
+        if !self.features.contains(&feature) {
+            self.features.push(feature);
+        }
+        self
+    }
+
+    /// Enables `feature` if the selected device supports it, without rejecting devices that do
+    /// not.
+    pub fn request_feature(mut self, feature: Feature) -> Self {
+        if !self.optional_features.contains(&feature) {
+            self.optional_features.push(feature);
+        }
+        self
+    }
+
+    pub fn require_extension(mut self, extension: &CStr) -> Self {
+        if !self.extensions.iter().any(|e| e.as_c_str() == extension) {
+            self.extensions.push(extension.to_owned());
+        }
+        self
+    }
+
+    /// Enables `extension` if the selected device supports it, without rejecting devices that do
+    /// not.
+    pub fn request_extension(mut self, extension: &CStr) -> Self {
+        if !self
+            .optional_extensions
+            .iter()
+            .any(|e| e.as_c_str() == extension)
+        {
+            self.optional_extensions.push(extension.to_owned());
+        }
+        self
+    }
+
+    /// Picks the device matching `device_override`, if the environment does not override it.
+    pub fn device(mut self, device_override: DeviceOverride) -> Self {
+        self.device_override = Some(device_override);
+        self
+    }
+
+    pub fn get_features(&self) -> &[Feature] {
+        &self.features
+    }
+
+    pub fn get_optional_features(&self) -> &[Feature] {
+        &self.optional_features
+    }
+
+    pub fn get_extensions(&self) -> &[CString] {
+        &self.extensions
+    }
+
+    pub fn get_optional_extensions(&self) -> &[CString] {
+        &self.optional_extensions
+    }
+
+    /// Looks at every device of `instance` without failing if none of them is suitable.
+    ///
+    /// If a `surface` is given, the device must also be able to present to it.
+    pub fn report(
+        &self,
+        instance: &Instance,
+        surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+    ) -> Result<DeviceReport> {
+        let mut candidates = unsafe { instance.enumerate_physical_devices()? }
+            .into_iter()
+            .enumerate()
+            .map(|(index, p_dev)| self.evaluate(instance, p_dev, index, surface))
+            .collect::<Result<Vec<_>>>()?;
+
+        let device_override = DeviceOverride::from_env().or_else(|| self.device_override.clone());
+        let selected = select_candidate(&mut candidates, device_override.as_ref());
+
+        Ok(DeviceReport {
+            candidates,
+            selected,
+            device_override,
+        })
+    }
+
+    /// Selects a device of `instance`, logging the report.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::NoSuitableDevice`] if no device is suitable or the device picked by
+    /// the override is not.
+    pub fn select(
+        &self,
+        instance: &Instance,
+        surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+    ) -> Result<(PhysicalDevice, DeviceReport)> {
+        let report = self.report(instance, surface)?;
+
+        match report.get_selected() {
+            Some(candidate) => {
+                info!("{}", report);
+                Ok((candidate.physical_device, report))
+            }
+            None => {
+                error!("{}", report);
+                Err(RenderError::NoSuitableDevice)
+            }
+        }
+    }
+
+    fn evaluate(
+        &self,
+        instance: &Instance,
+        p_dev: PhysicalDevice,
+        index: usize,
+        surface: Option<(&khr::Surface, vk::SurfaceKHR)>,
+    ) -> Result<Candidate> {
+        let props = unsafe { instance.get_physical_device_properties(p_dev) };
+        let available: Vec<String> =
+            unsafe { instance.enumerate_device_extension_properties(p_dev)? }
+                .iter()
+                .map(|props| vk_to_str(&props.extension_name))
+                .collect();
+        let mut rejections = self.check_support(
+            props.api_version,
+            &Features::query(instance, p_dev),
+            &available,
+        );
+
+        let queue_families = find_queue_families(instance, p_dev, surface)?;
+        if queue_families.graphics_family.is_none() {
+            rejections.push(Rejection::NoGraphicsQueue);
+        }
+        if surface.is_some() && queue_families.present_family.is_none() {
+            rejections.push(Rejection::CannotPresent);
+        }
+
+        Ok(Candidate {
+            physical_device: p_dev,
+            index,
+            name: vk_to_str(&props.device_name),
+            device_type: props.device_type,
+            api_version: props.api_version,
+            score: score(instance, p_dev, &props),
+            rejections,
+        })
+    }
+
+    /// The reasons a device with `api_version`, `features` and the `available` extensions can not
+    /// be used, every required feature and extension has to be supported.
+    fn check_support(
+        &self,
+        api_version: u32,
+        features: &Features,
+        available: &[String],
+    ) -> Vec<Rejection> {
+        let mut rejections = vec![];
+        if api_version < MIN_API_VERSION {
+            rejections.push(Rejection::ApiVersion(api_version));
+        }
+
+        rejections.extend(
+            self.features
+                .iter()
+                .filter(|feature| !features.supports(**feature))
+                .map(|feature| Rejection::MissingFeature(*feature)),
+        );
+        rejections.extend(
+            self.extensions
+                .iter()
+                .map(|extension| extension.to_string_lossy().into_owned())
+                .filter(|extension| !available.contains(extension))
+                .map(Rejection::MissingExtension),
+        );
+
+        rejections
+    }
+}
+
+/// Picks the index into `candidates` of the device to run on, rejecting the suitable devices the
+/// override did not pick with [`Rejection::Overridden`].
+///
+/// Without an override or a device matching it the suitable device with the highest score is
+/// picked. A device picked by the override is not replaced if it is unsuitable.
+fn select_candidate(
+    candidates: &mut [Candidate],
+    device_override: Option<&DeviceOverride>,
+) -> Option<usize> {
+    // a name can match several devices, prefer one that is suitable
+    let overridden = device_override.and_then(|device_override| {
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| device_override.matches(candidate.index, &candidate.name))
+            .min_by_key(|(_, candidate)| !candidate.is_suitable())
+            .map(|(i, _)| i)
+    });
+
+    match overridden {
+        Some(overridden) => {
+            for (i, candidate) in candidates.iter_mut().enumerate() {
+                if i != overridden && candidate.is_suitable() {
+                    candidate.rejections.push(Rejection::Overridden);
+                }
+            }
+
+            Some(overridden).filter(|i| candidates[*i].is_suitable())
+        }
+        None => {
+            if let Some(device_override) = device_override {
+                warn!(
+                    "No device matches the override by {}, selecting by score",
+                    device_override
+                );
+            }
+
+            candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, candidate)| candidate.is_suitable())
+                // the first device wins ties, as drivers tend to list the primary device first
+                .max_by_key(|(i, candidate)| (candidate.score, std::cmp::Reverse(*i)))
+                .map(|(i, _)| i)
+        }
+    }
+}
+
+fn score(instance: &Instance, p_dev: PhysicalDevice, props: &vk::PhysicalDeviceProperties) -> u64 {
+    let type_score = match props.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 1000,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 500,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 100,
+        vk::PhysicalDeviceType::CPU => 10,
+        _ => 0,
+    };
+
+    // a GiB of device local memory is worth a point, to pick the bigger of two similar GPUs
+    let mem_props = unsafe { instance.get_physical_device_memory_properties(p_dev) };
+    let device_local: u64 = mem_props.memory_heaps[..mem_props.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+
+    type_score + (device_local >> 30)
+}
+
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, score: u64, rejections: Vec<Rejection>) -> Candidate {
+        Candidate {
+            physical_device: PhysicalDevice::null(),
+            index: 0,
+            name: name.to_owned(),
+            device_type: vk::PhysicalDeviceType::DISCRETE_GPU,
+            api_version: MIN_API_VERSION,
+            score,
+            rejections,
+        }
+    }
+
+    /// Candidates indexed in the order they are listed.
+    fn candidates<const N: usize>(candidates: [Candidate; N]) -> Vec<Candidate> {
+        candidates
+            .into_iter()
+            .enumerate()
+            .map(|(index, candidate)| Candidate { index, ..candidate })
+            .collect()
+    }
+
+    #[test]
+    fn rejects_every_missing_feature_and_extension() {
+        let selector = DeviceSelector::new()
+            .require_extension(khr::Swapchain::name())
+            .require_extension(ext::MeshShader::name());
+        let mut features = Features::enabled(&Feature::REQUIRED);
+        features.vk12.buffer_device_address = vk::FALSE;
+        features.vk13.dynamic_rendering = vk::FALSE;
+        let available = ["VK_KHR_swapchain".to_owned()];
+
+        assert_eq!(
+            selector.check_support(vk::API_VERSION_1_2, &features, &available),
+            [
+                Rejection::ApiVersion(vk::API_VERSION_1_2),
+                Rejection::MissingFeature(Feature::DynamicRendering),
+                Rejection::MissingFeature(Feature::BufferDeviceAddress),
+                Rejection::MissingExtension("VK_EXT_mesh_shader".to_owned()),
+            ]
+        );
+        assert!(selector
+            .check_support(
+                MIN_API_VERSION,
+                &Features::enabled(&Feature::REQUIRED),
+                &[
+                    "VK_EXT_mesh_shader".to_owned(),
+                    "VK_KHR_swapchain".to_owned()
+                ],
+            )
+            .is_empty());
+        // requested features and extensions never reject
+        assert!(DeviceSelector::new()
+            .check_support(MIN_API_VERSION, &Features::enabled(&Feature::REQUIRED), &[])
+            .is_empty());
+    }
+
+    #[test]
+    fn parses_the_override_as_an_index_or_a_name() {
+        assert_eq!(DeviceOverride::parse(" 1 "), Some(DeviceOverride::Index(1)));
+        assert_eq!(
+            DeviceOverride::parse("RTX 4090"),
+            Some(DeviceOverride::Name("RTX 4090".to_owned()))
+        );
+        assert_eq!(
+            DeviceOverride::parse("-1"),
+            Some(DeviceOverride::Name("-1".to_owned()))
+        );
+        assert_eq!(DeviceOverride::parse("  "), None);
+
+        std::env::set_var(DEVICE_ENV_VAR, "llvmpipe");
+        assert_eq!(
+            DeviceOverride::from_env(),
+            Some(DeviceOverride::Name("llvmpipe".to_owned()))
+        );
+        std::env::remove_var(DEVICE_ENV_VAR);
+        assert_eq!(DeviceOverride::from_env(), None);
+    }
+
+    #[test]
+    fn name_override_prefers_a_suitable_device_and_rejects_the_others() {
+        let mut devices = candidates([
+            candidate(
+                "NVIDIA GeForce GTX 680",
+                1002,
+                vec![Rejection::ApiVersion(vk::API_VERSION_1_2)],
+            ),
+            candidate("AMD Radeon RX 7900", 1024, vec![]),
+            candidate("NVIDIA GeForce RTX 4090", 1024, vec![]),
+            candidate("llvmpipe", 10, vec![Rejection::NoGraphicsQueue]),
+        ]);
+        let device_override = DeviceOverride::Name("nvidia".to_owned());
+
+        assert_eq!(
+            select_candidate(&mut devices, Some(&device_override)),
+            Some(2)
+        );
+        let rejections: Vec<_> = devices
+            .iter()
+            .map(|candidate| candidate.rejections.as_slice())
+            .collect();
+        assert_eq!(
+            rejections,
+            [
+                &[Rejection::ApiVersion(vk::API_VERSION_1_2)][..],
+                &[Rejection::Overridden],
+                &[],
+                &[Rejection::NoGraphicsQueue],
+            ]
+        );
+    }
+
+    #[test]
+    fn unsuitable_override_selects_nothing() {
+        let mut devices = candidates([
+            candidate("AMD Radeon RX 7900", 1024, vec![]),
+            candidate("llvmpipe", 10, vec![Rejection::NoGraphicsQueue]),
+        ]);
+
+        assert_eq!(
+            select_candidate(&mut devices, Some(&DeviceOverride::Index(1))),
+            None
+        );
+        assert_eq!(devices[0].rejections, [Rejection::Overridden]);
+    }
+
+    #[test]
+    fn selects_the_highest_score_and_the_first_of_ties() {
+        let mut devices = candidates([
+            candidate("Intel UHD Graphics", 500, vec![]),
+            candidate("AMD Radeon RX 7900", 1024, vec![]),
+            candidate("NVIDIA GeForce RTX 4090", 1024, vec![]),
+            candidate(
+                "NVIDIA GeForce RTX 5090",
+                2048,
+                vec![Rejection::CannotPresent],
+            ),
+        ]);
+
+        assert_eq!(select_candidate(&mut devices, None), Some(1));
+        // an override matching nothing falls back to the score
+        assert_eq!(
+            select_candidate(&mut devices, Some(&DeviceOverride::Index(7))),
+            Some(1)
+        );
+        assert!(devices
+            .iter()
+            .all(|candidate| !candidate.rejections.contains(&Rejection::Overridden)));
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+};
+
+use ash::vk;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    error::{RenderError, Result},
+    reflect::ShaderReflection,
+    Vk,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+    /// `VK_EXT_mesh_shader` task shaders.
+    Task,
+    /// `VK_EXT_mesh_shader` mesh shaders.
+    Mesh,
+}
+
+impl ShaderStage {
+    /// Guesses the stage from the `.vert`, `.frag`, `.comp`, `.task` or `.mesh` extension of
+    /// `path`, looking at the extension before `.spv` for SPIR-V files.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "vert" => Some(ShaderStage::Vertex),
+            "frag" => Some(ShaderStage::Fragment),
+            "comp" => Some(ShaderStage::Compute),
+            "task" => Some(ShaderStage::Task),
+            "mesh" => Some(ShaderStage::Mesh),
+            "spv" => Self::from_path(Path::new(path.file_stem()?)),
+            _ => None,
+        }
+    }
+
+    pub fn get_flags(self) -> vk::ShaderStageFlags {
+        match self {
+            ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
+            ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+            ShaderStage::Task => vk::ShaderStageFlags::TASK_EXT,
+            ShaderStage::Mesh => vk::ShaderStageFlags::MESH_EXT,
+        }
+    }
+
+    fn to_glslang(self) -> glslang::ShaderStage {
+        match self {
+            ShaderStage::Vertex => glslang::ShaderStage::Vertex,
+            ShaderStage::Fragment => glslang::ShaderStage::Fragment,
+            ShaderStage::Compute => glslang::ShaderStage::Compute,
+            ShaderStage::Task => glslang::ShaderStage::Task,
+            ShaderStage::Mesh => glslang::ShaderStage::Mesh,
+        }
+    }
+
+    pub(crate) fn to_execution_model(self) -> spirv::ExecutionModel {
+        match self {
+            ShaderStage::Vertex => spirv::ExecutionModel::Vertex,
+            ShaderStage::Fragment => spirv::ExecutionModel::Fragment,
+            ShaderStage::Compute => spirv::ExecutionModel::GLCompute,
+            ShaderStage::Task => spirv::ExecutionModel::TaskEXT,
+            ShaderStage::Mesh => spirv::ExecutionModel::MeshEXT,
+        }
+    }
+}
+
+/// A GLSL file a pipeline is built from, or a `.spv` file of SPIR-V compiled offline, e.g. by
+/// `glslc`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ShaderSource {
+    pub path: PathBuf,
+    pub stage: ShaderStage,
+}
+
+impl ShaderSource {
+    /// A source whose stage is taken from the extension of `path`, see [`ShaderStage::from_path`],
+    /// e.g. `cull.task.spv`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the extension is not a known shader stage.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let stage = ShaderStage::from_path(&path)
+            .unwrap_or_else(|| panic!("Unknown shader stage of {}", path.display()));
+
+        Self { path, stage }
+    }
+
+    /// Reads and compiles the file, or only reads it for `.spv` files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read, does not compile or is not SPIR-V.
+    pub fn compile(&self) -> Result<CompiledShader> {
+        let name = self.path.display().to_string();
+        let spirv = if self
+            .path
+            .extension()
+            .is_some_and(|extension| extension == "spv")
+        {
+            read_spirv(&fs::read(&self.path)?, &name)?
+        } else {
+            compile_glsl(&fs::read_to_string(&self.path)?, self.stage, &name)?
+        };
+
+        Ok(CompiledShader {
+            stage: self.stage,
+            spirv,
+        })
+    }
+}
+
+/// SPIR-V of one stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledShader {
+    pub stage: ShaderStage,
+    pub spirv: Vec<u32>,
+}
+
+impl CompiledShader {
+    /// Creates a shader module of the SPIR-V, which the caller has to destroy.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module could not be created.
+    pub fn create_module(&self, vk: &Vk) -> Result<vk::ShaderModule> {
+        let module_info = vk::ShaderModuleCreateInfo::builder().code(&self.spirv);
+
+        Ok(unsafe { vk.get_device().create_shader_module(&module_info, None)? })
+    }
+
+    /// Reflects the inputs and resources of the SPIR-V.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the SPIR-V can not be reflected, see [`ShaderReflection::new`].
+    pub fn reflect(&self) -> Result<ShaderReflection> {
+        ShaderReflection::new(&self.spirv, self.stage)
+    }
+}
+
+/// Compiles GLSL `source` of `stage` to SPIR-V 1.6 for Vulkan 1.3 in-process with glslang, `name`
+/// being used in error messages.
+///
+/// Vulkan GLSL extensions such as `GL_EXT_nonuniform_qualifier`, `GL_EXT_buffer_reference` and
+/// `GL_EXT_mesh_shader` are supported, so shaders can use [`crate::bindless::GLSL_DECLARATIONS`].
+///
+/// # Errors
+///
+/// Returns [`RenderError::ShaderCompilation`] with the messages of the compiler pointing into
+/// `source` if it does not compile.
+pub fn compile_glsl(source: &str, stage: ShaderStage, name: &str) -> Result<Vec<u32>> {
+    let compilation_error = |e: glslang::error::GlslangError| {
+        let message = match e {
+            glslang::error::GlslangError::PreprocessError(log)
+            | glslang::error::GlslangError::ParseError(log)
+            | glslang::error::GlslangError::LinkError(log) => log.log,
+            e => e.to_string(),
+        };
+
+        RenderError::ShaderCompilation {
+            name: name.to_owned(),
+            message: name_locations(&message, name),
+        }
+    };
+
+    let compiler = glslang::Compiler::acquire().ok_or_else(|| RenderError::ShaderCompilation {
+        name: name.to_owned(),
+        message: "could not initialize glslang".to_owned(),
+    })?;
+
+    let source = glslang::ShaderSource::from(source);
+    let options = glslang::CompilerOptions {
+        target: glslang::Target::Vulkan {
+            version: glslang::VulkanVersion::Vulkan1_3,
+            spirv_version: glslang::SpirvVersion::SPIRV1_6,
+        },
+        ..Default::default()
+    };
+    let input = glslang::ShaderInput::new(
+        &source,
+        stage.to_glslang(),
+        &options,
+        None::<&[(&str, Option<&str>)]>,
+        None,
+    )
+    .map_err(compilation_error)?;
+
+    compiler
+        .create_shader(input)
+        .and_then(|shader| shader.compile())
+        .map_err(compilation_error)
+}
+
+/// Replaces the source string index glslang prefixes locations with, as in `ERROR: 0:4: ...`, by
+/// `name`.
+fn name_locations(log: &str, name: &str) -> String {
+    log.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            for severity in ["ERROR: 0:", "WARNING: 0:"] {
+                if let Some(rest) = line.strip_prefix(severity) {
+                    return format!("{}{}:{}", &severity[..severity.len() - 2], name, rest);
+                }
+            }
+            line.to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reads the words of a SPIR-V binary, `name` being used in error messages.
+///
+/// # Errors
+///
+/// Returns [`RenderError::ShaderCompilation`] if `bytes` do not start with the SPIR-V magic number.
+pub fn read_spirv(bytes: &[u8], name: &str) -> Result<Vec<u32>> {
+    let words = ash::util::read_spv(&mut std::io::Cursor::new(bytes)).map_err(|e| {
+        RenderError::ShaderCompilation {
+            name: name.to_owned(),
+            message: format!("not SPIR-V: {}", e),
+        }
+    })?;
+
+    Ok(words)
+}
+
+/// Identifies something registered with a [`ShaderReloader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReloadId(usize);
+
+type BuildFn<T> = Box<dyn FnMut(&Vk, &[CompiledShader]) -> Result<T>>;
+
+struct Reloadable<T> {
+    name: String,
+    sources: Vec<ShaderSource>,
+    build: BuildFn<T>,
+    current: T,
+}
+
+/// Owns pipelines, or anything else built from shaders, and rebuilds them when one of their GLSL
+/// sources changes on disk.
+///
+/// [`ShaderReloader::update`] has to be called regularly, e.g. once per frame. If a changed shader
+/// fails to compile or the rebuild fails, the error is logged and the old value is kept, so a typo
+/// does not take down the running renderer. Values are dropped once replaced, so `T` is expected to
+/// free its Vulkan objects in [`Drop`].
+pub struct ShaderReloader<T> {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    watched_dirs: HashSet<PathBuf>,
+    entries: Vec<Reloadable<T>>,
+}
+
+impl<T> ShaderReloader<T> {
+    /// # Errors
+    ///
+    /// Returns an error if the file watcher of the platform could not be created.
+    pub fn new() -> Result<Self> {
+        let (sender, events) = channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            // the receiver is gone when the reloader was dropped, nothing to do then
+            let _ = sender.send(event);
+        })
+        .map_err(watch_error)?;
+
+        Ok(Self {
+            watcher,
+            events,
+            watched_dirs: HashSet::new(),
+            entries: vec![],
+        })
+    }
+
+    /// Compiles `sources`, builds the value with `build` and rebuilds it whenever one of the
+    /// sources changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a source does not compile, `build` fails or the sources can not be
+    /// watched.
+    pub fn add(
+        &mut self,
+        vk: &Vk,
+        name: &str,
+        sources: Vec<ShaderSource>,
+        mut build: impl FnMut(&Vk, &[CompiledShader]) -> Result<T> + 'static,
+    ) -> Result<ReloadId> {
+        let sources = sources
+            .into_iter()
+            .map(|source| {
+                Ok(ShaderSource {
+                    path: fs::canonicalize(&source.path)?,
+                    stage: source.stage,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let compiled = sources
+            .iter()
+            .map(ShaderSource::compile)
+            .collect::<Result<Vec<_>>>()?;
+        let current = build(vk, &compiled)?;
+
+        for source in &sources {
+            self.watch_dir(&source.path)?;
+        }
+
+        self.entries.push(Reloadable {
+            name: name.to_owned(),
+            sources,
+            build: Box::new(build),
+            current,
+        });
+
+        Ok(ReloadId(self.entries.len() - 1))
+    }
+
+    pub fn get(&self, id: ReloadId) -> &T {
+        &self.entries[id.0].current
+    }
+
+    /// Rebuilds everything whose sources changed since the last update, returning how many were
+    /// replaced.
+    ///
+    /// Waits for the device to be idle once before replacing anything, as the old values may still
+    /// be in use by frames in flight.
+    pub fn update(&mut self, vk: &Vk) -> usize {
+        let mut changed = HashSet::new();
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Shader watcher error: {}", e),
+            }
+        }
+
+        if changed.is_empty() {
+            return 0;
+        }
+
+        let mut rebuilt = vec![];
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            if !entry
+                .sources
+                .iter()
+                .any(|source| changed.contains(&source.path))
+            {
+                continue;
+            }
+
+            let value = entry
+                .sources
+                .iter()
+                .map(ShaderSource::compile)
+                .collect::<Result<Vec<_>>>()
+                .and_then(|compiled| (entry.build)(vk, &compiled));
+
+            match value {
+                Ok(value) => rebuilt.push((i, value)),
+                Err(e) => error!("Keeping the old {}: {}", entry.name, e),
+            }
+        }
+
+        if rebuilt.is_empty() {
+            return 0;
+        }
+        // one wait for everything a change rebuilt, e.g. all users of a shared include
+        if let Err(e) = unsafe { vk.get_device().device_wait_idle() } {
+            error!("Could not wait for the old values to be unused: {}", e);
+            return 0;
+        }
+
+        let reloaded = rebuilt.len();
+        for (i, value) in rebuilt {
+            let entry = &mut self.entries[i];
+            entry.current = value;
+            info!("Reloaded {}", entry.name);
+        }
+
+        reloaded
+    }
+
+    /// Watches the directory of `path` instead of the file itself, as editors often save by
+    /// replacing the file.
+    fn watch_dir(&mut self, path: &Path) -> Result<()> {
+        let Some(dir) = path.parent() else {
+            return Ok(());
+        };
+
+        if self.watched_dirs.insert(dir.to_owned()) {
+            self.watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn watch_error(e: notify::Error) -> RenderError {
+    match e.kind {
+        notify::ErrorKind::Io(e) => RenderError::Io(e),
+        _ => RenderError::Io(std::io::Error::other(e.to_string())),
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use crate::{
+    error::Result,
+    memory::{GpuBuffer, MemoryLocation},
+    Vk,
+};
+
+/// Size of the staging ring [`Vk`] creates on the first upload.
+pub const DEFAULT_STAGING_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+
+/// Space in the ring that was handed out before `submission` was recorded.
+struct Region {
+    end: vk::DeviceSize,
+    bytes: vk::DeviceSize,
+    submission: u64,
+}
+
+/// A host visible buffer that uploads are copied into before the GPU copies them to their
+/// destination, reused in a circle instead of creating a staging buffer per upload.
+///
+/// Space is handed out with [`StagingRing::push`]. Everything pushed since the last
+/// [`StagingRing::submit`] belongs to the submission it returns, and is only reused once
+/// [`StagingRing::release`] is called with that submission or a later one, i.e. once the GPU is
+/// done copying out of it.
+pub struct StagingRing {
+    buffer: GpuBuffer,
+    /// Where the next push starts.
+    head: vk::DeviceSize,
+    /// Start of the oldest region still in use.
+    tail: vk::DeviceSize,
+    /// Bytes in use, including padding and space skipped when wrapping around.
+    live: vk::DeviceSize,
+    /// Bytes pushed since the last submit.
+    pending: vk::DeviceSize,
+    in_flight: VecDeque<Region>,
+    next_submission: u64,
+}
+
+impl StagingRing {
+    /// Creates a ring of `capacity` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer could not be allocated.
+    pub fn new(vk: &Vk, capacity: vk::DeviceSize) -> Result<Self> {
+        let buffer = GpuBuffer::new(
+            vk,
+            "staging ring",
+            capacity,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::Upload,
+        )?;
+
+        Ok(Self {
+            buffer,
+            head: 0,
+            tail: 0,
+            live: 0,
+            pending: 0,
+            in_flight: VecDeque::new(),
+            next_submission: 1,
+        })
+    }
+
+    /// Copies `data` into the ring at an offset aligned to `alignment`, which has to be a power of
+    /// two.
+    ///
+    /// Returns the offset `data` was written to, or `None` if there is no contiguous free space
+    /// left for it until earlier submissions are released.
+    pub fn push(
+        &mut self,
+        data: &[u8],
+        alignment: vk::DeviceSize,
+    ) -> Result<Option<vk::DeviceSize>> {
+        let Some(offset) = self.reserve(data.len() as vk::DeviceSize, alignment) else {
+            return Ok(None);
+        };
+
+        self.buffer.write(offset, data)?;
+
+        Ok(Some(offset))
+    }
+
+    /// Marks everything pushed since the last submit as in use by a new submission, returning its
+    /// id.
+    pub fn submit(&mut self) -> u64 {
+        let submission = self.next_submission;
+        self.next_submission += 1;
+
+        if self.pending > 0 {
+            self.in_flight.push_back(Region {
+                end: self.head,
+                bytes: self.pending,
+                submission,
+            });
+            self.pending = 0;
+        }
+
+        submission
+    }
+
+    /// Frees the space of every submission up to and including `completed`.
+    pub fn release(&mut self, completed: u64) {
+        while let Some(region) = self.in_flight.front() {
+            if region.submission > completed {
+                break;
+            }
+
+            self.tail = region.end;
+            self.live -= region.bytes;
+            self.in_flight.pop_front();
+        }
+
+        if self.live == 0 {
+            self.head = 0;
+            self.tail = 0;
+        }
+    }
+
+    pub fn get_buffer(&self) -> vk::Buffer {
+        self.buffer.get_buffer()
+    }
+
+    pub fn get_capacity(&self) -> vk::DeviceSize {
+        self.buffer.get_size()
+    }
+
+    fn reserve(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let capacity = self.get_capacity();
+        let aligned = align_up(self.head, alignment);
+
+        let offset = if self.live == 0 || self.head > self.tail {
+            // free space runs from the head to the end, and from the start to the tail
+            if aligned + size <= capacity {
+                aligned
+            } else if size <= self.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if aligned + size <= self.tail {
+            aligned
+        } else {
+            return None;
+        };
+
+        // padding and space skipped at the end count as used until the region is released
+        let used = if offset >= self.head {
+            offset + size - self.head
+        } else {
+            capacity - self.head + offset + size
+        };
+
+        self.head = offset + size;
+        self.live += used;
+        self.pending += used;
+
+        Some(offset)
+    }
+}
+
+fn align_up(offset: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    (offset + alignment - 1) & !(alignment - 1)
+}
@@ -1,3 +1,440 @@
-pub fn create_swapchain() {
-    todo!();
+use ash::{
+    extensions::khr,
+    prelude::VkResult,
+    vk::{self, PhysicalDevice},
+    Device, Instance,
+};
+use tracing::{debug, info};
+
+use crate::error::{RenderError, Result};
+
+pub struct SwapChainSupportDetail {
+    pub capabilities: vk::SurfaceCapabilitiesKHR,
+    pub formats: Vec<vk::SurfaceFormatKHR>,
+    pub present_modes: Vec<vk::PresentModeKHR>,
+}
+
+impl SwapChainSupportDetail {
+    pub fn query(
+        surface_loader: &khr::Surface,
+        p_dev: PhysicalDevice,
+        surface: vk::SurfaceKHR,
+    ) -> Result<Self> {
+        unsafe {
+            let capabilities =
+                surface_loader.get_physical_device_surface_capabilities(p_dev, surface)?;
+
+            let formats = surface_loader.get_physical_device_surface_formats(p_dev, surface)?;
+
+            let present_modes =
+                surface_loader.get_physical_device_surface_present_modes(p_dev, surface)?;
+
+            Ok(SwapChainSupportDetail {
+                capabilities,
+                formats,
+                present_modes,
+            })
+        }
+    }
+
+    /// Returns true if the surface can be presented to at all.
+    pub fn is_adequate(&self) -> bool {
+        !self.formats.is_empty() && !self.present_modes.is_empty()
+    }
+}
+
+/// Owns a [`vk::SwapchainKHR`] together with the surface it presents to and the image views of its
+/// images.
+///
+/// The swapchain can be recreated in place with [`Swapchain::recreate`] when the window is resized,
+/// the old swapchain is handed to the driver so presentation does not have to be torn down.
+pub struct Swapchain {
+    device: Device,
+    loader: khr::Swapchain,
+    surface_loader: khr::Surface,
+    surface: vk::SurfaceKHR,
+    p_dev: PhysicalDevice,
+    swapchain: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
+    format: vk::SurfaceFormatKHR,
+    extent: vk::Extent2D,
+    /// The present mode picked for the surface, see [`Swapchain::get_present_mode`].
+    present_mode: vk::PresentModeKHR,
+    /// The present mode asked for, used again whenever the swapchain is recreated.
+    requested_present_mode: vk::PresentModeKHR,
+    /// The families rendering to and presenting the images, see [`create_swapchain`].
+    queue_families: Vec<u32>,
+    usage: vk::ImageUsageFlags,
+}
+
+impl Swapchain {
+    /// Creates a new [`Swapchain`] for `surface`, taking ownership of the surface once the
+    /// swapchain exists. On error the surface is left alive for the caller to try again.
+    ///
+    /// `present_mode` is used if the surface supports it, otherwise FIFO is used as it is always
+    /// available.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the surface can not be queried, has no extent, e.g. because the window
+    /// is minimized, or the swapchain could not be created.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        instance: &Instance,
+        device: &Device,
+        p_dev: PhysicalDevice,
+        surface_loader: khr::Surface,
+        surface: vk::SurfaceKHR,
+        extent: vk::Extent2D,
+        present_mode: vk::PresentModeKHR,
+        queue_families: &[u32],
+    ) -> Result<Self> {
+        let loader = khr::Swapchain::new(instance, device);
+
+        let mut swapchain = Swapchain {
+            device: device.clone(),
+            loader,
+            surface_loader,
+            surface,
+            p_dev,
+            swapchain: vk::SwapchainKHR::null(),
+            images: vec![],
+            image_views: vec![],
+            format: vk::SurfaceFormatKHR::default(),
+            extent,
+            present_mode,
+            requested_present_mode: present_mode,
+            queue_families: queue_families.to_vec(),
+            usage: vk::ImageUsageFlags::empty(),
+        };
+
+        let created = swapchain.recreate(extent).and_then(|recreated| {
+            if !recreated {
+                return Err(RenderError::SurfaceCreationFailed(
+                    vk::Result::ERROR_OUT_OF_DATE_KHR,
+                ));
+            }
+            Ok(())
+        });
+        if let Err(e) = created {
+            // the surface still belongs to the caller, so dropping must not destroy it
+            swapchain.surface = vk::SurfaceKHR::null();
+            return Err(e);
+        }
+
+        Ok(swapchain)
+    }
+
+    /// Recreates the swapchain with a new extent, e.g. after the window has been resized.
+    ///
+    /// The old swapchain is passed as `old_swapchain` and destroyed once the new one exists, so the
+    /// device and surface stay alive.
+    ///
+    /// Returns whether the swapchain was recreated. Nothing is done, and the device is not waited
+    /// for, if the surface reports a zero extent, as it does for minimized windows on some
+    /// platforms even when the window size is not zero.
+    pub fn recreate(&mut self, extent: vk::Extent2D) -> Result<bool> {
+        let support =
+            SwapChainSupportDetail::query(&self.surface_loader, self.p_dev, self.surface)?;
+
+        let current = support.capabilities.current_extent;
+        if current.width == 0 || current.height == 0 {
+            debug!("Surface has no extent, keeping the old swapchain");
+            return Ok(false);
+        }
+
+        unsafe { self.device.device_wait_idle()? };
+
+        let (swapchain, format, extent, present_mode) = create_swapchain(
+            &self.loader,
+            self.surface,
+            &support,
+            extent,
+            self.requested_present_mode,
+            &self.queue_families,
+            self.swapchain,
+        )?;
+
+        self.destroy_image_views();
+        if self.swapchain != vk::SwapchainKHR::null() {
+            unsafe { self.loader.destroy_swapchain(self.swapchain, None) };
+        }
+
+        self.swapchain = swapchain;
+        self.format = format;
+        self.usage = choose_image_usage(&support.capabilities);
+        self.extent = extent;
+        self.present_mode = present_mode;
+        self.images = unsafe { self.loader.get_swapchain_images(swapchain)? };
+        self.image_views = self
+            .images
+            .iter()
+            .map(|image| create_image_view(&self.device, *image, format.format))
+            .collect::<Result<_>>()?;
+
+        info!(
+            "Created swapchain {}x{} with {} images, format {:?}, present mode {:?}",
+            extent.width,
+            extent.height,
+            self.images.len(),
+            format.format,
+            present_mode
+        );
+
+        Ok(true)
+    }
+
+    /// Acquires the next image of the swapchain, signaling `semaphore` and/or `fence` when it is
+    /// ready to be rendered to.
+    ///
+    /// Returns the image index and whether the swapchain is suboptimal for the surface. An
+    /// `ERROR_OUT_OF_DATE_KHR` error means [`Swapchain::recreate`] must be called.
+    pub fn acquire(&self, semaphore: vk::Semaphore, fence: vk::Fence) -> VkResult<(u32, bool)> {
+        unsafe {
+            self.loader
+                .acquire_next_image(self.swapchain, u64::MAX, semaphore, fence)
+        }
+    }
+
+    /// Presents `image_index` on `queue` after `wait_semaphores` have been signaled.
+    ///
+    /// Returns whether the swapchain is suboptimal for the surface.
+    pub fn present(
+        &self,
+        queue: vk::Queue,
+        image_index: u32,
+        wait_semaphores: &[vk::Semaphore],
+    ) -> VkResult<bool> {
+        let swapchains = [self.swapchain];
+        let image_indices = [image_index];
+
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+
+        unsafe { self.loader.queue_present(queue, &present_info) }
+    }
+
+    pub fn get_swapchain(&self) -> vk::SwapchainKHR {
+        self.swapchain
+    }
+
+    pub fn get_surface(&self) -> vk::SurfaceKHR {
+        self.surface
+    }
+
+    pub fn get_images(&self) -> &[vk::Image] {
+        &self.images
+    }
+
+    pub fn get_image_views(&self) -> &[vk::ImageView] {
+        &self.image_views
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.format.format
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Whether the images can be copied into buffers, which
+    /// [`crate::FrameLoop::capture_frame`] needs. Nearly every surface allows it.
+    pub fn supports_capture(&self) -> bool {
+        self.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC)
+    }
+
+    /// How the images can be used, always as color attachments and as transfer source and
+    /// destination where the surface allows it.
+    pub fn get_usage(&self) -> vk::ImageUsageFlags {
+        self.usage
+    }
+
+    /// The present mode in use, which is FIFO if the requested one is not supported.
+    pub fn get_present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    /// Changes the requested present mode, which takes effect on the next
+    /// [`Swapchain::recreate`].
+    pub fn set_present_mode(&mut self, present_mode: vk::PresentModeKHR) {
+        self.requested_present_mode = present_mode;
+    }
+
+    fn destroy_image_views(&mut self) {
+        for view in self.image_views.drain(..) {
+            unsafe { self.device.destroy_image_view(view, None) };
+        }
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        unsafe {
+            // nothing sensible can be done about a lost device while dropping
+            let _ = self.device.device_wait_idle();
+
+            self.destroy_image_views();
+            self.loader.destroy_swapchain(self.swapchain, None);
+            self.surface_loader.destroy_surface(self.surface, None);
+        }
+    }
+}
+
+/// Creates a [`vk::SwapchainKHR`] for `surface`, reusing resources from `old_swapchain` if it is
+/// not null.
+///
+/// The images are shared concurrently between `queue_families` if they hold more than one family,
+/// e.g. when presenting uses another family than rendering, so no ownership transfer is needed.
+///
+/// Returns the swapchain together with the format, extent and present mode that was picked.
+pub fn create_swapchain(
+    loader: &khr::Swapchain,
+    surface: vk::SurfaceKHR,
+    support: &SwapChainSupportDetail,
+    extent: vk::Extent2D,
+    present_mode: vk::PresentModeKHR,
+    queue_families: &[u32],
+    old_swapchain: vk::SwapchainKHR,
+) -> Result<(
+    vk::SwapchainKHR,
+    vk::SurfaceFormatKHR,
+    vk::Extent2D,
+    vk::PresentModeKHR,
+)> {
+    let format = choose_swap_surface_format(&support.formats)?;
+    let present_mode = choose_swap_present_mode(&support.present_modes, present_mode);
+    let extent = choose_swap_extent(&support.capabilities, extent);
+
+    let mut image_count = support.capabilities.min_image_count + 1;
+    if support.capabilities.max_image_count != 0
+        && image_count > support.capabilities.max_image_count
+    {
+        image_count = support.capabilities.max_image_count
+    };
+
+    let mut families = queue_families.to_vec();
+    families.sort_unstable();
+    families.dedup();
+
+    let mut swapchain_info = vk::SwapchainCreateInfoKHR::builder()
+        .surface(surface)
+        .min_image_count(image_count)
+        .image_format(format.format)
+        .image_color_space(format.color_space)
+        .image_extent(extent)
+        .image_usage(choose_image_usage(&support.capabilities))
+        .pre_transform(support.capabilities.current_transform)
+        .composite_alpha(choose_composite_alpha(&support.capabilities))
+        .present_mode(present_mode)
+        .clipped(true)
+        .image_array_layers(1)
+        .old_swapchain(old_swapchain);
+    swapchain_info = if families.len() > 1 {
+        swapchain_info
+            .image_sharing_mode(vk::SharingMode::CONCURRENT)
+            .queue_family_indices(&families)
+    } else {
+        swapchain_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+    };
+
+    let swapchain = unsafe { loader.create_swapchain(&swapchain_info, None)? };
+
+    Ok((swapchain, format, extent, present_mode))
+}
+
+/// Color attachment usage is guaranteed by the spec, the transfer usages are added if the surface
+/// supports them.
+fn choose_image_usage(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::ImageUsageFlags {
+    let transfer = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+
+    vk::ImageUsageFlags::COLOR_ATTACHMENT | (capabilities.supported_usage_flags & transfer)
+}
+
+/// Picks the first supported alpha mode, preferring to ignore the alpha of the images.
+fn choose_composite_alpha(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::CompositeAlphaFlagsKHR {
+    [
+        vk::CompositeAlphaFlagsKHR::OPAQUE,
+        vk::CompositeAlphaFlagsKHR::INHERIT,
+        vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+        vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+    ]
+    .into_iter()
+    .find(|&alpha| capabilities.supported_composite_alpha.contains(alpha))
+    .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE)
+}
+
+fn choose_swap_surface_format(
+    available_formats: &[vk::SurfaceFormatKHR],
+) -> Result<vk::SurfaceFormatKHR> {
+    // prefer the sRGB variants of the common 8 bit formats with a nonlinear color space
+    let preferred = [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB];
+
+    preferred
+        .iter()
+        .find_map(|preferred| {
+            available_formats.iter().find(|format| {
+                format.format == *preferred
+                    && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            })
+        })
+        .or_else(|| available_formats.first())
+        .copied()
+        .ok_or(RenderError::SurfaceCreationFailed(
+            vk::Result::ERROR_FORMAT_NOT_SUPPORTED,
+        ))
+}
+
+fn choose_swap_present_mode(
+    available_present_modes: &[vk::PresentModeKHR],
+    requested: vk::PresentModeKHR,
+) -> vk::PresentModeKHR {
+    if available_present_modes.contains(&requested) {
+        return requested;
+    }
+
+    // if not, return FIFO as guaranteed to be available
+    vk::PresentModeKHR::FIFO
+}
+
+fn choose_swap_extent(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+    extent: vk::Extent2D,
+) -> vk::Extent2D {
+    if capabilities.current_extent.width != u32::MAX {
+        capabilities.current_extent
+    } else {
+        let min = capabilities.min_image_extent;
+        let max = capabilities.max_image_extent;
+
+        vk::Extent2D {
+            width: extent.width.clamp(min.width, max.width),
+            height: extent.height.clamp(min.height, max.height),
+        }
+    }
+}
+
+fn create_image_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+) -> Result<vk::ImageView> {
+    let imageview_create_info = vk::ImageViewCreateInfo::builder()
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .components(vk::ComponentMapping::default())
+        .subresource_range(
+            *vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1),
+        )
+        .image(image);
+
+    Ok(unsafe { device.create_image_view(&imageview_create_info, None)? })
 }
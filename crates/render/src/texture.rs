@@ -0,0 +1,542 @@
+use std::path::Path;
+
+use ash::{vk, Device};
+use image::{DynamicImage, ImageFormat};
+use tracing::{debug, warn};
+
+use crate::{
+    bindless::{Bindless, TextureId},
+    error::{RenderError, Result},
+    memory::{GpuBuffer, GpuImage, MemoryLocation},
+    offscreen::find_supported_format,
+    Vk,
+};
+
+/// How the 8 bit channels of an image are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    /// Colors, e.g. albedo maps, decoded to linear when sampled.
+    #[default]
+    Srgb,
+    /// Data, e.g. normal or roughness maps, sampled as they are.
+    Linear,
+}
+
+/// The texels of a texture on the CPU, ready to be uploaded by [`Texture::new`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureData {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    /// Array layers, 6 for a cubemap.
+    pub layers: u32,
+    pub cube: bool,
+    /// The mip levels from the largest, each holding every layer tightly packed one after the
+    /// other. Usually only the first, the others are generated by [`Texture::new`].
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+    /// Decodes the PNG, JPEG or Radiance HDR image at `path`, see [`TextureData::from_memory`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read or decoded.
+    pub fn load(path: impl AsRef<Path>, color_space: ColorSpace) -> Result<Self> {
+        let path = path.as_ref();
+        let load_error = |e: image::ImageError| RenderError::ImageLoad {
+            name: path.display().to_string(),
+            message: e.to_string(),
+        };
+
+        let image = image::io::Reader::open(path)?
+            .with_guessed_format()?
+            .decode()
+            .map_err(load_error)?;
+
+        Ok(Self::from_image(image, color_space))
+    }
+
+    /// Decodes an encoded PNG, JPEG or Radiance HDR image, e.g. one embedded in a model file.
+    ///
+    /// 8 bit images become RGBA8 in `color_space`, HDR images become RGBA32F whatever
+    /// `color_space` is, as they are always linear.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RenderError::ImageLoad`] if `bytes` are not an image of a supported format.
+    pub fn from_memory(bytes: &[u8], color_space: ColorSpace) -> Result<Self> {
+        let load_error = |e: image::ImageError| RenderError::ImageLoad {
+            name: "in memory".to_owned(),
+            message: e.to_string(),
+        };
+
+        let format = image::guess_format(bytes).map_err(load_error)?;
+        if !matches!(
+            format,
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Hdr
+        ) {
+            return Err(RenderError::ImageLoad {
+                name: "in memory".to_owned(),
+                message: format!("{:?} images are not supported", format),
+            });
+        }
+        let image = image::load_from_memory_with_format(bytes, format).map_err(load_error)?;
+
+        Ok(Self::from_image(image, color_space))
+    }
+
+    /// A single layer texture of RGBA8 `pixels`, e.g. a solid color fallback.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` does not hold `width` by `height` RGBA8 pixels.
+    pub fn from_rgba8(width: u32, height: u32, pixels: Vec<u8>, color_space: ColorSpace) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "Pixels do not match the size {}x{}",
+            width,
+            height
+        );
+
+        Self {
+            format: match color_space {
+                ColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
+                ColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
+            },
+            width,
+            height,
+            layers: 1,
+            cube: false,
+            levels: vec![pixels],
+        }
+    }
+
+    fn from_image(image: DynamicImage, color_space: ColorSpace) -> Self {
+        let (width, height) = (image.width(), image.height());
+
+        match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => Self {
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                width,
+                height,
+                layers: 1,
+                cube: false,
+                levels: vec![bytemuck::cast_slice(image.to_rgba32f().as_raw()).to_vec()],
+            },
+            image => Self::from_rgba8(width, height, image.to_rgba8().into_raw(), color_space),
+        }
+    }
+
+    /// The size of mip `level`.
+    pub fn get_level_extent(&self, level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.width >> level).max(1),
+            height: (self.height >> level).max(1),
+        }
+    }
+}
+
+/// How many mip levels a full chain down to 1x1 has for an image of `width` by `height`.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// A sampled image in `SHADER_READ_ONLY_OPTIMAL` layout with a view of every mip level and layer.
+pub struct Texture {
+    device: Device,
+    image: GpuImage,
+    view: vk::ImageView,
+    layers: u32,
+}
+
+impl Texture {
+    /// Uploads `data` through a staging buffer, blocking until the upload has finished.
+    ///
+    /// If `generate_mips` is set and `data` only has its first level, the rest of the chain is
+    /// generated with linear blits. Formats that can not be blitted, like compressed formats, keep
+    /// the levels of `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image or staging buffer could not be created or the upload failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` has no levels.
+    pub fn new(vk: &Vk, name: &str, data: &TextureData, generate_mips: bool) -> Result<Self> {
+        assert!(!data.levels.is_empty(), "Texture {} has no texels", name);
+
+        let blit = generate_mips && data.levels.len() == 1 && {
+            let blittable = vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+            let supported = supports_format(vk, data.format, blittable);
+            if !supported {
+                warn!("Can not generate mips of {:?} for {}", data.format, name);
+            }
+
+            supported
+        };
+        let mip_levels = if blit {
+            mip_level_count(data.width, data.height)
+        } else {
+            data.levels.len() as u32
+        };
+
+        let mut usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        if blit {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let image_info = vk::ImageCreateInfo::builder()
+            .flags(if data.cube {
+                vk::ImageCreateFlags::CUBE_COMPATIBLE
+            } else {
+                vk::ImageCreateFlags::empty()
+            })
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(data.format)
+            .extent(vk::Extent3D {
+                width: data.width,
+                height: data.height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(data.layers)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image = GpuImage::new(vk, name, &image_info, MemoryLocation::GpuOnly)?;
+
+        upload(vk, &image, data, blit)?;
+        debug!(
+            "Uploaded texture {} with {} mip levels{}",
+            name,
+            mip_levels,
+            if blit { ", generated" } else { "" }
+        );
+
+        let view_type = match (data.cube, data.layers) {
+            (true, 6) => vk::ImageViewType::CUBE,
+            (true, _) => vk::ImageViewType::CUBE_ARRAY,
+            (false, 1) => vk::ImageViewType::TYPE_2D,
+            (false, _) => vk::ImageViewType::TYPE_2D_ARRAY,
+        };
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image.get_image())
+            .view_type(view_type)
+            .format(data.format)
+            .subresource_range(subresource_range(0, mip_levels, data.layers));
+        let view = unsafe { vk.get_device().create_image_view(&view_info, None)? };
+        vk.set_debug_name(view, name);
+
+        Ok(Self {
+            device: vk.get_device().clone(),
+            image,
+            view,
+            layers: data.layers,
+        })
+    }
+
+    /// Loads the image at `path` with a full mip chain, see [`TextureData::load`].
+    ///
+    /// `.ktx2` files are loaded with [`crate::ktx::load`] instead, their format decides the color
+    /// space.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image could not be decoded or uploaded.
+    pub fn load(vk: &Vk, path: impl AsRef<Path>, color_space: ColorSpace) -> Result<Self> {
+        let path = path.as_ref();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "ktx2")
+        {
+            return crate::ktx::load(vk, path);
+        }
+
+        let data = TextureData::load(path, color_space)?;
+
+        Self::new(vk, &path.display().to_string(), &data, true)
+    }
+
+    pub fn get_image(&self) -> vk::Image {
+        self.image.get_image()
+    }
+
+    pub fn get_view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.image.get_format()
+    }
+
+    pub fn get_extent(&self) -> vk::Extent2D {
+        let extent = self.image.get_extent();
+
+        vk::Extent2D {
+            width: extent.width,
+            height: extent.height,
+        }
+    }
+
+    pub fn get_mip_levels(&self) -> u32 {
+        self.image.get_mip_levels()
+    }
+
+    pub fn get_layers(&self) -> u32 {
+        self.layers
+    }
+
+    /// For a combined image sampler, or a sampled image if `sampler` is null.
+    pub fn get_descriptor_info(&self, sampler: vk::Sampler) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler,
+            image_view: self.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    /// Adds the texture to the global set, to be sampled with a sampler from
+    /// [`crate::sampler::SamplerCache::get_id`].
+    pub fn register(&self, bindless: &mut Bindless) -> TextureId {
+        bindless.add_image(self.view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        // the image frees itself
+        unsafe { self.device.destroy_image_view(self.view, None) };
+    }
+}
+
+/// Whether images of `format` with optimal tiling support `features`.
+pub fn supports_format(vk: &Vk, format: vk::Format, features: vk::FormatFeatureFlags) -> bool {
+    find_supported_format(vk, &[format], features).is_some()
+}
+
+/// Copies the levels of `data` into `image` and generates the remaining levels with blits if
+/// `blit` is set, leaving every level in `SHADER_READ_ONLY_OPTIMAL`.
+fn upload(vk: &Vk, image: &GpuImage, data: &TextureData, blit: bool) -> Result<()> {
+    let size: usize = data.levels.iter().map(Vec::len).sum();
+    let mut staging = GpuBuffer::new(
+        vk,
+        &format!("{} staging", image.get_name()),
+        size as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::Upload,
+    )?;
+
+    let mut regions = Vec::with_capacity(data.levels.len());
+    let mut offset = 0;
+    for (level, texels) in data.levels.iter().enumerate() {
+        staging.write(offset, texels)?;
+
+        let extent = data.get_level_extent(level as u32);
+        regions.push(
+            *vk::BufferImageCopy::builder()
+                .buffer_offset(offset)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level as u32,
+                    base_array_layer: 0,
+                    layer_count: data.layers,
+                })
+                .image_extent(vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                }),
+        );
+        offset += texels.len() as vk::DeviceSize;
+    }
+
+    let mip_levels = image.get_mip_levels();
+    let layers = data.layers;
+    vk.immediate_submit(|device, cmd| unsafe {
+        let to_transfer = [level_barrier(
+            image.get_image(),
+            subresource_range(0, mip_levels, layers),
+            (
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+            (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+            (
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        )];
+        device.cmd_pipeline_barrier2(
+            cmd,
+            &vk::DependencyInfo::builder().image_memory_barriers(&to_transfer),
+        );
+        device.cmd_copy_buffer_to_image(
+            cmd,
+            staging.get_buffer(),
+            image.get_image(),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &regions,
+        );
+
+        let to_shader = if blit {
+            record_mip_blits(device, cmd, image, data, layers)
+        } else {
+            level_barrier(
+                image.get_image(),
+                subresource_range(0, mip_levels, layers),
+                (
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                (
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                ),
+                SHADER_READ,
+            )
+        };
+        device.cmd_pipeline_barrier2(
+            cmd,
+            &vk::DependencyInfo::builder().image_memory_barriers(&[to_shader]),
+        );
+    })
+}
+
+/// Where and how textures are read.
+const SHADER_READ: (vk::PipelineStageFlags2, vk::AccessFlags2) = (
+    vk::PipelineStageFlags2::from_raw(
+        vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw()
+            | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw(),
+    ),
+    vk::AccessFlags2::SHADER_SAMPLED_READ,
+);
+
+/// Records halving every level into the next, leaving the levels that were blitted from in
+/// `SHADER_READ_ONLY_OPTIMAL`. Returns the barrier moving the last level there.
+unsafe fn record_mip_blits(
+    device: &Device,
+    cmd: vk::CommandBuffer,
+    image: &GpuImage,
+    data: &TextureData,
+    layers: u32,
+) -> vk::ImageMemoryBarrier2 {
+    let offset = |level: u32| {
+        let extent = data.get_level_extent(level);
+        vk::Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: 1,
+        }
+    };
+    let subresource = |level: u32| vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: level,
+        base_array_layer: 0,
+        layer_count: layers,
+    };
+
+    for level in 1..image.get_mip_levels() {
+        let to_src = [level_barrier(
+            image.get_image(),
+            subresource_range(level - 1, 1, layers),
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            (
+                vk::PipelineStageFlags2::COPY | vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_READ,
+            ),
+        )];
+        device.cmd_pipeline_barrier2(
+            cmd,
+            &vk::DependencyInfo::builder().image_memory_barriers(&to_src),
+        );
+
+        let blit = [vk::ImageBlit {
+            src_subresource: subresource(level - 1),
+            src_offsets: [vk::Offset3D::default(), offset(level - 1)],
+            dst_subresource: subresource(level),
+            dst_offsets: [vk::Offset3D::default(), offset(level)],
+        }];
+        device.cmd_blit_image(
+            cmd,
+            image.get_image(),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            image.get_image(),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &blit,
+            vk::Filter::LINEAR,
+        );
+
+        let to_shader = [level_barrier(
+            image.get_image(),
+            subresource_range(level - 1, 1, layers),
+            (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (vk::PipelineStageFlags2::BLIT, vk::AccessFlags2::NONE),
+            SHADER_READ,
+        )];
+        device.cmd_pipeline_barrier2(
+            cmd,
+            &vk::DependencyInfo::builder().image_memory_barriers(&to_shader),
+        );
+    }
+
+    level_barrier(
+        image.get_image(),
+        subresource_range(image.get_mip_levels() - 1, 1, layers),
+        (
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        ),
+        (
+            vk::PipelineStageFlags2::COPY | vk::PipelineStageFlags2::BLIT,
+            vk::AccessFlags2::TRANSFER_WRITE,
+        ),
+        SHADER_READ,
+    )
+}
+
+fn subresource_range(base_level: u32, levels: u32, layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: base_level,
+        level_count: levels,
+        base_array_layer: 0,
+        layer_count: layers,
+    }
+}
+
+/// Like [`crate::offscreen::image_barrier`], for any levels and layers.
+fn level_barrier(
+    image: vk::Image,
+    range: vk::ImageSubresourceRange,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_stage, src_access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+    (dst_stage, dst_access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+) -> vk::ImageMemoryBarrier2 {
+    *vk::ImageMemoryBarrier2::builder()
+        .src_stage_mask(src_stage)
+        .src_access_mask(src_access)
+        .dst_stage_mask(dst_stage)
+        .dst_access_mask(dst_access)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(range)
+}
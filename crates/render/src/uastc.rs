@@ -0,0 +1,229 @@
+//! A transcoder from UASTC, the high quality format of Basis Universal, to ASTC.
+//!
+//! UASTC blocks are 4x4 ASTC blocks limited to a few modes and packed more tightly, with hints
+//! for transcoding to other formats on top, so they are repacked without loss.
+
+use crate::astc::{encode_ise, select_partition, Kind, Range};
+
+/// The code a block of each mode starts with, read from the lowest bit, and its length. The
+/// 7 bit code `0x45` is reserved.
+const MODE_CODES: [(u32, u32); 19] = [
+    (0x1, 4),
+    (0x35, 6),
+    (0x1d, 5),
+    (0x3, 5),
+    (0x13, 5),
+    (0xb, 5),
+    (0x1b, 5),
+    (0x7, 5),
+    (0x17, 5),
+    (0xf, 5),
+    (0x2, 3),
+    (0x0, 2),
+    (0x6, 3),
+    (0x1f, 5),
+    (0xd, 5),
+    (0x5, 7),
+    (0x15, 6),
+    (0x25, 6),
+    (0x9, 4),
+];
+
+/// The layout of a mode.
+struct Mode {
+    /// Bits of hints for transcoding to ETC1 and BC1 after the mode code, which ASTC does not
+    /// need.
+    hint_bits: u32,
+    subsets: u32,
+    dual_plane: bool,
+    /// The ASTC color endpoint mode, luminance and alpha, RGB or RGBA.
+    endpoint_mode: u32,
+    endpoints: Range,
+    weight_bits: u32,
+}
+
+impl Mode {
+    const fn new(
+        hint_bits: u32,
+        subsets: u32,
+        dual_plane: bool,
+        endpoint_mode: u32,
+        endpoints: Range,
+        weight_bits: u32,
+    ) -> Self {
+        Self {
+            hint_bits,
+            subsets,
+            dual_plane,
+            endpoint_mode,
+            endpoints,
+            weight_bits,
+        }
+    }
+}
+
+/// The layout of every mode but mode 8, which stores the RGBA8 color of a solid block.
+const MODES: [Option<Mode>; 19] = [
+    Some(Mode::new(15, 1, false, 8, Range::new(Kind::Trits, 6), 4)),
+    Some(Mode::new(15, 1, false, 8, Range::new(Kind::Bits, 8), 2)),
+    Some(Mode::new(15, 2, false, 8, Range::new(Kind::Bits, 4), 3)),
+    Some(Mode::new(15, 3, false, 8, Range::new(Kind::Trits, 2), 2)),
+    Some(Mode::new(15, 2, false, 8, Range::new(Kind::Quints, 3), 2)),
+    Some(Mode::new(15, 1, false, 8, Range::new(Kind::Bits, 8), 3)),
+    Some(Mode::new(15, 1, true, 8, Range::new(Kind::Quints, 5), 2)),
+    Some(Mode::new(15, 2, false, 8, Range::new(Kind::Quints, 3), 2)),
+    None,
+    Some(Mode::new(23, 2, false, 12, Range::new(Kind::Bits, 4), 2)),
+    Some(Mode::new(17, 1, false, 12, Range::new(Kind::Trits, 4), 4)),
+    Some(Mode::new(17, 1, true, 12, Range::new(Kind::Trits, 4), 2)),
+    Some(Mode::new(17, 1, false, 12, Range::new(Kind::Trits, 6), 3)),
+    Some(Mode::new(23, 1, true, 12, Range::new(Kind::Bits, 8), 1)),
+    Some(Mode::new(23, 1, false, 4, Range::new(Kind::Bits, 8), 2)),
+    Some(Mode::new(23, 1, false, 4, Range::new(Kind::Bits, 8), 4)),
+    Some(Mode::new(23, 2, false, 4, Range::new(Kind::Bits, 8), 2)),
+    Some(Mode::new(23, 1, true, 4, Range::new(Kind::Bits, 8), 2)),
+    Some(Mode::new(15, 1, false, 8, Range::new(Kind::Bits, 5), 5)),
+];
+
+/// The ASTC partition seeds of the 2 subset partitions UASTC shares with BC7.
+const PARTITIONS2: [u32; 30] = [
+    28, 20, 16, 29, 91, 9, 107, 72, 149, 204, 50, 114, 496, 17, 78, 39, 252, 828, 43, 156, 116,
+    210, 476, 273, 684, 359, 246, 195, 694, 524,
+];
+
+/// The ASTC partition seeds of the 3 subset partitions UASTC shares with BC7.
+const PARTITIONS3: [u32; 11] = [260, 74, 32, 156, 183, 15, 745, 0, 335, 902, 254];
+
+/// The ASTC partition seeds of mode 7, 2 subset partitions that merge two subsets of a 3 subset
+/// BC7 partition.
+const MODE7_PARTITIONS: [u32; 19] = [
+    36, 48, 61, 137, 161, 183, 226, 281, 302, 307, 479, 495, 593, 594, 605, 799, 812, 988, 993,
+];
+
+/// A void extent block of the magenta the ASTC decoder uses for malformed blocks.
+const ERROR_BLOCK: u128 = void_extent([0xff, 0, 0xff, 0xff]);
+
+/// Transcodes a 16 byte UASTC block into a 4x4 ASTC block, malformed blocks become magenta.
+pub(crate) fn transcode_block(block: &[u8]) -> [u8; 16] {
+    let bits = u128::from_le_bytes(block[..16].try_into().unwrap());
+
+    transcode(bits).unwrap_or(ERROR_BLOCK).to_le_bytes()
+}
+
+fn transcode(bits: u128) -> Option<u128> {
+    let index = MODE_CODES
+        .iter()
+        .position(|&(code, length)| bits as u32 & ((1 << length) - 1) == code)?;
+    let mut position = MODE_CODES[index].1;
+    let mut read = |count: u32| {
+        let value = if position < 128 {
+            (bits >> position) as u32 & ((1u64 << count) - 1) as u32
+        } else {
+            0
+        };
+        position += count;
+        value
+    };
+
+    let Some(mode) = &MODES[index] else {
+        return Some(void_extent([0, 1, 2, 3].map(|_| read(8) as u8)));
+    };
+    read(mode.hint_bits);
+
+    let seed = match mode.subsets {
+        1 => 0,
+        2 if index == 7 => *MODE7_PARTITIONS.get(read(5) as usize)?,
+        2 => *PARTITIONS2.get(read(5) as usize)?,
+        _ => *PARTITIONS3.get(read(4) as usize)?,
+    };
+    // luminance and alpha blocks always weight alpha separately
+    let plane2_channel = match (mode.dual_plane, mode.endpoint_mode) {
+        (false, _) => None,
+        (true, 4) => Some(3),
+        (true, _) => Some(read(2)),
+    };
+
+    // trits and quints are packed in groups of 5 and 3 before the bits of all values
+    let value_count = (mode.endpoint_mode / 4 + 1) * 2 * mode.subsets;
+    let range = mode.endpoints;
+    let (base, group_size, group_bits): (u32, u32, &[u32]) = match range.kind {
+        Kind::Bits => (1, 1, &[0]),
+        Kind::Trits => (3, 5, &[2, 4, 5, 7, 8]),
+        Kind::Quints => (5, 3, &[3, 5, 7]),
+    };
+    let groups: Vec<u32> = (0..value_count.div_ceil(group_size))
+        .map(|group| {
+            let size = group_size.min(value_count - group * group_size);
+            read(group_bits[size as usize - 1])
+        })
+        .collect();
+    let endpoints: Vec<u32> = (0..value_count)
+        .map(|i| {
+            let digit = groups[(i / group_size) as usize] / base.pow(i % group_size) % base;
+            read(range.bits) | (digit << range.bits)
+        })
+        .collect();
+
+    // the first texel of each subset drops the top bit of its weights, which is 0
+    let partition = |i: u32| match mode.subsets {
+        1 => 0,
+        subsets => select_partition(seed, i % 4, i / 4, subsets, true),
+    };
+    let anchors: Vec<u32> = (0..mode.subsets)
+        .filter_map(|subset| (0..16).find(|&i| partition(i) == subset as usize))
+        .collect();
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weights: Vec<u32> = (0..16 * planes)
+        .map(|i| read(mode.weight_bits - anchors.contains(&(i / planes)) as u32))
+        .collect();
+
+    let (range_bits, high_precision) = match mode.weight_bits {
+        1 => (2, 0),
+        2 => (4, 0),
+        3 => (7, 0),
+        4 => (4, 1),
+        _ => (7, 1),
+    };
+    // a 4x4 weight grid
+    let block_mode = (range_bits >> 1)
+        | (range_bits & 1) << 4
+        | 2 << 5
+        | high_precision << 9
+        | (mode.dual_plane as u32) << 10;
+    let mut astc = (block_mode | (mode.subsets - 1) << 11) as u128;
+    let color_start = if mode.subsets == 1 {
+        astc |= (mode.endpoint_mode as u128) << 13;
+        17
+    } else {
+        // all subsets share the endpoint mode
+        astc |= (seed as u128) << 13 | (mode.endpoint_mode as u128) << 25;
+        29
+    };
+    encode_ise(&mut astc, color_start, &endpoints, range);
+
+    let weight_bits = 16 * planes * mode.weight_bits;
+    if let Some(channel) = plane2_channel {
+        astc |= (channel as u128) << (128 - weight_bits - 2);
+    }
+    let mut reversed_weights = 0;
+    encode_ise(
+        &mut reversed_weights,
+        0,
+        &weights,
+        Range::new(Kind::Bits, mode.weight_bits),
+    );
+
+    Some(astc | reversed_weights.reverse_bits())
+}
+
+/// An LDR void extent block of `color` covering the whole texture.
+const fn void_extent(color: [u8; 4]) -> u128 {
+    let mut block = 0xffff_ffff_ffff_fdfc;
+    let mut c = 0;
+    while c < 4 {
+        block |= (color[c] as u128 * 0x101) << (64 + c * 16);
+        c += 1;
+    }
+
+    block
+}
@@ -0,0 +1,450 @@
+use std::{collections::VecDeque, mem::size_of, time::Duration};
+
+use ash::{vk, Device};
+use bytemuck::Pod;
+use tracing::debug;
+
+use crate::{
+    buffer::Buffer,
+    error::Result,
+    staging::{StagingRing, DEFAULT_STAGING_SIZE},
+    Queue, Vk,
+};
+
+/// Identifies a batch of uploads submitted by [`UploadManager::flush`].
+///
+/// Tickets are ordered, a ticket is complete once every earlier ticket is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket(u64);
+
+impl UploadTicket {
+    /// The value the timeline semaphore of the [`UploadManager`] reaches once the batch is done.
+    pub fn get_value(self) -> u64 {
+        self.0
+    }
+}
+
+/// A buffer range whose ownership still has to be acquired by the graphics queue.
+struct Acquire {
+    value: u64,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+struct InFlight {
+    value: u64,
+    cmd: vk::CommandBuffer,
+    staging_submission: u64,
+}
+
+/// Records copies out of its own staging ring into one command buffer on the transfer queue, so
+/// many uploads cost a single submit and never wait on the graphics queue.
+///
+/// Every [`UploadManager::flush`] signals the next value of a timeline semaphore, returned as an
+/// [`UploadTicket`] that can be polled, waited on, or waited on by the GPU. If the transfer queue
+/// belongs to another family than the graphics queue, the uploaded buffers are released by the
+/// transfer queue and [`UploadManager::record_acquires`] has to be recorded on the graphics queue
+/// before they are used.
+pub struct UploadManager {
+    device: Device,
+    transfer: Queue,
+    graphics_family: u32,
+    command_pool: vk::CommandPool,
+    timeline: vk::Semaphore,
+    staging: StagingRing,
+    /// The batch being recorded, if anything was written since the last flush.
+    recording: Option<vk::CommandBuffer>,
+    /// Ranges written into the batch being recorded.
+    batch: Vec<Acquire>,
+    acquires: Vec<Acquire>,
+    in_flight: VecDeque<InFlight>,
+    free_command_buffers: Vec<vk::CommandBuffer>,
+    last_submitted: u64,
+    /// Highest value handed to [`UploadManager::record_acquires`] so far.
+    last_acquired: u64,
+}
+
+impl UploadManager {
+    /// Creates an upload manager on the transfer queue of `vk` with a staging ring of
+    /// [`DEFAULT_STAGING_SIZE`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the staging ring, command pool or semaphore could not be created.
+    pub fn new(vk: &Vk) -> Result<Self> {
+        Self::with_staging_size(vk, DEFAULT_STAGING_SIZE)
+    }
+
+    /// Creates an upload manager with a staging ring of `staging_size` bytes, which bounds how
+    /// much data can be in flight at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the staging ring, command pool or semaphore could not be created.
+    pub fn with_staging_size(vk: &Vk, staging_size: vk::DeviceSize) -> Result<Self> {
+        let device = vk.get_device().clone();
+        let queues = vk.get_queues();
+
+        let pool_info = vk::CommandPoolCreateInfo::builder()
+            .flags(
+                vk::CommandPoolCreateFlags::TRANSIENT
+                    | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            )
+            .queue_family_index(queues.transfer.family);
+        let command_pool = unsafe { device.create_command_pool(&pool_info, None)? };
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let semaphore_info = vk::SemaphoreCreateInfo::builder().push_next(&mut type_info);
+        let timeline = unsafe { device.create_semaphore(&semaphore_info, None)? };
+        vk.set_debug_name(timeline, "upload timeline");
+
+        Ok(Self {
+            device,
+            transfer: queues.transfer,
+            graphics_family: queues.graphics.family,
+            command_pool,
+            timeline,
+            staging: StagingRing::new(vk, staging_size)?,
+            recording: None,
+            batch: vec![],
+            acquires: vec![],
+            in_flight: VecDeque::new(),
+            free_command_buffers: vec![],
+            last_submitted: 0,
+            last_acquired: 0,
+        })
+    }
+
+    /// Records a copy of `data` into `dst` at `dst_offset` bytes into the current batch.
+    ///
+    /// Nothing is submitted until [`UploadManager::flush`], unless the staging ring runs full, in
+    /// which case the batch is flushed and the oldest batches are waited on to make room.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if recording, submitting or waiting failed.
+    pub fn write_buffer(
+        &mut self,
+        dst: vk::Buffer,
+        dst_offset: vk::DeviceSize,
+        data: &[u8],
+    ) -> Result<()> {
+        // half the ring, so one chunk can be written while the other half is still in flight
+        let chunk_size = (self.staging.get_capacity() / 2).max(1) as usize;
+
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            let src_offset = self.push(chunk)?;
+            let dst_offset = dst_offset + (i * chunk_size) as vk::DeviceSize;
+            let cmd = self.begin()?;
+
+            let region = [vk::BufferCopy {
+                src_offset,
+                dst_offset,
+                size: chunk.len() as vk::DeviceSize,
+            }];
+            unsafe {
+                self.device
+                    .cmd_copy_buffer(cmd, self.staging.get_buffer(), dst, &region)
+            };
+
+            self.batch.push(Acquire {
+                value: 0,
+                buffer: dst,
+                offset: dst_offset,
+                size: chunk.len() as vk::DeviceSize,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Records a copy of `data` into the elements of `buffer` starting at `first`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if recording, submitting or waiting failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not fit at `first`.
+    pub fn write<T: Pod>(&mut self, buffer: &Buffer<T>, first: usize, data: &[T]) -> Result<()> {
+        assert!(
+            first + data.len() <= buffer.len(),
+            "Elements {}..{} are out of bounds of buffer {} of {} elements",
+            first,
+            first + data.len(),
+            buffer.get_gpu_buffer().get_name(),
+            buffer.len()
+        );
+
+        self.write_buffer(
+            buffer.get_buffer(),
+            (first * size_of::<T>()) as vk::DeviceSize,
+            bytemuck::cast_slice(data),
+        )
+    }
+
+    /// Submits everything written since the last flush to the transfer queue.
+    ///
+    /// Returns the ticket of the batch, or of the last batch if nothing was written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the submit failed.
+    pub fn flush(&mut self) -> Result<UploadTicket> {
+        let Some(cmd) = self.recording.take() else {
+            return Ok(UploadTicket(self.last_submitted));
+        };
+
+        let value = self.last_submitted + 1;
+        let barriers: Vec<vk::BufferMemoryBarrier2> = self
+            .batch
+            .iter()
+            .map(|acquire| self.release_barrier(acquire))
+            .collect();
+        let dependency_info = vk::DependencyInfo::builder().buffer_memory_barriers(&barriers);
+
+        unsafe {
+            self.device.cmd_pipeline_barrier2(cmd, &dependency_info);
+            self.device.end_command_buffer(cmd)?;
+        }
+
+        let command_buffers = [*vk::CommandBufferSubmitInfo::builder().command_buffer(cmd)];
+        let signals = [*vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.timeline)
+            .value(value)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+        let submit_info = [*vk::SubmitInfo2::builder()
+            .command_buffer_infos(&command_buffers)
+            .signal_semaphore_infos(&signals)];
+
+        unsafe {
+            self.device
+                .queue_submit2(self.transfer.queue, &submit_info, vk::Fence::null())?
+        };
+
+        debug!("Submitted {} uploads as {}", self.batch.len(), value);
+
+        self.last_submitted = value;
+        self.in_flight.push_back(InFlight {
+            value,
+            cmd,
+            staging_submission: self.staging.submit(),
+        });
+        self.acquires.extend(
+            self.batch
+                .drain(..)
+                .map(|acquire| Acquire { value, ..acquire }),
+        );
+
+        Ok(UploadTicket(value))
+    }
+
+    /// Returns whether the batch of `ticket` has finished, without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the semaphore could not be queried, e.g. because the device was lost.
+    pub fn poll(&self, ticket: UploadTicket) -> Result<bool> {
+        Ok(self.get_completed()? >= ticket.0)
+    }
+
+    /// Blocks until the batch of `ticket` has finished or `timeout` passed, returning whether it
+    /// finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting failed, e.g. because the device was lost.
+    pub fn wait(&self, ticket: UploadTicket, timeout: Duration) -> Result<bool> {
+        let semaphores = [self.timeline];
+        let values = [ticket.0];
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(&semaphores)
+            .values(&values);
+
+        let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+
+        match unsafe { self.device.wait_semaphores(&wait_info, timeout) } {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reuses the command buffers and staging space of every finished batch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the semaphore could not be queried.
+    pub fn collect(&mut self) -> Result<()> {
+        let completed = self.get_completed()?;
+
+        while let Some(batch) = self.in_flight.front() {
+            if batch.value > completed {
+                break;
+            }
+
+            unsafe {
+                self.device
+                    .reset_command_buffer(batch.cmd, vk::CommandBufferResetFlags::empty())?
+            };
+            self.free_command_buffers.push(batch.cmd);
+            self.staging.release(batch.staging_submission);
+            self.in_flight.pop_front();
+        }
+
+        Ok(())
+    }
+
+    /// Records the queue family ownership acquire of every finished upload into `cmd`, which has
+    /// to be submitted to the graphics queue.
+    ///
+    /// Returns the semaphore wait the submit of `cmd` needs, or `None` if nothing finished since
+    /// the last call. The wait is on a value that is already reached, so it does not stall.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the semaphore could not be queried.
+    pub fn record_acquires(
+        &mut self,
+        cmd: vk::CommandBuffer,
+    ) -> Result<Option<vk::SemaphoreSubmitInfo>> {
+        let completed = self.get_completed()?;
+        if completed <= self.last_acquired {
+            return Ok(None);
+        }
+
+        let (done, waiting): (Vec<Acquire>, Vec<Acquire>) = self
+            .acquires
+            .drain(..)
+            .partition(|acquire| acquire.value <= completed);
+        self.acquires = waiting;
+
+        if self.is_transferring_ownership() && !done.is_empty() {
+            let barriers: Vec<vk::BufferMemoryBarrier2> = done
+                .iter()
+                .map(|acquire| {
+                    *vk::BufferMemoryBarrier2::builder()
+                        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .dst_access_mask(
+                            vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                        )
+                        .src_queue_family_index(self.transfer.family)
+                        .dst_queue_family_index(self.graphics_family)
+                        .buffer(acquire.buffer)
+                        .offset(acquire.offset)
+                        .size(acquire.size)
+                })
+                .collect();
+            let dependency_info = vk::DependencyInfo::builder().buffer_memory_barriers(&barriers);
+
+            unsafe { self.device.cmd_pipeline_barrier2(cmd, &dependency_info) };
+        }
+
+        self.last_acquired = completed;
+
+        Ok(Some(
+            *vk::SemaphoreSubmitInfo::builder()
+                .semaphore(self.timeline)
+                .value(completed)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+        ))
+    }
+
+    /// The timeline semaphore reaching [`UploadTicket::get_value`] once a batch is done.
+    pub fn get_semaphore(&self) -> vk::Semaphore {
+        self.timeline
+    }
+
+    fn get_completed(&self) -> Result<u64> {
+        Ok(unsafe { self.device.get_semaphore_counter_value(self.timeline)? })
+    }
+
+    fn is_transferring_ownership(&self) -> bool {
+        self.transfer.family != self.graphics_family
+    }
+
+    /// Copies `data` into the staging ring, flushing and waiting for older batches if it is full.
+    fn push(&mut self, data: &[u8]) -> Result<vk::DeviceSize> {
+        loop {
+            if let Some(offset) = self.staging.push(data, 4)? {
+                return Ok(offset);
+            }
+
+            // the ring only frees up once the batches copying out of it are done
+            self.flush()?;
+            let oldest = match self.in_flight.front() {
+                Some(batch) => UploadTicket(batch.value),
+                None => UploadTicket(self.last_submitted),
+            };
+            self.wait(oldest, Duration::MAX)?;
+            self.collect()?;
+        }
+    }
+
+    /// Returns the command buffer of the current batch, beginning one if needed.
+    fn begin(&mut self) -> Result<vk::CommandBuffer> {
+        if let Some(cmd) = self.recording {
+            return Ok(cmd);
+        }
+
+        let cmd = match self.free_command_buffers.pop() {
+            Some(cmd) => cmd,
+            None => {
+                let alloc_info = vk::CommandBufferAllocateInfo::builder()
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_pool(self.command_pool)
+                    .command_buffer_count(1);
+
+                unsafe { self.device.allocate_command_buffers(&alloc_info)?[0] }
+            }
+        };
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { self.device.begin_command_buffer(cmd, &begin_info)? };
+
+        self.recording = Some(cmd);
+
+        Ok(cmd)
+    }
+
+    /// Makes the copy into `acquire` visible, releasing it to the graphics queue family if the
+    /// transfer queue is of another family.
+    fn release_barrier(&self, acquire: &Acquire) -> vk::BufferMemoryBarrier2 {
+        let barrier = vk::BufferMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .buffer(acquire.buffer)
+            .offset(acquire.offset)
+            .size(acquire.size);
+
+        if self.is_transferring_ownership() {
+            *barrier
+                .src_queue_family_index(self.transfer.family)
+                .dst_queue_family_index(self.graphics_family)
+        } else {
+            *barrier
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        }
+    }
+}
+
+impl Drop for UploadManager {
+    fn drop(&mut self) {
+        unsafe {
+            // nothing sensible can be done about a lost device while dropping
+            let _ = self.flush();
+            let _ = self.wait(UploadTicket(self.last_submitted), Duration::MAX);
+
+            self.device.destroy_command_pool(self.command_pool, None);
+            self.device.destroy_semaphore(self.timeline, None);
+        }
+    }
+}
@@ -1,28 +1,28 @@
 use std::ffi::{c_char, CStr};
 
-use ash::Entry;
+use ash::{vk, Entry};
 use tracing::{error, info};
 
+use crate::error::{RenderError, Result};
+
 pub const VALIDATION_LAYERS: [&str; 2] = ["VK_LAYER_KHRONOS_validation", "VK_LAYER_LUNARG_monitor"];
-pub const REQUIRED_EXTENSIONS: [&str; 3] =
-    ["VK_KHR_swapchain", "VK_EXT_mesh_shader", "VK_KHR_surface"];
 
 pub fn vk_to_str(raw: &[c_char]) -> String {
     let raw = unsafe { CStr::from_ptr(raw.as_ptr()) };
 
-    raw.to_str()
-        .expect("Failed to convert CString to String!")
-        .to_owned()
+    raw.to_string_lossy().into_owned()
 }
 
-pub fn check_validation_layer_support(entry: &Entry) -> bool {
-    let layer_properties = entry
-        .enumerate_instance_layer_properties()
-        .expect("failed to enumerate instance layers properties!");
+/// Checks that every layer in [`VALIDATION_LAYERS`] is available.
+///
+/// # Errors
+///
+/// Returns [`RenderError::MissingLayer`] with the first layer that is not available.
+pub fn check_validation_layer_support(entry: &Entry) -> Result<()> {
+    let layer_properties = entry.enumerate_instance_layer_properties()?;
 
     if layer_properties.is_empty() {
         error!("no available layers.");
-        return false;
     }
 
     info!("instance available layers: ");
@@ -30,12 +30,86 @@ pub fn check_validation_layer_support(entry: &Entry) -> bool {
         tracing::info!("\t {}", vk_to_str(&layer.layer_name));
     }
 
-    VALIDATION_LAYERS
-        .iter()
-        .fold(false, |_acc, required_layer| {
-            layer_properties
-                .iter()
-                .map(|ep| vk_to_str(&ep.layer_name))
-                .any(|s| required_layer == &s.as_str())
-        })
+    match VALIDATION_LAYERS.iter().find(|required_layer| {
+        !layer_properties
+            .iter()
+            .map(|ep| vk_to_str(&ep.layer_name))
+            .any(|s| **required_layer == s.as_str())
+    }) {
+        Some(missing) => Err(RenderError::MissingLayer((*missing).to_owned())),
+        None => Ok(()),
+    }
+}
+
+/// The aspects of an image of `format`, both depth and stencil for combined formats.
+pub fn aspect_of(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+/// Returns the size in bytes of a single texel of the uncompressed color `format`, `None` if the
+/// format is not supported.
+pub fn format_texel_size(format: vk::Format) -> Option<u32> {
+    let size = match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => 1,
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::A2R10G10B10_UNORM_PACK32
+        | vk::Format::R32_SFLOAT
+        | vk::Format::D32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    };
+
+    Some(size)
+}
+
+/// Serializes a `vk::Format` as its raw value, for `#[serde(with = "...")]`.
+pub mod serde_format {
+    use ash::vk;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(format: &vk::Format, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(format.as_raw())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<vk::Format, D::Error> {
+        i32::deserialize(deserializer).map(vk::Format::from_raw)
+    }
+}
+
+/// Like [`serde_format`], for a list of formats.
+pub mod serde_formats {
+    use ash::vk;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        formats: &[vk::Format],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let raw: Vec<i32> = formats.iter().map(|format| format.as_raw()).collect();
+        raw.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<vk::Format>, D::Error> {
+        let raw = Vec::<i32>::deserialize(deserializer)?;
+        Ok(raw.into_iter().map(vk::Format::from_raw).collect())
+    }
 }
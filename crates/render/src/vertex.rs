@@ -0,0 +1,158 @@
+use std::{
+    any::type_name,
+    mem::{offset_of, size_of},
+};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+/// One field of a [`Vertex`], read by the shader input at `location`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VertexAttribute {
+    pub location: u32,
+    #[serde(with = "crate::utils::serde_format")]
+    pub format: vk::Format,
+    /// Byte offset of the field, usually `std::mem::offset_of!(Self, field)`.
+    pub offset: u32,
+}
+
+/// A `#[repr(C)]` vertex read from a vertex buffer.
+///
+/// The attributes are checked against the inputs of the vertex shader by
+/// [`crate::reflect::PipelineReflection::vertex_input`], so they do not have to be kept in sync by
+/// hand:
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod, Zeroable)]
+/// struct ColoredVertex {
+///     pos: Vec2,
+///     color: Vec3,
+/// }
+///
+/// impl Vertex for ColoredVertex {
+///     const ATTRIBUTES: &'static [VertexAttribute] = &[
+///         VertexAttribute {
+///             location: 0,
+///             format: vk::Format::R32G32_SFLOAT,
+///             offset: offset_of!(ColoredVertex, pos) as u32,
+///         },
+///         VertexAttribute {
+///             location: 1,
+///             format: vk::Format::R32G32B32_SFLOAT,
+///             offset: offset_of!(ColoredVertex, color) as u32,
+///         },
+///     ];
+/// }
+/// ```
+pub trait Vertex: Pod {
+    const ATTRIBUTES: &'static [VertexAttribute];
+
+    fn get_binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        VertexLayout::of::<Self>().get_binding_description(binding)
+    }
+
+    fn get_attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription> {
+        VertexLayout::of::<Self>().get_attribute_descriptions(binding)
+    }
+}
+
+/// The layout of a [`Vertex`] as data, so it can be part of a serialized
+/// [`crate::pipeline::PipelineDesc`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VertexLayout {
+    /// Used in error messages, the type name for [`VertexLayout::of`].
+    pub name: String,
+    pub stride: u32,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn of<V: Vertex>() -> Self {
+        Self {
+            name: type_name::<V>().to_owned(),
+            stride: size_of::<V>() as u32,
+            attributes: V::ATTRIBUTES.to_vec(),
+        }
+    }
+
+    pub fn get_binding_description(&self, binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: self.stride,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    pub fn get_attribute_descriptions(
+        &self,
+        binding: u32,
+    ) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .map(|attribute| vk::VertexInputAttributeDescription {
+                location: attribute.location,
+                binding,
+                format: attribute.format,
+                offset: attribute.offset,
+            })
+            .collect()
+    }
+}
+
+/// The standard vertex of 3D meshes, read by shaders as
+///
+/// ```glsl
+/// layout(location = 0) in vec3 position;
+/// layout(location = 1) in vec3 normal;
+/// layout(location = 2) in vec4 tangent;
+/// layout(location = 3) in vec2 uv;
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// The direction of increasing u in `xyz`, and in `w` the sign of the bitangent
+    /// `cross(normal, tangent.xyz) * w`, as in glTF.
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+impl MeshVertex {
+    /// A vertex with a tangent along x, for meshes without normal maps.
+    pub fn new(position: [f32; 3], normal: [f32; 3], uv: [f32; 2]) -> Self {
+        Self {
+            position,
+            normal,
+            tangent: [1., 0., 0., 1.],
+            uv,
+        }
+    }
+}
+
+impl Vertex for MeshVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            location: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: offset_of!(MeshVertex, position) as u32,
+        },
+        VertexAttribute {
+            location: 1,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: offset_of!(MeshVertex, normal) as u32,
+        },
+        VertexAttribute {
+            location: 2,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: offset_of!(MeshVertex, tangent) as u32,
+        },
+        VertexAttribute {
+            location: 3,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: offset_of!(MeshVertex, uv) as u32,
+        },
+    ];
+}
@@ -0,0 +1,60 @@
+use ash::vk;
+use render::{
+    capture::{save_png, to_rgba8},
+    RenderError,
+};
+
+#[test]
+fn swizzles_bgra_and_makes_alpha_opaque() {
+    let bgra = [10, 20, 30, 0, 40, 50, 60, 128];
+
+    let rgba = to_rgba8(vk::Format::B8G8R8A8_SRGB, &bgra).expect("BGRA is supported");
+    assert_eq!(rgba, [30, 20, 10, 255, 60, 50, 40, 255]);
+
+    let rgba = to_rgba8(vk::Format::R8G8B8A8_UNORM, &bgra).expect("RGBA is supported");
+    assert_eq!(rgba, [10, 20, 30, 255, 40, 50, 60, 255]);
+}
+
+#[test]
+fn truncates_ten_bit_channels() {
+    let packed: u32 = 1023 | (512 << 10) | (4 << 20);
+
+    let rgba = to_rgba8(vk::Format::A2B10G10R10_UNORM_PACK32, &packed.to_le_bytes())
+        .expect("A2B10G10R10 is supported");
+    assert_eq!(rgba, [255, 128, 1, 255]);
+
+    let rgba = to_rgba8(vk::Format::A2R10G10B10_UNORM_PACK32, &packed.to_le_bytes())
+        .expect("A2R10G10B10 is supported");
+    assert_eq!(rgba, [1, 128, 255, 255]);
+}
+
+#[test]
+fn rejects_float_formats() {
+    match to_rgba8(vk::Format::R16G16B16A16_SFLOAT, &[0; 8]) {
+        Err(RenderError::UnsupportedFormat(format)) => {
+            assert_eq!(format, vk::Format::R16G16B16A16_SFLOAT)
+        }
+        result => panic!("Expected an unsupported format, got {:?}", result),
+    }
+}
+
+#[test]
+fn saved_png_reads_back() {
+    let extent = vk::Extent2D {
+        width: 2,
+        height: 1,
+    };
+    let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+    let path = std::env::temp_dir().join("youniverse-capture-test.png");
+
+    save_png(&path, extent, &pixels).expect("Could not save PNG");
+
+    let decoder = png::Decoder::new(std::fs::File::open(&path).expect("PNG was not written"));
+    let mut reader = decoder.read_info().expect("Not a PNG");
+    let mut read = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut read).expect("Could not decode PNG");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!((info.width, info.height), (2, 1));
+    assert_eq!(read, pixels);
+}
@@ -0,0 +1,163 @@
+//! Runs a compute shader that doubles a buffer, bound through a reflected descriptor set and
+//! through a buffer address in a given pipeline layout.
+
+use std::mem::size_of;
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use render::{
+    buffer::{Buffer, BufferAddress, BufferUsage},
+    compute::ComputePipeline,
+    memory::{GpuBuffer, MemoryLocation},
+    pipeline::{create_pipeline_layout, PushConstants},
+    shader::{compile_glsl, CompiledShader, ShaderStage},
+    Vk,
+};
+
+const DOUBLE: &str = "#version 450
+
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Values {
+    uint values[];
+};
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index < values.length()) {
+        values[index] *= 2;
+    }
+}
+";
+
+const DOUBLE_BY_ADDRESS: &str = "#version 460
+#extension GL_EXT_buffer_reference : require
+
+layout(local_size_x = 64) in;
+
+layout(buffer_reference, std430) buffer Values { uint values[]; };
+
+layout(push_constant) uniform Constants {
+    Values values;
+    uint count;
+};
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index < count) {
+        values.values[index] *= 2;
+    }
+}
+";
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Constants {
+    values: BufferAddress<u32>,
+    count: u32,
+    _pad: u32,
+}
+
+impl PushConstants for Constants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::COMPUTE;
+}
+
+fn compile(source: &str) -> CompiledShader {
+    CompiledShader {
+        stage: ShaderStage::Compute,
+        spirv: compile_glsl(source, ShaderStage::Compute, "double.comp")
+            .unwrap_or_else(|e| panic!("{}", e)),
+    }
+}
+
+#[test]
+fn doubles_buffer() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let pipeline =
+        ComputePipeline::new(&vk, "double", &compile(DOUBLE)).expect("Could not create pipeline");
+    assert_eq!(pipeline.get_workgroup_size(), [64, 1, 1]);
+
+    // not a multiple of the workgroup size, so the bounds check is exercised
+    let input: Vec<u32> = (0..1000).collect();
+    let output = pipeline.run(&vk, &input).expect("Could not run shader");
+
+    let expected: Vec<u32> = input.iter().map(|value| value * 2).collect();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn doubles_buffer_by_address_with_given_layout() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let layout = create_pipeline_layout::<Constants>(&vk, &[]).expect("Could not create layout");
+    let pipeline = ComputePipeline::with_layout(
+        &vk,
+        "double by address",
+        &compile(DOUBLE_BY_ADDRESS),
+        layout,
+        Some(Constants::get_range()),
+    )
+    .expect("Could not create pipeline");
+
+    let input: Vec<u32> = (0..1000).collect();
+    let buffer = Buffer::from_slice(&vk, "values", BufferUsage::Storage, &input)
+        .expect("Could not create buffer");
+    let readback = GpuBuffer::new(
+        &vk,
+        "values readback",
+        buffer.get_size(),
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryLocation::Readback,
+    )
+    .expect("Could not create readback buffer");
+
+    let constants = Constants {
+        values: buffer.get_device_address(),
+        count: input.len() as u32,
+        _pad: 0,
+    };
+    vk.immediate_submit(|device, cmd| {
+        let to_copy = [*vk::MemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+            .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)];
+        let to_host = [*vk::MemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ)];
+        let region = [vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size: buffer.get_size(),
+        }];
+
+        pipeline.bind(cmd, None);
+        pipeline.push_constants(cmd, &constants);
+        pipeline.dispatch(cmd, [pipeline.get_group_count(input.len() as u32), 1, 1]);
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().memory_barriers(&to_copy),
+            );
+            device.cmd_copy_buffer(cmd, buffer.get_buffer(), readback.get_buffer(), &region);
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().memory_barriers(&to_host),
+            );
+        }
+    })
+    .expect("Could not run shader");
+
+    let output: Vec<u32> = readback
+        .read(0, buffer.get_size() as usize)
+        .expect("Could not read buffer")
+        .chunks_exact(size_of::<u32>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect();
+    let expected: Vec<u32> = input.iter().map(|value| value * 2).collect();
+    assert_eq!(output, expected);
+
+    drop(pipeline);
+    unsafe { vk.get_device().destroy_pipeline_layout(layout, None) };
+}
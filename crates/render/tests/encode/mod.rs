@@ -0,0 +1,15 @@
+//! Encodes test images in memory, for the loaders that read them from files or embedded data.
+
+/// Encodes `width` by `height` RGBA8 `pixels` as a PNG.
+pub fn encode_png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .expect("Could not encode PNG");
+
+    bytes
+}
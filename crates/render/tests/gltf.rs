@@ -0,0 +1,196 @@
+mod encode;
+
+use ash::vk;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use encode::encode_png;
+use glam::Vec4;
+use render::{
+    gltf_import::from_memory,
+    sampler::SamplerDesc,
+    scene::{AlphaMode, LightKind, Primitive, Projection},
+    vertex::MeshVertex,
+    RenderError,
+};
+
+/// A right triangle in the XY plane, as positions then indices.
+fn triangle_buffer() -> Vec<u8> {
+    let positions: [f32; 9] = [0., 0., 0., 1., 0., 0., 0., 1., 0.];
+    let indices: [u16; 3] = [0, 1, 2];
+
+    let mut bytes = bytemuck::cast_slice(&positions).to_vec();
+    bytes.extend(bytemuck::cast_slice(&indices));
+    bytes.extend([0; 2]);
+
+    bytes
+}
+
+/// A scene with the triangle under a translated parent, a camera, a light and a textured material.
+fn scene_json(buffer_uri: &str) -> String {
+    let image_uri = format!(
+        "data:image/png;base64,{}",
+        STANDARD.encode(encode_png(1, 1, &[255, 128, 0, 255]))
+    );
+
+    format!(
+        r#"{{
+            "asset": {{ "version": "2.0" }},
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": {{
+                "KHR_lights_punctual": {{
+                    "lights": [{{ "type": "spot", "color": [1, 0.5, 0], "intensity": 20,
+                        "spot": {{ "innerConeAngle": 0.1, "outerConeAngle": 0.5 }} }}]
+                }}
+            }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [
+                {{ "name": "parent", "translation": [1, 2, 3], "children": [1, 2] }},
+                {{ "name": "child", "mesh": 0, "translation": [0, 0, -1] }},
+                {{ "camera": 0, "extensions": {{ "KHR_lights_punctual": {{ "light": 0 }} }} }}
+            ],
+            "cameras": [{{ "type": "perspective",
+                "perspective": {{ "yfov": 0.8, "znear": 0.1 }} }}],
+            "meshes": [{{ "name": "triangle", "primitives": [{{
+                "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}],
+            "materials": [{{
+                "name": "orange",
+                "pbrMetallicRoughness": {{ "baseColorFactor": [1, 1, 1, 0.5],
+                    "baseColorTexture": {{ "index": 0 }}, "metallicFactor": 0 }},
+                "alphaMode": "MASK",
+                "doubleSided": true
+            }}],
+            "textures": [{{ "source": 0, "sampler": 0 }}],
+            "samplers": [{{ "magFilter": 9728, "minFilter": 9728, "wrapS": 33071, "wrapT": 33071 }}],
+            "images": [{{ "uri": "{image_uri}" }}],
+            "buffers": [{{ "byteLength": 44, "uri": "{buffer_uri}" }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ]
+        }}"#
+    )
+}
+
+#[test]
+fn imports_embedded_gltf() {
+    let buffer_uri = format!(
+        "data:application/octet-stream;base64,{}",
+        STANDARD.encode(triangle_buffer())
+    );
+    let scene = from_memory(scene_json(&buffer_uri).as_bytes()).expect("Scene is valid");
+
+    let primitive = &scene.meshes[0].primitives[0];
+    assert_eq!(scene.meshes[0].name.as_deref(), Some("triangle"));
+    assert_eq!(primitive.indices, [0, 1, 2]);
+    assert_eq!(primitive.material, Some(0));
+    assert_eq!(primitive.vertices[1].position, [1., 0., 0.]);
+    // normals are generated from the counter clockwise winding
+    assert!(primitive.vertices.iter().all(|v| v.normal == [0., 0., 1.]));
+
+    let material = &scene.materials[0];
+    assert_eq!(material.base_color_factor, [1., 1., 1., 0.5]);
+    assert_eq!(material.base_color_texture, Some(0));
+    assert_eq!(material.metallic_factor, 0.);
+    assert_eq!(material.roughness_factor, 1.);
+    assert_eq!(material.alpha_mode, AlphaMode::Mask { cutoff: 0.5 });
+    assert!(material.double_sided);
+
+    let texture = &scene.textures[0];
+    assert_eq!(texture.data.format, vk::Format::R8G8B8A8_SRGB);
+    assert_eq!(texture.data.levels[0], [255, 128, 0, 255]);
+    assert_eq!(
+        texture.sampler,
+        SamplerDesc::new(
+            vk::Filter::NEAREST,
+            vk::SamplerMipmapMode::NEAREST,
+            vk::SamplerAddressMode::CLAMP_TO_EDGE
+        )
+    );
+
+    assert_eq!(scene.roots, [0]);
+    assert_eq!(scene.nodes[0].children, [1, 2]);
+    assert_eq!(scene.nodes[1].mesh, Some(0));
+    assert_eq!(scene.nodes[2].camera, Some(0));
+    assert_eq!(scene.nodes[2].light, Some(0));
+    assert_eq!(
+        scene.get_world_transforms()[1].w_axis,
+        Vec4::new(1., 2., 2., 1.)
+    );
+
+    assert_eq!(
+        scene.cameras[0].projection,
+        Projection::Perspective {
+            yfov: 0.8,
+            aspect_ratio: None,
+            znear: 0.1,
+            zfar: None
+        }
+    );
+    assert_eq!(
+        scene.lights[0].kind,
+        LightKind::Spot {
+            inner_cone_angle: 0.1,
+            outer_cone_angle: 0.5
+        }
+    );
+    assert_eq!(scene.lights[0].color, [1., 0.5, 0.]);
+    assert_eq!(scene.lights[0].intensity, 20.);
+}
+
+#[test]
+fn imports_binary_gltf() {
+    let mut json = scene_json("").replace(r#", "uri": """#, "").into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let bin = triangle_buffer();
+
+    let mut glb = b"glTF".to_vec();
+    glb.extend(2u32.to_le_bytes());
+    glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(&json);
+    glb.extend((bin.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(&bin);
+
+    let scene = from_memory(&glb).expect("Scene is valid");
+    assert_eq!(
+        scene.meshes[0].primitives[0].vertices[2].position,
+        [0., 1., 0.]
+    );
+}
+
+#[test]
+fn rejects_external_files_in_memory() {
+    let result = from_memory(scene_json("triangle.bin").as_bytes());
+
+    assert!(matches!(result, Err(RenderError::ModelLoad { .. })));
+}
+
+#[test]
+fn generates_tangents_along_u() {
+    let mut primitive = Primitive {
+        vertices: vec![
+            MeshVertex::new([0., 0., 0.], [0., 0., 1.], [0., 0.]),
+            MeshVertex::new([0., 2., 0.], [0., 0., 1.], [1., 0.]),
+            MeshVertex::new([-2., 0., 0.], [0., 0., 1.], [0., 1.]),
+        ],
+        indices: vec![0, 1, 2],
+        material: None,
+    };
+
+    primitive.generate_tangents();
+    for vertex in &primitive.vertices {
+        assert_eq!(vertex.tangent, [0., 1., 0., 1.]);
+    }
+
+    // mirrored texture coordinates flip the bitangent
+    primitive.vertices[2].uv = [0., -1.];
+    primitive.generate_tangents();
+    assert_eq!(primitive.vertices[0].tangent, [0., 1., 0., -1.]);
+}
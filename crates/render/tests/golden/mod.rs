@@ -0,0 +1,189 @@
+//! Golden image comparison for rendered frames.
+//!
+//! Rendered frames are written to `$CARGO_TARGET_TMPDIR/golden/<name>.png` and compared against
+//! the reference image checked in at `tests/golden/reference/<name>.png`. On a mismatch a
+//! `<name>.diff.png` is written next to the rendered frame, with every pixel that is off by more
+//! than the tolerance painted red.
+//!
+//! Set `GOLDEN_BLESS=1` to write the rendered frames as the reference images, e.g. for a new test.
+//! A missing reference fails the test otherwise, so review blessed images before committing them.
+
+#![allow(dead_code)]
+
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// An 8 bit RGBA image with tightly packed rows.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            (width * height * 4) as usize,
+            "Pixel data does not match a {}x{} RGBA8 image",
+            width,
+            height
+        );
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn read_png(path: &Path) -> Self {
+        let file =
+            File::open(path).unwrap_or_else(|e| panic!("Could not open {}: {}", path.display(), e));
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND);
+
+        let mut reader = decoder.read_info().expect("Could not read png header");
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut pixels)
+            .expect("Could not decode png");
+
+        assert_eq!(
+            (info.color_type, info.bit_depth),
+            (png::ColorType::Rgba, png::BitDepth::Eight),
+            "{} is not an 8 bit RGBA image",
+            path.display()
+        );
+        pixels.truncate(info.buffer_size());
+
+        Self::new(info.width, info.height, pixels)
+    }
+
+    pub fn write_png(&self, path: &Path) {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).expect("Could not create output directory");
+        }
+
+        let file = File::create(path)
+            .unwrap_or_else(|e| panic!("Could not create {}: {}", path.display(), e));
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .expect("Could not write png");
+    }
+}
+
+/// Result of comparing two images pixel by pixel.
+pub struct Comparison {
+    /// Number of pixels where any channel differs by more than the tolerance.
+    pub mismatched: usize,
+    /// Largest difference of any channel in the image.
+    pub max_difference: u8,
+    /// The reference dimmed to grey, with mismatched pixels painted red.
+    pub diff: Image,
+}
+
+/// Compares `actual` against `reference`, counting pixels where any channel differs by more than
+/// `tolerance`.
+pub fn compare(actual: &Image, reference: &Image, tolerance: u8) -> Comparison {
+    assert_eq!(
+        (actual.width, actual.height),
+        (reference.width, reference.height),
+        "Rendered image and reference have different dimensions"
+    );
+
+    let mut mismatched = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(reference.pixels.len());
+
+    for (a, r) in actual
+        .pixels
+        .chunks_exact(4)
+        .zip(reference.pixels.chunks_exact(4))
+    {
+        let difference = a.iter().zip(r).map(|(a, r)| a.abs_diff(*r)).max().unwrap();
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = ((r[0] as u32 * 3 + r[1] as u32 * 6 + r[2] as u32) / 10) as u8;
+            diff.extend_from_slice(&[luma / 3, luma / 3, luma / 3, 255]);
+        }
+    }
+
+    Comparison {
+        mismatched,
+        max_difference,
+        diff: Image::new(reference.width, reference.height, diff),
+    }
+}
+
+/// Writes `actual` to the output directory and asserts that it matches the reference image
+/// `name` within a per channel `tolerance`.
+///
+/// # Panics
+///
+/// Panics if any pixel is off by more than `tolerance`, after writing a diff image.
+pub fn assert_golden(name: &str, actual: &Image, tolerance: u8) {
+    let output = output_dir().join(format!("{}.png", name));
+    let reference_path = reference_dir().join(format!("{}.png", name));
+
+    actual.write_png(&output);
+
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        actual.write_png(&reference_path);
+        eprintln!(
+            "Wrote reference image {}, review it before committing",
+            reference_path.display()
+        );
+        return;
+    }
+
+    assert!(
+        reference_path.exists(),
+        "Missing reference image {}, run with GOLDEN_BLESS=1 to create it",
+        reference_path.display()
+    );
+
+    let reference = Image::read_png(&reference_path);
+    let comparison = compare(actual, &reference, tolerance);
+
+    if comparison.mismatched > 0 {
+        let diff_path = output_dir().join(format!("{}.diff.png", name));
+        comparison.diff.write_png(&diff_path);
+
+        panic!(
+            "{} of {} pixels differ from {} by more than {} (max difference {}).\n\
+             rendered: {}\n\
+             diff: {}",
+            comparison.mismatched,
+            reference.width * reference.height,
+            reference_path.display(),
+            tolerance,
+            comparison.max_difference,
+            output.display(),
+            diff_path.display()
+        );
+    }
+}
+
+pub fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+pub fn reference_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join("reference")
+}
@@ -0,0 +1,189 @@
+use ash::vk;
+use render::graph::{
+    Access, ImageHandle, ImportedImage, RenderGraph, ResourceState, TransientImageDesc,
+};
+
+const SHADERS: vk::PipelineStageFlags2 = vk::PipelineStageFlags2::from_raw(
+    vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw()
+        | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw(),
+);
+
+fn imported(graph: &mut RenderGraph) -> ImageHandle {
+    let image = ImportedImage {
+        image: vk::Image::null(),
+        view: vk::ImageView::null(),
+        format: vk::Format::B8G8R8A8_SRGB,
+        extent: vk::Extent2D {
+            width: 64,
+            height: 64,
+        },
+    };
+
+    graph.import_image("target", image, None, Access::ColorAttachment)
+}
+
+fn transient(format: vk::Format) -> TransientImageDesc {
+    TransientImageDesc {
+        format,
+        extent: vk::Extent2D {
+            width: 64,
+            height: 64,
+        },
+    }
+}
+
+#[test]
+fn reads_in_new_stages_wait_on_the_last_write() {
+    let mut state = ResourceState::new(None);
+    state.transition_buffer(Access::StorageWrite);
+
+    let uniform = state
+        .transition_buffer(Access::Uniform)
+        .expect("Uniform read waits on the storage write");
+    assert_eq!(uniform.src_stage, SHADERS);
+    assert!(uniform
+        .src_access
+        .contains(vk::AccessFlags2::SHADER_STORAGE_WRITE));
+
+    let vertices = state
+        .transition_buffer(Access::VertexBuffer)
+        .expect("Vertex input was not synchronized with the write yet");
+    assert!(vertices.src_stage.contains(SHADERS));
+    assert_eq!(
+        vertices.dst_stage,
+        vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT
+    );
+    assert!(state.transition_buffer(Access::Indirect).is_some());
+
+    // every stage has seen the write now
+    assert_eq!(state.transition_buffer(Access::Uniform), None);
+    assert_eq!(state.transition_buffer(Access::VertexBuffer), None);
+}
+
+#[test]
+fn writes_wait_on_reads_without_making_them_available() {
+    let mut state = ResourceState::new(Some(Access::Uniform));
+    assert_eq!(state.transition_buffer(Access::Uniform), None);
+
+    let write = state
+        .transition_buffer(Access::TransferWrite)
+        .expect("Write after read");
+    assert!(write
+        .src_stage
+        .contains(vk::PipelineStageFlags2::VERTEX_SHADER));
+    assert_eq!(write.src_access, vk::AccessFlags2::NONE);
+
+    let write = state
+        .transition_buffer(Access::TransferWrite)
+        .expect("Write after write");
+    assert_eq!(write.src_access, vk::AccessFlags2::TRANSFER_WRITE);
+}
+
+#[test]
+fn images_change_layout_between_accesses() {
+    let mut state = ResourceState::new(None);
+
+    let attachment = state
+        .transition(Access::ColorAttachment, false)
+        .expect("First use");
+    assert_eq!(attachment.old_layout, vk::ImageLayout::UNDEFINED);
+
+    let sampled = state
+        .transition(Access::Sampled, true)
+        .expect("Layout transition");
+    assert_eq!(
+        (sampled.old_layout, sampled.new_layout),
+        (
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        )
+    );
+    assert_eq!(state.transition(Access::Sampled, true), None);
+
+    // storage reads are in the general layout, which needs another transition
+    let storage = state
+        .transition(Access::StorageRead, true)
+        .expect("Layout transition");
+    assert!(storage
+        .src_stage
+        .contains(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT | SHADERS));
+    assert_eq!(state.get_layout(), vk::ImageLayout::GENERAL);
+
+    let discarded = state
+        .transition(Access::DepthAttachment, false)
+        .expect("Write");
+    assert_eq!(discarded.old_layout, vk::ImageLayout::UNDEFINED);
+    assert_eq!(
+        discarded.new_layout,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+    );
+}
+
+#[test]
+fn culls_passes_whose_writes_are_unused() {
+    let mut graph = RenderGraph::new();
+    let target = imported(&mut graph);
+    let shadow = graph.create_image("shadow", transient(vk::Format::D32_SFLOAT));
+    let unused = graph.create_image("unused", transient(vk::Format::R8G8B8A8_UNORM));
+
+    graph
+        .add_pass("unused")
+        .image(unused, Access::StorageWrite)
+        .execute(|_| {});
+    graph
+        .add_pass("shadow")
+        .image(shadow, Access::DepthAttachment)
+        .execute(|_| {});
+    graph
+        .add_pass("main")
+        .image(shadow, Access::Sampled)
+        .image(target, Access::ColorAttachment)
+        .execute(|_| {});
+    graph
+        .add_pass("readback")
+        .image(target, Access::TransferRead)
+        .side_effects()
+        .execute(|_| {});
+
+    assert_eq!(graph.get_culled_passes(), ["unused"]);
+    assert_eq!(graph.get_transient_slots(), [None, Some(0), None]);
+}
+
+#[test]
+fn transient_images_share_slots_when_lifetimes_do_not_overlap() {
+    let mut graph = RenderGraph::new();
+    let target = imported(&mut graph);
+    let color = transient(vk::Format::R16G16B16A16_SFLOAT);
+    let first = graph.create_image("first", color);
+    let overlapping = graph.create_image("overlapping", color);
+    let later = graph.create_image("later", color);
+    let other_format = graph.create_image("other format", transient(vk::Format::R8_UNORM));
+
+    graph
+        .add_pass("0")
+        .image(first, Access::StorageWrite)
+        .execute(|_| {});
+    graph
+        .add_pass("1")
+        .image(first, Access::Sampled)
+        .image(overlapping, Access::StorageWrite)
+        .execute(|_| {});
+    graph
+        .add_pass("2")
+        .image(overlapping, Access::Sampled)
+        .image(later, Access::StorageWrite)
+        .image(other_format, Access::StorageWrite)
+        .execute(|_| {});
+    graph
+        .add_pass("3")
+        .image(later, Access::Sampled)
+        .image(other_format, Access::Sampled)
+        .image(target, Access::ColorAttachment)
+        .execute(|_| {});
+
+    assert!(graph.get_culled_passes().is_empty());
+    assert_eq!(
+        graph.get_transient_slots(),
+        [None, Some(0), Some(1), Some(0), Some(2)]
+    );
+}
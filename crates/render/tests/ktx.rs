@@ -0,0 +1,507 @@
+use ash::vk;
+use render::{
+    block::{decompress, decompressed_format},
+    ktx::{decode, decompress_levels},
+    texture::TextureData,
+    RenderError,
+};
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+
+/// The order the lengths of the code length code of a BasisLZ Huffman code are stored in.
+const CODE_LENGTH_ORDER: [u32; 21] = [
+    17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+];
+
+/// Writes a KTX2 container with an empty data format descriptor and key/value data.
+fn container(
+    format: vk::Format,
+    extent: (u32, u32),
+    layers: (u32, u32),
+    supercompression: u32,
+    levels: &[&[u8]],
+) -> Vec<u8> {
+    let dfd = 4u32.to_le_bytes();
+    write_container(format, extent, layers, supercompression, &dfd, &[], levels)
+}
+
+/// Writes a KTX2 container of one Basis Universal image with a data format descriptor of
+/// `color_model` and a sample of each of `channels`.
+fn basis_container(
+    color_model: u8,
+    srgb: bool,
+    channels: &[u8],
+    extent: (u32, u32),
+    supercompression: u32,
+    global_data: &[u8],
+    level: &[u8],
+) -> Vec<u8> {
+    let block_size = 24 + 16 * channels.len() as u16;
+    let mut dfd = (4 + block_size as u32).to_le_bytes().to_vec();
+    dfd.extend([0; 4]);
+    dfd.extend(2u16.to_le_bytes());
+    dfd.extend(block_size.to_le_bytes());
+    dfd.extend([color_model, 1, if srgb { 2 } else { 1 }, 0, 3, 3, 0, 0]);
+    dfd.extend([0; 8]);
+    for (i, &channel) in channels.iter().enumerate() {
+        dfd.extend((i as u16 * 64).to_le_bytes());
+        dfd.extend([63, channel, 0, 0, 0, 0]);
+        dfd.extend(0u32.to_le_bytes());
+        dfd.extend(u32::MAX.to_le_bytes());
+    }
+
+    write_container(
+        vk::Format::UNDEFINED,
+        extent,
+        (0, 1),
+        supercompression,
+        &dfd,
+        global_data,
+        &[level],
+    )
+}
+
+fn write_container(
+    format: vk::Format,
+    (width, height): (u32, u32),
+    (layers, faces): (u32, u32),
+    supercompression: u32,
+    dfd: &[u8],
+    global_data: &[u8],
+    levels: &[&[u8]],
+) -> Vec<u8> {
+    let dfd_offset = 80 + 24 * levels.len();
+    let global_data_offset = dfd_offset + dfd.len();
+    let mut level_offset = global_data_offset + global_data.len();
+
+    let mut bytes = IDENTIFIER.to_vec();
+    for field in [
+        format.as_raw() as u32,
+        1,
+        width,
+        height,
+        0,
+        layers,
+        faces,
+        levels.len() as u32,
+        supercompression,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        0,
+        0,
+    ] {
+        bytes.extend(field.to_le_bytes());
+    }
+    for field in [global_data_offset, global_data.len()] {
+        bytes.extend((field as u64).to_le_bytes());
+    }
+    for level in levels {
+        for field in [level_offset, level.len(), level.len()] {
+            bytes.extend((field as u64).to_le_bytes());
+        }
+        level_offset += level.len();
+    }
+    bytes.extend(dfd);
+    bytes.extend(global_data);
+    for level in levels {
+        bytes.extend(*level);
+    }
+
+    bytes
+}
+
+/// Packs `(value, bit count)` fields from the lowest bit up.
+fn pack(fields: &[(u128, u32)]) -> [u8; 16] {
+    let (bits, _) = fields
+        .iter()
+        .fold((0, 0), |(bits, start), &(value, count)| {
+            (bits | value << start, start + count)
+        });
+
+    u128::to_le_bytes(bits)
+}
+
+/// Writes bits from the lowest bit of each byte up, as BasisLZ stores them.
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    count: usize,
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, count: u32) {
+        for i in 0..count {
+            if self.count.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            *self.bytes.last_mut().unwrap() |= ((value >> i) as u8 & 1) << (self.count % 8);
+            self.count += 1;
+        }
+    }
+
+    /// Writes a Huffman code giving each of `symbol_count` symbols a code of the same length,
+    /// the symbol itself, and returns the length.
+    fn put_code(&mut self, symbol_count: u32) -> u32 {
+        let length = (u32::BITS - (symbol_count - 1).leading_zeros()).max(1);
+        self.put(symbol_count, 14);
+        self.put(CODE_LENGTH_ORDER.len() as u32, 5);
+        for symbol in CODE_LENGTH_ORDER {
+            self.put((symbol == length) as u32, 3);
+        }
+        // the only code length used has the code 0
+        for _ in 0..symbol_count {
+            self.put(0, 1);
+        }
+
+        length
+    }
+
+    /// Writes `symbol` with a code of `length` bits from [`Bits::put_code`], highest bit first.
+    fn put_symbol(&mut self, symbol: u32, length: u32) {
+        self.put(symbol.reverse_bits() >> (32 - length), length);
+    }
+}
+
+/// Selectors in the history of the ETC1S test slices.
+const HISTORY_SIZE: u32 = 4;
+
+/// Writes a BasisLZ container of 2x2 ETC1S blocks with `channels` and every slice the same.
+///
+/// Endpoint 0 is white and endpoint 1 black, both with the first modifier table. Selector 0
+/// runs through all four selectors in the top row and has the darkest elsewhere, selector 1 is
+/// the brightest everywhere. The blocks use endpoints 0, 1, 0, 0 and selectors 0, 1, 1, 0.
+fn etc1s_container(channels: &[u8], extent: (u32, u32), image_flags: u32) -> Vec<u8> {
+    let mut endpoints = Bits::default();
+    let color_deltas = [32; 3].map(|count| endpoints.put_code(count))[0];
+    let table_deltas = endpoints.put_code(8);
+    endpoints.put(0, 1);
+    // from the initial 16 up to 31, then around to 0
+    for delta in [15, 1] {
+        endpoints.put_symbol(0, table_deltas);
+        for _ in 0..3 {
+            endpoints.put_symbol(delta, color_deltas);
+        }
+    }
+
+    let mut selectors = Bits::default();
+    selectors.put(0b100, 3);
+    for row in [0b1110_0100, 0, 0, 0, 0xff, 0xff, 0xff, 0xff] {
+        selectors.put(row, 8);
+    }
+
+    let mut tables = Bits::default();
+    let [predictions, endpoint_deltas, selector_symbols, _] =
+        [257, 2, 2 + HISTORY_SIZE + 1, 64].map(|count| tables.put_code(count));
+    tables.put(HISTORY_SIZE, 13);
+
+    // the top blocks are deltas from the previous endpoint, the bottom left takes the one above
+    // and the bottom right the one to the upper left, the bottom left takes the last selector
+    // added to the history
+    let mut slice = Bits::default();
+    slice.put_symbol(3 | 3 << 2 | 1 << 4 | 2 << 6, predictions);
+    for (delta, selector) in [(Some(0), 0), (Some(1), 1), (None, 2 + 3), (None, 0)] {
+        if let Some(delta) = delta {
+            slice.put_symbol(delta, endpoint_deltas);
+        }
+        slice.put_symbol(selector, selector_symbols);
+    }
+
+    let mut global_data = [2u16, 2].map(u16::to_le_bytes).concat();
+    for section in [&endpoints, &selectors, &tables] {
+        global_data.extend((section.bytes.len() as u32).to_le_bytes());
+    }
+    global_data.extend(0u32.to_le_bytes());
+    let slice_length = slice.bytes.len() as u32;
+    for field in [image_flags, 0, slice_length, 0, slice_length] {
+        global_data.extend(field.to_le_bytes());
+    }
+    for section in [endpoints, selectors, tables] {
+        global_data.extend(section.bytes);
+    }
+
+    basis_container(163, false, channels, extent, 1, &global_data, &slice.bytes)
+}
+
+/// A BC1 block with a red and a blue endpoint whose first 4 texels use the palette in order.
+const BC1_BLOCK: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0b1110_0100, 0, 0, 0];
+
+#[test]
+fn reads_layers_and_levels() {
+    let level0: Vec<u8> = (0..2 * 2 * 4 * 2).collect();
+    let level1: Vec<u8> = (0..4 * 2).collect();
+    let bytes = container(
+        vk::Format::R8G8B8A8_UNORM,
+        (2, 2),
+        (2, 1),
+        0,
+        &[&level0, &level1],
+    );
+
+    let data = decode(&bytes).expect("Container is valid");
+    assert_eq!(data.format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!((data.width, data.height, data.layers), (2, 2, 2));
+    assert!(!data.cube);
+    assert_eq!(data.levels, [level0, level1]);
+}
+
+#[test]
+fn reads_cubemaps_as_six_layers() {
+    let faces = vec![0; 6 * 8];
+    let bytes = container(vk::Format::BC1_RGB_SRGB_BLOCK, (4, 4), (0, 6), 0, &[&faces]);
+
+    let data = decode(&bytes).expect("Container is valid");
+    assert_eq!(data.layers, 6);
+    assert!(data.cube);
+}
+
+#[test]
+fn undoes_zstandard_supercompression() {
+    let texels = [7; 16];
+    // a single frame with one raw block
+    let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, texels.len() as u8];
+    frame.extend(&(1 | (texels.len() as u32) << 3).to_le_bytes()[..3]);
+    frame.extend(texels);
+    let bytes = container(vk::Format::R8G8B8A8_UNORM, (2, 2), (0, 1), 2, &[&frame]);
+
+    let data = decode(&bytes).expect("Container is valid");
+    assert_eq!(data.levels, [texels.to_vec()]);
+}
+
+#[test]
+fn transcodes_uastc_to_astc() {
+    let solid = pack(&[(0x17, 5), (10, 8), (20, 8), (30, 8), (40, 8)]);
+    // mode 0, RGB endpoints as trits above 6 bits, black and the top value with trits 0, 2
+    // and 1, the anchor texel 0 weighs 0 and the others 15
+    let mut trits = vec![(0x1, 4), (0, 15), (54, 8), (1, 2)];
+    trits.extend([(0, 6), (1, 6)].repeat(3));
+    trits.push((0, 3));
+    trits.extend([(15, 4)].repeat(15));
+    let trits = pack(&trits);
+    // mode 16, luminance and alpha with the left half black and the right half white
+    let partitioned = pack(&[
+        (0x15, 6),
+        (0, 23),
+        (0, 5),
+        (0xffff_ffff_ffff_0000, 64),
+        (0, 30),
+    ]);
+    // mode 6, RGB endpoints as quints above 5 bits, black and the top value with quints 4, 3
+    // and 2, green weighed separately with 0 and the others with the full weight
+    let mut quints = vec![(0x1b, 5), (0, 15), (1, 2), (20, 7), (53, 7)];
+    quints.extend([(0, 5), (1, 5)].repeat(3));
+    quints.extend([(0, 1), (0, 1)]);
+    quints.extend([(3, 2), (0, 2)].repeat(15));
+    let quints = pack(&quints);
+    let blocks = [solid, trits, partitioned, quints].concat();
+    let bytes = basis_container(166, true, &[0], (16, 4), 0, &[], &blocks);
+
+    let data = decode(&bytes).expect("Container is valid");
+    assert_eq!(data.format, vk::Format::ASTC_4X4_SRGB_BLOCK);
+    let rgba = decompress(data.format, 16, 4, &data.levels[0]).expect("ASTC");
+    let texel = |x: usize, y: usize| &rgba[(y * 16 + x) * 4..][..4];
+    assert!((0..16).all(|i| texel(i % 4, i / 4) == [10, 20, 30, 40]));
+    assert_eq!(texel(4, 0), [0, 0, 0, 255]);
+    assert_eq!(texel(5, 0), [255, 253, 254, 255]);
+    assert_eq!(texel(7, 3), [255, 253, 254, 255]);
+    assert_eq!(texel(9, 3), [0, 0, 0, 255]);
+    assert_eq!(texel(10, 0), [255, 255, 255, 255]);
+    assert_eq!(texel(12, 0), [0, 0, 0, 255]);
+    assert_eq!(texel(15, 3), [249, 0, 252, 255]);
+}
+
+#[test]
+fn malformed_uastc_blocks_become_magenta() {
+    // the reserved mode code
+    let block = pack(&[(0x45, 7)]);
+    let bytes = basis_container(166, false, &[0], (4, 4), 0, &[], &block);
+
+    let data = decode(&bytes).expect("Container is valid");
+    let rgba = decompress(data.format, 4, 4, &data.levels[0]).expect("ASTC");
+    assert!(rgba
+        .chunks_exact(4)
+        .all(|texel| texel == [255, 0, 255, 255]));
+}
+
+#[test]
+fn transcodes_etc1s_to_etc1() {
+    let bytes = etc1s_container(&[0], (8, 8), 0);
+
+    let data = decode(&bytes).expect("Container is valid");
+    assert_eq!(data.format, vk::Format::ETC2_R8G8B8_UNORM_BLOCK);
+    assert_eq!(data.levels[0].len(), 4 * 8);
+    let rgba = decompress(data.format, 8, 8, &data.levels[0]).expect("ETC2");
+    let texel = |x: usize, y: usize| rgba[(y * 8 + x) * 4];
+    assert_eq!([0, 1, 2, 3].map(|x| texel(x, 0)), [247, 253, 255, 255]);
+    assert_eq!([texel(0, 3), texel(4, 0), texel(0, 4)], [247, 8, 255]);
+    assert_eq!([texel(4, 4), texel(5, 4), texel(5, 5)], [247, 253, 247]);
+}
+
+#[test]
+fn transcodes_etc1s_with_alpha_to_cropped_rgba8() {
+    let bytes = etc1s_container(&[0, 15], (6, 6), 0);
+
+    let data = decode(&bytes).expect("Container is valid");
+    assert_eq!(data.format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!(data.levels[0].len(), 6 * 6 * 4);
+    let texel = |x: usize, y: usize| &data.levels[0][(y * 6 + x) * 4..][..4];
+    assert_eq!(texel(1, 0), [253; 4]);
+    assert_eq!(texel(4, 0), [8; 4]);
+    assert_eq!(texel(5, 5), [247; 4]);
+}
+
+#[test]
+fn rejects_malformed_basis_payloads_and_garbage() {
+    // no data format descriptor to tell what the payload is
+    let bytes = container(vk::Format::UNDEFINED, (4, 4), (0, 1), 1, &[&[0; 16]]);
+    assert!(matches!(decode(&bytes), Err(RenderError::ImageLoad { .. })));
+
+    // a P-frame of a video
+    let bytes = etc1s_container(&[0], (8, 8), 2);
+    assert!(matches!(decode(&bytes), Err(RenderError::ImageLoad { .. })));
+
+    // codebooks cut short
+    let bytes = basis_container(163, false, &[0], (4, 4), 1, &[2, 0, 2, 0], &[0; 16]);
+    assert!(matches!(decode(&bytes), Err(RenderError::ImageLoad { .. })));
+
+    assert!(matches!(
+        decode(b"not a container"),
+        Err(RenderError::ImageLoad { .. })
+    ));
+}
+
+#[test]
+fn decompresses_bc1() {
+    let rgba = decompress(vk::Format::BC1_RGBA_UNORM_BLOCK, 4, 4, &BC1_BLOCK).expect("BC1");
+
+    assert_eq!(rgba.len(), 4 * 4 * 4);
+    assert_eq!(
+        rgba[..16],
+        [255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255, 85, 0, 170, 255]
+    );
+    // the remaining texels use the first endpoint
+    assert_eq!(rgba[16..20], [255, 0, 0, 255]);
+}
+
+#[test]
+fn bc1_three_color_blocks_are_transparent_only_with_alpha() {
+    // endpoints swapped, so index 3 is transparent black
+    let block = [0x1f, 0x00, 0x00, 0xf8, 0b1100_0000, 0, 0, 0];
+
+    let rgba = decompress(vk::Format::BC1_RGBA_SRGB_BLOCK, 4, 4, &block).expect("BC1");
+    assert_eq!(rgba[12..16], [0, 0, 0, 0]);
+
+    let rgb = decompress(vk::Format::BC1_RGB_SRGB_BLOCK, 4, 4, &block).expect("BC1");
+    assert_eq!(rgb[12..16], [0, 0, 0, 255]);
+}
+
+#[test]
+fn decompresses_etc2_individual_blocks_column_major() {
+    // base color 0x88 in both sub-blocks, first modifier table, pixel (0, 1) uses -2
+    let block = [0x88, 0x88, 0x88, 0x00, 0x00, 0x02, 0x00, 0x00];
+
+    let rgba = decompress(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 4, 4, &block).expect("ETC2");
+    for (i, texel) in rgba.chunks_exact(4).enumerate() {
+        let expected = if i == 4 { 134 } else { 138 };
+        assert_eq!(texel, [expected, expected, expected, 255], "texel {}", i);
+    }
+}
+
+#[test]
+fn decompresses_eac_alpha() {
+    // base 128, multiplier 1, first table, pixel (0, 0) uses +2 and the others -3
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&[128, 0x10, 0x80, 0, 0, 0, 0, 0]);
+    block[8..].copy_from_slice(&[0x88, 0x88, 0x88, 0x00, 0, 0, 0, 0]);
+
+    let rgba = decompress(vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK, 4, 4, &block).expect("ETC2");
+    assert_eq!(rgba[3], 130);
+    assert!(rgba[4..].chunks_exact(4).all(|texel| texel[3] == 125));
+}
+
+#[test]
+fn crops_partial_blocks_and_widens_every_layer() {
+    let data = TextureData {
+        format: vk::Format::BC1_RGBA_SRGB_BLOCK,
+        width: 2,
+        height: 2,
+        layers: 2,
+        cube: false,
+        levels: vec![[BC1_BLOCK, BC1_BLOCK].concat()],
+    };
+
+    let rgba = decompress_levels(&data).expect("BC1 has a decoder");
+    assert_eq!(rgba.format, vk::Format::R8G8B8A8_SRGB);
+    assert_eq!(rgba.layers, 2);
+    assert_eq!(rgba.levels[0].len(), 2 * 2 * 4 * 2);
+    assert_eq!(rgba.levels[0][..8], [255, 0, 0, 255, 0, 0, 255, 255]);
+    assert_eq!(rgba.levels[0][16..24], rgba.levels[0][..8]);
+}
+
+#[test]
+fn decompresses_bc7() {
+    // mode 6, red from 0 to 127 and alpha 127 with p-bits 0 and 1, which also set the low bit
+    // of green and blue, texels 1 and 2 use indices 15 and 8
+    let bits: u128 = 1 << 6 | 127 << 14 | 127 << 49 | 127 << 56 | 1 << 64 | 15 << 68 | 8 << 72;
+
+    let rgba = decompress(vk::Format::BC7_UNORM_BLOCK, 4, 4, &bits.to_le_bytes()).expect("BC7");
+    assert_eq!(rgba[..12], [0, 0, 0, 254, 255, 1, 1, 255, 135, 1, 1, 255]);
+}
+
+#[test]
+fn decompresses_bc6h_to_half_floats() {
+    // mode 11 with 10 bit endpoints 0 and 495, which unquantizes to 1.0, texel 1 uses index 15
+    let bits: u128 = 0b11 | 495 << 35 | 495 << 45 | 495 << 55 | 15 << 68;
+
+    let format = vk::Format::BC6H_UFLOAT_BLOCK;
+    assert_eq!(
+        decompressed_format(format),
+        Some(vk::Format::R16G16B16A16_SFLOAT)
+    );
+    let texels = decompress(format, 4, 4, &bits.to_le_bytes()).expect("BC6H");
+    assert_eq!(texels.len(), 4 * 4 * 8);
+    assert_eq!(texels[..8], [0, 0, 0, 0, 0, 0, 0x00, 0x3c]);
+    assert_eq!(
+        texels[8..16],
+        [0x00, 0x3c, 0x00, 0x3c, 0x00, 0x3c, 0x00, 0x3c]
+    );
+}
+
+#[test]
+fn decompresses_astc() {
+    // a 4x4 grid of 2 bit weights and direct RGB endpoints black and magenta, stored as 8 bits
+    // each, texel 1 has the full weight and texel 2 a third of it
+    let bits: u128 = 0x42 | 8 << 13 | 0xff << 25 | 0xff << 57 | 0b11 << 124 | 1 << 123;
+
+    let rgba =
+        decompress(vk::Format::ASTC_4X4_UNORM_BLOCK, 4, 4, &bits.to_le_bytes()).expect("ASTC");
+    assert_eq!(rgba[..12], [0, 0, 0, 255, 255, 0, 255, 255, 84, 0, 84, 255]);
+}
+
+#[test]
+fn decompresses_astc_void_extents_of_any_footprint() {
+    // a constant color block with no extent, RGBA as 16 bit values
+    let color: u128 = 0xffff | 0x8000 << 16 | 0xffff << 48;
+    let bits = 0x1fc | 0b11 << 10 | ((1 << 52) - 1) << 12 | color << 64;
+    let blocks = [bits.to_le_bytes(), bits.to_le_bytes()].concat();
+
+    let format = vk::Format::ASTC_6X6_SRGB_BLOCK;
+    assert_eq!(decompressed_format(format), Some(vk::Format::R8G8B8A8_SRGB));
+    let rgba = decompress(format, 8, 4, &blocks).expect("ASTC");
+    assert_eq!(rgba.len(), 8 * 4 * 4);
+    assert!(rgba
+        .chunks_exact(4)
+        .all(|texel| texel == [255, 128, 0, 255]));
+}
+
+#[test]
+fn formats_without_decoders_are_unsupported() {
+    assert_eq!(decompressed_format(vk::Format::BC5_SNORM_BLOCK), None);
+    assert!(matches!(
+        decompress(vk::Format::EAC_R11_SNORM_BLOCK, 4, 4, &[0; 8]),
+        Err(RenderError::UnsupportedFormat(
+            vk::Format::EAC_R11_SNORM_BLOCK
+        ))
+    ));
+}
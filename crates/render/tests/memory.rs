@@ -0,0 +1,110 @@
+//! Writes GPU buffers from the host and reads them back, both from host visible memory and after a
+//! copy through device local memory.
+
+use ash::vk;
+use render::{
+    memory::{GpuBuffer, MemoryLocation},
+    Vk,
+};
+
+#[test]
+fn host_visible_buffer_reads_back_writes() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut buffer = GpuBuffer::new(
+        &vk,
+        "host visible",
+        64,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::Readback,
+    )
+    .expect("Could not create buffer");
+    assert!(buffer.get_mapped().is_some());
+
+    let data: Vec<u8> = (0..16).collect();
+    buffer.write(8, &data).expect("Could not write buffer");
+
+    assert_eq!(buffer.read(8, 16).expect("Could not read buffer"), data);
+    assert_eq!(
+        buffer.read(12, 4).expect("Could not read buffer"),
+        [4, 5, 6, 7]
+    );
+}
+
+#[test]
+fn copies_through_device_local_buffer() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let size = 256;
+    let usage = vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+
+    let mut upload = GpuBuffer::new(&vk, "upload", size, usage, MemoryLocation::Upload)
+        .expect("Could not create upload buffer");
+    let device_local = GpuBuffer::new(&vk, "device local", size, usage, MemoryLocation::GpuOnly)
+        .expect("Could not create device local buffer");
+    let readback = GpuBuffer::new(&vk, "readback", size, usage, MemoryLocation::Readback)
+        .expect("Could not create readback buffer");
+    assert!(device_local.get_mapped().is_none());
+
+    let data: Vec<u8> = (0..size).map(|i| (i * 7) as u8).collect();
+    upload.write(0, &data).expect("Could not write buffer");
+
+    vk.immediate_submit(|device, cmd| {
+        let region = [vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size,
+        }];
+        let between_copies = [*vk::MemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)];
+        let to_host = [*vk::MemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ)];
+
+        unsafe {
+            device.cmd_copy_buffer(cmd, upload.get_buffer(), device_local.get_buffer(), &region);
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().memory_barriers(&between_copies),
+            );
+            device.cmd_copy_buffer(
+                cmd,
+                device_local.get_buffer(),
+                readback.get_buffer(),
+                &region,
+            );
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().memory_barriers(&to_host),
+            );
+        }
+    })
+    .expect("Could not copy buffers");
+
+    assert_eq!(
+        readback
+            .read(0, size as usize)
+            .expect("Could not read buffer"),
+        data
+    );
+}
+
+#[test]
+#[should_panic(expected = "still alive")]
+fn dropping_the_device_before_its_buffers_panics() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let buffer = GpuBuffer::new(
+        &vk,
+        "outlives the device",
+        64,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::Readback,
+    )
+    .expect("Could not create buffer");
+
+    std::mem::drop(vk);
+    std::mem::drop(buffer);
+}
@@ -0,0 +1,110 @@
+use std::mem::size_of;
+
+use glam::Vec3;
+use render::{
+    meshlet::{
+        MeshletConstants, Meshlets, MAX_MESHLET_TRIANGLES, MAX_MESHLET_VERTICES,
+        MESHLET_GLSL_DECLARATIONS, MESHLET_MESH_GLSL, MESHLET_TASK_GLSL, MESHLET_VERTEX_GLSL,
+        TASK_WORKGROUP_SIZE,
+    },
+    reflect::{PipelineReflection, ShaderReflection},
+    shader::{compile_glsl, ShaderStage},
+    vertex::MeshVertex,
+};
+
+/// A flat `size` by `size` quad grid in the xy plane facing +z, two triangles per quad.
+fn grid(size: u32) -> (Vec<u32>, Vec<[f32; 3]>) {
+    let positions = (0..=size)
+        .flat_map(|y| (0..=size).map(move |x| [x as f32, y as f32, 0.]))
+        .collect();
+
+    let row = size + 1;
+    let indices = (0..size)
+        .flat_map(|y| (0..size).map(move |x| y * row + x))
+        .flat_map(|i| [i, i + 1, i + row + 1, i, i + row + 1, i + row])
+        .collect();
+
+    (indices, positions)
+}
+
+#[test]
+fn respects_meshlet_limits() {
+    let (indices, positions) = grid(32);
+    let meshlets = Meshlets::build(&indices, &positions);
+
+    assert!(meshlets.len() > 1);
+    for meshlet in &meshlets.meshlets {
+        assert!(meshlet.vertex_count as usize <= MAX_MESHLET_VERTICES);
+        assert!(meshlet.triangle_count as usize <= MAX_MESHLET_TRIANGLES);
+    }
+    assert_eq!(meshlets.triangles.len(), indices.len() / 3);
+}
+
+#[test]
+fn to_indices_keeps_triangles() {
+    let (mut indices, positions) = grid(20);
+    // degenerate, dropped
+    indices.extend([3, 3, 4]);
+    let meshlets = Meshlets::build(&indices, &positions);
+
+    assert_eq!(meshlets.to_indices(), indices[..indices.len() - 3]);
+}
+
+#[test]
+fn bounds_contain_vertices() {
+    let (indices, positions) = grid(16);
+    let meshlets = Meshlets::build(&indices, &positions);
+
+    for meshlet in &meshlets.meshlets {
+        let start = meshlet.vertex_offset as usize;
+        for &vertex in &meshlets.vertices[start..start + meshlet.vertex_count as usize] {
+            let position = positions[vertex as usize];
+            let distance = (0..3)
+                .map(|axis| (position[axis] - meshlet.center[axis]).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            assert!(distance <= meshlet.radius + 1e-4, "{:?}", meshlet);
+        }
+    }
+}
+
+#[test]
+fn flat_meshlets_are_culled_from_behind() {
+    let (indices, positions) = grid(4);
+    let meshlets = Meshlets::build(&indices, &positions);
+    let meshlet = meshlets.meshlets[0];
+
+    assert_eq!(meshlet.cone_axis, [0., 0., 1.]);
+    assert_eq!(meshlet.cone_cutoff, 0.);
+    assert!(meshlet.is_backfacing(Vec3::new(2., 2., -10.)));
+    assert!(!meshlet.is_backfacing(Vec3::new(2., 2., 10.)));
+}
+
+/// Compiles `main` after the meshlet declarations and reflects it.
+fn reflect_meshlet_shader(main: &str, stage: ShaderStage) -> ShaderReflection {
+    let source = format!("#version 460\n{}\n{}", MESHLET_GLSL_DECLARATIONS, main);
+    let spirv = compile_glsl(&source, stage, "meshlet").expect("Meshlet shaders compile");
+
+    ShaderReflection::new(&spirv, stage).expect("Meshlet shaders reflect")
+}
+
+#[test]
+fn meshlet_shaders_compile_with_the_push_constants() {
+    let task = reflect_meshlet_shader(MESHLET_TASK_GLSL, ShaderStage::Task);
+    let mesh = reflect_meshlet_shader(MESHLET_MESH_GLSL, ShaderStage::Mesh);
+    let vertex = reflect_meshlet_shader(MESHLET_VERTEX_GLSL, ShaderStage::Vertex);
+
+    assert_eq!(task.workgroup_size, [TASK_WORKGROUP_SIZE, 1, 1]);
+    for shader in [&task, &mesh, &vertex] {
+        assert!(shader.bindings.is_empty());
+        assert_eq!(
+            shader.push_constant_size as usize,
+            size_of::<MeshletConstants>()
+        );
+    }
+
+    let fallback = PipelineReflection::new(&[vertex]).expect("The vertex stage is valid");
+    fallback
+        .vertex_input::<MeshVertex>(0)
+        .expect("The fallback reads mesh vertices");
+}
@@ -0,0 +1,155 @@
+mod encode;
+
+use ash::vk;
+use encode::encode_png;
+use render::{
+    obj_import::{from_memory, load},
+    scene::AlphaMode,
+    RenderError,
+};
+
+/// A unit quad in the XY plane as a single polygon, without normals.
+const QUAD: &str = "
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+f 1/1 2/2 3/3 4/4
+";
+
+const MATERIALS: &str = "
+newmtl red
+Kd 1 0 0
+Ns 0
+d 0.5
+bump -bm 0.5 normal.png
+
+newmtl green
+Kd 0 1 0
+Ns 998
+map_Kd albedo.png
+";
+
+#[test]
+fn triangulates_polygons_and_generates_normals() {
+    let scene = from_memory(QUAD.as_bytes(), None).expect("OBJ is valid");
+
+    assert_eq!(scene.meshes.len(), 1);
+    assert_eq!(scene.meshes[0].name.as_deref(), Some("quad"));
+    let primitive = &scene.meshes[0].primitives[0];
+    assert_eq!(primitive.indices, [0, 1, 2, 0, 2, 3]);
+    assert_eq!(primitive.vertices.len(), 4);
+    assert_eq!(primitive.material, None);
+    assert!(primitive.vertices.iter().all(|v| v.normal == [0., 0., 1.]));
+    // texture coordinates are flipped to a top left origin
+    assert_eq!(primitive.vertices[3].uv, [0., 0.]);
+    assert_eq!(primitive.vertices[1].uv, [1., 1.]);
+
+    assert_eq!(scene.roots, [0]);
+    assert_eq!(scene.nodes[0].mesh, Some(0));
+}
+
+#[test]
+fn deduplicates_shared_vertices() {
+    let obj = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+vn 0 0 -1
+f 1//1 2//1 3//1
+f 1//1 3//1 4//1
+f 1//2 3//2 2//2
+";
+    let scene = from_memory(obj.as_bytes(), None).expect("OBJ is valid");
+
+    let primitive = &scene.meshes[0].primitives[0];
+    assert_eq!(primitive.indices.len(), 9);
+    // the back face shares positions but not normals with the front
+    assert_eq!(primitive.vertices.len(), 7);
+    assert_eq!(primitive.vertices[6].normal, [0., 0., -1.]);
+}
+
+#[test]
+fn splits_objects_by_material() {
+    let obj = format!("mtllib quad.mtl\nusemtl red\n{QUAD}usemtl green\nf 1 3 4\n");
+    let scene = from_memory(obj.as_bytes(), Some(MATERIALS.as_bytes())).expect("OBJ is valid");
+
+    assert_eq!(scene.meshes.len(), 1);
+    let primitives = &scene.meshes[0].primitives;
+    assert_eq!(primitives.len(), 2);
+    assert_eq!(primitives[0].material, Some(0));
+    assert_eq!(primitives[1].material, Some(1));
+
+    let red = &scene.materials[0];
+    assert_eq!(red.name.as_deref(), Some("red"));
+    assert_eq!(red.base_color_factor, [1., 0., 0., 0.5]);
+    assert_eq!(red.alpha_mode, AlphaMode::Blend);
+    assert_eq!(red.metallic_factor, 0.);
+    assert_eq!(red.roughness_factor, 1.);
+    assert!((scene.materials[1].roughness_factor - 0.0447).abs() < 1e-4);
+    // texture maps can not be resolved in memory
+    assert_eq!(red.normal_texture, None);
+    assert!(scene.textures.is_empty());
+}
+
+#[test]
+fn loads_texture_maps_next_to_the_file() {
+    let dir = std::env::temp_dir().join(format!("youniverse-obj-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Could not create directory");
+    std::fs::write(
+        dir.join("albedo.png"),
+        encode_png(1, 1, &[255, 128, 0, 255]),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("normal.png"),
+        encode_png(1, 1, &[128, 128, 255, 255]),
+    )
+    .unwrap();
+    let materials = format!("{MATERIALS}\nnewmtl blue\nKd 0 0 1\nmap_Kd albedo.png\n");
+    std::fs::write(dir.join("quad.mtl"), materials).unwrap();
+    let obj = format!("mtllib quad.mtl\nusemtl red\n{QUAD}");
+    std::fs::write(dir.join("quad.obj"), obj).unwrap();
+
+    let scene = load(dir.join("quad.obj"));
+    std::fs::remove_dir_all(&dir).ok();
+    let scene = scene.expect("OBJ is valid");
+
+    let [red, green, blue] = &scene.materials[..] else {
+        panic!("Expected 3 materials");
+    };
+    assert_eq!(red.base_color_texture, None);
+    assert_eq!(red.normal_texture, Some(0));
+    assert_eq!(green.base_color_texture, Some(1));
+    // the same map is decoded only once
+    assert_eq!(blue.base_color_texture, Some(1));
+    assert_eq!(scene.textures.len(), 2);
+    assert_eq!(scene.textures[0].data.format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!(scene.textures[1].data.format, vk::Format::R8G8B8A8_SRGB);
+    assert_eq!(scene.textures[1].data.levels[0], [255, 128, 0, 255]);
+
+    // normal maps need tangents, along u and with v pointing down the quad once flipped
+    let primitive = &scene.meshes[0].primitives[0];
+    assert!(primitive
+        .vertices
+        .iter()
+        .all(|v| v.tangent == [1., 0., 0., -1.]));
+}
+
+#[test]
+fn rejects_invalid_files() {
+    let result = from_memory(b"f 1 2 3\n", None);
+
+    assert!(matches!(result, Err(RenderError::ModelLoad { .. })));
+    assert!(matches!(
+        load("does/not/exist.obj"),
+        Err(RenderError::ModelLoad { .. })
+    ));
+}
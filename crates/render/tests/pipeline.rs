@@ -0,0 +1,86 @@
+use ash::vk::{self, Handle};
+use render::{
+    pipeline::{Blend, CullMode, DepthState, PipelineDesc, RasterState, Topology},
+    shader::ShaderSource,
+    vertex::{VertexAttribute, VertexLayout},
+};
+
+fn quad_desc() -> PipelineDesc {
+    PipelineDesc {
+        vertex: Some(VertexLayout {
+            name: "QuadVertex".to_owned(),
+            stride: 20,
+            attributes: vec![
+                VertexAttribute {
+                    location: 0,
+                    format: vk::Format::R32G32_SFLOAT,
+                    offset: 0,
+                },
+                VertexAttribute {
+                    location: 1,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: 8,
+                },
+            ],
+        }),
+        depth: Some(DepthState::default()),
+        depth_format: vk::Format::D32_SFLOAT,
+        ..PipelineDesc::new(
+            vec![
+                ShaderSource::new("tests/shaders/quad.vert"),
+                ShaderSource::new("tests/shaders/quad.frag"),
+            ],
+            vec![vk::Format::B8G8R8A8_SRGB],
+        )
+    }
+}
+
+#[test]
+fn desc_round_trips_through_json() {
+    let desc = quad_desc();
+
+    let json = serde_json::to_string(&desc).expect("Could not serialize");
+    let parsed: PipelineDesc = serde_json::from_str(&json).expect("Could not deserialize");
+
+    assert_eq!(parsed, desc);
+    assert_eq!(parsed.get_hash(), desc.get_hash());
+}
+
+#[test]
+fn hash_tells_descs_apart() {
+    let desc = quad_desc();
+
+    let blended = PipelineDesc {
+        blend: Blend::Alpha,
+        ..quad_desc()
+    };
+    let lines = PipelineDesc {
+        topology: Topology::LineList,
+        ..quad_desc()
+    };
+    let two_sided = PipelineDesc {
+        raster: RasterState {
+            cull_mode: CullMode::None,
+            ..Default::default()
+        },
+        ..quad_desc()
+    };
+
+    assert_eq!(desc.get_hash(), quad_desc().get_hash());
+    for other in [blended, lines, two_sided] {
+        assert_ne!(desc.get_hash(), other.get_hash(), "{:?}", other);
+    }
+}
+
+#[test]
+fn given_layout_is_not_serialized() {
+    let desc = PipelineDesc {
+        layout: Some(vk::PipelineLayout::from_raw(1)),
+        ..quad_desc()
+    };
+    assert_ne!(desc.get_hash(), quad_desc().get_hash());
+
+    let json = serde_json::to_string(&desc).expect("Could not serialize");
+    let parsed: PipelineDesc = serde_json::from_str(&json).expect("Could not deserialize");
+    assert_eq!(parsed, quad_desc());
+}
@@ -0,0 +1,97 @@
+//! Captures scopes with a profiler on a headless device and checks the written trace.
+
+use std::path::Path;
+
+use render::{
+    profile::{GpuProfiler, DEFAULT_MAX_SCOPES},
+    Vk,
+};
+
+#[test]
+fn writes_chrome_trace() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut profiler =
+        GpuProfiler::new(&vk, 2, DEFAULT_MAX_SCOPES).expect("Could not create profiler");
+
+    profiler.start_capture();
+    profiler.begin_cpu_scope("update");
+    profiler.begin_cpu_scope("physics");
+    profiler.end_cpu_scope();
+    profiler.end_cpu_scope();
+
+    let path = std::env::temp_dir().join("youniverse-profile-test.json");
+    profiler
+        .write_chrome_trace(&path)
+        .expect("Could not write trace");
+    assert!(!profiler.is_capturing());
+
+    // inner scopes end first
+    assert_eq!(read_scopes(&path, "cpu"), ["physics", "update"]);
+}
+
+#[test]
+fn resolves_gpu_scopes_when_the_slot_comes_around() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut profiler =
+        GpuProfiler::new(&vk, 1, DEFAULT_MAX_SCOPES).expect("Could not create profiler");
+    if !profiler.is_supported() {
+        return;
+    }
+
+    profiler.start_capture();
+    vk.immediate_submit(|_, cmd| {
+        profiler
+            .begin_frame(cmd, 0, 7)
+            .expect("Nothing to resolve yet");
+        profiler.begin_scope(cmd, "frame");
+        profiler.begin_scope(cmd, "opaque");
+        profiler.end_scope(cmd);
+        profiler.end_scope(cmd);
+        profiler.end_frame();
+    })
+    .expect("Could not submit");
+    assert_eq!(profiler.get_last_frame_number(), None);
+
+    vk.immediate_submit(|_, cmd| {
+        profiler
+            .begin_frame(cmd, 0, 8)
+            .expect("Could not resolve frame 7");
+        profiler.end_frame();
+    })
+    .expect("Could not submit");
+
+    assert_eq!(profiler.get_last_frame_number(), Some(7));
+    let scopes: Vec<_> = profiler
+        .get_last_frame()
+        .iter()
+        .map(|timing| (timing.name.as_str(), timing.depth))
+        .collect();
+    assert_eq!(scopes, [("frame", 0), ("opaque", 1)]);
+    let [frame, opaque] = profiler.get_last_frame() else {
+        unreachable!()
+    };
+    assert!(opaque.start >= frame.start);
+    assert!(opaque.start + opaque.duration <= frame.start + frame.duration);
+
+    let path = std::env::temp_dir().join("youniverse-profile-gpu-test.json");
+    profiler
+        .write_chrome_trace(&path)
+        .expect("Could not write trace");
+    assert_eq!(read_scopes(&path, "gpu"), ["frame", "opaque"]);
+}
+
+/// The names of the complete events of `category` in the trace at `path`, which is removed.
+fn read_scopes(path: &Path, category: &str) -> Vec<String> {
+    let trace: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(path).expect("Trace was not written"))
+            .expect("Trace is not JSON");
+    let _ = std::fs::remove_file(path);
+
+    trace["traceEvents"]
+        .as_array()
+        .expect("Trace has no events")
+        .iter()
+        .filter(|event| event["ph"] == "X" && event["cat"] == category)
+        .map(|event| event["name"].as_str().unwrap().to_owned())
+        .collect()
+}
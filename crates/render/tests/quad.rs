@@ -0,0 +1,289 @@
+//! Renders the spinning quad from `render_bk` offscreen at a fixed point in time and compares it
+//! against the golden reference image.
+
+mod golden;
+
+use std::{ffi::CString, mem::size_of};
+
+use ash::{vk, Device};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3};
+use golden::{assert_golden, Image};
+use render::{
+    buffer::{Buffer, BufferUsage},
+    offscreen::OffscreenTarget,
+    shader::{compile_glsl, ShaderStage},
+    Vk,
+};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+/// Seconds since start the frame is rendered at. Picked so that no pixel center is closer to an
+/// outer edge of the quad than the sub-pixel precision of a GPU, which would make coverage differ
+/// between drivers.
+const TIME: f32 = 0.38;
+const TOLERANCE: u8 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    pos: Vec2,
+    color: Vec3,
+}
+
+const VERTS: [Vertex; 4] = [
+    Vertex {
+        pos: Vec2::new(-0.5, -0.5),
+        color: Vec3::new(1., 0., 0.),
+    },
+    Vertex {
+        pos: Vec2::new(0.5, -0.5),
+        color: Vec3::new(0., 1., 0.),
+    },
+    Vertex {
+        pos: Vec2::new(0.5, 0.5),
+        color: Vec3::new(0., 0., 1.),
+    },
+    Vertex {
+        pos: Vec2::new(-0.5, 0.5),
+        color: Vec3::new(1., 1., 1.),
+    },
+];
+
+const INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+/// Same transform as `Vulkan::update_uniform_buffer` in `render_bk`, at `time` seconds.
+fn mvp(time: f32, aspect: f32) -> Mat4 {
+    let model = Mat4::from_rotation_z(time * 90.0f32.to_radians());
+
+    let view = Mat4::look_at_rh(
+        glam::vec3(2.0, 2.0, 2.0),
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::vec3(0.0, 0.0, 1.0),
+    );
+
+    let mut proj = Mat4::perspective_rh_gl(45.0f32.to_radians(), aspect, 0.1, 10.0);
+    proj.y_axis *= -1.;
+
+    proj * view * model
+}
+
+#[test]
+fn spinning_quad() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let extent = vk::Extent2D {
+        width: WIDTH,
+        height: HEIGHT,
+    };
+    let target = OffscreenTarget::new(&vk, extent, vk::Format::R8G8B8A8_UNORM)
+        .expect("Could not create offscreen target");
+
+    let scene = QuadScene::new(&vk, &target);
+    let mvp = mvp(TIME, WIDTH as f32 / HEIGHT as f32);
+
+    let pixels = vk
+        .render_offscreen(&target, [0., 0., 0., 1.], |device, cmd| {
+            scene.record(device, cmd, extent, &mvp)
+        })
+        .expect("Could not render frame");
+
+    assert_golden(
+        "spinning_quad",
+        &Image::new(WIDTH, HEIGHT, pixels),
+        TOLERANCE,
+    );
+}
+
+struct QuadScene<'a> {
+    device: &'a Device,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    vertices: Buffer<Vertex>,
+    indices: Buffer<u32>,
+}
+
+impl<'a> QuadScene<'a> {
+    fn new(vk: &'a Vk, target: &OffscreenTarget) -> Self {
+        let device = vk.get_device();
+
+        let vert = create_shader_module(
+            device,
+            include_str!("shaders/quad.vert"),
+            ShaderStage::Vertex,
+        );
+        let frag = create_shader_module(
+            device,
+            include_str!("shaders/quad.frag"),
+            ShaderStage::Fragment,
+        );
+
+        let main_function_name = CString::new("main").unwrap();
+        let stages = [
+            *vk::PipelineShaderStageCreateInfo::builder()
+                .name(&main_function_name)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert),
+            *vk::PipelineShaderStageCreateInfo::builder()
+                .name(&main_function_name)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag),
+        ];
+
+        let push_constant_ranges = [*vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(size_of::<Mat4>() as u32)];
+        let layout_info =
+            vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(&layout_info, None)
+                .expect("Failed to create pipeline layout!")
+        };
+
+        let binding_descriptions = [*vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Vertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)];
+        let attribute_descriptions = [
+            *vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(0),
+            *vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(size_of::<Vec2>() as u32),
+        ];
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&binding_descriptions)
+            .vertex_attribute_descriptions(&attribute_descriptions);
+
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .line_width(1.);
+
+        let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let color_blend_attachments = [*vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+            .blend_enable(false)];
+        let color_blending =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+        let color_formats = [target.get_color_format()];
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(&color_formats)
+            .depth_attachment_format(target.get_depth_format());
+
+        let pipeline_info = [*vk::GraphicsPipelineCreateInfo::builder()
+            .push_next(&mut rendering_info)
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisampling)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blending)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)];
+
+        let pipeline = unsafe {
+            let pipeline = device
+                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_info, None)
+                .expect("Failed to create graphics pipeline!")[0];
+
+            device.destroy_shader_module(vert, None);
+            device.destroy_shader_module(frag, None);
+
+            pipeline
+        };
+
+        Self {
+            device,
+            layout,
+            pipeline,
+            vertices: Buffer::from_slice(vk, "quad vertices", BufferUsage::Vertex, &VERTS)
+                .expect("Could not upload vertices"),
+            indices: Buffer::from_slice(vk, "quad indices", BufferUsage::Index, &INDICES)
+                .expect("Could not upload indices"),
+        }
+    }
+
+    fn record(&self, device: &Device, cmd: vk::CommandBuffer, extent: vk::Extent2D, mvp: &Mat4) {
+        let viewports = [vk::Viewport {
+            x: 0.,
+            y: 0.,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.,
+            max_depth: 1.,
+        }];
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        }];
+
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_set_viewport(cmd, 0, &viewports);
+            device.cmd_set_scissor(cmd, 0, &scissors);
+            device.cmd_push_constants(
+                cmd,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(mvp as *const Mat4 as *const u8, size_of::<Mat4>()),
+            );
+            device.cmd_bind_vertex_buffers(cmd, 0, &[self.vertices.get_buffer()], &[0]);
+            device.cmd_bind_index_buffer(
+                cmd,
+                self.indices.get_buffer(),
+                0,
+                self.indices.get_index_type(),
+            );
+            device.cmd_draw_indexed(cmd, self.indices.len() as u32, 1, 0, 0, 0);
+        }
+    }
+}
+
+impl Drop for QuadScene<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+fn create_shader_module(device: &Device, source: &str, stage: ShaderStage) -> vk::ShaderModule {
+    let code = compile_glsl(source, stage, "quad").unwrap_or_else(|e| panic!("{}", e));
+    let module_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+
+    unsafe {
+        device
+            .create_shader_module(&module_info, None)
+            .expect("Failed to create shader module!")
+    }
+}
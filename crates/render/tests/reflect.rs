@@ -0,0 +1,253 @@
+use std::mem::offset_of;
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3};
+use render::{
+    reflect::{PipelineReflection, ShaderReflection},
+    shader::{compile_glsl, ShaderStage},
+    vertex::{MeshVertex, Vertex, VertexAttribute},
+    RenderError,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct QuadVertex {
+    pos: Vec2,
+    color: Vec3,
+}
+
+impl Vertex for QuadVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            location: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: offset_of!(QuadVertex, pos) as u32,
+        },
+        VertexAttribute {
+            location: 1,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: offset_of!(QuadVertex, color) as u32,
+        },
+    ];
+}
+
+/// Has the position of [`QuadVertex`] but no color.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct PositionVertex {
+    pos: Vec2,
+}
+
+impl Vertex for PositionVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute {
+        location: 0,
+        format: vk::Format::R32G32_SFLOAT,
+        offset: 0,
+    }];
+}
+
+const TEXTURED_FRAG: &str = "#version 450
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform Globals {
+    vec4 tint;
+} globals;
+layout(set = 1, binding = 0) uniform texture2D albedo;
+layout(set = 1, binding = 1) uniform sampler albedoSampler;
+
+void main() {
+    outColor = texture(sampler2D(albedo, albedoSampler), fragColor.xy) * globals.tint;
+}
+";
+
+fn reflect(source: &str, stage: ShaderStage) -> ShaderReflection {
+    let code = compile_glsl(source, stage, "test").unwrap_or_else(|e| panic!("{}", e));
+    ShaderReflection::new(&code, stage).expect("Could not reflect shader")
+}
+
+#[test]
+fn reflects_vertex_inputs_and_push_constants() {
+    let vert = reflect(include_str!("shaders/quad.vert"), ShaderStage::Vertex);
+
+    let inputs: Vec<_> = vert
+        .inputs
+        .iter()
+        .map(|input| (input.location, input.format))
+        .collect();
+    assert_eq!(
+        inputs,
+        [
+            (0, vk::Format::R32G32_SFLOAT),
+            (1, vk::Format::R32G32B32_SFLOAT)
+        ]
+    );
+    assert!(vert.bindings.is_empty());
+    // a mat4
+    assert_eq!(vert.push_constant_size, 64);
+}
+
+#[test]
+fn merges_bindings_of_stages() {
+    let vert = reflect(include_str!("shaders/quad.vert"), ShaderStage::Vertex);
+    let frag = reflect(TEXTURED_FRAG, ShaderStage::Fragment);
+    let pipeline = PipelineReflection::new(&[vert, frag]).expect("Stages should agree");
+
+    let bindings: Vec<_> = pipeline
+        .get_bindings()
+        .iter()
+        .map(|binding| (binding.set, binding.binding, binding.descriptor_type))
+        .collect();
+    assert_eq!(
+        bindings,
+        [
+            (0, 0, vk::DescriptorType::UNIFORM_BUFFER),
+            (1, 0, vk::DescriptorType::SAMPLED_IMAGE),
+            (1, 1, vk::DescriptorType::SAMPLER),
+        ]
+    );
+
+    let push_constants = pipeline
+        .get_push_constant_range()
+        .expect("quad.vert has push constants");
+    assert_eq!(push_constants.stage_flags, vk::ShaderStageFlags::VERTEX);
+    assert_eq!(push_constants.size, 64);
+}
+
+#[test]
+fn builds_vertex_input_of_matching_vertex() {
+    let vert = reflect(include_str!("shaders/quad.vert"), ShaderStage::Vertex);
+    let pipeline = PipelineReflection::new(&[vert]).unwrap();
+
+    let layout = pipeline
+        .vertex_input::<QuadVertex>(0)
+        .expect("QuadVertex matches quad.vert");
+    assert_eq!(layout.bindings[0].stride, 20);
+    assert_eq!(layout.attributes[1].offset, 8);
+}
+
+#[test]
+fn rejects_mismatching_vertex() {
+    let vert = reflect(include_str!("shaders/quad.vert"), ShaderStage::Vertex);
+    let pipeline = PipelineReflection::new(&[vert]).unwrap();
+
+    match pipeline.vertex_input::<PositionVertex>(0) {
+        Err(RenderError::VertexMismatch { vertex, message }) => {
+            assert!(vertex.ends_with("PositionVertex"), "{}", vertex);
+            assert!(message.contains("location 1"), "{}", message);
+        }
+        result => panic!("Expected a vertex mismatch, got {:?}", result),
+    }
+}
+
+#[test]
+fn reflects_workgroup_size() {
+    let source = "#version 450
+
+layout(local_size_x = 8, local_size_y = 4) in;
+layout(set = 0, binding = 0, rgba8) uniform writeonly image2D target;
+
+void main() {
+    imageStore(target, ivec2(gl_GlobalInvocationID.xy), vec4(1.0));
+}
+";
+    let comp = reflect(source, ShaderStage::Compute);
+
+    assert_eq!(comp.workgroup_size, [8, 4, 1]);
+    assert_eq!(
+        comp.bindings[0].descriptor_type,
+        vk::DescriptorType::STORAGE_IMAGE
+    );
+}
+
+#[test]
+fn mesh_vertex_matches_standard_inputs() {
+    let source = "#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+
+layout(location = 0) out vec2 fragUv;
+
+void main() {
+    fragUv = uv + tangent.xy * normal.z;
+    gl_Position = vec4(position, 1.0);
+}
+";
+    let vert = reflect(source, ShaderStage::Vertex);
+    let pipeline = PipelineReflection::new(&[vert]).unwrap();
+
+    let layout = pipeline
+        .vertex_input::<MeshVertex>(0)
+        .expect("MeshVertex matches the standard inputs");
+    assert_eq!(layout.bindings[0].stride, 48);
+    let offsets: Vec<_> = layout.attributes.iter().map(|a| a.offset).collect();
+    assert_eq!(offsets, [0, 12, 24, 40]);
+}
+
+#[test]
+fn reflects_buffer_references_in_push_constants() {
+    let source = "#version 460
+#extension GL_EXT_buffer_reference : require
+
+layout(buffer_reference, std430) readonly buffer Positions { vec4 positions[]; };
+
+layout(push_constant) uniform DrawConstants {
+    mat4 transform;
+    Positions positions;
+    uint first;
+};
+
+void main() {
+    gl_Position = transform * positions.positions[gl_VertexIndex + first];
+}
+";
+    let vert = reflect(source, ShaderStage::Vertex);
+
+    assert!(vert.inputs.is_empty());
+    // a mat4, the 64 bit address and a uint
+    assert_eq!(vert.push_constant_size, 76);
+}
+
+#[test]
+fn reflects_bindless_runtime_arrays() {
+    let source = format!(
+        "#version 460
+{}
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 outColor;
+
+layout(push_constant) uniform Constants {{
+    uint textureIndex;
+    uint samplerIndex;
+}};
+
+void main() {{
+    outColor = texture(sampler2D(bindless_textures[nonuniformEXT(textureIndex)],
+        bindless_samplers[samplerIndex]), uv);
+    outColor.a = float(bindless_buffers[textureIndex].data[0]);
+}}
+",
+        render::bindless::GLSL_DECLARATIONS
+    );
+    let frag = reflect(&source, ShaderStage::Fragment);
+
+    let bindings: Vec<_> = frag
+        .bindings
+        .iter()
+        .map(|binding| (binding.binding, binding.descriptor_type, binding.count))
+        .collect();
+    assert_eq!(
+        bindings,
+        [
+            (0, vk::DescriptorType::SAMPLED_IMAGE, None),
+            (1, vk::DescriptorType::SAMPLER, None),
+            (2, vk::DescriptorType::STORAGE_BUFFER, None),
+        ]
+    );
+    assert_eq!(frag.push_constant_size, 8);
+}
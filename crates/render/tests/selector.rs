@@ -0,0 +1,17 @@
+use render::selector::{DeviceSelector, Feature};
+
+#[test]
+fn bindless_features_are_requested_not_required() {
+    let selector = DeviceSelector::new();
+
+    assert_eq!(selector.get_features(), Feature::REQUIRED);
+    assert_eq!(selector.get_optional_features(), Feature::BINDLESS);
+    assert!(Feature::BINDLESS
+        .iter()
+        .all(|feature| !Feature::REQUIRED.contains(feature)));
+
+    let selector = selector.require_feature(Feature::RuntimeDescriptorArray);
+    assert!(selector
+        .get_features()
+        .contains(&Feature::RuntimeDescriptorArray));
+}
@@ -0,0 +1,48 @@
+use render::{
+    shader::{compile_glsl, ShaderStage},
+    RenderError,
+};
+
+#[test]
+fn compiles_glsl_to_spirv() {
+    let code = compile_glsl(
+        include_str!("shaders/quad.frag"),
+        ShaderStage::Fragment,
+        "quad",
+    )
+    .expect("quad.frag should compile");
+
+    // SPIR-V magic number
+    assert_eq!(code[0], 0x0723_0203);
+}
+
+#[test]
+fn reports_line_of_error() {
+    let source = "#version 450\n\nvoid main() {\n    undefined_function();\n}\n";
+
+    match compile_glsl(source, ShaderStage::Fragment, "broken.frag") {
+        Err(RenderError::ShaderCompilation { name, message }) => {
+            assert_eq!(name, "broken.frag");
+            assert!(message.contains("broken.frag:4:"), "{}", message);
+        }
+        result => panic!("Expected a compilation error, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn compiles_bindless_declarations() {
+    let source = format!(
+        "#version 460\n{}\n\
+         layout(push_constant) uniform Push {{ uint texture; uint sampler_; }} push;\n\
+         layout(location = 0) in vec2 uv;\n\
+         layout(location = 0) out vec4 outColor;\n\
+         void main() {{\n\
+         \x20   outColor = texture(sampler2D(bindless_textures[nonuniformEXT(push.texture)], \
+         bindless_samplers[push.sampler_]), uv);\n\
+         }}\n",
+        render::bindless::GLSL_DECLARATIONS
+    );
+
+    compile_glsl(&source, ShaderStage::Fragment, "bindless.frag")
+        .unwrap_or_else(|e| panic!("{}", e));
+}
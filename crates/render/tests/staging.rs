@@ -0,0 +1,59 @@
+//! Checks how the staging ring hands out and reuses its space, which needs a device for the buffer
+//! but submits nothing.
+
+use render::{staging::StagingRing, Vk};
+
+#[test]
+fn aligns_pushes() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut ring = StagingRing::new(&vk, 256).expect("Could not create staging ring");
+
+    assert_eq!(ring.push(&[1; 3], 4).unwrap(), Some(0));
+    assert_eq!(ring.push(&[2; 8], 16).unwrap(), Some(16));
+    assert_eq!(ring.push(&[3; 4], 4).unwrap(), Some(24));
+}
+
+#[test]
+fn wraps_around_once_released() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut ring = StagingRing::new(&vk, 256).expect("Could not create staging ring");
+
+    assert_eq!(ring.push(&[0; 100], 4).unwrap(), Some(0));
+    let first = ring.submit();
+    assert_eq!(ring.push(&[0; 100], 4).unwrap(), Some(100));
+    let second = ring.submit();
+
+    // neither after the head nor before the tail is there room until the first is released
+    assert_eq!(ring.push(&[0; 100], 4).unwrap(), None);
+    ring.release(first);
+    assert_eq!(ring.push(&[0; 100], 4).unwrap(), Some(0));
+    let third = ring.submit();
+
+    // the head caught up with the tail of the second submission
+    assert_eq!(ring.push(&[0; 1], 4).unwrap(), None);
+    ring.release(second);
+    assert_eq!(ring.push(&[0; 100], 4).unwrap(), Some(100));
+    let fourth = ring.submit();
+    assert!(fourth > third);
+
+    // releasing everything starts over at the front instead of after the last push
+    ring.release(fourth);
+    assert_eq!(ring.push(&[0; 200], 4).unwrap(), Some(0));
+}
+
+#[test]
+fn releasing_a_later_submission_frees_earlier_ones() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut ring = StagingRing::new(&vk, 256).expect("Could not create staging ring");
+
+    for _ in 0..2 {
+        ring.push(&[0; 128], 4).unwrap().expect("Ring has room");
+        ring.submit();
+    }
+    // an empty submission is still counted
+    let last = ring.submit();
+    assert_eq!(ring.push(&[0; 1], 4).unwrap(), None);
+
+    ring.release(last);
+    assert_eq!(ring.push(&[0; 256], 4).unwrap(), Some(0));
+}
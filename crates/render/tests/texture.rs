@@ -0,0 +1,60 @@
+mod encode;
+
+use ash::vk;
+use encode::encode_png;
+use render::{
+    texture::{mip_level_count, ColorSpace, TextureData},
+    RenderError,
+};
+
+#[test]
+fn counts_mips_down_to_one_texel() {
+    assert_eq!(mip_level_count(1, 1), 1);
+    assert_eq!(mip_level_count(2, 1), 2);
+    assert_eq!(mip_level_count(256, 256), 9);
+    assert_eq!(mip_level_count(300, 20), 9);
+    assert_eq!(mip_level_count(0, 0), 1);
+}
+
+#[test]
+fn decodes_png_in_the_requested_color_space() {
+    let pixels = [255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 10, 20, 30, 40];
+    let png = encode_png(2, 2, &pixels);
+
+    let srgb = TextureData::from_memory(&png, ColorSpace::Srgb).expect("PNG is supported");
+    assert_eq!(srgb.format, vk::Format::R8G8B8A8_SRGB);
+    assert_eq!((srgb.width, srgb.height, srgb.layers), (2, 2, 1));
+    assert!(!srgb.cube);
+    assert_eq!(srgb.levels, [pixels.to_vec()]);
+
+    let linear = TextureData::from_memory(&png, ColorSpace::Linear).expect("PNG is supported");
+    assert_eq!(linear.format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!(linear.levels, srgb.levels);
+}
+
+#[test]
+fn rejects_unknown_images() {
+    let result = TextureData::from_memory(b"not an image", ColorSpace::Srgb);
+
+    assert!(matches!(result, Err(RenderError::ImageLoad { .. })));
+}
+
+#[test]
+fn halves_level_extents() {
+    let data = TextureData::from_rgba8(8, 2, vec![0; 8 * 2 * 4], ColorSpace::Srgb);
+
+    assert_eq!(
+        data.get_level_extent(1),
+        vk::Extent2D {
+            width: 4,
+            height: 1
+        }
+    );
+    assert_eq!(
+        data.get_level_extent(3),
+        vk::Extent2D {
+            width: 1,
+            height: 1
+        }
+    );
+}
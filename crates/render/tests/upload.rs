@@ -0,0 +1,135 @@
+//! Uploads through the transfer queue and reads the buffers back on the graphics queue, checking
+//! that tickets complete in order and that uploads larger than the staging ring are split.
+
+use std::{mem::size_of, time::Duration};
+
+use ash::vk;
+use render::{
+    buffer::{Buffer, BufferUsage},
+    memory::{GpuBuffer, MemoryLocation},
+    upload::UploadManager,
+    Vk,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Acquires the finished uploads of `uploads` on the graphics queue and copies `buffer` back.
+fn read_back(vk: &Vk, uploads: &mut UploadManager, buffer: &Buffer<u32>) -> Vec<u32> {
+    let readback = GpuBuffer::new(
+        vk,
+        "upload readback",
+        buffer.get_size(),
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryLocation::Readback,
+    )
+    .expect("Could not create readback buffer");
+
+    let mut acquired = Ok(None);
+    vk.immediate_submit(|device, cmd| {
+        acquired = uploads.record_acquires(cmd);
+
+        let to_copy = [*vk::MemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)];
+        let to_host = [*vk::MemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+            .dst_access_mask(vk::AccessFlags2::HOST_READ)];
+        let region = [vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size: buffer.get_size(),
+        }];
+
+        unsafe {
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().memory_barriers(&to_copy),
+            );
+            device.cmd_copy_buffer(cmd, buffer.get_buffer(), readback.get_buffer(), &region);
+            device.cmd_pipeline_barrier2(
+                cmd,
+                &vk::DependencyInfo::builder().memory_barriers(&to_host),
+            );
+        }
+    })
+    .expect("Could not read buffer back");
+    acquired.expect("Could not record acquires");
+
+    let bytes = readback
+        .read(0, buffer.get_size() as usize)
+        .expect("Could not read buffer");
+    bytes
+        .chunks_exact(size_of::<u32>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
+
+#[test]
+fn uploads_and_reads_back() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut uploads = UploadManager::new(&vk).expect("Could not create upload manager");
+    let buffer = Buffer::<u32>::new(&vk, "values", BufferUsage::Storage, 1000)
+        .expect("Could not create buffer");
+
+    let values: Vec<u32> = (0..1000).collect();
+    uploads.write(&buffer, 0, &values[..600]).unwrap();
+    uploads.write(&buffer, 600, &values[600..]).unwrap();
+    let ticket = uploads.flush().expect("Could not flush uploads");
+
+    assert!(uploads.wait(ticket, TIMEOUT).expect("Could not wait"));
+    assert!(uploads.poll(ticket).expect("Could not poll"));
+    assert_eq!(read_back(&vk, &mut uploads, &buffer), values);
+}
+
+#[test]
+fn tickets_are_ordered() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    let mut uploads = UploadManager::new(&vk).expect("Could not create upload manager");
+    let buffer = Buffer::<u32>::new(&vk, "values", BufferUsage::Storage, 4)
+        .expect("Could not create buffer");
+
+    // nothing written yet, so the ticket is already complete
+    let empty = uploads.flush().expect("Could not flush uploads");
+    assert_eq!(empty.get_value(), 0);
+    assert!(uploads.poll(empty).expect("Could not poll"));
+
+    // disjoint ranges, as the first range may already be released to the graphics family
+    uploads.write(&buffer, 0, &[1, 2]).unwrap();
+    let first = uploads.flush().expect("Could not flush uploads");
+    uploads.write(&buffer, 2, &[5, 6]).unwrap();
+    let second = uploads.flush().expect("Could not flush uploads");
+    assert!(second > first);
+    assert_eq!(uploads.flush().expect("Could not flush uploads"), second);
+
+    // waiting for the later batch completes the earlier one as well
+    assert!(uploads.wait(second, TIMEOUT).expect("Could not wait"));
+    assert!(uploads.poll(first).expect("Could not poll"));
+    uploads.collect().expect("Could not collect batches");
+
+    assert_eq!(read_back(&vk, &mut uploads, &buffer), [1, 2, 5, 6]);
+}
+
+#[test]
+fn uploads_more_than_the_staging_ring_holds() {
+    let vk = Vk::headless().expect("Could not create headless Vulkan device");
+    // far smaller than the data, so writing has to flush and wait for the ring to free up
+    let mut uploads =
+        UploadManager::with_staging_size(&vk, 256).expect("Could not create upload manager");
+    let buffer = Buffer::<u32>::new(&vk, "values", BufferUsage::Storage, 1024)
+        .expect("Could not create buffer");
+
+    let values: Vec<u32> = (0..1024).map(|i| i * 3).collect();
+    uploads.write(&buffer, 0, &values).unwrap();
+    let ticket = uploads.flush().expect("Could not flush uploads");
+    assert!(
+        ticket.get_value() > 1,
+        "The ring should have been flushed while writing"
+    );
+
+    assert!(uploads.wait(ticket, TIMEOUT).expect("Could not wait"));
+    assert_eq!(read_back(&vk, &mut uploads, &buffer), values);
+}
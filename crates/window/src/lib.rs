@@ -1,12 +1,14 @@
 mod events;
 mod raw_handle;
-use ash::vk;
-use render::Vk;
+use ash::{vk, Entry, Instance};
+use render::{depth::DepthBuffer, frame::DEFAULT_FRAMES_IN_FLIGHT, FrameLoop, RenderError, Vk};
 
 use anyhow::{bail, Result};
 use events::windowevents;
+use tracing::error;
 use winit::{
-    event::Event,
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
     event_loop::EventLoop,
     keyboard::ModifiersState,
     raw_window_handle::{HasDisplayHandle, HasWindowHandle},
@@ -20,22 +22,104 @@ pub struct Window {
 }
 
 pub struct WindowContext {
+    /// Created with the swapchain, declared first so it is dropped before `vk`.
+    frames: Option<FrameLoop>,
+    /// Created and resized with the swapchain.
+    depth: Option<DepthBuffer>,
     vk: Vk,
-    surface: vk::SurfaceKHR,
 }
 
 impl WindowContext {
-    pub fn new(ext: Vec<*const i8>) -> Self {
-        let vk = Vk::new(&ext);
-
-        Self {
+    /// Creates the renderer for `window`, picking a device that can present to it.
+    pub fn new(ext: Vec<*const i8>, window: &WinitWindow) -> render::Result<Self> {
+        let vk = Vk::new(&ext, |entry, instance| {
+            create_surface_khr(window, entry, instance)
+        })?;
+
+        Ok(Self {
+            frames: None,
+            depth: None,
             vk,
-            surface: vk::SurfaceKHR::null(),
+        })
+    }
+
+    /// Creates the swapchain for the window surface.
+    pub fn create_swapchain(
+        &mut self,
+        size: PhysicalSize<u32>,
+        present_mode: vk::PresentModeKHR,
+    ) -> render::Result<()> {
+        self.vk.create_swapchain(to_extent(size), present_mode)?;
+        self.frames = Some(FrameLoop::new(&self.vk, DEFAULT_FRAMES_IN_FLIGHT)?);
+        self.depth = Some(DepthBuffer::new(&self.vk, self.get_swapchain_extent())?);
+
+        Ok(())
+    }
+
+    /// Renders and presents one frame, recreating the swapchain for `size` if it is out of date.
+    ///
+    /// Does nothing before the swapchain has been created or while the window is minimized.
+    pub fn draw(&mut self, size: PhysicalSize<u32>) -> render::Result<()> {
+        let (Some(frames), Some(depth)) = (self.frames.as_mut(), self.depth.as_ref()) else {
+            return Ok(());
+        };
+        if size.width == 0 || size.height == 0 {
+            return Ok(());
+        }
+
+        let Some(frame) = frames.begin(&self.vk)? else {
+            self.resize(size)?;
+            return Ok(());
+        };
+
+        frame.begin_rendering_with_depth([0.01, 0.01, 0.01, 1.], depth);
+        frame.end_rendering();
+
+        if frames.end(&self.vk, frame)? {
+            self.resize(size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the next frame drawn to `path` as a PNG, see [`FrameLoop::capture_frame`].
+    ///
+    /// Does nothing before the swapchain has been created.
+    pub fn capture_frame(&mut self, path: impl Into<std::path::PathBuf>) {
+        if let Some(frames) = self.frames.as_mut() {
+            frames.capture_frame(path);
+        }
+    }
+
+    /// Recreates the swapchain for the new window size, keeping the device alive.
+    ///
+    /// Returns whether the swapchain was recreated, which it is not while the surface has no
+    /// extent.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> render::Result<bool> {
+        if !self.vk.recreate_swapchain(to_extent(size))? {
+            return Ok(false);
         }
+
+        let extent = self.get_swapchain_extent();
+        if let Some(depth) = self.depth.as_mut() {
+            depth.resize(&self.vk, extent)?;
+        }
+
+        Ok(true)
     }
 
-    pub fn set_surface(&mut self, surface: vk::SurfaceKHR) {
-        self.surface = surface;
+    /// The extent the surface picked, which can differ from the window size.
+    fn get_swapchain_extent(&self) -> vk::Extent2D {
+        self.vk
+            .get_swapchain()
+            .map_or(vk::Extent2D::default(), |swapchain| swapchain.get_extent())
+    }
+}
+
+fn to_extent(size: PhysicalSize<u32>) -> vk::Extent2D {
+    vk::Extent2D {
+        width: size.width,
+        height: size.height,
     }
 }
 
@@ -54,31 +138,48 @@ impl Window {
             bail!("Failed to create window!")
         };
 
-        let ext =
-            raw_handle::enumerate_required_extensions(window.display_handle().unwrap()).unwrap();
+        let display_handle = window
+            .display_handle()
+            .map_err(|_| RenderError::SurfaceCreationFailed(vk::Result::ERROR_UNKNOWN))?;
+        // fails on platforms without a surface extension
+        let ext = raw_handle::enumerate_required_extensions(display_handle)
+            .map_err(RenderError::SurfaceCreationFailed)?;
 
-        let ctx = WindowContext::new(ext);
+        let mut ctx = WindowContext::new(ext, &window)?;
+        ctx.create_swapchain(window.inner_size(), vk::PresentModeKHR::MAILBOX)?;
 
-        let mut wind = Window {
+        Ok(Window {
             window,
             evt_loop,
             ctx,
-        };
-
-        let surface = wind.create_surface_khr();
-        wind.ctx.set_surface(surface);
-
-        Ok(wind)
+        })
     }
 
-    pub fn run(self) {
+    pub fn run(mut self) {
         let mut modifiers = ModifiersState::default();
 
         let evt_res = self.evt_loop.run(move |event, elwt| match event {
             // Event::NewEvents(_) => todo!(),
             Event::WindowEvent { window_id, event } if window_id == self.window.id() => {
+                match event {
+                    WindowEvent::Resized(size) => {
+                        if let Err(e) = self.ctx.resize(size) {
+                            error!("Could not recreate swapchain: {}", e);
+                            elwt.exit();
+                        }
+                    }
+                    WindowEvent::RedrawRequested => {
+                        if let Err(e) = self.ctx.draw(self.window.inner_size()) {
+                            error!("Could not draw frame: {}", e);
+                            elwt.exit();
+                        }
+                    }
+                    _ => {}
+                }
+
                 windowevents(&mut modifiers, &event, elwt);
             }
+            Event::AboutToWait => self.window.request_redraw(),
             // Event::DeviceEvent { event, .. } => match event {
             // winit::event::DeviceEvent::MouseMotion { delta } => todo!("Mouse mothion"),
             // winit::event::DeviceEvent::MouseWheel { delta } => todo!("Mouse wheel"),
@@ -95,29 +196,31 @@ impl Window {
         }
     }
 
-    pub fn create_surface_khr(&self) -> vk::SurfaceKHR {
-        let rdh = self
-            .window
-            .display_handle()
-            .expect("Could not get display handle");
-
-        let rwh = self
-            .window
-            .window_handle()
-            .expect("Could not get window handle");
-
-        unsafe {
-            if let Ok(surface) = crate::raw_handle::create_surface(
-                self.ctx.vk.get_entry(),
-                self.ctx.vk.get_instance(),
-                rwh,
-                rdh,
-                None,
-            ) {
-                surface
-            } else {
-                panic!("Could not create surface")
-            }
+    /// Sets the preferred present mode and recreates the swapchain with it.
+    ///
+    /// Falls back to FIFO if the surface does not support `present_mode`.
+    pub fn set_present_mode(&mut self, present_mode: vk::PresentModeKHR) -> render::Result<()> {
+        if let Some(swapchain) = self.ctx.vk.get_swapchain_mut() {
+            swapchain.set_present_mode(present_mode);
         }
+
+        self.ctx.resize(self.window.inner_size())?;
+
+        Ok(())
+    }
+}
+
+pub fn create_surface_khr(
+    window: &WinitWindow,
+    entry: &Entry,
+    instance: &Instance,
+) -> render::Result<vk::SurfaceKHR> {
+    let surface_error = |_| RenderError::SurfaceCreationFailed(vk::Result::ERROR_UNKNOWN);
+    let rdh = window.display_handle().map_err(surface_error)?;
+    let rwh = window.window_handle().map_err(surface_error)?;
+
+    unsafe {
+        crate::raw_handle::create_surface(entry, instance, rwh, rdh, None)
+            .map_err(RenderError::SurfaceCreationFailed)
     }
 }
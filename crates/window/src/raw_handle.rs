@@ -31,7 +31,7 @@ pub unsafe fn create_surface(
             let surface_desc = Win32SurfaceCreateInfoKHR {
                 hinstance: win
                     .hinstance
-                    .expect("Could not get hinstance of surface")
+                    .ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?
                     .get() as *const c_void,
                 hwnd: win.hwnd.get() as *const c_void,
                 ..Default::default()
@@ -64,7 +64,8 @@ pub unsafe fn create_surface(
             let surface_fn = khr::WaylandSurface::new(entry, instance);
             surface_fn.create_wayland_surface(&surface_desc, alloc_clb)
         }
-        _ => panic!("Unsupported window/display handle combination for creating surface."),
+        // unsupported window/display handle combination for creating a surface
+        _ => Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT),
     }
 }
 